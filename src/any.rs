@@ -0,0 +1,321 @@
+//! A key-value client that works with either etcd's v2 or v3 API.
+//!
+//! `any::Client` chooses an API by asking the cluster members for their versions, and offers the
+//! operations the two APIs have in common: getting, setting, and deleting keys, and watching them
+//! for changes. Libraries that need no more than that can use it to support clusters on either
+//! side of an upgrade from etcd 2 to etcd 3.
+//!
+//! The v2 and v3 APIs keep separate key spaces, so keys written with one API are not visible with
+//! the other. After a cluster is upgraded to etcd 3, a client that detects the v3 API won't see
+//! the keys written before the upgrade until they are migrated. To keep using them, choose the v2
+//! API explicitly with `any::Client::with_api`.
+//!
+//! Keys are used as given with the v3 API. With the v2 API, a slash is added to the beginning of
+//! keys that don't start with one.
+
+use futures::future::{Either, Future};
+use futures::stream::Stream;
+use hyper::client::connect::Connect;
+
+use crate::client::Client as V2Client;
+use crate::error::Error;
+use crate::kv::{
+    self,
+    has_error_code,
+    Action,
+    GetOptions,
+    Node,
+    WatchItem,
+    WatchOptions,
+    KEY_NOT_FOUND,
+};
+use crate::v3;
+use crate::version::Feature;
+
+/// A version of etcd's API.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Api {
+    /// The v2 API, used by the `kv` module.
+    V2,
+    /// The v3 API, used by the `v3` module.
+    V3,
+}
+
+/// A key and its value.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct KeyValue {
+    /// The key.
+    pub key: String,
+    /// The value. This is empty after the key is deleted.
+    pub value: String,
+    /// The revision at which the key was last changed: its modified index in the v2 API, or its
+    /// modification revision in the v3 API.
+    pub revision: u64,
+}
+
+/// The kind of change to a key in an `Event`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EventType {
+    /// The key was created or modified.
+    Set,
+    /// The key was deleted or expired.
+    Delete,
+}
+
+/// A change to a key, yielded by `any::Client::watch`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Event {
+    /// The kind of change.
+    pub kind: EventType,
+    /// The key and its value after the change.
+    pub kv: KeyValue,
+}
+
+/// A key-value client for either etcd's v2 or v3 API.
+#[derive(Clone, Debug)]
+pub struct Client<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    api: Api,
+    client: V2Client<C>,
+}
+
+impl<C> Client<C>
+where
+    C: Clone + Connect,
+{
+    /// Constructs a client that uses the newest API every reachable cluster member supports.
+    ///
+    /// # Parameters
+    ///
+    /// * client: The `Client` to make API calls with.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no cluster member could be reached.
+    pub fn detect(
+        client: &V2Client<C>,
+    ) -> impl Future<Item = Client<C>, Error = Vec<Error>> + Send {
+        let client = client.clone();
+
+        client.supports(Feature::V3Api).map(move |v3| Client {
+            api: if v3 { Api::V3 } else { Api::V2 },
+            client,
+        })
+    }
+
+    /// Constructs a client that uses the given API.
+    ///
+    /// # Parameters
+    ///
+    /// * client: The `Client` to make API calls with.
+    /// * api: The API to use.
+    pub fn with_api(client: &V2Client<C>, api: Api) -> Self {
+        Client {
+            api,
+            client: client.clone(),
+        }
+    }
+
+    /// Returns the API the client uses.
+    pub fn api(&self) -> Api {
+        self.api
+    }
+
+    /// Returns the `Client` the client makes API calls with, for using the features particular
+    /// to its API.
+    pub fn inner(&self) -> &V2Client<C> {
+        &self.client
+    }
+
+    /// Deletes a key. Returns whether or not the key existed.
+    ///
+    /// # Parameters
+    ///
+    /// * key: The key to delete.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no cluster member could be reached.
+    pub fn delete(&self, key: &str) -> impl Future<Item = bool, Error = Vec<Error>> + Send {
+        match self.api {
+            Api::V2 => Either::A(
+                kv::delete(&self.client, &v2_key(key), false).then(|result| match result {
+                    Ok(_) => Ok(true),
+                    Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(false),
+                    Err(errors) => Err(errors),
+                }),
+            ),
+            Api::V3 => {
+                Either::B(v3::kv::delete(&self.client, key).map(|response| response.data > 0))
+            }
+        }
+    }
+
+    /// Gets a key and its value, or `None` if the key doesn't exist.
+    ///
+    /// # Parameters
+    ///
+    /// * key: The key to get.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no cluster member could be reached, or if the value isn't valid UTF-8.
+    pub fn get(
+        &self,
+        key: &str,
+    ) -> impl Future<Item = Option<KeyValue>, Error = Vec<Error>> + Send {
+        let key = key.to_owned();
+
+        match self.api {
+            Api::V2 => Either::A(
+                kv::get(&self.client, &v2_key(&key), GetOptions::default()).then(move |result| {
+                    match result {
+                        Ok(response) => Ok(Some(from_node(key, &response.data.node))),
+                        Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(None),
+                        Err(errors) => Err(errors),
+                    }
+                }),
+            ),
+            Api::V3 => Either::B(v3::kv::get(&self.client, &key).and_then(move |response| {
+                match response.data {
+                    Some(kv) => from_key_value(key, kv)
+                        .map(Some)
+                        .map_err(|error| vec![error]),
+                    None => Ok(None),
+                }
+            })),
+        }
+    }
+
+    /// Sets the value of a key, creating it if it doesn't exist. Returns the key and its new
+    /// value.
+    ///
+    /// # Parameters
+    ///
+    /// * key: The key to set.
+    /// * value: The new value.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no cluster member could be reached.
+    pub fn set(
+        &self,
+        key: &str,
+        value: &str,
+    ) -> impl Future<Item = KeyValue, Error = Vec<Error>> + Send {
+        let key = key.to_owned();
+
+        match self.api {
+            Api::V2 => Either::A(
+                kv::set(&self.client, &v2_key(&key), value, None)
+                    .map(move |response| from_node(key, &response.data.node)),
+            ),
+            Api::V3 => {
+                let value = value.to_owned();
+
+                Either::B(
+                    v3::kv::put(&self.client, &key, value.as_bytes()).map(move |response| {
+                        KeyValue {
+                            key,
+                            value,
+                            revision: response.data.revision as u64,
+                        }
+                    }),
+                )
+            }
+        }
+    }
+
+    /// Watches a key for changes, yielding each change as it takes place.
+    ///
+    /// # Parameters
+    ///
+    /// * key: The key to watch.
+    /// * revision: The revision to start watching from, or the next revision if `None`.
+    ///
+    /// Starting from an earlier revision yields the changes made since then first. To resume
+    /// watching after an event without missing any changes, start from the revision after the
+    /// event's.
+    ///
+    /// # Errors
+    ///
+    /// The stream ends with an error under the same conditions as `kv::watch_stream` or
+    /// `v3::kv::watch`, or if a value isn't valid UTF-8.
+    pub fn watch(
+        &self,
+        key: &str,
+        revision: Option<u64>,
+    ) -> impl Stream<Item = Event, Error = Vec<Error>> + Send {
+        let key = key.to_owned();
+
+        match self.api {
+            Api::V2 => {
+                let options = WatchOptions {
+                    index: revision,
+                    ..WatchOptions::default()
+                };
+
+                Either::A(
+                    kv::watch_stream(&self.client, &v2_key(&key), options).filter_map(
+                        move |item| match item {
+                            WatchItem::Event(response) => Some(Event {
+                                kind: match response.data.action {
+                                    Action::CompareAndDelete | Action::Delete | Action::Expire => {
+                                        EventType::Delete
+                                    }
+                                    _ => EventType::Set,
+                                },
+                                kv: from_node(key.clone(), &response.data.node),
+                            }),
+                            WatchItem::Idle => None,
+                        },
+                    ),
+                )
+            }
+            Api::V3 => Either::B(
+                v3::kv::watch(&self.client, &key, revision.map(|revision| revision as i64))
+                    .and_then(move |response| {
+                        let kind = match response.data.kind {
+                            v3::kv::EventType::Put => EventType::Set,
+                            v3::kv::EventType::Delete => EventType::Delete,
+                        };
+
+                        from_key_value(key.clone(), response.data.kv)
+                            .map(|kv| Event { kind, kv })
+                            .map_err(|error| vec![error])
+                    }),
+            ),
+        }
+    }
+}
+
+/// Returns a key with a slash at the beginning, as the v2 API requires.
+fn v2_key(key: &str) -> String {
+    if key.starts_with('/') {
+        key.to_owned()
+    } else {
+        format!("/{}", key)
+    }
+}
+
+/// Converts a node from the v2 API.
+fn from_node(key: String, node: &Node) -> KeyValue {
+    KeyValue {
+        key,
+        value: node.value.clone().unwrap_or_default(),
+        revision: node.modified_index.unwrap_or(0),
+    }
+}
+
+/// Converts a key and its value from the v3 API.
+fn from_key_value(key: String, kv: v3::KeyValue) -> Result<KeyValue, Error> {
+    let value = String::from_utf8(kv.value).map_err(|error| Error::Codec(error.to_string()))?;
+
+    Ok(KeyValue {
+        key,
+        value,
+        revision: kv.mod_revision as u64,
+    })
+}
@@ -2,17 +2,21 @@
 //!
 //! These API endpoints are used to manage users and roles.
 
+use std::fmt::{Debug, Error as FmtError, Formatter};
 use std::str::FromStr;
 
 use futures::{Future, IntoFuture, Stream};
 use hyper::client::connect::Connect;
 use hyper::{StatusCode, Uri};
+use serde::{Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
 
-use crate::client::{Client, ClusterInfo, Response};
+use crate::client::{BasicAuth, Client, ClusterInfo, Response};
 use crate::error::{ApiError, Error};
 use crate::first_ok::first_ok;
+use crate::http::endpoint_url;
+use crate::secret::REDACTED;
 
 /// The structure returned by the `GET /v2/auth/enable` endpoint.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -30,6 +34,21 @@ pub enum AuthChange {
     Unchanged,
 }
 
+/// The result of checking a username and password with `verify_credentials`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Credentials {
+    /// The credentials were accepted.
+    Valid,
+    /// The credentials were rejected.
+    ///
+    /// etcd responds this way when the user doesn't exist or the password is wrong, but also when
+    /// the user is not permitted to read the root of the keyspace.
+    Unauthorized,
+    /// The credentials were accepted, but the user is not permitted to read the root of the
+    /// keyspace.
+    PermissionDenied,
+}
+
 /// An existing etcd user with a list of their granted roles.
 #[derive(Debug, Clone, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct User {
@@ -37,6 +56,7 @@ pub struct User {
     #[serde(rename = "user")]
     name: String,
     /// The names of roles granted to the user.
+    #[serde(default, deserialize_with = "deserialize_role_names")]
     roles: Vec<String>,
 }
 
@@ -52,6 +72,37 @@ impl User {
     }
 }
 
+/// A role granted to a user, which etcd reports either by name or as a role object depending on
+/// the version and endpoint.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GrantedRole {
+    /// Only the name of the role.
+    Name(String),
+    /// A role object, of which only the name is used.
+    Role {
+        /// The name of the role.
+        role: String,
+    },
+}
+
+/// Deserializes a user's roles as a list of role names, accepting either response shape.
+fn deserialize_role_names<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let roles: Option<Vec<GrantedRole>> = Deserialize::deserialize(deserializer)?;
+
+    Ok(roles
+        .unwrap_or_default()
+        .into_iter()
+        .map(|role| match role {
+            GrantedRole::Name(name) => name,
+            GrantedRole::Role { role } => role,
+        })
+        .collect())
+}
+
 /// An existing etcd user with details of granted roles.
 #[derive(Debug, Clone, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct UserDetail {
@@ -81,7 +132,9 @@ struct Users {
 }
 
 /// Paramters used to create a new etcd user.
-#[derive(Debug, Deserialize, Clone, Eq, Hash, PartialEq, Serialize)]
+///
+/// The password is left out of the `Debug` output.
+#[derive(Deserialize, Clone, Eq, Hash, PartialEq, Serialize)]
 pub struct NewUser {
     /// The user's name.
     #[serde(rename = "user")]
@@ -124,8 +177,20 @@ impl NewUser {
     }
 }
 
+impl Debug for NewUser {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("NewUser")
+            .field("name", &self.name)
+            .field("password", &REDACTED)
+            .field("roles", &self.roles)
+            .finish()
+    }
+}
+
 /// Parameters used to update an existing etcd user.
-#[derive(Debug, Deserialize, Clone, Eq, Hash, PartialEq, Serialize)]
+///
+/// Any new password is left out of the `Debug` output.
+#[derive(Deserialize, Clone, Eq, Hash, PartialEq, Serialize)]
 pub struct UserUpdate {
     /// The user's name.
     #[serde(rename = "user")]
@@ -193,6 +258,17 @@ impl UserUpdate {
     }
 }
 
+impl Debug for UserUpdate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("UserUpdate")
+            .field("name", &self.name)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("grants", &self.grants)
+            .field("revocations", &self.revocations)
+            .finish()
+    }
+}
+
 /// An authorization role.
 #[derive(Debug, Deserialize, Clone, Eq, Hash, PartialEq, Serialize)]
 pub struct Role {
@@ -215,6 +291,38 @@ impl Role {
         }
     }
 
+    /// Creates a new role with access to a single directory in etcd's key-value store.
+    ///
+    /// The role is granted read and write permission for all keys under the directory, plus read
+    /// permission for the directory itself so that it can be listed. The prefix may be given with
+    /// or without leading and trailing slashes or a trailing `*`, e.g. `app`, `/app/`, and
+    /// `/app/*` all produce the permissions `/app` (read) and `/app/*` (read and write).
+    pub fn for_prefix<N, P>(name: N, prefix: P) -> Self
+    where
+        N: Into<String>,
+        P: AsRef<str>,
+    {
+        let dir = prefix
+            .as_ref()
+            .trim_end_matches('*')
+            .trim_matches('/')
+            .to_string();
+
+        let (dir, glob) = if dir.is_empty() {
+            ("/".to_owned(), "/*".to_owned())
+        } else {
+            (format!("/{}", dir), format!("/{}/*", dir))
+        };
+
+        let mut role = Role::new(name);
+
+        role.grant_kv_read_permission(dir);
+        role.grant_kv_read_permission(glob.clone());
+        role.grant_kv_write_permission(glob);
+
+        role
+    }
+
     /// Gets the name of the role.
     pub fn name(&self) -> &str {
         &self.name
@@ -413,6 +521,22 @@ impl Permission {
     }
 }
 
+/// Creates a new role with access to a single directory in etcd's key-value store.
+///
+/// See `Role::for_prefix` for the permissions granted.
+pub fn create_prefix_role<C, N, P>(
+    client: &Client<C>,
+    name: N,
+    prefix: P,
+) -> impl Future<Item = Response<Role>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+    N: Into<String>,
+    P: AsRef<str>,
+{
+    create_role(client, Role::for_prefix(name, prefix))
+}
+
 /// Creates a new role.
 pub fn create_role<C>(
     client: &Client<C>,
@@ -423,39 +547,43 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let body = serde_json::to_string(&role)
-            .map_err(Error::from)
-            .into_future();
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let body = serde_json::to_string(&role)
+                .map_err(Error::from)
+                .into_future();
 
-        let url = build_url(member, &format!("/roles/{}", role.name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
+            let url = build_url(member, &format!("/roles/{}", role.name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
 
-        let params = uri.join(body);
+            let params = uri.join(body);
 
-        let http_client = http_client.clone();
+            let http_client = http_client.clone();
 
-        let response =
-            params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
+            let response =
+                params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
 
-            body.and_then(move |ref body| match status {
-                StatusCode::OK | StatusCode::CREATED => {
-                    match serde_json::from_slice::<Role>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
+                body.and_then(move |ref body| match status {
+                    StatusCode::OK | StatusCode::CREATED => {
+                        match serde_json::from_slice::<Role>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
                     }
-                }
-                status => Err(Error::UnexpectedStatus(status)),
+                    status => Err(Error::UnexpectedStatus(status)),
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Creates a new user.
@@ -468,39 +596,43 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let body = serde_json::to_string(&user)
-            .map_err(Error::from)
-            .into_future();
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let body = serde_json::to_string(&user)
+                .map_err(Error::from)
+                .into_future();
 
-        let url = build_url(member, &format!("/users/{}", user.name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
+            let url = build_url(member, &format!("/users/{}", user.name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
 
-        let params = uri.join(body);
+            let params = uri.join(body);
 
-        let http_client = http_client.clone();
+            let http_client = http_client.clone();
 
-        let response =
-            params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
+            let response =
+                params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
 
-            body.and_then(move |ref body| match status {
-                StatusCode::OK | StatusCode::CREATED => {
-                    match serde_json::from_slice::<User>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
+                body.and_then(move |ref body| match status {
+                    StatusCode::OK | StatusCode::CREATED => {
+                        match serde_json::from_slice::<User>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
                     }
-                }
-                status => Err(Error::UnexpectedStatus(status)),
+                    status => Err(Error::UnexpectedStatus(status)),
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Deletes a role.
@@ -515,30 +647,34 @@ where
     let http_client = client.http_client().clone();
     let name = name.into();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, &format!("/roles/{}", name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, &format!("/roles/{}", name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
 
-        let http_client = http_client.clone();
+            let http_client = http_client.clone();
 
-        let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
+            let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
 
-            if status == StatusCode::OK {
-                Ok(Response {
-                    data: (),
-                    cluster_info,
-                })
-            } else {
-                Err(Error::UnexpectedStatus(status))
-            }
-        })
-    })
+                if status == StatusCode::OK {
+                    Ok(Response {
+                        data: (),
+                        cluster_info,
+                    })
+                } else {
+                    Err(Error::UnexpectedStatus(status))
+                }
+            })
+        },
+    )
 }
 
 /// Deletes a user.
@@ -553,30 +689,34 @@ where
     let http_client = client.http_client().clone();
     let name = name.into();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, &format!("/users/{}", name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, &format!("/users/{}", name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
 
-        let http_client = http_client.clone();
+            let http_client = http_client.clone();
 
-        let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
+            let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
 
-            if status == StatusCode::OK {
-                Ok(Response {
-                    data: (),
-                    cluster_info,
-                })
-            } else {
-                Err(Error::UnexpectedStatus(status))
-            }
-        })
-    })
+                if status == StatusCode::OK {
+                    Ok(Response {
+                        data: (),
+                        cluster_info,
+                    })
+                } else {
+                    Err(Error::UnexpectedStatus(status))
+                }
+            })
+        },
+    )
 }
 
 /// Attempts to disable the auth system.
@@ -588,33 +728,37 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, "/enable");
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-
-            match status {
-                StatusCode::OK => Ok(Response {
-                    data: AuthChange::Changed,
-                    cluster_info,
-                }),
-                StatusCode::CONFLICT => Ok(Response {
-                    data: AuthChange::Unchanged,
-                    cluster_info,
-                }),
-                _ => Err(Error::UnexpectedStatus(status)),
-            }
-        })
-    })
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, "/enable");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+
+                match status {
+                    StatusCode::OK => Ok(Response {
+                        data: AuthChange::Changed,
+                        cluster_info,
+                    }),
+                    StatusCode::CONFLICT => Ok(Response {
+                        data: AuthChange::Unchanged,
+                        cluster_info,
+                    }),
+                    _ => Err(Error::UnexpectedStatus(status)),
+                }
+            })
+        },
+    )
 }
 
 /// Attempts to enable the auth system.
@@ -626,34 +770,38 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, "/enable");
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response =
-            uri.and_then(move |uri| http_client.put(uri, "".to_owned()).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-
-            match status {
-                StatusCode::OK => Ok(Response {
-                    data: AuthChange::Changed,
-                    cluster_info,
-                }),
-                StatusCode::CONFLICT => Ok(Response {
-                    data: AuthChange::Unchanged,
-                    cluster_info,
-                }),
-                _ => return Err(Error::UnexpectedStatus(status)),
-            }
-        })
-    })
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, "/enable");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response =
+                uri.and_then(move |uri| http_client.put(uri, "".to_owned()).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+
+                match status {
+                    StatusCode::OK => Ok(Response {
+                        data: AuthChange::Changed,
+                        cluster_info,
+                    }),
+                    StatusCode::CONFLICT => Ok(Response {
+                        data: AuthChange::Unchanged,
+                        cluster_info,
+                    }),
+                    _ => return Err(Error::UnexpectedStatus(status)),
+                }
+            })
+        },
+    )
 }
 
 /// Get a role.
@@ -668,33 +816,37 @@ where
     let http_client = client.http_client().clone();
     let name = name.into();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, &format!("/roles/{}", name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<Role>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, &format!("/roles/{}", name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<Role>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        Err(Error::UnexpectedStatus(status))
                     }
-                } else {
-                    Err(Error::UnexpectedStatus(status))
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Gets all roles.
@@ -706,37 +858,41 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, "/roles");
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<Roles>(body) {
-                        Ok(roles) => {
-                            let data = roles.roles.unwrap_or_else(|| Vec::with_capacity(0));
-
-                            Ok(Response { data, cluster_info })
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, "/roles");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<Roles>(body) {
+                            Ok(roles) => {
+                                let data = roles.roles.unwrap_or_else(|| Vec::with_capacity(0));
+
+                                Ok(Response { data, cluster_info })
+                            }
+                            Err(error) => Err(Error::Serialization(error)),
                         }
-                        Err(error) => Err(Error::Serialization(error)),
+                    } else {
+                        Err(Error::UnexpectedStatus(status))
                     }
-                } else {
-                    Err(Error::UnexpectedStatus(status))
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Get a user.
@@ -751,33 +907,37 @@ where
     let http_client = client.http_client().clone();
     let name = name.into();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, &format!("/users/{}", name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<UserDetail>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, &format!("/users/{}", name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<UserDetail>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        Err(Error::UnexpectedStatus(status))
                     }
-                } else {
-                    Err(Error::UnexpectedStatus(status))
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Gets all users.
@@ -789,37 +949,41 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, "/users");
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<Users>(body) {
-                        Ok(users) => {
-                            let data = users.users.unwrap_or_else(|| Vec::with_capacity(0));
-
-                            Ok(Response { data, cluster_info })
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, "/users");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<Users>(body) {
+                            Ok(users) => {
+                                let data = users.users.unwrap_or_else(|| Vec::with_capacity(0));
+
+                                Ok(Response { data, cluster_info })
+                            }
+                            Err(error) => Err(Error::Serialization(error)),
                         }
-                        Err(error) => Err(Error::Serialization(error)),
+                    } else {
+                        Err(Error::UnexpectedStatus(status))
                     }
-                } else {
-                    Err(Error::UnexpectedStatus(status))
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Determines whether or not the auth system is enabled.
@@ -831,39 +995,43 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, "/enable");
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<AuthStatus>(body) {
-                        Ok(data) => Ok(Response {
-                            data: data.enabled,
-                            cluster_info,
-                        }),
-                        Err(error) => Err(Error::Serialization(error)),
-                    }
-                } else {
-                    match serde_json::from_slice::<ApiError>(body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, "/enable");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<AuthStatus>(body) {
+                            Ok(data) => Ok(Response {
+                                data: data.enabled,
+                                cluster_info,
+                            }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        match serde_json::from_slice::<ApiError>(body) {
+                            Ok(error) => Err(Error::Api(error)),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
                     }
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Updates an existing role.
@@ -876,40 +1044,44 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let body = serde_json::to_string(&role)
-            .map_err(Error::from)
-            .into_future();
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let body = serde_json::to_string(&role)
+                .map_err(Error::from)
+                .into_future();
 
-        let url = build_url(member, &format!("/roles/{}", role.name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
+            let url = build_url(member, &format!("/roles/{}", role.name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
 
-        let params = uri.join(body);
+            let params = uri.join(body);
 
-        let http_client = http_client.clone();
+            let http_client = http_client.clone();
 
-        let response =
-            params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
+            let response =
+                params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
 
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<Role>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<Role>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        Err(Error::UnexpectedStatus(status))
                     }
-                } else {
-                    Err(Error::UnexpectedStatus(status))
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Updates an existing user.
@@ -922,43 +1094,121 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let body = serde_json::to_string(&user)
-            .map_err(Error::from)
-            .into_future();
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let body = serde_json::to_string(&user)
+                .map_err(Error::from)
+                .into_future();
 
-        let url = build_url(member, &format!("/users/{}", user.name));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
+            let url = build_url(member, &format!("/users/{}", user.name));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
 
-        let params = uri.join(body);
+            let params = uri.join(body);
 
-        let http_client = http_client.clone();
+            let http_client = http_client.clone();
 
-        let response =
-            params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
+            let response =
+                params.and_then(move |(uri, body)| http_client.put(uri, body).map_err(Error::from));
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
 
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<User>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<User>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        Err(Error::UnexpectedStatus(status))
                     }
-                } else {
-                    Err(Error::UnexpectedStatus(status))
-                }
+                })
             })
-        })
-    })
+        },
+    )
+}
+
+/// Changes the password of an existing user.
+///
+/// This is a shortcut for `update_user` with a `UserUpdate` that only changes the password.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * username: The name of the user whose password to change.
+/// * new_password: The user's new password.
+pub fn change_password<C, N, P>(
+    client: &Client<C>,
+    username: N,
+    new_password: P,
+) -> impl Future<Item = Response<User>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+    N: Into<String>,
+    P: Into<String>,
+{
+    let mut update = UserUpdate::new(username);
+    update.update_password(new_password);
+
+    update_user(client, update)
+}
+
+/// Checks whether etcd accepts the given username and password.
+///
+/// The check reads the root of the keyspace with the given credentials, which changes nothing, so
+/// it can be used to validate configured credentials when an application starts. The client's
+/// own credentials, if any, are not used.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * basic_auth: The credentials to check.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached or a member responded with an unexpected status.
+/// Rejected credentials are not an error, but `Credentials::Unauthorized` or
+/// `Credentials::PermissionDenied`.
+pub fn verify_credentials<C>(
+    client: &Client<C>,
+    basic_auth: BasicAuth,
+) -> impl Future<Item = Credentials, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    let client = client.with_basic_auth(basic_auth);
+    let http_client = client.http_client().clone();
+
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = endpoint_url(member, "v2/keys/");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+
+            let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+            response.and_then(|response| match response.status() {
+                StatusCode::OK | StatusCode::NOT_FOUND => Ok(Credentials::Valid),
+                StatusCode::UNAUTHORIZED => Ok(Credentials::Unauthorized),
+                StatusCode::FORBIDDEN => Ok(Credentials::PermissionDenied),
+                status => Err(Error::UnexpectedStatus(status)),
+            })
+        },
+    )
 }
 
 /// Constructs the full URL for an API call.
 fn build_url(endpoint: &Uri, path: &str) -> String {
-    format!("{}v2/auth{}", endpoint, path)
+    endpoint_url(endpoint, &format!("v2/auth{}", path))
 }
@@ -0,0 +1,168 @@
+//! Sharing one watch among many consumers in a process.
+//!
+//! Each `kv::watch_stream` holds a long-poll connection to a cluster member open. An application
+//! with many components interested in the same keys can instead watch them once with
+//! `bus::subscribe`, and give each component its own `Subscription` to the changes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::future::Future;
+use futures::stream::Stream;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::sync::oneshot::{channel, Sender};
+use futures::{Async, Poll};
+use hyper::client::connect::Connect;
+use log::error;
+
+use crate::client::{Client, Response};
+use crate::kv::{watch_stream, KeyValueInfo, WatchItem, WatchOptions};
+use crate::sync::lock;
+
+/// A handle to a watch shared by any number of subscriptions, returned by `bus::subscribe`.
+///
+/// Clones of a bus share the same watch. The watch stops once every clone has been dropped, after
+/// which the subscriptions end.
+#[derive(Clone, Debug)]
+pub struct Bus {
+    shared: Arc<Shared>,
+}
+
+/// The state shared by a bus and its clones.
+#[derive(Debug)]
+struct Shared {
+    stale: Arc<AtomicBool>,
+    stop: Option<Sender<()>>,
+    subscribers: Arc<Mutex<Vec<UnboundedSender<Response<KeyValueInfo>>>>>,
+}
+
+/// A stream of the changes observed by a `Bus`, returned by `Bus::subscribe`.
+///
+/// The stream ends when the bus stops watching, either because every `Bus` handle has been
+/// dropped or because watching failed. It never yields an error; use `Bus::is_stale` to tell the
+/// two apart.
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: UnboundedReceiver<Response<KeyValueInfo>>,
+}
+
+/// Starts watching the nodes under a prefix for changes, returning a bus that any number of
+/// consumers can subscribe to.
+///
+/// The watch is spawned onto the current Tokio executor, and yields the changes made once it has
+/// started. Each subscription receives the changes made after it was created. A subscription
+/// that isn't polled buffers its changes without holding the others up.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * prefix: The name of the node to watch. Its descendants are watched as well.
+///
+/// # Panics
+///
+/// Panics if called outside of a task running on a Tokio executor.
+pub fn subscribe<C>(client: &Client<C>, prefix: &str) -> Bus
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    let stale = Arc::new(AtomicBool::new(false));
+    let subscribers = Arc::new(Mutex::new(Vec::<UnboundedSender<_>>::new()));
+    let (stop, stopped) = channel::<()>();
+
+    let options = WatchOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    let watch_prefix = prefix.to_string();
+    let watch_stale = stale.clone();
+    let watch_subscribers = subscribers.clone();
+
+    let watcher = watch_stream(client, prefix, options)
+        .map_err(move |errors| {
+            error!("watch of {} for bus failed: {:?}", watch_prefix, errors);
+        })
+        .for_each(move |item| {
+            if let WatchItem::Event(response) = item {
+                let mut subscribers = lock(&watch_subscribers);
+
+                // Subscriptions that have been dropped are forgotten.
+                subscribers
+                    .retain(|subscriber| subscriber.unbounded_send((*response).clone()).is_ok());
+            }
+
+            Ok(())
+        })
+        .then(move |_| {
+            watch_stale.store(true, Ordering::SeqCst);
+
+            Ok::<(), ()>(())
+        });
+
+    let watch_subscribers = subscribers.clone();
+
+    tokio::spawn(watcher.select2(stopped).then(move |_| {
+        // Ends the subscriptions.
+        lock(&watch_subscribers).clear();
+
+        Ok(())
+    }));
+
+    Bus {
+        shared: Arc::new(Shared {
+            stale,
+            stop: Some(stop),
+            subscribers,
+        }),
+    }
+}
+
+impl Bus {
+    /// Returns whether or not the bus has stopped receiving changes from etcd because watching
+    /// failed, e.g. because it fell too far behind etcd's event history.
+    pub fn is_stale(&self) -> bool {
+        self.shared.stale.load(Ordering::SeqCst)
+    }
+
+    /// Returns a new subscription to the changes observed by the bus.
+    ///
+    /// If the bus is stale, the subscription ends without yielding anything.
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = unbounded();
+        let mut subscribers = lock(&self.shared.subscribers);
+
+        // The watch marks the bus stale before ending the subscriptions, so checking with the
+        // lock held ensures the subscription isn't added after the others have ended.
+        if !self.is_stale() {
+            subscribers.push(sender);
+        }
+
+        Subscription { receiver }
+    }
+
+    /// Returns the number of subscriptions that haven't been dropped since the bus last delivered
+    /// a change.
+    pub fn subscriber_count(&self) -> usize {
+        lock(&self.shared.subscribers).len()
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Response<KeyValueInfo>;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.receiver.poll() {
+            Ok(Async::Ready(item)) => Ok(Async::Ready(item)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(()) => Ok(Async::Ready(None)),
+        }
+    }
+}
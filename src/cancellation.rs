@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::task::{self, Task};
+use futures::Async;
+
+use crate::sync::lock;
+
+/// The identifier to give the next operation registered with any token.
+///
+/// Identifiers are unique across tokens, so an operation can be registered with a token and its
+/// parents under the same identifier.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A handle for aborting requests that are in flight.
+///
+/// A token is attached to a client with `RequestOptions::cancellation` or
+/// `Client::with_cancellation`. Once `cancel` is called on the token or any of its clones, every
+/// pending API call made with such a client, including watches, fails with `Error::Cancelled`, as
+/// does every later call. `Client::health` and `Client::versions` are not affected.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+/// The state shared by a token and its clones.
+#[derive(Default)]
+struct Inner {
+    /// Whether or not the token has been cancelled.
+    cancelled: AtomicBool,
+    /// Tokens whose cancellation also cancels this token.
+    parents: Vec<CancellationToken>,
+    /// The tasks of pending operations, to notify when the token is cancelled.
+    tasks: Mutex<HashMap<usize, Task>>,
+    /// Tasks waiting for there to be no pending operations.
+    idle_waiters: Mutex<Vec<Task>>,
+}
+
+impl CancellationToken {
+    /// Constructs a new token that has not been cancelled.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Cancels all pending and future operations made with this token.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+
+        for task in lock(&self.inner.tasks).values() {
+            task.notify();
+        }
+    }
+
+    /// Returns whether or not the token, or a token it is linked to, has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+            || self
+                .inner
+                .parents
+                .iter()
+                .any(|parent| parent.is_cancelled())
+    }
+
+    /// Constructs a token that is cancelled when any of the given tokens is.
+    pub(crate) fn linked(parents: Vec<CancellationToken>) -> Self {
+        CancellationToken {
+            inner: Arc::new(Inner {
+                parents,
+                ..Inner::default()
+            }),
+        }
+    }
+
+    /// Returns `Async::Ready` once no operations are registered with the token, and otherwise
+    /// arranges for the current task to be notified when that happens.
+    pub(crate) fn poll_idle(&self) -> Async<()> {
+        {
+            let mut idle_waiters = lock(&self.inner.idle_waiters);
+
+            if !idle_waiters.iter().any(Task::will_notify_current) {
+                idle_waiters.push(task::current());
+            }
+        }
+
+        if lock(&self.inner.tasks).is_empty() {
+            Async::Ready(())
+        } else {
+            Async::NotReady
+        }
+    }
+
+    /// Arranges for the current task to be notified when the token is cancelled.
+    ///
+    /// `registration` holds the operation's identifier, which is assigned on the first call. The
+    /// caller must check `is_cancelled` after registering, so a cancellation between the check and
+    /// the registration is not missed.
+    pub(crate) fn register(&self, registration: &mut Option<usize>) {
+        let id = *registration.get_or_insert_with(|| NEXT_ID.fetch_add(1, Ordering::SeqCst));
+
+        self.register_id(id, &task::current());
+    }
+
+    /// Stops notifying the task of a finished operation.
+    pub(crate) fn deregister(&self, id: usize) {
+        let idle = {
+            let mut tasks = lock(&self.inner.tasks);
+
+            tasks.remove(&id).is_some() && tasks.is_empty()
+        };
+
+        if idle {
+            for task in lock(&self.inner.idle_waiters).drain(..) {
+                task.notify();
+            }
+        }
+
+        for parent in &self.inner.parents {
+            parent.deregister(id);
+        }
+    }
+
+    /// Registers an operation with the token and its parents.
+    fn register_id(&self, id: usize, task: &Task) {
+        lock(&self.inner.tasks).insert(id, task.clone());
+
+        for parent in &self.inner.parents {
+            parent.register_id(id, task);
+        }
+    }
+}
+
+impl Debug for CancellationToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Tokens are equal if they are clones of the same token.
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+impl Hash for CancellationToken {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&*self.inner as *const Inner).hash(state);
+    }
+}
@@ -1,20 +1,42 @@
 //! Contains the etcd client. All API calls are made via the client.
 
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::future::{join_all, poll_fn, Either};
 use futures::stream::futures_unordered;
 use futures::{Future, IntoFuture, Stream};
 use http::header::{HeaderMap, HeaderValue};
+use hyper::client::connect::dns::{GaiResolver, Resolve};
 use hyper::client::connect::{Connect, HttpConnector};
+use hyper::client::Builder;
 use hyper::{Client as Hyper, StatusCode, Uri};
 #[cfg(feature = "tls")]
 use hyper_tls::HttpsConnector;
 use log::error;
+#[cfg(feature = "tls")]
+use native_tls::TlsConnector;
 use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
+use tokio::timer::Timeout;
 
-use crate::error::{ApiError, Error};
-use crate::http::HttpClient;
-use crate::version::VersionInfo;
+use crate::cancellation::CancellationToken;
+use crate::codec::{Base64, ValueCodec};
+use crate::error::{AccessDenied, Error};
+use crate::http::{endpoint_url, HttpClient};
+use crate::kv::MutationObserver;
+use crate::latency::Latencies;
+use crate::logging::DebugLogging;
+use crate::secret::REDACTED;
+use crate::sync;
+#[cfg(feature = "tls")]
+use crate::tls::{ReloadableHttpsConnector, TlsOptions};
+use crate::version::{ClusterVersions, Feature, VersionInfo};
 
 // header! {
 //     /// The `X-Etcd-Cluster-Id` header.
@@ -48,12 +70,256 @@ pub struct Client<C>
 where
     C: Clone + Connect + Sync + 'static,
 {
-    endpoints: Vec<Uri>,
+    codec: Arc<dyn ValueCodec>,
+    /// The connector the client was constructed with, if the client constructed it, for
+    /// `Client::reload_tls`.
+    connector: Option<C>,
+    /// The cluster members' endpoints, shared by a client, its clones, and the clients derived
+    /// from it, for `Client::add_endpoint` and `Client::remove_endpoint`.
+    endpoints: Arc<RwLock<Vec<Uri>>>,
     http_client: HttpClient<C>,
+    options: RequestOptions,
+    /// The options requests are made with, which are also cancelled by `Client::shutdown`.
+    request_options: RequestOptions,
+    shutdown: CancellationToken,
+    /// The highest etcd index seen in a response to a write, for
+    /// `RequestOptions::read_your_writes`.
+    write_index: Arc<AtomicU64>,
+    /// The latency of reads from each cluster member, for `EndpointSelection::LowestLatency`.
+    latencies: Arc<Latencies>,
+    /// The observer of writes to the key-value store, from `Client::with_mutation_observer`.
+    mutation_observer: Option<Arc<dyn MutationObserver>>,
+}
+
+/// Options for the HTTP connections made by a client constructed with `Client::new_with`,
+/// `Client::https_with`, or their `_with_resolver` counterparts.
+///
+/// Clients constructed with `Client::custom` use whatever the given `hyper::Client` is configured
+/// with instead.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ConnectionOptions {
+    /// If given, establishing a connection to a cluster member fails if it takes longer than the
+    /// duration.
+    pub connect_timeout: Option<Duration>,
+    /// If given, the most connections to each cluster member to keep open while they are idle.
+    /// By default, there is no limit.
+    pub max_idle_per_host: Option<usize>,
+    /// If given, how long to keep idle connections open for reuse. By default, idle connections
+    /// are closed after 90 seconds.
+    pub idle_timeout: Option<Duration>,
+    /// Whether or not to speak HTTP/2 rather than HTTP/1.1, so that watches and other requests to
+    /// a cluster member share one connection.
+    ///
+    /// Over plain HTTP, HTTP/2 is spoken without negotiating it first (h2c), which requires a
+    /// server that accepts it with prior knowledge, such as a gateway or proxy in front of etcd.
+    /// Over TLS, clients constructed with `Client::https_with` and the other HTTPS constructors
+    /// ask for HTTP/2 with ALPN, which is how etcd itself accepts it. Every cluster member must
+    /// support HTTP/2, as connections to one that doesn't fail rather than falling back to
+    /// HTTP/1.1.
+    pub http2_only: bool,
+    /// If given, the most requests to have in flight to each cluster member at once. Further
+    /// requests wait for one of them to finish, so a burst of calls from one part of an
+    /// application can't take every connection. Watches neither count towards the limit nor wait
+    /// for it. By default, there is no limit.
+    pub max_in_flight_per_endpoint: Option<usize>,
+    /// If given, a request that has waited this long because of `max_in_flight_per_endpoint`
+    /// fails with `Error::Timeout`, and the next cluster member is tried.
+    pub queue_timeout: Option<Duration>,
+    /// If given, how long a connection may be idle before the operating system starts checking
+    /// that the cluster member on the other end is still there with TCP keepalive probes. This
+    /// detects members that went away without closing the connection, which would otherwise
+    /// leave watches and v3 API streams waiting until their timeouts lapse. It also keeps NAT
+    /// gateways and firewalls from dropping idle watch connections without telling either end.
+    pub tcp_keepalive: Option<Duration>,
+    /// Whether or not to disable Nagle's algorithm on connections with `TCP_NODELAY`, so that
+    /// small requests are sent straight away rather than being held back to be combined.
+    pub tcp_nodelay: bool,
+}
+
+impl ConnectionOptions {
+    /// Constructs the `hyper::Client` builder for these options.
+    fn hyper_builder(&self) -> Builder {
+        let mut builder = Hyper::builder();
+        builder.keep_alive(true).http2_only(self.http2_only);
+
+        if let Some(max_idle_per_host) = self.max_idle_per_host {
+            builder.max_idle_per_host(max_idle_per_host);
+        }
+
+        if let Some(idle_timeout) = self.idle_timeout {
+            builder.keep_alive_timeout(idle_timeout);
+        }
+
+        builder
+    }
+
+    /// Applies the options that are enforced by the client rather than by `hyper::Client`.
+    fn apply<C>(&self, mut client: Client<C>) -> Client<C>
+    where
+        C: Clone + Connect + Sync + 'static,
+    {
+        if let Some(max_in_flight) = self.max_in_flight_per_endpoint {
+            client.http_client = client
+                .http_client
+                .with_in_flight_limit(max_in_flight, self.queue_timeout);
+        }
+
+        client
+    }
+
+    /// Constructs the TLS connector for these options, with the system's default certificates.
+    #[cfg(feature = "tls")]
+    fn tls_connector(&self) -> Result<TlsConnector, Error> {
+        TlsOptions::default().tls_connector(self.http2_only)
+    }
+
+    /// Constructs the HTTP connector for these options.
+    fn http_connector(&self) -> HttpConnector {
+        self.http_connector_with(GaiResolver::new(4))
+    }
+
+    /// Constructs the HTTP connector for these options, resolving hostnames with the given
+    /// resolver.
+    fn http_connector_with<R>(&self, resolver: R) -> HttpConnector<R> {
+        let mut connector = HttpConnector::new_with_resolver(resolver);
+        connector.set_connect_timeout(self.connect_timeout);
+        connector.set_keepalive(self.tcp_keepalive);
+        connector.set_nodelay(self.tcp_nodelay);
+
+        connector
+    }
+}
+
+/// Options controlling how the client makes requests to the etcd cluster.
+///
+/// The options apply to every API call made with a client. Use `Client::with_options` to derive a
+/// client with different options.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct RequestOptions {
+    /// If given, cancelling the token makes pending and later requests fail with
+    /// `Error::Cancelled`.
+    pub cancellation: Option<CancellationToken>,
+    /// If given, a request to a cluster member fails with `Error::Timeout` if it hasn't completed
+    /// within the duration, and the next member is tried.
+    ///
+    /// This does not apply to `kv::watch` and the other watch operations, which use
+    /// `watch_timeout` instead.
+    pub timeout: Option<Duration>,
+    /// If given, an API call fails with `Error::Timeout` if it hasn't succeeded within the
+    /// duration, however many cluster members and retries it has tried.
+    ///
+    /// The time remaining is shared between the attempts that are left, so each attempt is given
+    /// the shorter of `timeout` and its share, and the time a call can take doesn't grow with the
+    /// size of the cluster. A share is never less than 100 milliseconds, or the time remaining if
+    /// that is less, so a large `retries` doesn't starve the attempts. This does not apply to
+    /// watches.
+    pub total_timeout: Option<Duration>,
+    /// If given, a watch request to a cluster member fails with `Error::Timeout` if it hasn't
+    /// completed within the duration, and the next member is tried.
+    ///
+    /// Watches are long polls that wait for a change, so this guards against connections that
+    /// stopped responding, and should be longer than a key is expected to go without changes. To
+    /// stop waiting for a change without treating it as a failure, use `WatchOptions::timeout`.
+    pub watch_timeout: Option<Duration>,
+    /// The number of additional times to try each cluster member after all of them have failed.
+    ///
+    /// The next member is only tried when a request fails with an error for which
+    /// `Error::is_retryable` is true. Other errors, e.g. a missing key, are returned right away.
+    pub retries: usize,
+    /// How long to wait before trying the cluster members again when a request to one of them
+    /// failed because the cluster was busy or electing a leader, i.e. with an error whose
+    /// `Error::retry_class` is `RetryClass::AfterBackoff`. Other retries aren't delayed.
+    ///
+    /// The wait doubles with each such round of attempts, and is cut short by `total_timeout`.
+    pub retry_backoff: Backoff,
+    /// If true, all reads from the key-value store synchronize with the quorum before returning
+    /// the value, as if `GetOptions::strong_consistency` were given.
+    pub strong_consistency: bool,
+    /// If true, a read from the key-value store fails with `Error::StaleRead` if the cluster
+    /// member's etcd index is behind the highest index the client has seen in a response to a
+    /// write, and the next member is tried. This keeps a member that has fallen behind, e.g. after
+    /// a failover, from returning data older than the client's own writes.
+    ///
+    /// The index is shared by a client, its clones, and the clients derived from it with methods
+    /// like `Client::with_options`. It does not apply to watches.
+    pub read_your_writes: bool,
+    /// If given, a read from the key-value store that hasn't succeeded within the duration is
+    /// also sent to the next cluster member, and the first successful response is used. This
+    /// trades extra load for lower tail latency when a member is slow. It does not apply to
+    /// watches or writes.
+    pub hedge_delay: Option<Duration>,
+    /// The order in which cluster members are tried for reads from the key-value store.
+    pub endpoint_selection: EndpointSelection,
+    /// The number of cluster members to send a read from the key-value store to at once. The
+    /// first successful response is used and the other requests are abandoned, so a read waits
+    /// for the fastest of the members rather than for each unresponsive member to time out in
+    /// turn. Zero and one both mean one member at a time. It does not apply to watches or writes.
+    pub concurrent_attempts: usize,
+    /// How long to wait before re-establishing a v3 API stream, such as `v3::kv::watch`, after it
+    /// fails with transient errors (see `Error::is_transient`), e.g. because a cluster member
+    /// couldn't be reached or stopped responding.
+    pub reconnect_backoff: Backoff,
+    /// If given, the ID sent as the `X-Request-Id` header of every request, so that the requests
+    /// made for one of an application's own operations can be found in the logs of the proxies
+    /// and gateways in front of etcd. By default, each request is sent with an ID of its own.
+    ///
+    /// Characters that can't appear in an HTTP header are replaced with underscores. The header
+    /// is also included when requests are logged with `Client::with_debug_logging`.
+    pub correlation_id: Option<String>,
+}
+
+/// How long to wait before trying again after consecutive failures.
+///
+/// The first wait is `initial`, and each wait after that is twice as long as the one before, up
+/// to `max`. The waits start over once an attempt succeeds.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Backoff {
+    /// How long to wait after the first failure.
+    pub initial: Duration,
+    /// The longest to wait after any failure.
+    pub max: Duration,
+}
+
+impl Backoff {
+    /// Returns how long to wait after the given wait, which doubles it up to `max`.
+    pub(crate) fn next(&self, wait: Duration) -> Duration {
+        (wait * 2).min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    /// Waits 100 milliseconds after the first failure, and up to 10 seconds after later ones.
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The order in which a client tries cluster members for reads from the key-value store.
+///
+/// Writes and watches always try the members in the order they were given to the client.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum EndpointSelection {
+    /// Try the members in the order they were given to the client.
+    #[default]
+    InOrder,
+    /// Prefer the members that have responded to reads the fastest.
+    ///
+    /// The client keeps a moving average of each member's latency, shared by its clones and the
+    /// clients derived from it. Reads are spread between the members that haven't been measured
+    /// yet and those close to the fastest, and the others are tried from fastest to slowest. A
+    /// member that hasn't been measured for 30 seconds counts as unmeasured again, so slow members
+    /// are tried again from time to time. See `Client::endpoint_latencies`.
+    LowestLatency,
 }
 
 /// A username and password to use for HTTP basic authentication.
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+///
+/// The password is left out of the `Debug` output. With the `zeroize` feature, the client's own
+/// copy of it is overwritten with zeros when the client is dropped.
+#[derive(Clone, Eq, Hash, PartialEq)]
 pub struct BasicAuth {
     /// The username to use for authentication.
     pub username: String,
@@ -61,11 +327,74 @@ pub struct BasicAuth {
     pub password: String,
 }
 
-/// A value returned by the health check API endpoint to indicate a healthy cluster member.
+impl Debug for BasicAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("BasicAuth")
+            .field("username", &self.username)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+/// A value returned by the health check API endpoint to indicate the health of a cluster member.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(from = "RawHealth", into = "RawHealth")]
 pub struct Health {
-    /// The health status of the cluster member.
+    /// The health status of the cluster member, exactly as returned by etcd.
     pub health: String,
+    /// Whether or not the cluster member reported itself as healthy.
+    pub healthy: bool,
+}
+
+/// The JSON representation of `Health`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct RawHealth {
+    health: String,
+}
+
+impl From<RawHealth> for Health {
+    fn from(raw: RawHealth) -> Self {
+        Health {
+            healthy: raw.health == "true",
+            health: raw.health,
+        }
+    }
+}
+
+impl From<Health> for RawHealth {
+    fn from(health: Health) -> Self {
+        RawHealth {
+            health: health.health,
+        }
+    }
+}
+
+/// The outcome of a health check against one cluster member, yielded by `Client::health`.
+#[derive(Debug)]
+pub enum EndpointHealth {
+    /// The member responded to the health check, reporting whether or not it's healthy.
+    Responded(Uri, Response<Health>),
+    /// The member couldn't be reached or the health check failed.
+    Failed(Uri, Error),
+}
+
+impl EndpointHealth {
+    /// Returns the endpoint of the member that was checked.
+    pub fn endpoint(&self) -> &Uri {
+        match self {
+            EndpointHealth::Responded(endpoint, _) | EndpointHealth::Failed(endpoint, _) => {
+                endpoint
+            }
+        }
+    }
+
+    /// Returns whether or not the member responded and reported itself as healthy.
+    pub fn is_healthy(&self) -> bool {
+        match self {
+            EndpointHealth::Responded(_, response) => response.data.healthy,
+            EndpointHealth::Failed(..) => false,
+        }
+    }
 }
 
 impl Client<HttpConnector> {
@@ -85,9 +414,51 @@ impl Client<HttpConnector> {
         endpoints: &[&str],
         basic_auth: Option<BasicAuth>,
     ) -> Result<Client<HttpConnector>, Error> {
-        let hyper = Hyper::builder().keep_alive(true).build_http();
+        Client::new_with(endpoints, basic_auth, ConnectionOptions::default())
+    }
+
+    /// Constructs a new client using the HTTP protocol, and connects to every cluster member
+    /// with `Client::connect` before returning it.
+    ///
+    /// # Parameters
+    ///
+    /// * endpoints: URLs for one or more cluster members, which API calls try in order.
+    /// * basic_auth: Credentials for HTTP basic authentication.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no endpoints are provided, if any of the endpoints is an invalid URL, or if any
+    /// cluster member could not be reached.
+    pub fn new_connected(
+        endpoints: &[&str],
+        basic_auth: Option<BasicAuth>,
+    ) -> impl Future<Item = Client<HttpConnector>, Error = Vec<Error>> + Send {
+        Client::new(endpoints, basic_auth)
+            .map_err(|error| vec![error])
+            .into_future()
+            .and_then(|client| client.connect().map(move |_| client))
+    }
+
+    /// Constructs a new client using the HTTP protocol and the given connection options.
+    ///
+    /// # Parameters
+    ///
+    /// * endpoints: URLs for one or more cluster members. When making an API call, the client will
+    /// make the call to each member in order until it receives a successful respponse.
+    /// * basic_auth: Credentials for HTTP basic authentication.
+    /// * options: Options for the client's HTTP connections.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no endpoints are provided or if any of the endpoints is an invalid URL.
+    pub fn new_with(
+        endpoints: &[&str],
+        basic_auth: Option<BasicAuth>,
+        options: ConnectionOptions,
+    ) -> Result<Client<HttpConnector>, Error> {
+        let hyper = options.hyper_builder().build(options.http_connector());
 
-        Client::custom(hyper, endpoints, basic_auth)
+        Client::custom(hyper, endpoints, basic_auth).map(|client| options.apply(client))
     }
 }
 
@@ -109,10 +480,183 @@ impl Client<HttpsConnector<HttpConnector>> {
         endpoints: &[&str],
         basic_auth: Option<BasicAuth>,
     ) -> Result<Client<HttpsConnector<HttpConnector>>, Error> {
-        let connector = HttpsConnector::new(4)?;
-        let hyper = Hyper::builder().keep_alive(true).build(connector);
+        Client::https_with(endpoints, basic_auth, ConnectionOptions::default())
+    }
+
+    /// Constructs a new client using the HTTPS protocol and the given connection options.
+    ///
+    /// # Parameters
+    ///
+    /// * endpoints: URLs for one or more cluster members. When making an API call, the client will
+    /// make the call to each member in order until it receives a successful respponse.
+    /// * basic_auth: Credentials for HTTP basic authentication.
+    /// * options: Options for the client's HTTP connections.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no endpoints are provided, if any of the endpoints is an invalid URL, or if TLS
+    /// could not be configured.
+    pub fn https_with(
+        endpoints: &[&str],
+        basic_auth: Option<BasicAuth>,
+        options: ConnectionOptions,
+    ) -> Result<Client<HttpsConnector<HttpConnector>>, Error> {
+        let mut http_connector = options.http_connector();
+        http_connector.enforce_http(false);
+
+        let tls_connector = options.tls_connector()?;
+        let connector = HttpsConnector::from((http_connector, tls_connector));
+        let hyper = options.hyper_builder().build(connector);
+
+        Client::custom(hyper, endpoints, basic_auth).map(|client| options.apply(client))
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Client<ReloadableHttpsConnector> {
+    /// Constructs a new client using the HTTPS protocol with the given connection and TLS
+    /// options. The TLS options can be replaced later with `Client::reload_tls`.
+    ///
+    /// # Parameters
+    ///
+    /// * endpoints: URLs for one or more cluster members, which API calls try in order.
+    /// * basic_auth: Credentials for HTTP basic authentication.
+    /// * options: Options for the client's HTTP connections.
+    /// * tls: The certificates to use for TLS.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no endpoints are provided, if any of the endpoints is an invalid URL, or if TLS
+    /// could not be configured.
+    ///
+    /// # Examples
+    ///
+    /// Configuring the client with PEM files for the CA certificate and the client certificate
+    /// and key:
+    ///
+    /// ```no_run
+    /// use etcd::{Client, ConnectionOptions, TlsOptions};
+    ///
+    /// let tls = TlsOptions::from_pem_files(
+    ///     Some("ca.pem"),
+    ///     Some(("client.pem", "client-key.pem")),
+    /// )
+    /// .unwrap();
+    ///
+    /// let client = Client::https_with_tls(
+    ///     &["https://etcd.example.com:2379"],
+    ///     None,
+    ///     ConnectionOptions::default(),
+    ///     tls,
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn https_with_tls(
+        endpoints: &[&str],
+        basic_auth: Option<BasicAuth>,
+        options: ConnectionOptions,
+        tls: TlsOptions,
+    ) -> Result<Client<ReloadableHttpsConnector>, Error> {
+        let connector =
+            ReloadableHttpsConnector::new(options.http_connector(), &tls, options.http2_only)?;
+        let hyper = options.hyper_builder().build(connector.clone());
+
+        Client::custom(hyper, endpoints, basic_auth).map(|mut client| {
+            client.connector = Some(connector);
+
+            options.apply(client)
+        })
+    }
+
+    /// Replaces the certificates the client uses for TLS, e.g. after they have been rotated.
+    ///
+    /// The change applies to this client and to every client derived from it or from the same
+    /// original client. Connections that are already open keep using the certificates they were
+    /// established with until they are closed; lower `ConnectionOptions::idle_timeout` to have
+    /// them replaced sooner.
+    ///
+    /// # Errors
+    ///
+    /// Fails if TLS could not be configured with the new options, in which case the current
+    /// certificates remain in use.
+    pub fn reload_tls(&self, tls: TlsOptions) -> Result<(), Error> {
+        match self.connector {
+            Some(ref connector) => connector.reload(&tls),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R> Client<HttpConnector<R>>
+where
+    R: Resolve + Clone + Send + Sync + 'static,
+    R::Future: Send,
+{
+    /// Constructs a new client using the HTTP protocol, the given connection options, and a
+    /// custom DNS resolver for the endpoints' hostnames.
+    ///
+    /// See the `dns` module for the resolvers this crate provides.
+    ///
+    /// # Parameters
+    ///
+    /// * endpoints: URLs for one or more cluster members, which API calls try in order.
+    /// * basic_auth: Credentials for HTTP basic authentication.
+    /// * options: Options for the client's HTTP connections.
+    /// * resolver: The resolver to look up the endpoints' hostnames with.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no endpoints are provided or if any of the endpoints is an invalid URL.
+    pub fn new_with_resolver(
+        endpoints: &[&str],
+        basic_auth: Option<BasicAuth>,
+        options: ConnectionOptions,
+        resolver: R,
+    ) -> Result<Client<HttpConnector<R>>, Error> {
+        let hyper = options
+            .hyper_builder()
+            .build(options.http_connector_with(resolver));
+
+        Client::custom(hyper, endpoints, basic_auth).map(|client| options.apply(client))
+    }
+}
 
-        Client::custom(hyper, endpoints, basic_auth)
+#[cfg(feature = "tls")]
+impl<R> Client<HttpsConnector<HttpConnector<R>>>
+where
+    R: Resolve + Clone + Send + Sync + 'static,
+    R::Future: Send,
+{
+    /// Constructs a new client using the HTTPS protocol, the given connection options, and a
+    /// custom DNS resolver for the endpoints' hostnames.
+    ///
+    /// See the `dns` module for the resolvers this crate provides.
+    ///
+    /// # Parameters
+    ///
+    /// * endpoints: URLs for one or more cluster members, which API calls try in order.
+    /// * basic_auth: Credentials for HTTP basic authentication.
+    /// * options: Options for the client's HTTP connections.
+    /// * resolver: The resolver to look up the endpoints' hostnames with.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no endpoints are provided, if any of the endpoints is an invalid URL, or if TLS
+    /// could not be configured.
+    pub fn https_with_resolver(
+        endpoints: &[&str],
+        basic_auth: Option<BasicAuth>,
+        options: ConnectionOptions,
+        resolver: R,
+    ) -> Result<Client<HttpsConnector<HttpConnector<R>>>, Error> {
+        let mut http_connector = options.http_connector_with(resolver);
+        http_connector.enforce_http(false);
+
+        let tls_connector = options.tls_connector()?;
+        let connector = HttpsConnector::from((http_connector, tls_connector));
+        let hyper = options.hyper_builder().build(connector);
+
+        Client::custom(hyper, endpoints, basic_auth).map(|client| options.apply(client))
     }
 }
 
@@ -139,7 +683,8 @@ where
     /// # Examples
     ///
     /// Configuring the client to authenticate with both HTTP basic auth and an X.509 client
-    /// certificate:
+    /// certificate from DER and PKCS #12 files. For PEM files, `Client::https_with_tls` with
+    /// `TlsOptions::from_pem_files` is simpler:
     ///
     /// ```no_run
     /// use std::fs::File;
@@ -206,46 +751,324 @@ where
             uri_endpoints.push(endpoint.parse()?);
         }
 
+        let shutdown = CancellationToken::new();
+
         Ok(Client {
-            endpoints: uri_endpoints,
+            codec: Arc::new(Base64),
+            connector: None,
+            endpoints: Arc::new(RwLock::new(uri_endpoints)),
             http_client: HttpClient::new(hyper, basic_auth),
+            options: RequestOptions::default(),
+            request_options: effective_options(&RequestOptions::default(), &shutdown),
+            shutdown,
+            write_index: Arc::new(AtomicU64::new(0)),
+            latencies: Arc::new(Latencies::default()),
+            mutation_observer: None,
         })
     }
 
+    /// Returns a client that makes requests using the given options.
+    ///
+    /// The returned client shares the underlying `hyper::Client`, and therefore its connection
+    /// pool, with this client, so this is cheap enough to use for individual parts of an
+    /// application that need different settings.
+    pub fn with_options(&self, options: RequestOptions) -> Client<C> {
+        Client {
+            codec: self.codec.clone(),
+            connector: self.connector.clone(),
+            endpoints: self.endpoints.clone(),
+            http_client: self
+                .http_client
+                .with_correlation_id(options.correlation_id.as_deref()),
+            request_options: effective_options(&options, &self.shutdown),
+            options,
+            shutdown: self.shutdown.clone(),
+            write_index: self.write_index.clone(),
+            latencies: self.latencies.clone(),
+            mutation_observer: self.mutation_observer.clone(),
+        }
+    }
+
+    /// Returns a client that uses the given codec to convert between bytes and etcd values in
+    /// `kv::get_bytes` and `kv::set_bytes`.
+    ///
+    /// Clients use `codec::Base64` by default. Like `Client::with_options`, the returned client
+    /// shares the underlying `hyper::Client` with this client.
+    pub fn with_codec<V>(&self, codec: V) -> Client<C>
+    where
+        V: ValueCodec + 'static,
+    {
+        Client {
+            codec: Arc::new(codec),
+            connector: self.connector.clone(),
+            endpoints: self.endpoints.clone(),
+            http_client: self.http_client.clone(),
+            options: self.options.clone(),
+            request_options: self.request_options.clone(),
+            shutdown: self.shutdown.clone(),
+            write_index: self.write_index.clone(),
+            latencies: self.latencies.clone(),
+            mutation_observer: self.mutation_observer.clone(),
+        }
+    }
+
+    /// Returns the codec used to convert between bytes and etcd values.
+    pub fn codec(&self) -> &dyn ValueCodec {
+        &*self.codec
+    }
+
+    /// Returns a client that authenticates its requests with the given credentials.
+    ///
+    /// Like `Client::with_options`, the returned client shares the underlying `hyper::Client` with
+    /// this client.
+    pub fn with_basic_auth(&self, basic_auth: BasicAuth) -> Client<C> {
+        Client {
+            codec: self.codec.clone(),
+            connector: self.connector.clone(),
+            endpoints: self.endpoints.clone(),
+            http_client: self.http_client.with_basic_auth(Some(basic_auth)),
+            options: self.options.clone(),
+            request_options: self.request_options.clone(),
+            shutdown: self.shutdown.clone(),
+            write_index: self.write_index.clone(),
+            latencies: self.latencies.clone(),
+            mutation_observer: self.mutation_observer.clone(),
+        }
+    }
+
+    /// Returns a client that logs each request it makes with the given settings.
+    ///
+    /// Requests are logged at the debug level, with credentials and the values of secret keys
+    /// redacted. Like `Client::with_options`, the returned client shares the underlying
+    /// `hyper::Client` with this client.
+    pub fn with_debug_logging(&self, debug_logging: DebugLogging) -> Client<C> {
+        Client {
+            codec: self.codec.clone(),
+            connector: self.connector.clone(),
+            endpoints: self.endpoints.clone(),
+            http_client: self.http_client.with_debug_logging(Some(debug_logging)),
+            options: self.options.clone(),
+            request_options: self.request_options.clone(),
+            shutdown: self.shutdown.clone(),
+            write_index: self.write_index.clone(),
+            latencies: self.latencies.clone(),
+            mutation_observer: self.mutation_observer.clone(),
+        }
+    }
+
+    /// Returns a client that passes each successful write to the key-value store made with the
+    /// `kv` module to the given observer, e.g. to keep an audit log.
+    ///
+    /// The observer replaces any the client already has. Like `Client::with_options`, the returned
+    /// client shares the underlying `hyper::Client` with this client, and clients derived from it
+    /// keep the observer.
+    pub fn with_mutation_observer<O>(&self, observer: O) -> Client<C>
+    where
+        O: MutationObserver + 'static,
+    {
+        Client {
+            codec: self.codec.clone(),
+            connector: self.connector.clone(),
+            endpoints: self.endpoints.clone(),
+            http_client: self.http_client.clone(),
+            options: self.options.clone(),
+            request_options: self.request_options.clone(),
+            shutdown: self.shutdown.clone(),
+            write_index: self.write_index.clone(),
+            latencies: self.latencies.clone(),
+            mutation_observer: Some(Arc::new(observer)),
+        }
+    }
+
+    /// Returns a client whose requests to each cluster member time out after the given duration.
+    ///
+    /// This is a shortcut for `Client::with_options` that keeps the client's other options.
+    pub fn with_timeout(&self, timeout: Duration) -> Client<C> {
+        self.with_options(RequestOptions {
+            timeout: Some(timeout),
+            ..self.options.clone()
+        })
+    }
+
+    /// Returns a client whose requests are aborted when the given token is cancelled.
+    ///
+    /// This is a shortcut for `Client::with_options` that keeps the client's other options.
+    pub fn with_cancellation(&self, cancellation: CancellationToken) -> Client<C> {
+        self.with_options(RequestOptions {
+            cancellation: Some(cancellation),
+            ..self.options.clone()
+        })
+    }
+
+    /// Returns a client that sends the given ID with every request, e.g. the ID of the trace of
+    /// the application operation the requests are made for.
+    ///
+    /// This is a shortcut for `Client::with_options` that keeps the client's other options. See
+    /// `RequestOptions::correlation_id`.
+    pub fn with_correlation_id(&self, correlation_id: &str) -> Client<C> {
+        self.with_options(RequestOptions {
+            correlation_id: Some(correlation_id.to_owned()),
+            ..self.options.clone()
+        })
+    }
+
+    /// Returns a client that records an OpenTelemetry span for each request it makes as a child
+    /// of the span in the given context, and sends the span's W3C trace context to etcd in the
+    /// `traceparent` header, so proxies in front of etcd can continue the trace.
+    ///
+    /// Without a context, requests are traced as children of the current context's span when
+    /// they are sent. A context attached to the thread an API call is made on isn't attached
+    /// while a Tokio executor runs it, so this is the way to trace calls made from within a span.
+    ///
+    /// Spans are recorded with the global tracer provider, under the name "etcd", with the
+    /// semantic convention attributes of HTTP clients. Like `Client::with_options`, the returned
+    /// client shares the underlying `hyper::Client` with this client.
+    ///
+    /// This method is only available when the "otel" Cargo feature is enabled.
+    #[cfg(feature = "otel")]
+    pub fn with_trace_context(&self, context: &opentelemetry::Context) -> Client<C> {
+        Client {
+            codec: self.codec.clone(),
+            connector: self.connector.clone(),
+            endpoints: self.endpoints.clone(),
+            http_client: self.http_client.with_trace_context(Some(context.clone())),
+            options: self.options.clone(),
+            request_options: self.request_options.clone(),
+            shutdown: self.shutdown.clone(),
+            write_index: self.write_index.clone(),
+            latencies: self.latencies.clone(),
+            mutation_observer: self.mutation_observer.clone(),
+        }
+    }
+
     /// Lets other internal code access the `HttpClient`.
     pub(crate) fn http_client(&self) -> &HttpClient<C> {
         &self.http_client
     }
 
-    /// Lets other internal code access the cluster endpoints.
-    pub(crate) fn endpoints(&self) -> &[Uri] {
-        &self.endpoints
+    /// Returns the endpoints of the cluster members the client makes API calls to.
+    pub fn endpoints(&self) -> Vec<Uri> {
+        sync::read(&self.endpoints).clone()
+    }
+
+    /// Adds the endpoint of a cluster member for API calls to try after the others, e.g. once a
+    /// new member has joined the cluster. Nothing happens if the client already has the
+    /// endpoint.
+    ///
+    /// The change applies to this client, its clones, and the clients derived from it with
+    /// methods like `Client::with_options`, which all keep their connection pools. API calls that
+    /// are already in progress keep trying the endpoints they started with.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the endpoint is an invalid URL.
+    pub fn add_endpoint(&self, endpoint: &str) -> Result<(), Error> {
+        let endpoint: Uri = endpoint.parse()?;
+        let mut endpoints = sync::write(&self.endpoints);
+
+        if !endpoints.contains(&endpoint) {
+            endpoints.push(endpoint);
+        }
+
+        Ok(())
+    }
+
+    /// Removes the endpoint of a cluster member, e.g. once the member has left the cluster.
+    /// Returns whether or not the client had the endpoint.
+    ///
+    /// Like `Client::add_endpoint`, the change applies to every client that shares this client's
+    /// endpoints, and not to API calls that are already in progress.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the endpoint is an invalid URL, or with `Error::NoEndpoints` if it is the client's
+    /// only endpoint.
+    pub fn remove_endpoint(&self, endpoint: &str) -> Result<bool, Error> {
+        let endpoint: Uri = endpoint.parse()?;
+        let mut endpoints = sync::write(&self.endpoints);
+
+        match endpoints.iter().position(|existing| *existing == endpoint) {
+            Some(_) if endpoints.len() == 1 => Err(Error::NoEndpoints),
+            Some(position) => {
+                endpoints.remove(position);
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Lets other internal code get the cluster endpoints in the order to try them for a read.
+    pub(crate) fn read_endpoints(&self) -> Vec<Uri> {
+        match self.request_options.endpoint_selection {
+            EndpointSelection::InOrder => self.endpoints(),
+            EndpointSelection::LowestLatency => self.latencies.order(&self.endpoints()),
+        }
+    }
+
+    /// Lets other internal code pass writes to the client's `MutationObserver`.
+    pub(crate) fn mutation_observer(&self) -> Option<&Arc<dyn MutationObserver>> {
+        self.mutation_observer.as_ref()
+    }
+
+    /// Lets other internal code record the latency of reads.
+    pub(crate) fn latencies(&self) -> &Arc<Latencies> {
+        &self.latencies
+    }
+
+    /// Returns the cluster members with the moving average of how long they took to respond to
+    /// reads from the key-value store, with the members that haven't been measured in the last 30
+    /// seconds first and the others from fastest to slowest.
+    pub fn endpoint_latencies(&self) -> Vec<(Uri, Option<Duration>)> {
+        self.latencies.rank(&self.endpoints())
+    }
+
+    /// Lets other internal code access the highest etcd index seen in a response to a write.
+    pub(crate) fn write_index(&self) -> &Arc<AtomicU64> {
+        &self.write_index
+    }
+
+    /// Lets other internal code access the options for making requests.
+    pub(crate) fn request_options(&self) -> &RequestOptions {
+        &self.request_options
+    }
+
+    /// Shuts the client down.
+    ///
+    /// Pending and later API calls made with this client, its clones, and the clients derived from
+    /// it with methods like `Client::with_options` fail with `Error::Cancelled`. Background tasks
+    /// that use the client, like the refresher of a `kv::Session` and the watcher of a
+    /// `kv::Cache`, stop as a result. `Client::health` and `Client::versions` are not affected.
+    ///
+    /// The returned future completes once no API calls are in flight, i.e. once every pending call
+    /// has been aborted or has finished.
+    pub fn shutdown(&self) -> impl Future<Item = (), Error = ()> + Send {
+        self.shutdown.cancel();
+
+        let shutdown = self.shutdown.clone();
+
+        poll_fn(move || Ok(shutdown.poll_idle()))
+    }
+
+    /// Returns whether or not `Client::shutdown` has been called on this client or a client it
+    /// shares its connections with.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown.is_cancelled()
     }
 
     /// Runs a basic health check against each etcd member.
-    pub fn health(&self) -> impl Stream<Item = Response<Health>, Error = Error> + Send {
-        let futures = self.endpoints.iter().map(|endpoint| {
-            let url = build_url(&endpoint, "health");
-            let uri = url.parse().map_err(Error::from).into_future();
-            let cloned_client = self.http_client.clone();
-            let response = uri.and_then(move |uri| cloned_client.get(uri).map_err(Error::from));
-            response.and_then(|response| {
-                let status = response.status();
-                let cluster_info = ClusterInfo::from(response.headers());
-                let body = response.into_body().concat2().map_err(Error::from);
-
-                body.and_then(move |ref body| {
-                    if status == StatusCode::OK {
-                        match serde_json::from_slice::<Health>(body) {
-                            Ok(data) => Ok(Response { data, cluster_info }),
-                            Err(error) => Err(Error::Serialization(error)),
-                        }
-                    } else {
-                        match serde_json::from_slice::<ApiError>(body) {
-                            Ok(error) => Err(Error::Api(error)),
-                            Err(error) => Err(Error::Serialization(error)),
-                        }
-                    }
+    ///
+    /// The stream yields the outcome for every member, in the order the checks finish, so a
+    /// member that can't be reached doesn't hide the health of the rest. It never fails.
+    pub fn health(&self) -> impl Stream<Item = EndpointHealth, Error = ()> + Send {
+        let endpoints = self.endpoints();
+        let futures = endpoints.iter().map(|endpoint| {
+            let failed_endpoint = endpoint.clone();
+
+            self.member_health(endpoint).then(move |result| {
+                Ok(match result {
+                    Ok((endpoint, response)) => EndpointHealth::Responded(endpoint, response),
+                    Err(error) => EndpointHealth::Failed(failed_endpoint, error),
                 })
             })
         });
@@ -253,37 +1076,187 @@ where
         futures_unordered(futures)
     }
 
-    /// Returns version information from each etcd cluster member the client was initialized with.
-    pub fn versions(&self) -> impl Stream<Item = Response<VersionInfo>, Error = Error> + Send {
-        let futures = self.endpoints.iter().map(|endpoint| {
-            let url = build_url(&endpoint, "version");
-            let uri = url.parse().map_err(Error::from).into_future();
-            let cloned_client = self.http_client.clone();
-            let response = uri.and_then(move |uri| cloned_client.get(uri).map_err(Error::from));
-            response.and_then(|response| {
-                let status = response.status();
-                let cluster_info = ClusterInfo::from(response.headers());
-                let body = response.into_body().concat2().map_err(Error::from);
-
-                body.and_then(move |ref body| {
-                    if status == StatusCode::OK {
-                        match serde_json::from_slice::<VersionInfo>(body) {
-                            Ok(data) => Ok(Response { data, cluster_info }),
-                            Err(error) => Err(Error::Serialization(error)),
-                        }
-                    } else {
-                        match serde_json::from_slice::<ApiError>(body) {
-                            Ok(error) => Err(Error::Api(error)),
-                            Err(error) => Err(Error::Serialization(error)),
-                        }
+    /// Checks whether or not every etcd member the client was initialized with is healthy.
+    ///
+    /// A member that can't be reached or returns an error counts as unhealthy.
+    ///
+    /// # Errors
+    ///
+    /// Never fails.
+    pub fn is_cluster_healthy(&self) -> impl Future<Item = bool, Error = Error> + Send {
+        self.health()
+            .fold(true, |healthy, health| {
+                Ok::<_, ()>(healthy && health.is_healthy())
+            })
+            .or_else(|()| Ok(false))
+    }
+
+    /// Runs a basic health check against a single etcd member.
+    fn member_health(
+        &self,
+        endpoint: &Uri,
+    ) -> impl Future<Item = (Uri, Response<Health>), Error = Error> + Send {
+        let url = build_url(&endpoint, "health");
+        let uri = url.parse().map_err(Error::from).into_future();
+        let cloned_client = self.http_client.clone();
+        let access = AccessDenied {
+            key: None,
+            user: cloned_client.username().map(str::to_owned),
+        };
+        let response = uri.and_then(move |uri| cloned_client.get(uri).map_err(Error::from));
+        let response = with_timeout(response, self.options.timeout);
+        let endpoint = endpoint.clone();
+        response.and_then(|response| {
+            let status = response.status();
+            let cluster_info = ClusterInfo::from(response.headers());
+            let body = response.into_body().concat2().map_err(Error::from);
+
+            body.and_then(move |ref body| {
+                if status == StatusCode::OK {
+                    match serde_json::from_slice::<Health>(body) {
+                        Ok(data) => Ok((endpoint, Response { data, cluster_info })),
+                        Err(error) => Err(Error::Serialization(error)),
                     }
-                })
+                } else {
+                    Err(Error::from_response(status, body, access))
+                }
             })
-        });
+        })
+    }
+
+    /// Connects to every etcd cluster member the client was initialized with by requesting its
+    /// version, so that the first API calls don't wait for connections to be established, and so
+    /// that unreachable members and TLS misconfigurations are found straight away.
+    ///
+    /// The connections are kept open for reuse as `ConnectionOptions::max_idle_per_host` and
+    /// `ConnectionOptions::idle_timeout` allow. The members' versions are returned, so they can
+    /// be checked as well, e.g. with `ClusterVersions::supports`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the error for each member that could not be reached, if there are any.
+    pub fn connect(&self) -> impl Future<Item = ClusterVersions, Error = Vec<Error>> + Send {
+        self.all_versions().and_then(|(versions, errors)| {
+            if errors.is_empty() {
+                Ok(versions)
+            } else {
+                Err(errors)
+            }
+        })
+    }
+
+    /// Returns version information from each etcd cluster member the client was initialized with.
+    ///
+    /// Each item is paired with the endpoint of the member that produced it.
+    pub fn versions(
+        &self,
+    ) -> impl Stream<Item = (Uri, Response<VersionInfo>), Error = Error> + Send {
+        let endpoints = self.endpoints();
+        let futures = endpoints
+            .iter()
+            .map(|endpoint| self.member_version(endpoint));
 
         futures_unordered(futures)
     }
 
+    /// Returns version information from every etcd cluster member the client was initialized
+    /// with, collected into a single value.
+    ///
+    /// Members that can't be reached are left out.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no member could be reached.
+    pub fn cluster_versions(
+        &self,
+    ) -> impl Future<Item = ClusterVersions, Error = Vec<Error>> + Send {
+        self.all_versions().and_then(|(versions, errors)| {
+            if versions.members.is_empty() {
+                Err(errors)
+            } else {
+                Ok(versions)
+            }
+        })
+    }
+
+    /// Checks whether or not every etcd member the client was initialized with supports the given
+    /// feature.
+    ///
+    /// This is a shortcut for `Client::cluster_versions` followed by `ClusterVersions::supports`.
+    /// Members that can't be reached are not considered.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no member could be reached.
+    pub fn supports(
+        &self,
+        feature: Feature,
+    ) -> impl Future<Item = bool, Error = Vec<Error>> + Send {
+        self.cluster_versions()
+            .map(move |versions| versions.supports(feature))
+    }
+
+    /// Requests version information from every cluster member, returning the versions of those
+    /// that responded and the errors for those that didn't.
+    fn all_versions(
+        &self,
+    ) -> impl Future<Item = (ClusterVersions, Vec<Error>), Error = Vec<Error>> + Send {
+        let futures: Vec<_> = self
+            .endpoints()
+            .iter()
+            .map(|endpoint| self.member_version(endpoint).then(Ok::<_, Vec<Error>>))
+            .collect();
+
+        join_all(futures).map(|results| {
+            let mut versions = ClusterVersions::default();
+            let mut errors = vec![];
+
+            for result in results {
+                match result {
+                    Ok((endpoint, response)) => {
+                        versions.members.insert(endpoint, response.data);
+                    }
+                    Err(error) => errors.push(error),
+                }
+            }
+
+            (versions, errors)
+        })
+    }
+
+    /// Gets version information from a single etcd member.
+    fn member_version(
+        &self,
+        endpoint: &Uri,
+    ) -> impl Future<Item = (Uri, Response<VersionInfo>), Error = Error> + Send {
+        let url = build_url(&endpoint, "version");
+        let uri = url.parse().map_err(Error::from).into_future();
+        let cloned_client = self.http_client.clone();
+        let access = AccessDenied {
+            key: None,
+            user: cloned_client.username().map(str::to_owned),
+        };
+        let response = uri.and_then(move |uri| cloned_client.get(uri).map_err(Error::from));
+        let response = with_timeout(response, self.options.timeout);
+        let endpoint = endpoint.clone();
+        response.and_then(|response| {
+            let status = response.status();
+            let cluster_info = ClusterInfo::from(response.headers());
+            let body = response.into_body().concat2().map_err(Error::from);
+
+            body.and_then(move |ref body| {
+                if status == StatusCode::OK {
+                    match serde_json::from_slice::<VersionInfo>(body) {
+                        Ok(data) => Ok((endpoint, Response { data, cluster_info })),
+                        Err(error) => Err(Error::Serialization(error)),
+                    }
+                } else {
+                    Err(Error::from_response(status, body, access))
+                }
+            })
+        })
+    }
+
     /// Lets other internal code make basic HTTP requests.
     pub(crate) fn request<U, T>(
         &self,
@@ -294,7 +1267,12 @@ where
         T: DeserializeOwned + Send + 'static,
     {
         let http_client = self.http_client.clone();
+        let access = AccessDenied {
+            key: None,
+            user: http_client.username().map(str::to_owned),
+        };
         let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+        let response = with_timeout(response, self.options.timeout);
         response.and_then(|response| {
             let status = response.status();
             let cluster_info = ClusterInfo::from(response.headers());
@@ -307,10 +1285,7 @@ where
                         Err(error) => Err(Error::Serialization(error)),
                     }
                 } else {
-                    match serde_json::from_slice::<ApiError>(&body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
-                    }
+                    Err(Error::from_response(status, &body, access))
                 }
             })
         })
@@ -329,8 +1304,34 @@ pub struct Response<T> {
     pub data: T,
 }
 
+impl<T> Response<T> {
+    /// Discards the cluster information and returns the primary data of the response.
+    pub fn into_data(self) -> T {
+        self.data
+    }
+
+    /// Transforms the primary data of the response, keeping the cluster information.
+    pub fn map<U, F>(self, f: F) -> Response<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        Response {
+            cluster_info: self.cluster_info,
+            data: f(self.data),
+        }
+    }
+}
+
+impl<T> Deref for Response<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
 /// Information about the state of the etcd cluster from an API response's HTTP headers.
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct ClusterInfo {
     /// An internal identifier for the cluster.
     pub cluster_id: Option<String>,
@@ -340,6 +1341,42 @@ pub struct ClusterInfo {
     pub raft_index: Option<u64>,
     /// The current Raft election term.
     pub raft_term: Option<u64>,
+    /// All of the response's HTTP headers, including those added by proxies in front of etcd.
+    ///
+    /// The headers are not serialized, and are ignored when hashing.
+    #[serde(skip)]
+    pub headers: HeaderMap<HeaderValue>,
+}
+
+impl Hash for ClusterInfo {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cluster_id.hash(state);
+        self.etcd_index.hash(state);
+        self.raft_index.hash(state);
+        self.raft_term.hash(state);
+    }
+}
+
+impl ClusterInfo {
+    /// Returns the cluster information as name-value pairs suitable for use as metric labels or
+    /// structured log fields.
+    ///
+    /// Only `cluster_id` and `raft_term` are included, as the indexes change with every write and
+    /// would give metrics an unbounded number of label values. Fields that are missing from the
+    /// response are omitted.
+    pub fn labels(&self) -> Vec<(&'static str, String)> {
+        let mut labels = Vec::with_capacity(2);
+
+        if let Some(ref cluster_id) = self.cluster_id {
+            labels.push(("cluster_id", cluster_id.clone()));
+        }
+
+        if let Some(raft_term) = self.raft_term {
+            labels.push(("raft_term", raft_term.to_string()));
+        }
+
+        labels
+    }
 }
 
 impl<'a> From<&'a HeaderMap<HeaderValue>> for ClusterInfo {
@@ -398,11 +1435,42 @@ impl<'a> From<&'a HeaderMap<HeaderValue>> for ClusterInfo {
             etcd_index: etcd_index,
             raft_index: raft_index,
             raft_term: raft_term,
+            headers: headers.clone(),
         }
     }
 }
 
+/// Returns the options to make requests with, whose cancellation token is also cancelled when the
+/// client is shut down.
+fn effective_options(options: &RequestOptions, shutdown: &CancellationToken) -> RequestOptions {
+    let mut tokens = vec![shutdown.clone()];
+    tokens.extend(options.cancellation.clone());
+
+    RequestOptions {
+        cancellation: Some(CancellationToken::linked(tokens)),
+        ..options.clone()
+    }
+}
+
+/// Makes the given request fail with `Error::Timeout` if it doesn't complete within the timeout,
+/// if any.
+pub(crate) fn with_timeout<F>(
+    future: F,
+    timeout: Option<Duration>,
+) -> impl Future<Item = F::Item, Error = Error> + Send
+where
+    F: Future<Error = Error> + Send,
+{
+    match timeout {
+        Some(duration) => Either::A(
+            Timeout::new(future, duration)
+                .map_err(|error| error.into_inner().unwrap_or(Error::Timeout)),
+        ),
+        None => Either::B(future),
+    }
+}
+
 /// Constructs the full URL for the versions API call.
 fn build_url(endpoint: &Uri, path: &str) -> String {
-    format!("{}{}", endpoint, path)
+    endpoint_url(endpoint, path)
 }
@@ -0,0 +1,313 @@
+//! Conversion between binary data and etcd values.
+//!
+//! Values in etcd's v2 API are strings. A `ValueCodec` converts bytes to and from strings so
+//! binary data can be stored with `kv::set_bytes` and retrieved with `kv::get_bytes`. The codec
+//! used by a client is chosen with `Client::with_codec`.
+
+use std::fmt::Debug;
+use std::str;
+
+use crate::error::Error;
+
+/// A conversion between bytes and etcd values.
+pub trait ValueCodec: Debug + Send + Sync {
+    /// Converts bytes into a value to store in etcd.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Codec` if the bytes can't be represented by this codec.
+    fn encode(&self, bytes: &[u8]) -> Result<String, Error>;
+
+    /// Converts a value stored in etcd back into bytes.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Codec` if the value was not produced by this codec.
+    fn decode(&self, value: &str) -> Result<Vec<u8>, Error>;
+}
+
+/// A codec that stores bytes as standard Base64 with padding.
+///
+/// This can store any bytes, at the cost of values being a third larger. It is the default codec.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Base64;
+
+impl ValueCodec for Base64 {
+    fn encode(&self, bytes: &[u8]) -> Result<String, Error> {
+        Ok(base64::encode(bytes))
+    }
+
+    fn decode(&self, value: &str) -> Result<Vec<u8>, Error> {
+        base64::decode(value).map_err(|error| Error::Codec(error.to_string()))
+    }
+}
+
+/// A codec that stores bytes as they are, which requires them to be valid UTF-8.
+///
+/// Values stored with this codec are readable by clients that don't use a codec.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Utf8;
+
+impl ValueCodec for Utf8 {
+    fn encode(&self, bytes: &[u8]) -> Result<String, Error> {
+        str::from_utf8(bytes)
+            .map(str::to_owned)
+            .map_err(|error| Error::Codec(error.to_string()))
+    }
+
+    fn decode(&self, value: &str) -> Result<Vec<u8>, Error> {
+        Ok(value.as_bytes().to_vec())
+    }
+}
+
+/// The prefix marking a value compressed with gzip by `Compressed`.
+#[cfg(feature = "compression")]
+const GZIP_MARKER: &str = "gzip:";
+
+/// The prefix marking a value compressed with Zstandard by `Compressed`.
+#[cfg(feature = "compression")]
+const ZSTD_MARKER: &str = "zstd:";
+
+/// The prefix marking a value that `Compressed` stored uncompressed, but whose encoding would
+/// otherwise be mistaken for a compressed value.
+#[cfg(feature = "compression")]
+const RAW_MARKER: &str = "raw:";
+
+/// A compression algorithm used by `Compressed`.
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Compression {
+    /// gzip, which is widely supported by other tools.
+    Gzip,
+    /// Zstandard, which is faster and usually compresses better than gzip.
+    Zstd,
+}
+
+/// A codec that compresses large values before encoding them with another codec.
+///
+/// Values at least as large as the threshold are compressed, and the result of encoding them with
+/// the inner codec is marked with a prefix naming the algorithm, e.g. `gzip:`. Smaller values are
+/// only encoded with the inner codec. Values compressed with either algorithm are decoded,
+/// regardless of the algorithm the codec compresses with.
+///
+/// Since compressed data is binary, the inner codec should be able to encode any bytes, like
+/// `Base64`.
+///
+/// This codec requires the `compression` Cargo feature.
+#[cfg(feature = "compression")]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Compressed<V> {
+    compression: Compression,
+    inner: V,
+    threshold: usize,
+}
+
+#[cfg(feature = "compression")]
+impl<V> Compressed<V>
+where
+    V: ValueCodec,
+{
+    /// Constructs a codec that compresses values of at least `threshold` bytes with the given
+    /// algorithm and encodes the results with `inner`.
+    pub fn new(inner: V, compression: Compression, threshold: usize) -> Self {
+        Compressed {
+            compression,
+            inner,
+            threshold,
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl<V> ValueCodec for Compressed<V>
+where
+    V: ValueCodec,
+{
+    fn encode(&self, bytes: &[u8]) -> Result<String, Error> {
+        use std::io::Write;
+
+        if bytes.len() < self.threshold {
+            let encoded = self.inner.encode(bytes)?;
+
+            if [GZIP_MARKER, ZSTD_MARKER, RAW_MARKER]
+                .iter()
+                .any(|marker| encoded.starts_with(marker))
+            {
+                return Ok(format!("{}{}", RAW_MARKER, encoded));
+            }
+
+            return Ok(encoded);
+        }
+
+        let (marker, compressed) = match self.compression {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+
+                let compressed = encoder
+                    .write_all(bytes)
+                    .and_then(|_| encoder.finish())
+                    .map_err(|error| Error::Codec(error.to_string()))?;
+
+                (GZIP_MARKER, compressed)
+            }
+            Compression::Zstd => {
+                let compressed =
+                    zstd::encode_all(bytes, 0).map_err(|error| Error::Codec(error.to_string()))?;
+
+                (ZSTD_MARKER, compressed)
+            }
+        };
+
+        Ok(format!("{}{}", marker, self.inner.encode(&compressed)?))
+    }
+
+    fn decode(&self, value: &str) -> Result<Vec<u8>, Error> {
+        use std::io::Read;
+
+        if let Some(value) = value.strip_prefix(GZIP_MARKER) {
+            let compressed = self.inner.decode(value)?;
+            let mut bytes = Vec::new();
+
+            flate2::read::GzDecoder::new(&compressed[..])
+                .read_to_end(&mut bytes)
+                .map_err(|error| Error::Codec(error.to_string()))?;
+
+            Ok(bytes)
+        } else if let Some(value) = value.strip_prefix(ZSTD_MARKER) {
+            let compressed = self.inner.decode(value)?;
+
+            zstd::decode_all(&compressed[..]).map_err(|error| Error::Codec(error.to_string()))
+        } else {
+            self.inner
+                .decode(value.strip_prefix(RAW_MARKER).unwrap_or(value))
+        }
+    }
+}
+
+/// Encryption and decryption of values before they are stored in etcd.
+///
+/// An encryptor is used through the `Encrypted` codec, so values stored with `kv::set_bytes` are
+/// encrypted at rest.
+pub trait Encryptor: Debug + Send + Sync {
+    /// Encrypts the given bytes.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Codec` if the bytes could not be encrypted.
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Decrypts bytes produced by `Encryptor::encrypt`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Codec` if the bytes could not be decrypted, e.g. because they were
+    /// encrypted with a different key or have been tampered with.
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// A codec that encrypts values before encoding them with another codec.
+///
+/// Since encrypted data is binary, the inner codec should be able to encode any bytes, like
+/// `Base64`. Encrypted data doesn't compress, so to compress values as well, use an `Encrypted`
+/// codec as the inner codec of a `Compressed` codec, which compresses values before they are
+/// encrypted.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Encrypted<E, V> {
+    encryptor: E,
+    inner: V,
+}
+
+impl<E, V> Encrypted<E, V>
+where
+    E: Encryptor,
+    V: ValueCodec,
+{
+    /// Constructs a codec that encrypts values with `encryptor` and encodes the results with
+    /// `inner`.
+    pub fn new(encryptor: E, inner: V) -> Self {
+        Encrypted { encryptor, inner }
+    }
+}
+
+impl<E, V> ValueCodec for Encrypted<E, V>
+where
+    E: Encryptor,
+    V: ValueCodec,
+{
+    fn encode(&self, bytes: &[u8]) -> Result<String, Error> {
+        self.inner.encode(&self.encryptor.encrypt(bytes)?)
+    }
+
+    fn decode(&self, value: &str) -> Result<Vec<u8>, Error> {
+        self.encryptor.decrypt(&self.inner.decode(value)?)
+    }
+}
+
+/// The length in bytes of the nonce `Aes256Gcm` prepends to each encrypted value.
+#[cfg(feature = "encryption")]
+const AES_GCM_NONCE_LENGTH: usize = 12;
+
+/// An `Encryptor` using AES-256 in Galois/Counter Mode.
+///
+/// Each value is encrypted with a random 96-bit nonce, which is stored in front of the ciphertext.
+/// Decryption fails if the value was encrypted with a different key or has been modified.
+///
+/// This encryptor requires the `encryption` Cargo feature.
+#[cfg(feature = "encryption")]
+#[derive(Clone)]
+pub struct Aes256Gcm {
+    cipher: aes_gcm::Aes256Gcm,
+}
+
+#[cfg(feature = "encryption")]
+impl Aes256Gcm {
+    /// Constructs an encryptor using the given 256-bit key.
+    pub fn new(key: &[u8; 32]) -> Self {
+        use aes_gcm::KeyInit;
+
+        Aes256Gcm {
+            cipher: aes_gcm::Aes256Gcm::new(key.into()),
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl Debug for Aes256Gcm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Aes256Gcm").finish()
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl Encryptor for Aes256Gcm {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        use aes_gcm::aead::{Aead, AeadCore, OsRng};
+
+        let nonce = aes_gcm::Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| Error::Codec("the value could not be encrypted".to_owned()))?;
+
+        let mut encrypted = Vec::with_capacity(nonce.len() + ciphertext.len());
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+
+        Ok(encrypted)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        use aes_gcm::aead::Aead;
+
+        if ciphertext.len() < AES_GCM_NONCE_LENGTH {
+            return Err(Error::Codec("the encrypted value is too short".to_owned()));
+        }
+
+        let (nonce, ciphertext) = ciphertext.split_at(AES_GCM_NONCE_LENGTH);
+
+        self.cipher
+            .decrypt(nonce.into(), ciphertext)
+            .map_err(|_| Error::Codec("the value could not be decrypted".to_owned()))
+    }
+}
@@ -0,0 +1,190 @@
+//! A source for the `config` crate backed by etcd.
+
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::panic;
+use std::thread;
+
+use ::config::{ConfigError, Map, Source, Value};
+use futures::{Future, Stream};
+use hyper::client::connect::Connect;
+use tokio::runtime::Runtime;
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::kv::{
+    self,
+    has_error_code,
+    normalize_prefix,
+    GetOptions,
+    WatchItem,
+    WatchOptions,
+    KEY_NOT_FOUND,
+};
+
+/// A `config::Source` that reads the key-value pairs under a prefix.
+///
+/// Each key-value pair becomes a configuration property named after its key relative to the
+/// prefix, with slashes replaced by dots, so under the prefix `/app`, the key
+/// `/app/database/url` becomes the property `database.url`. Values are strings, which `config`
+/// converts to the type each property is deserialized into.
+///
+/// `config` collects sources synchronously, so the keys are read on a separate thread with its own
+/// Tokio runtime, which makes it safe to build a configuration from within a task. To reload the
+/// configuration when the keys change, rebuild it each time `ConfigSource::changes` yields.
+///
+/// This type is only available when the "config" Cargo feature is enabled.
+///
+/// # Examples
+///
+/// ```no_run
+/// use config::Config;
+/// use etcd::{Client, ConfigSource};
+///
+/// let client = Client::new(&["http://etcd.example.com:2379"], None).unwrap();
+///
+/// let settings = Config::builder()
+///     .add_source(config::File::with_name("settings"))
+///     .add_source(ConfigSource::new(&client, "/app").required(true))
+///     .build()
+///     .unwrap();
+///
+/// let url: String = settings.get("database.url").unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ConfigSource<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    client: Client<C>,
+    prefix: String,
+    required: bool,
+}
+
+impl<C> ConfigSource<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    /// Constructs a source for the key-value pairs under a prefix. The prefix isn't required to
+    /// exist; see `ConfigSource::required`.
+    ///
+    /// # Parameters
+    ///
+    /// * client: A `Client` to use to make the API calls.
+    /// * prefix: The name of the directory to read.
+    pub fn new(client: &Client<C>, prefix: &str) -> Self {
+        ConfigSource {
+            client: client.clone(),
+            prefix: normalize_prefix(prefix),
+            required: false,
+        }
+    }
+
+    /// Sets whether or not collecting the source fails if the prefix doesn't exist, rather than
+    /// contributing no properties.
+    pub fn required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
+    }
+
+    /// Watches the key-value pairs under the prefix, yielding each time one of them changes.
+    ///
+    /// Changes that happen while a configuration is being rebuilt are still yielded, so none are
+    /// missed, though several may be yielded for one rebuild.
+    ///
+    /// # Errors
+    ///
+    /// The stream ends with an error under the same conditions as `kv::watch_stream`.
+    pub fn changes(&self) -> impl Stream<Item = (), Error = Vec<Error>> + Send {
+        let options = WatchOptions {
+            recursive: true,
+            ..Default::default()
+        };
+
+        kv::watch_stream(&self.client, &self.prefix, options).filter_map(|item| match item {
+            WatchItem::Event(_) => Some(()),
+            WatchItem::Idle => None,
+        })
+    }
+
+    /// Reads the key-value pairs under the prefix, keyed by property name.
+    fn properties(&self) -> Result<Vec<(String, String)>, Vec<Error>> {
+        let client = self.client.clone();
+        let prefix = self.prefix.clone();
+        let required = self.required;
+
+        let reader = thread::spawn(move || {
+            let options = GetOptions {
+                recursive: true,
+                ..Default::default()
+            };
+
+            let work = kv::get(&client, &prefix, options).then(move |result| match result {
+                Ok(response) => Ok(response
+                    .data
+                    .node
+                    .flatten()
+                    .into_iter()
+                    .filter_map(|(key, value)| {
+                        let name = key.get(prefix.len() + 1..)?.replace('/', ".");
+
+                        Some((name, value))
+                    })
+                    .collect()),
+                Err(ref errors) if !required && has_error_code(errors, KEY_NOT_FOUND) => Ok(vec![]),
+                Err(errors) => Err(errors),
+            });
+
+            let mut runtime = Runtime::new().map_err(|error| vec![Error::Io(error)])?;
+            let result = runtime.block_on(work);
+
+            runtime.shutdown_now();
+
+            result
+        });
+
+        reader
+            .join()
+            .unwrap_or_else(|panic| panic::resume_unwind(panic))
+    }
+}
+
+impl<C> Debug for ConfigSource<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("ConfigSource")
+            .field("endpoints", &self.client.endpoints())
+            .field("prefix", &self.prefix)
+            .field("required", &self.required)
+            .finish()
+    }
+}
+
+impl<C> Source for ConfigSource<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let properties = self.properties().map_err(|errors| {
+            let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+
+            ConfigError::Message(format!(
+                "failed to read {} from etcd: {}",
+                self.prefix,
+                messages.join("; ")
+            ))
+        })?;
+
+        let origin = format!("etcd {}", self.prefix);
+
+        Ok(properties
+            .into_iter()
+            .map(|(name, value)| (name, Value::new(Some(&origin), value)))
+            .collect())
+    }
+}
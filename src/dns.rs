@@ -0,0 +1,99 @@
+//! DNS resolution of cluster members' hostnames.
+//!
+//! By default, a client looks up hostnames with the operating system's blocking `getaddrinfo` on
+//! a small thread pool, each time it opens a connection. `Client::new_with_resolver` and
+//! `Client::https_with_resolver` take any other implementation of `Resolve` instead, such as one
+//! backed by trust-dns, or one that resolves names from a service registry.
+//!
+//! `CachingResolver` wraps another resolver and reuses its answers for a while, so opening new
+//! connections doesn't wait for a lookup each time.
+//!
+//! A resolver only provides IP addresses; the port of each connection is always the one in the
+//! endpoint's URL. SRV records aren't looked up, so members can't be discovered from them through
+//! a resolver. To use etcd's SRV discovery, look the records up with a DNS library and build the
+//! endpoints from them, adding members found later with `Client::add_endpoint`.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::vec;
+
+use futures::future::{self, Future};
+
+pub use hyper::client::connect::dns::{GaiResolver, Name, Resolve};
+
+use crate::sync::lock;
+
+/// The addresses of each host looked up by a `CachingResolver`, and when they were looked up.
+type Cache = HashMap<String, (Vec<IpAddr>, Instant)>;
+
+/// A resolver that caches the answers of another resolver.
+///
+/// Answers are reused until they are older than the resolver's TTL, after which the next
+/// connection to the host looks it up again. Failed lookups aren't cached, so they are retried
+/// by the next connection. Clones of a resolver share its cache.
+///
+/// The resolver doesn't see whether connections to the addresses it returns succeed, so an answer
+/// that has gone stale, e.g. because a member moved to another address, keeps being used until
+/// the TTL lapses. To look a host up again sooner, keep a clone of the resolver and call
+/// `CachingResolver::invalidate` when requests to the host fail with `Error::Request` errors whose
+/// `is_connect` is true.
+#[derive(Clone, Debug)]
+pub struct CachingResolver<R> {
+    cache: Arc<Mutex<Cache>>,
+    inner: R,
+    ttl: Duration,
+}
+
+impl<R> CachingResolver<R> {
+    /// Constructs a resolver that caches the answers of `inner` for `ttl`.
+    pub fn new(inner: R, ttl: Duration) -> Self {
+        CachingResolver {
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            inner,
+            ttl,
+        }
+    }
+
+    /// Forgets all cached answers, so that every host is looked up again.
+    pub fn clear(&self) {
+        lock(&self.cache).clear();
+    }
+
+    /// Forgets the cached answer for a host, so that it is looked up again, e.g. after
+    /// connections to its addresses have failed.
+    pub fn invalidate(&self, host: &str) {
+        lock(&self.cache).remove(host);
+    }
+}
+
+impl<R> Resolve for CachingResolver<R>
+where
+    R: Resolve,
+    R::Future: Send + 'static,
+{
+    type Addrs = vec::IntoIter<IpAddr>;
+    type Future = Box<dyn Future<Item = Self::Addrs, Error = io::Error> + Send>;
+
+    fn resolve(&self, name: Name) -> Self::Future {
+        let host = name.as_str().to_owned();
+
+        if let Some((addrs, resolved_at)) = lock(&self.cache).get(&host) {
+            if resolved_at.elapsed() < self.ttl {
+                return Box::new(future::ok(addrs.clone().into_iter()));
+            }
+        }
+
+        let cache = self.cache.clone();
+
+        Box::new(self.inner.resolve(name).map(move |addrs| {
+            let addrs: Vec<IpAddr> = addrs.collect();
+
+            lock(&cache).insert(host, (addrs.clone(), Instant::now()));
+
+            addrs.into_iter()
+        }))
+    }
+}
@@ -3,6 +3,7 @@
 use std::convert::From;
 use std::error::Error as StdError;
 use std::fmt::{Display, Error as FmtError, Formatter};
+use std::io::Error as IoError;
 
 use http::uri::InvalidUri;
 use hyper::{Error as HttpError, StatusCode};
@@ -13,6 +14,27 @@ use serde_json::Error as SerializationError;
 use tokio::timer::timeout::Error as TokioTimeoutError;
 use url::ParseError as UrlError;
 
+/// The etcd error code returned when the conditions of a compare-and-swap or compare-and-delete
+/// operation did not match.
+const TEST_FAILED: u64 = 101;
+
+/// The etcd error code returned when a cluster member failed to commit a request through Raft.
+const RAFT_INTERNAL: u64 = 300;
+
+/// The etcd error code returned when the cluster is electing a leader.
+const LEADER_ELECT: u64 = 301;
+
+/// The etcd error code returned when a watch was dropped because its cluster member recovered
+/// from a snapshot.
+const WATCHER_CLEARED: u64 = 400;
+
+/// The gRPC status returned when a v3 request didn't complete within its deadline.
+const DEADLINE_EXCEEDED: u32 = 4;
+
+/// The gRPC status returned when a v3 request can't be served for now, e.g. because the cluster
+/// has no leader.
+const UNAVAILABLE: u32 = 14;
+
 /// An error returned by an etcd API endpoint.
 ///
 /// This is a logical error, as opposed to other types of errors that may occur when using this
@@ -30,55 +52,401 @@ pub struct ApiError {
     pub message: String,
 }
 
+impl ApiError {
+    /// Returns the details of a failed compare-and-swap or compare-and-delete operation.
+    ///
+    /// Returns `None` if this error is not a comparison failure (etcd error code 101) or if its
+    /// cause could not be parsed.
+    ///
+    /// etcd only reports the conditions that failed. If only one condition failed, it is assumed
+    /// to be the modified index when both sides of the comparison are integers, so a failed
+    /// comparison of two numeric values is reported as an index mismatch.
+    pub fn compare_failure(&self) -> Option<CompareFailure> {
+        if self.error_code != TEST_FAILED {
+            return None;
+        }
+
+        let cause = self.cause.as_ref()?;
+        let cause = cause.trim();
+
+        if !cause.starts_with('[') || !cause.ends_with(']') {
+            return None;
+        }
+
+        let cause = &cause[1..cause.len() - 1];
+
+        let mut failure = CompareFailure::default();
+
+        match cause.rfind("] [") {
+            Some(position) => {
+                let (expected_value, actual_value) = parse_comparison(&cause[..position])?;
+                let (expected_index, actual_index) = parse_comparison(&cause[position + 3..])?;
+
+                failure.expected_value = Some(expected_value.to_owned());
+                failure.actual_value = Some(actual_value.to_owned());
+                failure.expected_index = Some(expected_index.parse().ok()?);
+                failure.actual_index = Some(actual_index.parse().ok()?);
+            }
+            None => {
+                let (expected, actual) = parse_comparison(cause)?;
+
+                match (expected.parse(), actual.parse()) {
+                    (Ok(expected_index), Ok(actual_index)) => {
+                        failure.expected_index = Some(expected_index);
+                        failure.actual_index = Some(actual_index);
+                    }
+                    _ => {
+                        failure.expected_value = Some(expected.to_owned());
+                        failure.actual_value = Some(actual.to_owned());
+                    }
+                }
+            }
+        }
+
+        Some(failure)
+    }
+}
+
+impl ApiError {
+    /// Returns whether and when the request that failed with this error may be made again.
+    ///
+    /// Failures to commit a request through Raft and leader elections leave the cluster unable to
+    /// serve requests for a while, so they are retried after a backoff. A watch dropped by a
+    /// member's recovery can be made again straight away. Every other error is the result of the
+    /// request itself, e.g. a failed comparison or a missing key, and is never retried.
+    pub fn retry_class(&self) -> RetryClass {
+        match self.error_code {
+            RAFT_INTERNAL | LEADER_ELECT => RetryClass::AfterBackoff,
+            WATCHER_CLEARED => RetryClass::Immediately,
+            _ => RetryClass::Never,
+        }
+    }
+}
+
 impl Display for ApiError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
         write!(f, "{}", self.message)
     }
 }
 
-impl StdError for ApiError {
-    fn description(&self) -> &str {
-        &self.message
+impl StdError for ApiError {}
+
+/// An error returned by an etcd v3 API endpoint.
+///
+/// The v3 API is served by etcd's gRPC gateway, which reports the gRPC status of failed calls.
+/// See `Error` for the other types of errors.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RpcError {
+    /// The gRPC status code, e.g. 5 for "not found" or 9 for "failed precondition".
+    #[serde(default)]
+    pub code: u32,
+    /// A human-friendly description of the error.
+    #[serde(default)]
+    pub message: String,
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{} (gRPC status {})", self.message, self.code)
+    }
+}
+
+impl StdError for RpcError {}
+
+/// Whether and when a request that failed may be made again, returned by `Error::retry_class` and
+/// `ApiError::retry_class`.
+///
+/// New variants may be added in future releases, so matches on this type need a wildcard arm.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum RetryClass {
+    /// The request failed because of the request itself, e.g. a failed comparison, so making it
+    /// again would fail the same way.
+    Never,
+    /// The request may be made again straight away, e.g. with the next cluster member.
+    Immediately,
+    /// The cluster is busy or has no leader, so the request should only be made again after a
+    /// backoff, such as `RequestOptions::retry_backoff`.
+    AfterBackoff,
+}
+
+/// The details of a failed compare-and-swap or compare-and-delete operation.
+///
+/// This value is returned by `ApiError::compare_failure`. Fields are only present for the
+/// conditions that failed.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CompareFailure {
+    /// The value the operation required the key to have.
+    pub expected_value: Option<String>,
+    /// The value the key actually had.
+    pub actual_value: Option<String>,
+    /// The modified index the operation required the key to have.
+    pub expected_index: Option<u64>,
+    /// The modified index the key actually had.
+    pub actual_index: Option<u64>,
+}
+
+/// The details of a request that etcd refused because of the client's credentials.
+///
+/// This value is carried by `Error::Unauthorized` and `Error::PermissionDenied`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct AccessDenied {
+    /// The key the request operated on, for requests to the key-value API.
+    pub key: Option<String>,
+    /// The name of the user the request was authenticated as, if the client has credentials.
+    pub user: Option<String>,
+}
+
+impl Display for AccessDenied {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self.user {
+            Some(ref user) => write!(f, "user {}", user)?,
+            None => write!(f, "anonymous user")?,
+        }
+
+        if let Some(ref key) = self.key {
+            write!(f, " on key {}", key)?;
+        }
+
+        Ok(())
     }
 }
 
 /// An error returned when an operation fails for some reaosn.
+///
+/// New variants may be added in future releases, so matches on this type need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     /// An error returned by an etcd API endpoint.
     Api(ApiError),
-    /// An error at the HTTP protocol layer.
+    /// An error returned when a request is aborted by cancelling the `CancellationToken` in the
+    /// client's `RequestOptions`.
+    Cancelled,
+    /// An error returned when a value could not be encoded or decoded, e.g. by the client's
+    /// `ValueCodec` or by `kv::large::get`. Contains a description of the problem.
+    Codec(String),
+    /// An error at the HTTP protocol layer, e.g. while receiving a response's body.
+    ///
+    /// Since 0.10.0, requests to cluster members that fail at the HTTP protocol layer, including
+    /// those that fail to connect, return `Error::Request` instead, so code that matches
+    /// `Error::Http` to detect unreachable members must match `Error::Request` as well.
+    /// `Error::http_error` returns the underlying error of either variant.
     Http(HttpError),
+    /// An error returned by a watch when the index it was asked to start from is older than the
+    /// oldest change etcd keeps (etcd error code 401).
+    ///
+    /// The changes since the index are lost. To resume watching, read the node again to catch up,
+    /// then watch from the index after `current_index`.
+    IndexCleared {
+        /// The etcd index when the watch was made, from the `X-Etcd-Index` header.
+        current_index: u64,
+    },
+    /// An error returned when a request to an etcd cluster member failed at the HTTP protocol
+    /// layer, e.g. because the member couldn't be connected to. Before 0.10.0, these failures
+    /// were returned as `Error::Http`.
+    Request {
+        /// The underlying error.
+        error: HttpError,
+        /// The ID the request was sent with in its `X-Request-Id` header.
+        request_id: String,
+    },
     /// An error returned when invalid conditions have been provided for a compare-and-delete or
     /// compare-and-swap operation.
     InvalidConditions,
+    /// An error returned when a key is rejected by `kv::Key::new`. Contains a description of the
+    /// problem.
+    InvalidKey(String),
+    /// An error returned by `sequence::reserve` when the value of a sequence's counter key isn't a
+    /// number.
+    InvalidSequence {
+        /// The counter key.
+        key: String,
+        /// The counter key's value.
+        value: String,
+    },
+    /// An error returned when a string is rejected by `members::MemberId::from_hex` or
+    /// `members::MemberId::from_decimal`. Contains the string.
+    InvalidMemberId(String),
     /// An error returned when an etcd cluster member's endpoint is not a valid URI.
     InvalidUri(InvalidUri),
     /// An error returned when the URL for a specific API endpoint cannot be generated.
     InvalidUrl(UrlError),
+    /// An error reading or writing a local file, e.g. by `journal::FileJournal`.
+    Io(IoError),
+    /// An error returned by `kv::Session::lost` when the session's key is no longer kept alive.
+    LeaseLost,
+    /// An error returned when a lock is no longer held: by `v3::kv::put_with_fence` when the lock
+    /// the write was fenced with has been released or has passed to another client, by
+    /// `v3::lock::lock_with_fence` when the lock was released before its token could be read, and
+    /// by `semaphore::Semaphore::acquire` when the client's key disappeared while it waited.
+    LockLost,
+    /// An error returned by the members API when a change conflicts with the cluster's
+    /// membership, e.g. because another member already uses the peer URLs (HTTP 409). Contains
+    /// etcd's description of the conflict.
+    MemberConflict(String),
     /// An error returned when attempting to create a client without at least one member endpoint.
     NoEndpoints,
+    /// An error returned when the etcd server refuses a request because the client's
+    /// credentials lack permission for it (HTTP 403).
+    PermissionDenied(AccessDenied),
+    /// An error returned by an etcd v3 API endpoint.
+    Rpc(RpcError),
     /// An error returned when attempting to deserializing invalid JSON.
     Serialization(SerializationError),
+    /// An error returned by `sequence::reserve` when reserving the IDs would take a sequence past
+    /// the largest `u64`. Contains the counter key.
+    SequenceExhausted(String),
+    /// An error returned when `RequestOptions::read_your_writes` is set and a cluster member
+    /// returned data from before the client's latest write.
+    StaleRead,
+    /// An error returned when a request to an etcd cluster member did not complete within the
+    /// timeout configured in the client's `RequestOptions`, or waited longer than
+    /// `ConnectionOptions::queue_timeout` to be sent.
+    Timeout,
     /// An error returned when configuring TLS.
     #[cfg(feature = "tls")]
     Tls(TlsError),
+    /// An error returned when the etcd server refuses a request because of the client's
+    /// credentials (HTTP 401).
+    ///
+    /// etcd's v2 API also responds this way when valid credentials lack permission for the
+    /// request.
+    Unauthorized(AccessDenied),
     /// An error returned when an unexpected HTTP status code is returned by the server.
     UnexpectedStatus(StatusCode),
 }
 
+impl Error {
+    /// Converts an unsuccessful response from etcd into an error.
+    pub(crate) fn from_response(status: StatusCode, body: &[u8], access: AccessDenied) -> Error {
+        match status {
+            StatusCode::UNAUTHORIZED => Error::Unauthorized(access),
+            StatusCode::FORBIDDEN => Error::PermissionDenied(access),
+            _ => match serde_json::from_slice::<ApiError>(body) {
+                Ok(error) => Error::Api(error),
+                // e.g. a proxy in front of the cluster member that couldn't reach it.
+                Err(_) if status.is_server_error() => Error::UnexpectedStatus(status),
+                Err(error) => Error::Serialization(error),
+            },
+        }
+    }
+
+    /// Returns whether and when the request that failed with this error may be made again.
+    ///
+    /// Requests that failed because a cluster member couldn't be reached or stopped responding,
+    /// responded with a server error, or fell behind the client's writes may be made again
+    /// straight away, with the same or another member. Those that failed because the cluster was
+    /// electing a leader or was unavailable should be made again after a backoff. See
+    /// `ApiError::retry_class` for the errors returned by etcd's v2 API.
+    pub fn retry_class(&self) -> RetryClass {
+        match *self {
+            Error::Api(ref error) => error.retry_class(),
+            Error::Http(ref error) | Error::Request { ref error, .. } if !error.is_user() => {
+                RetryClass::Immediately
+            }
+            Error::Rpc(ref error) => match error.code {
+                UNAVAILABLE => RetryClass::AfterBackoff,
+                DEADLINE_EXCEEDED => RetryClass::Immediately,
+                _ => RetryClass::Never,
+            },
+            Error::StaleRead | Error::Timeout => RetryClass::Immediately,
+            Error::UnexpectedStatus(StatusCode::SERVICE_UNAVAILABLE) => RetryClass::AfterBackoff,
+            Error::UnexpectedStatus(status) if status.is_server_error() => RetryClass::Immediately,
+            _ => RetryClass::Never,
+        }
+    }
+
+    /// Returns whether or not the error was caused by a temporary condition of the cluster or the
+    /// network, so that the request may succeed if it is made again later.
+    ///
+    /// This is true when a cluster member couldn't be reached or stopped responding, responded
+    /// with a server error, or was electing a leader, and false for permanent failures such as
+    /// invalid requests, failed comparisons, and missing credentials or permissions.
+    ///
+    /// A write that failed with a transient error may still have been applied if a member
+    /// received it but its response was lost.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            Error::StaleRead => false,
+            _ => self.retry_class() != RetryClass::Never,
+        }
+    }
+
+    /// Returns whether or not making the request again, with the same or another cluster member,
+    /// may succeed.
+    ///
+    /// This is true for transient errors (see `Error::is_transient`) and for errors that only
+    /// concern the member that returned them, such as `Error::StaleRead`. It is what the client
+    /// uses to decide whether to try the next cluster member after a request fails, so a retry
+    /// loop around an API call can use it to make the same decision. `Error::retry_class` also
+    /// tells whether to back off first.
+    pub fn is_retryable(&self) -> bool {
+        self.retry_class() != RetryClass::Never
+    }
+
+    /// Returns the underlying error of an `Error::Http` or `Error::Request`, e.g. to check
+    /// whether a cluster member couldn't be connected to with `is_connect`.
+    pub fn http_error(&self) -> Option<&HttpError> {
+        match *self {
+            Error::Http(ref error) | Error::Request { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Returns the ID of the request that failed, as sent in its `X-Request-Id` header, if the
+    /// error is from sending it.
+    ///
+    /// The ID can be used to find the request in the logs of the proxies and gateways in front of
+    /// etcd.
+    pub fn request_id(&self) -> Option<&str> {
+        match *self {
+            Error::Request { ref request_id, .. } => Some(request_id),
+            _ => None,
+        }
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
         match *self {
             Error::Api(ref error) => write!(f, "{}", error),
+            Error::Cancelled => write!(f, "the request was cancelled"),
+            Error::Codec(ref message) => write!(f, "{}", message),
             Error::Http(ref error) => write!(f, "{}", error),
-            ref error @ Error::InvalidConditions => write!(f, "{}", error.description()),
+            Error::IndexCleared { current_index } => write!(
+                f,
+                "the watch index has been cleared; the current index is {}",
+                current_index
+            ),
+            Error::InvalidConditions => write!(f, "current value or modified index is required"),
+            Error::InvalidKey(ref message) => write!(f, "{}", message),
+            Error::InvalidSequence { ref key, ref value } => {
+                write!(f, "{} is not a sequence counter: {:?}", key, value)
+            }
+            Error::InvalidMemberId(ref id) => write!(f, "invalid member ID: {}", id),
             Error::InvalidUri(ref error) => write!(f, "{}", error),
             Error::InvalidUrl(ref error) => write!(f, "{}", error),
-            ref error @ Error::NoEndpoints => write!(f, "{}", error.description()),
+            Error::Io(ref error) => write!(f, "{}", error),
+            Error::LeaseLost => write!(f, "the session's key is no longer kept alive"),
+            Error::LockLost => write!(f, "the lock is no longer held"),
+            Error::MemberConflict(ref message) => write!(f, "member conflict: {}", message),
+            Error::NoEndpoints => write!(f, "at least one endpoint is required to create a Client"),
+            Error::PermissionDenied(ref access) => write!(f, "permission denied for {}", access),
+            Error::Request { ref request_id, .. } => {
+                write!(f, "the request with ID {} failed", request_id)
+            }
+            Error::Rpc(ref error) => write!(f, "{}", error),
+            Error::Timeout => write!(f, "the request timed out"),
             #[cfg(feature = "tls")]
             Error::Tls(ref error) => write!(f, "{}", error),
             Error::Serialization(ref error) => write!(f, "{}", error),
+            Error::SequenceExhausted(ref key) => write!(f, "sequence {} has run out of IDs", key),
+            Error::StaleRead => write!(
+                f,
+                "the cluster member has not caught up with the client's writes"
+            ),
+            Error::Unauthorized(ref access) => write!(f, "unauthorized {}", access),
             Error::UnexpectedStatus(ref status) => write!(
                 f,
                 "the etcd server returned an unexpected HTTP status code: {}",
@@ -89,18 +457,21 @@ impl Display for Error {
 }
 
 impl StdError for Error {
-    fn description(&self) -> &str {
+    // Variants that display the error they wrap pass its source on instead of returning it, so
+    // that its message isn't reported twice.
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
-            Error::Api(_) => "the etcd server returned an error",
-            Error::Http(_) => "an error occurred during the HTTP request",
-            Error::InvalidConditions => "current value or modified index is required",
-            Error::InvalidUri(_) => "a supplied endpoint could not be parsed as a URI",
-            Error::InvalidUrl(_) => "a URL for the request could not be generated",
-            Error::NoEndpoints => "at least one endpoint is required to create a Client",
+            Error::Api(ref error) => error.source(),
+            Error::Rpc(ref error) => error.source(),
+            Error::Http(ref error) => error.source(),
+            Error::Request { ref error, .. } => Some(error),
+            Error::InvalidUri(ref error) => error.source(),
+            Error::InvalidUrl(ref error) => error.source(),
+            Error::Io(ref error) => error.source(),
+            Error::Serialization(ref error) => error.source(),
             #[cfg(feature = "tls")]
-            Error::Tls(_) => "an error occurred configuring TLS",
-            Error::Serialization(_) => "an error occurred deserializing JSON",
-            Error::UnexpectedStatus(_) => "the etcd server returned an unexpected HTTP status code",
+            Error::Tls(ref error) => error.source(),
+            _ => None,
         }
     }
 }
@@ -124,6 +495,12 @@ impl From<UrlError> for Error {
     }
 }
 
+impl From<IoError> for Error {
+    fn from(error: IoError) -> Error {
+        Error::Io(error)
+    }
+}
+
 impl From<SerializationError> for Error {
     fn from(error: SerializationError) -> Error {
         Error::Serialization(error)
@@ -136,8 +513,14 @@ impl From<InvalidUri> for Error {
     }
 }
 
-/// An error returned by `kv::watch`.
+/// An error returned by `kv::watch` in earlier versions of this crate.
+///
+/// The watch functions now fail with `Vec<Error>` like the other functions in `kv`, with
+/// `Error::Timeout` in place of `WatchError::Timeout`. A `WatchError` can be converted into the
+/// new error type with `From`.
+#[deprecated(since = "0.10.0", note = "watch functions now fail with `Vec<Error>`")]
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum WatchError {
     /// An error for each failed request to an etcd member.
     Other(Vec<Error>),
@@ -145,23 +528,55 @@ pub enum WatchError {
     Timeout,
 }
 
+#[allow(deprecated)]
 impl<T> From<TokioTimeoutError<T>> for WatchError {
     fn from(_: TokioTimeoutError<T>) -> Self {
         WatchError::Timeout
     }
 }
 
+#[allow(deprecated)]
+impl From<WatchError> for Vec<Error> {
+    fn from(error: WatchError) -> Self {
+        match error {
+            WatchError::Other(errors) => errors,
+            WatchError::Timeout => vec![Error::Timeout],
+        }
+    }
+}
+
+#[allow(deprecated)]
 impl Display for WatchError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
         match *self {
-            WatchError::Timeout => write!(f, "{}", self.description()),
-            ref other => other.fmt(f),
+            WatchError::Other(ref errors) => {
+                write!(f, "every etcd cluster member failed the request")?;
+
+                for (i, error) in errors.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { ";" }, error)?;
+                }
+
+                Ok(())
+            }
+            WatchError::Timeout => write!(f, "operation timed out"),
         }
     }
 }
 
+#[allow(deprecated)]
 impl StdError for WatchError {
-    fn description(&self) -> &str {
-        "operation timed out"
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            WatchError::Other(ref errors) if errors.len() == 1 => Some(&errors[0]),
+            _ => None,
+        }
     }
 }
+
+/// Splits one side of a comparison failure cause, e.g. `bar != baz`, into the expected and actual
+/// values.
+fn parse_comparison(comparison: &str) -> Option<(&str, &str)> {
+    let position = comparison.find(" != ")?;
+
+    Some((&comparison[..position], &comparison[position + 4..]))
+}
@@ -1,76 +1,393 @@
+use std::convert::TryFrom;
 use std::mem::replace;
-use std::vec::IntoIter;
+use std::time::{Duration, Instant};
 
-use futures::{Async, Future, Poll};
+use futures::future::Either;
+use futures::{Async, Future, IntoFuture, Poll};
 use hyper::Uri;
+use tokio::timer::Delay;
+
+use crate::cancellation::CancellationToken;
+use crate::client::{Backoff, RequestOptions};
+use crate::error::{Error, RetryClass};
+
+/// The least time an attempt is given out of a total timeout, however many attempts are left.
+const MIN_ATTEMPT_SHARE: Duration = Duration::from_millis(100);
 
 /// Executes the given closure with each cluster member and short-circuit returns the first
-/// successful result. If all members are exhausted without success, the final error is
-/// returned.
-pub fn first_ok<F, T>(endpoints: Vec<Uri>, callback: F) -> FirstOk<F, T>
+/// successful result. If all members are exhausted without success, they are tried again as many
+/// times as `options.retries` allows, after which all the errors are returned.
+///
+/// The next member is only tried if an attempt fails with an error for which
+/// `Error::is_retryable` is true. Otherwise the errors so far are returned right away. If an
+/// attempt failed with an error to retry after a backoff, the members are only tried again once
+/// `options.retry_backoff` allows.
+///
+/// If `options.timeout` is given, each attempt fails with `Error::Timeout` if it has not completed
+/// within the duration. If `options.total_timeout` is given, the attempts are also limited to a
+/// share of the time remaining, and no more are made once it has lapsed. If
+/// `options.cancellation` is given and gets cancelled, the whole operation fails with
+/// `Error::Cancelled`.
+pub fn first_ok<F, T>(endpoints: Vec<Uri>, options: &RequestOptions, callback: F) -> FirstOk<F, T>
+where
+    F: Fn(&Uri) -> T,
+    T: Future<Error = Error>,
+{
+    first_ok_concurrently(endpoints, options, 1, total_deadline(options), callback)
+}
+
+/// Like `first_ok`, but tries as many cluster members at once as `options.concurrent_attempts`
+/// allows. Each time an attempt fails, the next member is tried, and once one succeeds the others
+/// are abandoned.
+///
+/// The members are only tried again for `options.retries` once every attempt has failed, so no
+/// member is tried more than once at a time.
+pub fn concurrent_first_ok<F, T>(
+    endpoints: Vec<Uri>,
+    options: &RequestOptions,
+    callback: F,
+) -> FirstOk<F, T>
 where
     F: Fn(&Uri) -> T,
-    T: Future,
+    T: Future<Error = Error>,
 {
-    let max_errors = endpoints.len();
+    first_ok_concurrently(
+        endpoints,
+        options,
+        options.concurrent_attempts,
+        total_deadline(options),
+        callback,
+    )
+}
 
+/// Returns when an operation that starts now must have completed by, for
+/// `RequestOptions::total_timeout`.
+fn total_deadline(options: &RequestOptions) -> Option<Instant> {
+    options
+        .total_timeout
+        .map(|total_timeout| Instant::now() + total_timeout)
+}
+
+/// Constructs a `FirstOk` that has up to `concurrency` attempts in flight at once and completes by
+/// `total_deadline`.
+fn first_ok_concurrently<F, T>(
+    endpoints: Vec<Uri>,
+    options: &RequestOptions,
+    concurrency: usize,
+    total_deadline: Option<Instant>,
+    callback: F,
+) -> FirstOk<F, T>
+where
+    F: Fn(&Uri) -> T,
+    T: Future<Error = Error>,
+{
     FirstOk {
+        backoff: options.retry_backoff,
         callback,
-        current_future: None,
-        endpoints: endpoints.into_iter(),
-        errors: Vec::with_capacity(max_errors),
+        cancellation: options.cancellation.clone(),
+        concurrency: concurrency.max(1),
+        delay: None,
+        errors: Vec::with_capacity(endpoints.len()),
+        endpoints,
+        in_flight: Vec::new(),
+        position: 0,
+        registration: None,
+        retries: options.retries,
+        round_start: 0,
+        timeout: options.timeout,
+        total_deadline,
+        wait: options.retry_backoff.initial,
     }
 }
 
+/// Like `concurrent_first_ok`, but if `options.hedge_delay` is given and the first attempt hasn't
+/// succeeded within the delay, the members are also tried starting from the next one, and
+/// whichever succeeds first is used. Dropping the returned future abandons both. Both share the
+/// deadline for `options.total_timeout`, which starts when this function is called.
+///
+/// If the first fails, its errors are returned straight away: it has either tried every member,
+/// or failed with an error the other members would fail with too. If the second fails first, the
+/// first is still waited for, and if it fails as well, its errors are followed by the errors from
+/// the second.
+pub fn hedged_first_ok<F, T>(
+    endpoints: Vec<Uri>,
+    options: &RequestOptions,
+    callback: F,
+) -> impl Future<Item = T::Item, Error = Vec<Error>>
+where
+    F: Clone + Fn(&Uri) -> T,
+    T: Future<Error = Error>,
+{
+    let delay = match options.hedge_delay {
+        Some(delay) if endpoints.len() > 1 => delay,
+        _ => return Either::A(concurrent_first_ok(endpoints, options, callback)),
+    };
+
+    let mut hedge_endpoints = endpoints.clone();
+    hedge_endpoints.rotate_left(1);
+
+    let total_deadline = total_deadline(options);
+    let concurrency = options.concurrent_attempts;
+    let primary = first_ok_concurrently(
+        endpoints,
+        options,
+        concurrency,
+        total_deadline,
+        callback.clone(),
+    );
+    let hedge_options = options.clone();
+    let hedge = Delay::new(Instant::now() + delay).then(move |_| {
+        first_ok_concurrently(
+            hedge_endpoints,
+            &hedge_options,
+            concurrency,
+            total_deadline,
+            callback,
+        )
+    });
+
+    let result = primary.select2(hedge).then(|result| match result {
+        Ok(Either::A((item, _))) | Ok(Either::B((item, _))) => Either::A(Ok(item).into_future()),
+        // Dropping the hedge abandons it.
+        Err(Either::A((errors, _))) => Either::A(Err(errors).into_future()),
+        Err(Either::B((hedge_errors, primary))) => Either::B(
+            primary.map_err(move |errors| errors.into_iter().chain(hedge_errors).collect()),
+        ),
+    });
+
+    Either::B(result)
+}
+
 #[derive(Debug)]
 #[must_use = "futures do nothing unless polled"]
 pub struct FirstOk<F, T>
 where
     F: Fn(&Uri) -> T,
-    T: Future,
+    T: Future<Error = Error>,
 {
+    /// How long to wait between rounds of attempts that failed with errors to retry after a
+    /// backoff.
+    backoff: Backoff,
     callback: F,
-    current_future: Option<T>,
-    endpoints: IntoIter<Uri>,
-    errors: Vec<T::Error>,
+    cancellation: Option<CancellationToken>,
+    /// The most attempts to have in flight at once.
+    concurrency: usize,
+    /// The wait before the next round of attempts, once it has started.
+    delay: Option<Delay>,
+    endpoints: Vec<Uri>,
+    errors: Vec<Error>,
+    /// The attempts in flight, with their deadlines.
+    in_flight: Vec<(T, Option<Delay>)>,
+    position: usize,
+    /// The identifier of this operation's registration with the cancellation token, if any.
+    registration: Option<usize>,
+    retries: usize,
+    /// The position in `errors` of the first error from the current round of attempts.
+    round_start: usize,
+    timeout: Option<Duration>,
+    /// When the whole operation must have completed by, for `RequestOptions::total_timeout`.
+    total_deadline: Option<Instant>,
+    /// How long to wait before the next round of attempts that needs a backoff.
+    wait: Duration,
 }
 
 impl<F, T> Future for FirstOk<F, T>
 where
     F: Fn(&Uri) -> T,
-    T: Future,
+    T: Future<Error = Error>,
 {
     type Item = T::Item;
-    type Error = Vec<T::Error>;
+    type Error = Vec<Error>;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        if let Some(mut current_future) = self.current_future.take() {
-            match current_future.poll() {
-                Ok(Async::NotReady) => {
-                    self.current_future = Some(current_future);
+        let cancellation = match self.cancellation {
+            Some(ref cancellation) => cancellation.clone(),
+            None => return self.poll_endpoints(),
+        };
+
+        if cancellation.is_cancelled() {
+            return Err(vec![Error::Cancelled]);
+        }
 
-                    Ok(Async::NotReady)
+        match self.poll_endpoints()? {
+            Async::Ready(item) => Ok(Async::Ready(item)),
+            Async::NotReady => {
+                cancellation.register(&mut self.registration);
+
+                if cancellation.is_cancelled() {
+                    return Err(vec![Error::Cancelled]);
                 }
-                Ok(Async::Ready(item)) => Ok(Async::Ready(item)),
-                Err(error) => {
-                    self.errors.push(error);
 
-                    self.poll()
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+impl<F, T> FirstOk<F, T>
+where
+    F: Fn(&Uri) -> T,
+    T: Future<Error = Error>,
+{
+    /// Polls the attempts in flight, moving on to the next endpoints as attempts fail.
+    fn poll_endpoints(&mut self) -> Poll<T::Item, Vec<Error>> {
+        loop {
+            while self.in_flight.len() < self.concurrency {
+                if self.position == self.endpoints.len() {
+                    if self.retries == 0 || self.endpoints.is_empty() || !self.in_flight.is_empty()
+                    {
+                        break;
+                    }
+
+                    if let Async::NotReady = self.poll_backoff() {
+                        return Ok(Async::NotReady);
+                    }
+
+                    self.retries -= 1;
+                    self.position = 0;
+                    self.round_start = self.errors.len();
                 }
+
+                let timeout = match self.attempt_timeout() {
+                    Some(timeout) if timeout == Duration::from_secs(0) => {
+                        // The total timeout has lapsed, so no more attempts are made.
+                        if self.in_flight.is_empty() {
+                            self.errors.push(Error::Timeout);
+                        }
+
+                        break;
+                    }
+                    timeout => timeout,
+                };
+
+                let endpoint = self.endpoints[self.position].clone();
+                self.position += 1;
+
+                let deadline = timeout.map(|timeout| Delay::new(Instant::now() + timeout));
+
+                self.in_flight.push(((self.callback)(&endpoint), deadline));
             }
-        } else {
-            match self.endpoints.next() {
-                Some(endpoint) => {
-                    self.current_future = Some((self.callback)(&endpoint));
 
-                    self.poll()
+            if self.in_flight.is_empty() {
+                let errors = replace(&mut self.errors, vec![]);
+
+                return Err(errors);
+            }
+
+            let mut failed = false;
+            let mut index = 0;
+
+            while index < self.in_flight.len() {
+                let (ref mut future, ref mut deadline) = self.in_flight[index];
+
+                let error = match future.poll() {
+                    Ok(Async::Ready(item)) => {
+                        // Dropping the other attempts abandons them.
+                        self.in_flight.clear();
+
+                        return Ok(Async::Ready(item));
+                    }
+                    Ok(Async::NotReady) => match deadline {
+                        Some(ref mut deadline) => match deadline.poll() {
+                            Ok(Async::NotReady) => None,
+                            Ok(Async::Ready(())) | Err(_) => Some(Error::Timeout),
+                        },
+                        None => None,
+                    },
+                    Err(error) => Some(error),
+                };
+
+                match error {
+                    Some(error) => {
+                        self.in_flight.remove(index);
+
+                        // The other members would fail the same way, so they aren't tried.
+                        if !error.is_retryable() {
+                            self.in_flight.clear();
+                            self.errors.push(error);
+
+                            return Err(self.errors.split_off(0));
+                        }
+
+                        self.errors.push(error);
+                        failed = true;
+                    }
+                    None => index += 1,
                 }
-                None => {
-                    let errors = replace(&mut self.errors, vec![]);
+            }
+
+            if !failed {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+
+    /// Waits before the next round of attempts if an attempt in the current round failed with an
+    /// error to retry after a backoff.
+    fn poll_backoff(&mut self) -> Async<()> {
+        if self.delay.is_none() {
+            let back_off = self.errors[self.round_start..]
+                .iter()
+                .any(|error| error.retry_class() == RetryClass::AfterBackoff);
 
-                    Err(errors)
+            if !back_off {
+                return Async::Ready(());
+            }
+
+            let now = Instant::now();
+            let wait = match self.total_deadline {
+                Some(total_deadline) => {
+                    self.wait.min(total_deadline.saturating_duration_since(now))
                 }
+                None => self.wait,
+            };
+
+            self.delay = Some(Delay::new(now + wait));
+            self.wait = self.backoff.next(self.wait);
+        }
+
+        match self.delay.as_mut().map(Delay::poll) {
+            Some(Ok(Async::NotReady)) => Async::NotReady,
+            _ => {
+                self.delay = None;
+
+                Async::Ready(())
             }
         }
     }
+
+    /// Returns how long the next attempt may take.
+    ///
+    /// With a total timeout, the time remaining is shared equally between the rounds of attempts
+    /// that are left, so later members still get a chance when earlier ones don't respond. Each
+    /// attempt still gets at least `MIN_ATTEMPT_SHARE`, or what remains if that is less, so that
+    /// many retries don't leave every attempt too little time to succeed.
+    fn attempt_timeout(&self) -> Option<Duration> {
+        let total_deadline = match self.total_deadline {
+            Some(total_deadline) => total_deadline,
+            None => return self.timeout,
+        };
+
+        let remaining = total_deadline.saturating_duration_since(Instant::now());
+        let attempts_left = (self.endpoints.len() - self.position)
+            .saturating_add(self.retries.saturating_mul(self.endpoints.len()));
+        let rounds_left = attempts_left.div_ceil(self.concurrency);
+        let share = (remaining / u32::try_from(rounds_left).unwrap_or(u32::MAX).max(1))
+            .max(MIN_ATTEMPT_SHARE)
+            .min(remaining);
+
+        Some(self.timeout.map_or(share, |timeout| timeout.min(share)))
+    }
+}
+
+impl<F, T> Drop for FirstOk<F, T>
+where
+    F: Fn(&Uri) -> T,
+    T: Future<Error = Error>,
+{
+    fn drop(&mut self) {
+        if let (Some(cancellation), Some(id)) = (self.cancellation.as_ref(), self.registration) {
+            cancellation.deregister(id);
+        }
+    }
 }
@@ -1,19 +1,197 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use base64::encode;
-use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use futures::future::{Either, Future, IntoFuture};
+use futures::task::{self, Task};
+use futures::{Async, Poll, Stream};
+use http::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use http::request::Builder;
 use hyper::client::connect::Connect;
-use hyper::client::ResponseFuture;
-use hyper::{Body, Client as Hyper, Method, Request, Uri};
+use hyper::{Body, Client as Hyper, Method, Request, Response, Uri};
+#[cfg(feature = "otel")]
+use opentelemetry::Context;
+use tokio::timer::Delay;
 
 use crate::client::BasicAuth;
+use crate::error::Error;
+use crate::logging::{DebugLogging, RequestLog};
+#[cfg(feature = "otel")]
+use crate::otel::RequestSpan;
+use crate::secret::{zeroize, Secret};
+use crate::sync;
+
+/// The HTTP header that identifies each request to the proxies and gateways in front of etcd.
+const X_REQUEST_ID: &str = "X-Request-Id";
+
+/// The number of requests this process has generated IDs for.
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// A random number that starts every generated request ID, so that IDs from different processes
+/// don't collide. It is zero until the first ID is generated.
+static REQUEST_ID_PREFIX: AtomicU64 = AtomicU64::new(0);
+
+/// Joins a cluster member's endpoint and a path relative to it into a URL.
+///
+/// The endpoint's authority is used as is, so IPv6 hosts keep their brackets, and a path given in
+/// the endpoint is kept as a prefix whether or not it ends with a slash.
+pub fn endpoint_url(endpoint: &Uri, path: &str) -> String {
+    let scheme = endpoint
+        .scheme_part()
+        .map_or("http", |scheme| scheme.as_str());
+    let authority = endpoint
+        .authority_part()
+        .map_or("", |authority| authority.as_str());
+    let prefix = endpoint.path().trim_end_matches('/');
+
+    format!(
+        "{}://{}{}/{}",
+        scheme,
+        authority,
+        prefix,
+        path.trim_start_matches('/')
+    )
+}
 
 #[derive(Clone, Debug)]
 pub struct HttpClient<C>
 where
     C: Clone + Connect + Sync + 'static,
 {
-    basic_auth: Option<BasicAuth>,
+    basic_auth: Option<Credentials>,
+    /// The ID to send with every request in place of a generated one, for
+    /// `RequestOptions::correlation_id`.
+    correlation_id: Option<HeaderValue>,
+    debug_logging: Option<Arc<DebugLogging>>,
     hyper: Hyper<C>,
+    limits: Option<Arc<InFlightLimits>>,
+    /// The context whose span requests are traced as children of, for
+    /// `Client::with_trace_context`.
+    #[cfg(feature = "otel")]
+    trace_context: Option<Context>,
+    v3_token: Arc<V3Token>,
+}
+
+/// The credentials requests are authenticated with, copied from the `BasicAuth` the client was
+/// given.
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    /// The username to use for authentication.
+    pub username: String,
+    /// The password to use for authentication.
+    pub password: Secret,
+}
+
+impl From<BasicAuth> for Credentials {
+    fn from(basic_auth: BasicAuth) -> Self {
+        let BasicAuth { username, password } = basic_auth;
+
+        Credentials {
+            username,
+            password: Secret::new(password),
+        }
+    }
+}
+
+/// The token the v3 API issued for the client's credentials, and whether the cluster was found to
+/// have authentication disabled instead, shared between the clients derived from one another until
+/// their credentials change.
+#[derive(Default)]
+struct V3Token {
+    token: Mutex<Option<Secret>>,
+    auth_disabled: AtomicBool,
+}
+
+/// The number of requests in flight to each cluster member, for
+/// `ConnectionOptions::max_in_flight_per_endpoint`.
+struct InFlightLimits {
+    /// The most requests to have in flight to each member at once.
+    max_in_flight: usize,
+    /// How long a request may wait for one of the others to finish.
+    queue_timeout: Option<Duration>,
+    /// The requests in flight to, and the tasks of requests waiting for, each member, by
+    /// authority.
+    endpoints: Mutex<HashMap<String, (usize, Vec<Task>)>>,
+}
+
+/// A request waiting for fewer requests to be in flight to its cluster member.
+struct Acquire {
+    deadline: Option<Delay>,
+    endpoint: String,
+    limits: Arc<InFlightLimits>,
+}
+
+/// Permission for a request to be in flight, given up when dropped.
+struct Permit {
+    endpoint: String,
+    limits: Arc<InFlightLimits>,
+}
+
+impl Future for Acquire {
+    type Item = Permit;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Permit, Error> {
+        let mut endpoints = sync::lock(&self.limits.endpoints);
+        let (in_flight, waiters) = endpoints.entry(self.endpoint.clone()).or_default();
+
+        if *in_flight >= self.limits.max_in_flight {
+            if !waiters.iter().any(Task::will_notify_current) {
+                waiters.push(task::current());
+            }
+
+            return match self.deadline.as_mut().map(Delay::poll) {
+                Some(Ok(Async::Ready(()))) | Some(Err(_)) => Err(Error::Timeout),
+                Some(Ok(Async::NotReady)) | None => Ok(Async::NotReady),
+            };
+        }
+
+        *in_flight += 1;
+
+        Ok(Async::Ready(Permit {
+            endpoint: self.endpoint.clone(),
+            limits: self.limits.clone(),
+        }))
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut endpoints = sync::lock(&self.limits.endpoints);
+
+        if let Some((in_flight, waiters)) = endpoints.get_mut(&self.endpoint) {
+            *in_flight = in_flight.saturating_sub(1);
+
+            for task in waiters.drain(..) {
+                task.notify();
+            }
+        }
+    }
+}
+
+impl Debug for V3Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        let issued = sync::lock(&self.token).is_some();
+
+        f.debug_struct("V3Token")
+            .field("issued", &issued)
+            .field("auth_disabled", &self.auth_disabled.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl Debug for InFlightLimits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("InFlightLimits")
+            .field("max_in_flight", &self.max_in_flight)
+            .field("queue_timeout", &self.queue_timeout)
+            .finish()
+    }
 }
 
 impl<C> HttpClient<C>
@@ -22,59 +200,478 @@ where
 {
     /// Constructs a new `HttpClient`.
     pub fn new(hyper: Hyper<C>, basic_auth: Option<BasicAuth>) -> Self {
-        HttpClient { basic_auth, hyper }
+        HttpClient {
+            basic_auth: basic_auth.map(Credentials::from),
+            correlation_id: None,
+            debug_logging: None,
+            hyper,
+            limits: None,
+            #[cfg(feature = "otel")]
+            trace_context: None,
+            v3_token: Arc::default(),
+        }
+    }
+
+    /// Returns an `HttpClient` that makes each request, other than watches, wait while the given
+    /// number of requests are already in flight to the same cluster member.
+    ///
+    /// A request that has waited longer than `queue_timeout` fails with `Error::Timeout`.
+    pub fn with_in_flight_limit(
+        &self,
+        max_in_flight: usize,
+        queue_timeout: Option<Duration>,
+    ) -> Self {
+        HttpClient {
+            limits: Some(Arc::new(InFlightLimits {
+                max_in_flight: max_in_flight.max(1),
+                queue_timeout,
+                endpoints: Mutex::new(HashMap::new()),
+            })),
+            ..self.clone()
+        }
+    }
+
+    /// Returns an `HttpClient` that shares the `hyper::Client` but uses the given credentials.
+    pub fn with_basic_auth(&self, basic_auth: Option<BasicAuth>) -> Self {
+        HttpClient {
+            basic_auth: basic_auth.map(Credentials::from),
+            v3_token: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns an `HttpClient` that shares the `hyper::Client` but logs requests with the given
+    /// settings, or doesn't log them if `None` is given.
+    pub fn with_debug_logging(&self, debug_logging: Option<DebugLogging>) -> Self {
+        HttpClient {
+            debug_logging: debug_logging.map(Arc::new),
+            ..self.clone()
+        }
+    }
+
+    /// Returns an `HttpClient` that shares the `hyper::Client` but sends the given ID with every
+    /// request, or generates one for each request if `None` is given.
+    ///
+    /// Characters that can't appear in an HTTP header are replaced with underscores.
+    pub fn with_correlation_id(&self, correlation_id: Option<&str>) -> Self {
+        HttpClient {
+            correlation_id: correlation_id.map(|id| {
+                let id: String = id
+                    .chars()
+                    .map(|c| {
+                        if c == ' ' || c.is_ascii_graphic() {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect();
+
+                HeaderValue::from_str(&id).unwrap()
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Returns an `HttpClient` that shares the `hyper::Client` but traces requests as children of
+    /// the span in the given context, or of the current context's span if `None` is given.
+    #[cfg(feature = "otel")]
+    pub fn with_trace_context(&self, trace_context: Option<Context>) -> Self {
+        HttpClient {
+            trace_context,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the credentials requests are authenticated with, if any.
+    pub fn basic_auth(&self) -> Option<&Credentials> {
+        self.basic_auth.as_ref()
+    }
+
+    /// Returns the token the v3 API issued for the client's credentials, if it has issued one.
+    pub fn v3_token(&self) -> Option<String> {
+        sync::lock(&self.v3_token.token)
+            .as_ref()
+            .map(|token| token.expose().to_owned())
+    }
+
+    /// Stores the token the v3 API issued for the client's credentials, or forgets it if `None`
+    /// is given.
+    pub fn set_v3_token(&self, token: Option<String>) {
+        *sync::lock(&self.v3_token.token) = token.map(Secret::new);
+    }
+
+    /// Returns whether or not the v3 API reported that authentication is not enabled in the
+    /// cluster when the client last requested a token.
+    pub fn v3_auth_disabled(&self) -> bool {
+        self.v3_token.auth_disabled.load(Ordering::Relaxed)
+    }
+
+    /// Records whether or not authentication is enabled in the cluster, so that requests to the
+    /// v3 API skip requesting a token while it isn't.
+    pub fn set_v3_auth_disabled(&self, auth_disabled: bool) {
+        self.v3_token
+            .auth_disabled
+            .store(auth_disabled, Ordering::Relaxed);
+    }
+
+    /// Returns the name of the user requests are authenticated as, if any.
+    pub fn username(&self) -> Option<&str> {
+        self.basic_auth
+            .as_ref()
+            .map(|basic_auth| basic_auth.username.as_str())
     }
 
     /// Makes a DELETE request to etcd.
-    pub fn delete(&self, uri: Uri) -> ResponseFuture {
+    pub fn delete(&self, uri: Uri) -> impl Future<Item = Response<Body>, Error = Error> + Send {
         self.request(Method::DELETE, uri)
     }
 
     /// Makes a GET request to etcd.
-    pub fn get(&self, uri: Uri) -> ResponseFuture {
+    pub fn get(&self, uri: Uri) -> impl Future<Item = Response<Body>, Error = Error> + Send {
         self.request(Method::GET, uri)
     }
 
     /// Makes a POST request to etcd.
-    pub fn post(&self, uri: Uri, body: String) -> ResponseFuture {
+    pub fn post(
+        &self,
+        uri: Uri,
+        body: String,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
         self.request_with_body(Method::POST, uri, body)
     }
 
+    /// Makes a POST request with a JSON body to etcd's v3 API.
+    ///
+    /// The request is authenticated with the given v3 API token, if any, instead of the client's
+    /// credentials.
+    pub fn post_json(
+        &self,
+        uri: Uri,
+        body: String,
+        token: Option<String>,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
+        let request = json_request(uri, body, token, self.request_id());
+
+        self.traced(request, |request| {
+            let log = self.request_log(&request, Some(request.body()));
+
+            logged(log, true, self.send(request.map(Body::from)))
+        })
+    }
+
     /// Makes a PUT request to etcd.
-    pub fn put(&self, uri: Uri, body: String) -> ResponseFuture {
+    pub fn put(
+        &self,
+        uri: Uri,
+        body: String,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
         self.request_with_body(Method::PUT, uri, body)
     }
 
+    /// Makes a GET request to etcd that waits for a change.
+    ///
+    /// Watches can stay in flight indefinitely, so they don't count towards, and aren't held back
+    /// by, the limit on requests in flight.
+    pub fn watch(&self, uri: Uri) -> impl Future<Item = Response<Body>, Error = Error> + Send {
+        let mut request = Request::builder();
+        request
+            .method(Method::GET)
+            .uri(uri)
+            .header(X_REQUEST_ID, self.request_id());
+
+        self.add_auth_header(&mut request);
+
+        let request = request.body(Body::empty()).unwrap();
+
+        self.traced(request, |request| {
+            let log = self.request_log(&request, None);
+
+            logged(log, true, dispatch(&self.hyper, request))
+        })
+    }
+
+    /// Makes a POST request with a JSON body to etcd's v3 API that waits for something to happen,
+    /// e.g. for a lock to be acquired, or that streams its response.
+    ///
+    /// Like watches, these requests don't count towards, and aren't held back by, the limit on
+    /// requests in flight. Their response bodies are never logged, since a streamed body may not
+    /// end.
+    pub fn watch_json(
+        &self,
+        uri: Uri,
+        body: String,
+        token: Option<String>,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
+        let request = json_request(uri, body, token, self.request_id());
+
+        self.traced(request, |request| {
+            let log = self.request_log(&request, Some(request.body()));
+
+            logged(log, false, dispatch(&self.hyper, request.map(Body::from)))
+        })
+    }
+
     // private
 
     /// Adds the Authorization HTTP header to a request if a credentials were supplied.
     fn add_auth_header<'a>(&self, request: &mut Builder) {
         if let Some(ref basic_auth) = self.basic_auth {
-            let auth = format!("{}:{}", basic_auth.username, basic_auth.password);
+            let mut auth = format!("{}:{}", basic_auth.username, basic_auth.password.expose());
             let header_value = format!("Basic {}", encode(&auth));
 
+            zeroize(&mut auth);
+
             request.header(AUTHORIZATION, header_value);
         }
     }
 
     /// Makes a request to etcd.
-    fn request(&self, method: Method, uri: Uri) -> ResponseFuture {
+    fn request(
+        &self,
+        method: Method,
+        uri: Uri,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
         let mut request = Request::builder();
-        request.method(method).uri(uri);
+        request
+            .method(method)
+            .uri(uri)
+            .header(X_REQUEST_ID, self.request_id());
 
         self.add_auth_header(&mut request);
 
-        self.hyper.request(request.body(Body::empty()).unwrap())
+        let request = request.body(Body::empty()).unwrap();
+
+        self.traced(request, |request| {
+            let log = self.request_log(&request, None);
+
+            logged(log, true, self.send(request))
+        })
     }
 
     /// Makes a request with an HTTP body to etcd.
-    fn request_with_body(&self, method: Method, uri: Uri, body: String) -> ResponseFuture {
+    fn request_with_body(
+        &self,
+        method: Method,
+        uri: Uri,
+        body: String,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
         let mut request = Request::builder();
-        request.method(method).uri(uri);
+        request
+            .method(method)
+            .uri(uri)
+            .header(X_REQUEST_ID, self.request_id());
         request.header(CONTENT_TYPE, "application/x-www-form-urlencoded");
 
         self.add_auth_header(&mut request);
 
-        self.hyper.request(request.body(Body::from(body)).unwrap())
+        let request = request.body(body).unwrap();
+
+        self.traced(request, |request| {
+            let log = self.request_log(&request, Some(request.body()));
+
+            logged(log, true, self.send(request.map(Body::from)))
+        })
+    }
+
+    /// Returns the ID to send with the next request: the correlation ID if the client has one, or
+    /// else an ID that is unique to the request.
+    fn request_id(&self) -> HeaderValue {
+        if let Some(ref correlation_id) = self.correlation_id {
+            return correlation_id.clone();
+        }
+
+        let prefix = request_id_prefix();
+        let count = REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+        HeaderValue::from_str(&format!("{:016x}-{:x}", prefix, count)).unwrap()
+    }
+
+    /// Sends a request with `send`, recording an OpenTelemetry span for it.
+    #[cfg(feature = "otel")]
+    fn traced<B, F, R>(
+        &self,
+        mut request: Request<B>,
+        send: F,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send>
+    where
+        F: FnOnce(Request<B>) -> R,
+        R: Future<Item = Response<Body>, Error = Error> + Send + 'static,
+    {
+        let request_id = request_id_of(&request);
+        let span = RequestSpan::start(self.trace_context.as_ref(), &request_id, &mut request);
+
+        Box::new(span.end_with(send(request)))
     }
+
+    /// Sends a request with `send`.
+    #[cfg(not(feature = "otel"))]
+    fn traced<B, F, R>(&self, request: Request<B>, send: F) -> R
+    where
+        F: FnOnce(Request<B>) -> R,
+    {
+        send(request)
+    }
+
+    /// Records the details of a request to log, if debug logging is enabled.
+    fn request_log<B>(&self, request: &Request<B>, body: Option<&str>) -> Option<RequestLog> {
+        self.debug_logging.as_ref().map(|settings| {
+            RequestLog::new(
+                settings,
+                request.method(),
+                request.uri(),
+                request.headers(),
+                body,
+            )
+        })
+    }
+
+    /// Sends a request once the limit on requests in flight to its cluster member allows it.
+    ///
+    /// The request counts as in flight until the response headers are received.
+    fn send(
+        &self,
+        request: Request<Body>,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send {
+        let limits = match self.limits {
+            Some(ref limits) => limits.clone(),
+            None => return Either::A(dispatch(&self.hyper, request)),
+        };
+
+        let endpoint = request
+            .uri()
+            .authority_part()
+            .map_or_else(String::new, |authority| authority.to_string());
+        let acquire = Acquire {
+            deadline: limits
+                .queue_timeout
+                .map(|timeout| Delay::new(Instant::now() + timeout)),
+            endpoint,
+            limits,
+        };
+        let hyper = self.hyper.clone();
+
+        Either::B(acquire.and_then(move |permit| {
+            dispatch(&hyper, request).map(move |response| {
+                drop(permit);
+
+                response
+            })
+        }))
+    }
+}
+
+/// Sends a request with hyper. If it fails, the error carries the request's ID.
+fn dispatch<C>(
+    hyper: &Hyper<C>,
+    request: Request<Body>,
+) -> impl Future<Item = Response<Body>, Error = Error> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    let request_id = request_id_of(&request);
+
+    hyper
+        .request(request)
+        .map_err(move |error| Error::Request { error, request_id })
+}
+
+/// Returns the ID a request is sent with in its `X-Request-Id` header.
+fn request_id_of<B>(request: &Request<B>) -> String {
+    request
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|request_id| request_id.to_str().ok())
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// Returns the random number that starts every generated request ID, choosing it the first time.
+fn request_id_prefix() -> u64 {
+    let prefix = REQUEST_ID_PREFIX.load(Ordering::Relaxed);
+
+    if prefix != 0 {
+        return prefix;
+    }
+
+    let chosen = RandomState::new().build_hasher().finish().max(1);
+
+    // If another thread chose a prefix first, its prefix is used instead.
+    match REQUEST_ID_PREFIX.compare_exchange(0, chosen, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => chosen,
+        Err(prefix) => prefix,
+    }
+}
+
+/// Builds a POST request with a JSON body to etcd's v3 API, authenticated with the given token if
+/// any.
+fn json_request(
+    uri: Uri,
+    body: String,
+    token: Option<String>,
+    request_id: HeaderValue,
+) -> Request<String> {
+    let mut request = Request::builder();
+    request
+        .method(Method::POST)
+        .uri(uri)
+        .header(CONTENT_TYPE, "application/json")
+        .header(X_REQUEST_ID, request_id);
+
+    if let Some(token) = token {
+        request.header(AUTHORIZATION, token);
+    }
+
+    request.body(body).unwrap()
+}
+
+/// Logs a request once it completes, if it has a `RequestLog`.
+///
+/// If `log_body` is true and response bodies are logged, the body is received in full first.
+fn logged<F>(
+    log: Option<RequestLog>,
+    log_body: bool,
+    response: F,
+) -> impl Future<Item = Response<Body>, Error = Error> + Send
+where
+    F: Future<Item = Response<Body>, Error = Error> + Send,
+{
+    let log = match log {
+        Some(log) => log,
+        None => return Either::A(response),
+    };
+    let started = Instant::now();
+
+    Either::B(response.then(move |result| match result {
+        Ok(response) if log_body && log.logs_bodies() => {
+            let (parts, body) = response.into_parts();
+
+            Either::A(body.concat2().map_err(Error::from).then(move |result| {
+                let latency = started.elapsed().as_millis();
+
+                match result {
+                    Ok(body) => {
+                        log.response(parts.status, latency, Some(&body));
+
+                        Ok(Response::from_parts(parts, Body::from(body)))
+                    }
+                    Err(error) => {
+                        log.failure(latency, &error);
+
+                        Err(error)
+                    }
+                }
+            }))
+        }
+        Ok(response) => {
+            log.response(response.status(), started.elapsed().as_millis(), None);
+
+            Either::B(Ok(response).into_future())
+        }
+        Err(error) => {
+            log.failure(started.elapsed().as_millis(), &error);
+
+            Either::B(Err(error).into_future())
+        }
+    }))
 }
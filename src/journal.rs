@@ -0,0 +1,175 @@
+//! A durable record of the changes observed under a prefix, for replaying or auditing them.
+//!
+//! `journal::record` watches the nodes under a prefix and appends each change to a `JournalSink`
+//! before yielding it, along with the etcd index it was made at. `FileJournal` is a sink that
+//! appends the changes to a local file as newline-delimited JSON, which `FileJournal::read` reads
+//! back. Other destinations can be used by implementing `JournalSink`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use futures::stream::Stream;
+use hyper::client::connect::Connect;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::kv::{self, KeyValueInfo, WatchCheckpoint, WatchItem, WatchOptions};
+
+/// A change recorded by `journal::record`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct JournalEntry {
+    /// The etcd index the change was made at.
+    pub index: u64,
+    /// The change, as yielded by the watch.
+    pub event: KeyValueInfo,
+}
+
+impl JournalEntry {
+    /// Returns the checkpoint following this change, for resuming recording after it with
+    /// `WatchOptions::from_checkpoint`.
+    pub fn checkpoint(&self) -> WatchCheckpoint {
+        WatchCheckpoint {
+            index: self.index + 1,
+        }
+    }
+}
+
+/// A destination for the changes recorded by `journal::record`.
+pub trait JournalSink {
+    /// Appends a change to the journal.
+    ///
+    /// # Errors
+    ///
+    /// An error ends the stream returned by `journal::record`, and the change isn't yielded.
+    fn append(&mut self, entry: &JournalEntry) -> Result<(), Error>;
+}
+
+impl JournalSink for Vec<JournalEntry> {
+    fn append(&mut self, entry: &JournalEntry) -> Result<(), Error> {
+        self.push(entry.clone());
+
+        Ok(())
+    }
+}
+
+/// A journal kept in an append-only local file, with one JSON-encoded `JournalEntry` per line.
+///
+/// Each change is flushed to disk before it is yielded by `journal::record`, so a change that has
+/// been yielded is never missing from the file after a crash.
+#[derive(Debug)]
+pub struct FileJournal {
+    file: File,
+}
+
+impl FileJournal {
+    /// Opens a journal file for appending, creating it if it doesn't exist.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Io` if the file can't be opened.
+    pub fn open<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+
+        Ok(FileJournal { file })
+    }
+
+    /// Reads the changes recorded in a journal file, oldest first.
+    ///
+    /// A final line that was only partly written, e.g. because the process crashed while
+    /// appending it, is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Io` if the file can't be read, or with `Error::Serialization` if a
+    /// complete line isn't a valid entry.
+    pub fn read<P>(path: P) -> Result<Vec<JournalEntry>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut contents = String::new();
+
+        File::open(path)?.read_to_string(&mut contents)?;
+
+        let complete = match contents.rfind('\n') {
+            Some(end) => &contents[..end],
+            None => "",
+        };
+
+        complete
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(Error::from))
+            .collect()
+    }
+}
+
+impl JournalSink for FileJournal {
+    fn append(&mut self, entry: &JournalEntry) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(entry)?;
+
+        line.push(b'\n');
+
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+}
+
+/// Watches the nodes under a prefix, appending each change to a journal before yielding it.
+///
+/// To resume recording after a restart without missing or repeating changes, pass the
+/// `JournalEntry::checkpoint` of the last recorded change to `WatchOptions::from_checkpoint`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * prefix: The name of the node to watch. Its descendants are watched as well.
+/// * sink: The journal to append changes to.
+/// * options: Options to customize the behavior of the watch, as for `kv::watch_stream`.
+///
+/// # Errors
+///
+/// The stream ends with an error under the same conditions as `kv::watch_stream`, or if a change
+/// could not be appended to the journal.
+pub fn record<C, S>(
+    client: &Client<C>,
+    prefix: &str,
+    mut sink: S,
+    options: WatchOptions,
+) -> impl Stream<Item = JournalEntry, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+    S: JournalSink + Send + 'static,
+{
+    let options = WatchOptions {
+        recursive: true,
+        ..options
+    };
+
+    kv::watch_stream(client, prefix, options)
+        .filter_map(|item| match item {
+            WatchItem::Event(response) => Some(*response),
+            WatchItem::Idle => None,
+        })
+        .and_then(move |response| {
+            let entry = JournalEntry {
+                index: response
+                    .data
+                    .node
+                    .modified_index
+                    .or(response.cluster_info.etcd_index)
+                    .unwrap_or_default(),
+                event: response.data,
+            };
+
+            sink.append(&entry)
+                .map(|_| entry)
+                .map_err(|error| vec![error])
+        })
+}
@@ -5,30 +5,81 @@
 //! there other other key-value pairs "underneath" it, such as "/foo/bar".
 
 use std::collections::HashMap;
+use std::fmt::Debug;
+use std::slice;
 use std::str::FromStr;
-use std::time::Duration;
-
-use futures::future::{Future, IntoFuture};
-use futures::stream::Stream;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+use futures::future::{join_all, loop_fn, Either, Future, IntoFuture, Loop};
+use futures::stream::{self, Stream};
+use futures::{Async, Poll};
 use hyper::client::connect::Connect;
 use hyper::{StatusCode, Uri};
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
-use tokio::timer::Timeout;
+use tokio::timer::{Delay, Timeout};
 use url::Url;
 
+pub use self::cache::Cache;
+pub use self::key::Key;
+pub use self::session::Session;
+#[cfg(feature = "streaming")]
+pub use self::streaming::get_streaming;
+#[allow(deprecated)]
 pub use crate::error::WatchError;
 
+pub mod large;
+pub mod raw;
+pub mod tree;
+
+mod cache;
+mod key;
+mod session;
+#[cfg(feature = "streaming")]
+mod streaming;
+
 use crate::client::{Client, ClusterInfo, Response};
-use crate::error::{ApiError, Error};
-use crate::first_ok::first_ok;
+use crate::error::{AccessDenied, Error};
+use crate::first_ok::{first_ok, hedged_first_ok};
+use crate::http::endpoint_url;
 use crate::options::{
     ComparisonConditions,
-    DeleteOptions,
+    DeleteOptions as InternalDeleteOptions,
     GetOptions as InternalGetOptions,
-    SetOptions,
+    SetOptions as InternalSetOptions,
 };
 use url::form_urlencoded::Serializer;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+/// The etcd error code returned when a key does not exist.
+pub(crate) const KEY_NOT_FOUND: u64 = 100;
+
+/// The etcd error code returned when the conditions of a compare-and-swap or compare-and-delete
+/// operation did not match.
+const TEST_FAILED: u64 = 101;
+
+/// The etcd error code returned when a key is read as a directory but is a key-value pair.
+const NOT_A_DIRECTORY: u64 = 104;
+
+/// The etcd error code returned when a key already exists.
+pub(crate) const NODE_EXIST: u64 = 105;
+
+/// The etcd error code returned when a watch's index is older than the oldest change etcd keeps.
+const EVENT_INDEX_CLEARED: u64 = 401;
+
+/// The maximum number of requests `kv::multi_get` and `kv::multi_set` have in flight at once.
+const BATCH_CONCURRENCY: usize = 8;
+
+/// A single watch request, as made by `watch_once`.
+type WatchFuture =
+    Box<dyn Future<Item = Response<KeyValueInfo>, Error = Option<Vec<Error>>> + Send>;
+
+/// The request `kv::watch_stream` makes for the index to start watching after.
+type SeedFuture = Box<dyn Future<Item = Option<u64>, Error = Vec<Error>> + Send>;
 
 /// Information about the result of a successful key-value API operation.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -36,6 +87,9 @@ pub struct KeyValueInfo {
     /// The action that was taken, e.g. `get`, `set`.
     pub action: Action,
     /// The etcd `Node` that was operated upon.
+    ///
+    /// This is empty if the operation was a write made with `raw::SetOptions::no_value_on_success`.
+    #[serde(default)]
     pub node: Node,
     /// The previous state of the target node.
     #[serde(rename = "prevNode")]
@@ -74,7 +128,10 @@ pub enum Action {
 }
 
 /// An etcd key or directory.
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+///
+/// Nodes whose names begin with an underscore are hidden: etcd leaves them out of directory
+/// listings, though they can be read directly. See `Node::is_hidden`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct Node {
     /// The new value of the etcd creation index.
     #[serde(rename = "createdIndex")]
@@ -96,9 +153,131 @@ pub struct Node {
     pub value: Option<String>,
 }
 
+impl Node {
+    /// Returns an iterator over the key-value pairs in this node and all of its descendants.
+    ///
+    /// The tree is traversed depth-first, in the order the nodes appear in the response.
+    /// Directories themselves are not yielded.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { stack: vec![self] }
+    }
+
+    /// Returns the key-value pairs in this node and all of its descendants.
+    ///
+    /// The pairs are in the same order as the items yielded by `Node::iter`.
+    pub fn flatten(&self) -> Vec<(String, String)> {
+        self.iter()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    /// Returns whether or not the node is hidden, i.e. the last segment of its key begins with an
+    /// underscore.
+    pub fn is_hidden(&self) -> bool {
+        match self.key {
+            Some(ref key) => key.rsplit('/').next().unwrap_or("").starts_with('_'),
+            None => false,
+        }
+    }
+
+    /// Returns the node's remaining time to live, if it has a TTL.
+    ///
+    /// The duration is measured from when etcd handled the request, not from when the response
+    /// was received.
+    pub fn time_to_live(&self) -> Option<Duration> {
+        self.ttl
+            .filter(|&ttl| ttl >= 0)
+            .map(|ttl| Duration::from_secs(ttl as u64))
+    }
+
+    /// Returns when the node will expire, if it has a TTL and its expiration could be parsed.
+    #[cfg(feature = "chrono")]
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        let expiration = self.expiration.as_ref()?;
+
+        DateTime::parse_from_rfc3339(expiration)
+            .ok()
+            .map(|expiration| expiration.with_timezone(&Utc))
+    }
+
+    /// Returns the node with the given key, if it is this node or one of its descendants.
+    pub fn find(&self, key: &str) -> Option<&Node> {
+        if self.key.as_deref() == Some(key) {
+            return Some(self);
+        }
+
+        self.nodes
+            .as_ref()?
+            .iter()
+            .filter(|node| match node.key {
+                Some(ref node_key) => key.starts_with(node_key.as_str()),
+                None => false,
+            })
+            .filter_map(|node| node.find(key))
+            .next()
+    }
+}
+
+/// An iterator over the key-value pairs in a tree of nodes.
+///
+/// This value is returned by `Node::iter`.
+#[derive(Clone, Debug)]
+pub struct Iter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            if let Some(ref nodes) = node.nodes {
+                self.stack.extend(nodes.iter().rev());
+            }
+
+            if let (Some(ref key), Some(ref value)) = (&node.key, &node.value) {
+                return Some((key, value));
+            }
+        }
+
+        None
+    }
+}
+
+/// A change made by a successful write to the key-value store, passed to a `MutationObserver`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Mutation<'a> {
+    /// The key that was written.
+    pub key: &'a str,
+    /// The action etcd took, e.g. `Action::Set` or `Action::Delete`.
+    pub action: Action,
+    /// The key's value before the write, if it had one.
+    pub prev_value: Option<&'a str>,
+    /// The key's value after the write. This is `None` after a deletion, and after a write made
+    /// with `raw::SetOptions::no_value_on_success`.
+    pub value: Option<&'a str>,
+    /// The etcd index after the write, if etcd returned one.
+    pub etcd_index: Option<u64>,
+}
+
+/// An observer of the writes to the key-value store made with a client, e.g. to keep an audit log
+/// or to publish change events.
+///
+/// An observer is registered with `Client::with_mutation_observer`. It is called with each write
+/// made by the functions in this module once etcd has confirmed it, on the thread that polls the
+/// write, so it shouldn't block.
+pub trait MutationObserver: Debug + Send + Sync {
+    /// Called with a write that has succeeded.
+    fn on_mutation(&self, mutation: &Mutation<'_>);
+}
+
 /// Options for customizing the behavior of `kv::get`.
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub struct GetOptions {
+    /// The names of hidden children of the directory to read with separate requests and add to
+    /// the node's children, e.g. `_lock`. etcd leaves hidden nodes out of directory listings, so
+    /// they must be known in advance. Children that don't exist are skipped.
+    pub include_hidden: &'static [&'static str],
     /// If true and the node is a directory, child nodes will be returned as well.
     pub recursive: bool,
     /// If true and the node is a directory, any child nodes returned will be sorted
@@ -111,9 +290,50 @@ pub struct GetOptions {
     pub strong_consistency: bool,
 }
 
+impl GetOptions {
+    /// Returns options for reading a single node, without consistency guarantees.
+    pub fn new() -> Self {
+        GetOptions::default()
+    }
+
+    /// Reads the hidden children of the directory with the given names as well. See
+    /// `GetOptions::include_hidden`.
+    pub fn include_hidden(mut self, names: &'static [&'static str]) -> Self {
+        self.include_hidden = names;
+        self
+    }
+
+    /// Synchronizes with the quorum before returning the value. See
+    /// `GetOptions::strong_consistency`.
+    pub fn quorum(mut self) -> Self {
+        self.strong_consistency = true;
+        self
+    }
+
+    /// Returns the directory's child nodes as well.
+    pub fn recursive(mut self) -> Self {
+        self.recursive = true;
+        self
+    }
+
+    /// Sorts the directory's child nodes alphabetically.
+    pub fn sorted(mut self) -> Self {
+        self.sort = true;
+        self
+    }
+}
+
 /// Options for customizing the behavior of `kv::watch`.
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub struct WatchOptions {
+    /// If given, `kv::watch_stream` and `kv::watch_many` only yield changes with one of these
+    /// actions. Other changes are skipped, though watching still resumes after them. This has no
+    /// effect on `kv::watch`.
+    pub actions: Option<&'static [Action]>,
+    /// If given, `kv::watch_stream` and `kv::watch_many` hold back each change for the duration
+    /// and yield only the latest change received in that time, collapsing bursts of changes into
+    /// one. This has no effect on `kv::watch`.
+    pub coalesce_window: Option<Duration>,
     /// If given, the watch operation will return the first change at the index or greater,
     /// allowing you to watch for changes that happened in the past.
     pub index: Option<u64>,
@@ -123,6 +343,310 @@ pub struct WatchOptions {
     pub timeout: Option<Duration>,
 }
 
+impl WatchOptions {
+    /// Returns options for watching a single node for the next change, without a timeout.
+    pub fn new() -> Self {
+        WatchOptions::default()
+    }
+
+    /// Returns options for watching for changes made after the cluster state described by
+    /// `cluster_info`, e.g. that of the response to a `kv::get` of the watched key.
+    ///
+    /// Watching from the index after `cluster_info.etcd_index` yields every change made since the
+    /// response, so none are missed between reading a key and watching it. If the index is
+    /// missing, the options watch for the next change instead.
+    pub fn from_cluster_info(cluster_info: &ClusterInfo) -> Self {
+        WatchOptions {
+            index: cluster_info.etcd_index.map(|index| index + 1),
+            ..WatchOptions::default()
+        }
+    }
+
+    /// Returns options for resuming a watch from a saved checkpoint.
+    pub fn from_checkpoint(checkpoint: WatchCheckpoint) -> Self {
+        WatchOptions {
+            index: Some(checkpoint.index),
+            ..WatchOptions::default()
+        }
+    }
+
+    /// Only yields changes with one of the given actions. See `WatchOptions::actions`.
+    pub fn actions(mut self, actions: &'static [Action]) -> Self {
+        self.actions = Some(actions);
+        self
+    }
+
+    /// Collapses changes received within the duration of each other. See
+    /// `WatchOptions::coalesce_window`.
+    pub fn coalesce_window(mut self, window: Duration) -> Self {
+        self.coalesce_window = Some(window);
+        self
+    }
+
+    /// Returns the first change at the given index or greater.
+    pub fn index(mut self, index: u64) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Watches all child keys as well.
+    pub fn recursive(mut self) -> Self {
+        self.recursive = true;
+        self
+    }
+
+    /// Times out if no change takes place within the duration.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Options for customizing the behavior of `kv::set_with`.
+///
+/// Unlike `raw::SetOptions`, these options can only describe writes of a key-value pair's value.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SetOptions {
+    /// If given, the write only succeeds if the key does or doesn't already exist.
+    pub prev_exist: Option<bool>,
+    /// If given, the write only succeeds if the key is currently at this modified index.
+    pub prev_index: Option<u64>,
+    /// If given, the write only succeeds if the key currently has this value.
+    pub prev_value: Option<String>,
+    /// If given, the node will expire after this many seconds.
+    pub ttl: Option<u64>,
+}
+
+impl SetOptions {
+    /// Returns options for unconditionally setting a key-value pair without a TTL.
+    pub fn new() -> Self {
+        SetOptions::default()
+    }
+
+    /// Only succeeds if the key does or doesn't already exist.
+    pub fn prev_exist(mut self, prev_exist: bool) -> Self {
+        self.prev_exist = Some(prev_exist);
+        self
+    }
+
+    /// Only succeeds if the key is currently at the given modified index.
+    pub fn prev_index(mut self, prev_index: u64) -> Self {
+        self.prev_index = Some(prev_index);
+        self
+    }
+
+    /// Only succeeds if the key currently has the given value.
+    pub fn prev_value(mut self, prev_value: &str) -> Self {
+        self.prev_value = Some(prev_value.to_owned());
+        self
+    }
+
+    /// Expires the node after the given duration, rounded up to whole seconds.
+    pub fn expire_after(mut self, duration: Duration) -> Self {
+        self.ttl = Some(ttl_secs(duration));
+        self
+    }
+
+    /// Expires the node after the given number of seconds.
+    pub fn ttl(mut self, ttl: u64) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+/// Options for customizing the behavior of `kv::delete_with`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DeleteOptions {
+    /// If true, the node may be an empty directory.
+    pub dir: bool,
+    /// If given, the delete only succeeds if the key is currently at this modified index.
+    pub prev_index: Option<u64>,
+    /// If given, the delete only succeeds if the key currently has this value.
+    pub prev_value: Option<String>,
+    /// If true and the node is a directory, its descendants are deleted as well.
+    pub recursive: bool,
+}
+
+impl DeleteOptions {
+    /// Returns options for unconditionally deleting a key-value pair.
+    pub fn new() -> Self {
+        DeleteOptions::default()
+    }
+
+    /// Allows the node to be an empty directory.
+    pub fn dir(mut self) -> Self {
+        self.dir = true;
+        self
+    }
+
+    /// Only succeeds if the key is currently at the given modified index.
+    pub fn prev_index(mut self, prev_index: u64) -> Self {
+        self.prev_index = Some(prev_index);
+        self
+    }
+
+    /// Only succeeds if the key currently has the given value.
+    pub fn prev_value(mut self, prev_value: &str) -> Self {
+        self.prev_value = Some(prev_value.to_owned());
+        self
+    }
+
+    /// Deletes the directory's descendants as well.
+    pub fn recursive(mut self) -> Self {
+        self.recursive = true;
+        self
+    }
+}
+
+/// An item yielded by the stream returned by `kv::watch_stream`.
+#[derive(Clone, Debug)]
+pub enum WatchItem {
+    /// A change to the watched node.
+    Event(Box<Response<KeyValueInfo>>),
+    /// The duration given in `WatchOptions::timeout` lapsed without any change to the watched
+    /// node.
+    ///
+    /// The stream continues watching from the same index after yielding this item, so it can be
+    /// treated as a heartbeat.
+    Idle,
+}
+
+impl WatchItem {
+    /// Returns the checkpoint to resume watching from after this item, or `None` for
+    /// `WatchItem::Idle`.
+    pub fn checkpoint(&self) -> Option<WatchCheckpoint> {
+        match *self {
+            WatchItem::Event(ref response) => WatchCheckpoint::after(response),
+            WatchItem::Idle => None,
+        }
+    }
+
+    /// Returns the cluster's etcd index from the response's `X-Etcd-Index` header, or `None` for
+    /// `WatchItem::Idle`.
+    ///
+    /// For a watch, this is the cluster's index when the watch request was made, which may be
+    /// earlier than the change's modified index.
+    pub fn etcd_index(&self) -> Option<u64> {
+        match *self {
+            WatchItem::Event(ref response) => response.cluster_info.etcd_index,
+            WatchItem::Idle => None,
+        }
+    }
+}
+
+/// A position in the history of changes to resume watching from, e.g. after a restart.
+///
+/// Checkpoints are serializable, so services can save the one for each change they handle and
+/// pass it to `WatchOptions::from_checkpoint` when they start again. Changes are only kept by
+/// etcd for a limited time, so resuming from an old checkpoint fails as described by `kv::watch`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct WatchCheckpoint {
+    /// The index of the first change to yield when watching resumes.
+    pub index: u64,
+}
+
+impl WatchCheckpoint {
+    /// Returns the checkpoint following a change yielded by a watch, so that the change itself
+    /// isn't yielded again.
+    ///
+    /// The checkpoint follows the node's modified index, or the cluster's etcd index if the
+    /// response has no modified index. Returns `None` if it has neither.
+    pub fn after(response: &Response<KeyValueInfo>) -> Option<Self> {
+        response
+            .data
+            .node
+            .modified_index
+            .or(response.cluster_info.etcd_index)
+            .map(|index| WatchCheckpoint { index: index + 1 })
+    }
+}
+
+/// The result of `kv::get_if_newer`.
+#[derive(Clone, Debug)]
+pub enum GetIfNewer {
+    /// The node has been modified since the known index. Contains its current state.
+    Modified(Response<KeyValueInfo>),
+    /// The node has not been modified since the known index. Contains its current state.
+    NotModified(Response<KeyValueInfo>),
+}
+
+/// The result of `kv::get_or_create`.
+#[derive(Clone, Debug)]
+pub enum GetOrCreate {
+    /// The key did not exist and was created with the given value.
+    Created(Response<KeyValueInfo>),
+    /// The key already existed. Contains its current state.
+    Existing(Response<KeyValueInfo>),
+}
+
+/// A serializable copy of all the nodes under a key, produced by `kv::export`.
+///
+/// The snapshot can be restored with `kv::import`, either under the key it was exported from or
+/// under a different one.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct KeyspaceSnapshot {
+    /// The nodes in the snapshot, with each directory preceding its descendants.
+    pub entries: Vec<SnapshotEntry>,
+    /// The etcd index the snapshot was taken at.
+    pub index: Option<u64>,
+    /// The key the snapshot was exported from.
+    pub prefix: String,
+}
+
+/// A single node in a `KeyspaceSnapshot`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct SnapshotEntry {
+    /// Whether or not the node is a directory.
+    pub dir: bool,
+    /// The absolute key of the node.
+    pub key: String,
+    /// The node's remaining time to live in seconds at the time of the export.
+    pub ttl: Option<u64>,
+    /// The value of the node, if it is a key-value pair.
+    pub value: Option<String>,
+}
+
+/// Options for customizing the behavior of `kv::copy`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CopyOptions {
+    /// If true, existing key-value pairs at the destination are replaced. Otherwise, the copy
+    /// fails when it reaches a key that already exists.
+    pub overwrite: bool,
+    /// If true and the source is a directory, its descendants are copied as well. Otherwise, only
+    /// the directory itself is created.
+    pub recursive: bool,
+}
+
+/// Options for customizing the behavior of `kv::import`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ImportOptions {
+    /// If true, existing key-value pairs are replaced. Otherwise, the import fails when it
+    /// reaches a key that already exists.
+    pub overwrite: bool,
+    /// If given, the nodes are imported under this key instead of the snapshot's prefix.
+    pub prefix: Option<String>,
+}
+
+/// Options for customizing the behavior of `kv::multi_set`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SetBatchOptions {
+    /// If true and any of the writes fails, the writes that succeeded are undone.
+    pub rollback: bool,
+    /// If given, the nodes will expire after this many seconds.
+    pub ttl: Option<u64>,
+}
+
+/// The error returned by `kv::multi_set` when any of the writes fails.
+#[derive(Debug)]
+pub struct SetBatchFailure {
+    /// The result of each write, in the same order as the pairs given to `kv::multi_set`.
+    pub results: Vec<Result<Response<KeyValueInfo>, Vec<Error>>>,
+    /// The keys whose successful writes could not be undone, with the errors from the attempt.
+    ///
+    /// This is always empty if `SetBatchOptions::rollback` was false.
+    pub rollback_errors: Vec<(String, Vec<Error>)>,
+}
+
 /// Deletes a node only if the given current value and/or current modified index match.
 ///
 /// # Parameters
@@ -149,7 +673,7 @@ where
     raw_delete(
         client,
         key,
-        DeleteOptions {
+        InternalDeleteOptions {
             conditions: Some(ComparisonConditions {
                 value: current_value,
                 modified_index: current_modified_index,
@@ -190,7 +714,7 @@ where
     raw_set(
         client,
         key,
-        SetOptions {
+        InternalSetOptions {
             conditions: Some(ComparisonConditions {
                 value: current_value,
                 modified_index: current_modified_index,
@@ -202,6 +726,63 @@ where
     )
 }
 
+/// Copies a node to another key.
+///
+/// The source is read in a single, strongly consistent request and written to the destination
+/// one node at a time, as with `kv::import`, so the copy is not atomic. TTLs are copied with the
+/// time remaining when the source was read. Hidden nodes are not copied.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * from: The name of the node to copy.
+/// * to: The name of the node to create.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if the source doesn't exist, if a key at the destination already exists and
+/// `CopyOptions::overwrite` is false, or if any write fails. Succeeds with the number of
+/// key-value pairs written.
+pub fn copy<C>(
+    client: &Client<C>,
+    from: &str,
+    to: &str,
+    options: CopyOptions,
+) -> impl Future<Item = usize, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let to = to.to_string();
+
+    get(
+        &client,
+        from,
+        GetOptions {
+            recursive: options.recursive,
+            strong_consistency: true,
+            ..Default::default()
+        },
+    )
+    .and_then(move |response| {
+        let mut node = response.data.node;
+
+        if !options.recursive {
+            node.nodes = None;
+        }
+
+        import(
+            &client,
+            snapshot_of(&node),
+            ImportOptions {
+                overwrite: options.overwrite,
+                prefix: Some(to),
+            },
+        )
+    })
+}
+
 /// Creates a new key-value pair.
 ///
 /// # Parameters
@@ -226,7 +807,7 @@ where
     raw_set(
         client,
         key,
-        SetOptions {
+        InternalSetOptions {
             prev_exist: Some(false),
             ttl: ttl,
             value: Some(value),
@@ -257,7 +838,7 @@ where
     raw_set(
         client,
         key,
-        SetOptions {
+        InternalSetOptions {
             dir: Some(true),
             prev_exist: Some(false),
             ttl: ttl,
@@ -297,7 +878,7 @@ where
     raw_set(
         client,
         key,
-        SetOptions {
+        InternalSetOptions {
             create_in_order: true,
             ttl: ttl,
             value: Some(value),
@@ -329,7 +910,7 @@ where
     raw_delete(
         client,
         key,
-        DeleteOptions {
+        InternalDeleteOptions {
             recursive: Some(recursive),
             ..Default::default()
         },
@@ -356,144 +937,894 @@ where
     raw_delete(
         client,
         key,
-        DeleteOptions {
+        InternalDeleteOptions {
             dir: Some(true),
             ..Default::default()
         },
     )
 }
 
-/// Gets the value of a node.
+/// Deletes a node with the given options.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
-/// * key: The name of the node to retrieve.
+/// * key: The name of the node to delete.
 /// * options: Options to customize the behavior of the operation.
 ///
 /// # Errors
 ///
-/// Fails if the key doesn't exist.
-pub fn get<C>(
+/// Fails if the key is a directory and neither `options.dir` nor `options.recursive` is set, if a
+/// non-empty directory is deleted without `options.recursive`, or if the conditions in
+/// `options.prev_index` or `options.prev_value` don't match.
+pub fn delete_with<C>(
     client: &Client<C>,
     key: &str,
-    options: GetOptions,
+    options: DeleteOptions,
 ) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
-    raw_get(
+    raw_delete(
         client,
         key,
-        InternalGetOptions {
-            recursive: options.recursive,
-            sort: Some(options.sort),
-            strong_consistency: options.strong_consistency,
-            ..Default::default()
+        InternalDeleteOptions {
+            conditions: conditions(options.prev_index, options.prev_value.as_deref()),
+            dir: if options.dir { Some(true) } else { None },
+            recursive: Some(options.recursive),
         },
     )
 }
 
-/// Sets the value of a key-value pair.
-///
-/// Any previous value and TTL will be replaced.
+/// Checks whether or not a node exists.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
-/// * key: The name of the key-value pair to set.
-/// * value: The new value for the key-value pair.
-/// * ttl: If given, the node will expire after this many seconds.
+/// * key: The name of the node to check.
 ///
 /// # Errors
 ///
-/// Fails if the node is a directory.
-pub fn set<C>(
+/// Fails if the existence of the node could not be determined, e.g. because no cluster member
+/// could be reached.
+pub fn exists<C>(
     client: &Client<C>,
     key: &str,
-    value: &str,
-    ttl: Option<u64>,
-) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+) -> impl Future<Item = bool, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
-    raw_set(
-        client,
-        key,
-        SetOptions {
-            ttl: ttl,
-            value: Some(value),
-            ..Default::default()
-        },
-    )
+    get(client, key, GetOptions::default()).then(|result| match result {
+        Ok(_) => Ok(true),
+        Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(false),
+        Err(errors) => Err(errors),
+    })
 }
 
-/// Sets the key to an empty directory.
+/// Exports a node and all of its descendants.
 ///
-/// An existing key-value pair will be replaced, but an existing directory will not.
+/// The nodes are read in a single, strongly consistent request. Hidden nodes, whose names begin
+/// with an underscore, are not returned by etcd and are therefore not included.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
-/// * key: The name of the directory to set.
-/// * ttl: If given, the node will expire after this many seconds.
+/// * prefix: The name of the node to export.
 ///
 /// # Errors
 ///
-/// Fails if the node is an existing directory.
-pub fn set_dir<C>(
+/// Fails if the node doesn't exist.
+pub fn export<C>(
     client: &Client<C>,
-    key: &str,
-    ttl: Option<u64>,
-) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+    prefix: &str,
+) -> impl Future<Item = KeyspaceSnapshot, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
-    raw_set(
+    let prefix = normalize_prefix(prefix);
+
+    get(
         client,
-        key,
-        SetOptions {
-            dir: Some(true),
-            ttl: ttl,
+        if prefix.is_empty() { "/" } else { &prefix },
+        GetOptions {
+            recursive: true,
+            sort: true,
+            strong_consistency: true,
             ..Default::default()
         },
     )
+    .map(move |response| {
+        let mut entries = vec![];
+        let node = response.data.node;
+
+        match node.nodes {
+            Some(ref nodes) => collect_entries(nodes, &mut entries),
+            None if node.dir != Some(true) => collect_entries(&[node], &mut entries),
+            None => {}
+        }
+
+        KeyspaceSnapshot {
+            entries,
+            index: response.cluster_info.etcd_index,
+            prefix,
+        }
+    })
 }
 
-/// Updates an existing key-value pair.
+/// Gets the value of a node.
+///
+/// Hidden children of a directory are only included if they are named in
+/// `GetOptions::include_hidden`. If `GetOptions::sort` is set, they are sorted along with the other
+/// children. Each hidden child is read with a separate request, so it may be from a later index
+/// than the response's `etcd_index`. A key-value pair is returned without children.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
-/// * key: The name of the key-value pair to update.
-/// * value: The new value for the key-value pair.
-/// * ttl: If given, the node will expire after this many seconds.
+/// * key: The name of the node to retrieve.
+/// * options: Options to customize the behavior of the operation.
 ///
 /// # Errors
 ///
-/// Fails if the key does not exist.
-pub fn update<C>(
+/// Fails if the key doesn't exist.
+pub fn get<C>(
     client: &Client<C>,
     key: &str,
-    value: &str,
-    ttl: Option<u64>,
+    options: GetOptions,
 ) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
-    raw_set(
-        client,
-        key,
-        SetOptions {
-            prev_exist: Some(true),
-            ttl: ttl,
-            value: Some(value),
-            ..Default::default()
-        },
-    )
-}
+    let internal_options = InternalGetOptions {
+        recursive: options.recursive,
+        sort: Some(options.sort),
+        strong_consistency: options.strong_consistency,
+        ..Default::default()
+    };
 
-/// Updates a directory.
+    let response = raw_get(client, key, internal_options);
+
+    if options.include_hidden.is_empty() {
+        return Either::A(response);
+    }
+
+    let hidden: Vec<_> = options
+        .include_hidden
+        .iter()
+        .map(|name| {
+            let child = format!("{}/{}", key.trim_end_matches('/'), name);
+
+            raw_get(client, &child, internal_options).then(|result| match result {
+                Ok(response) => Ok(Some(response.data.node)),
+                Err(ref errors)
+                    if has_error_code(errors, KEY_NOT_FOUND)
+                        || has_error_code(errors, NOT_A_DIRECTORY) =>
+                {
+                    Ok(None)
+                }
+                Err(errors) => Err(errors),
+            })
+        })
+        .collect();
+
+    let sort = options.sort;
+
+    Either::B(
+        response
+            .join(join_all(hidden))
+            .map(move |(mut response, hidden)| {
+                let mut hidden = hidden.into_iter().flatten().peekable();
+
+                if hidden.peek().is_none() {
+                    return response;
+                }
+
+                let nodes = response.data.node.nodes.get_or_insert_with(Vec::new);
+
+                nodes.extend(hidden);
+
+                if sort {
+                    nodes.sort_by(|a, b| a.key.cmp(&b.key));
+                }
+
+                response
+            }),
+    )
+}
+
+/// Gets the value of a key-value pair as bytes.
+///
+/// The value is decoded with the client's `ValueCodec`, so it should have been stored with
+/// `kv::set_bytes` by a client using the same codec.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to retrieve.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if the key doesn't exist, if it is a directory, or if its value can't be decoded.
+pub fn get_bytes<C>(
+    client: &Client<C>,
+    key: &str,
+    options: GetOptions,
+) -> impl Future<Item = Response<Vec<u8>>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+
+    get(&client, key, options).and_then(move |response| {
+        let data = match response.data.node.value {
+            Some(ref value) => client.codec().decode(value),
+            None => Err(Error::Codec("the node is a directory".to_owned())),
+        };
+
+        match data {
+            Ok(data) => Ok(Response {
+                cluster_info: response.cluster_info,
+                data,
+            }),
+            Err(error) => Err(vec![error]),
+        }
+    })
+}
+
+/// Gets the value of a node if it has been modified since a known index.
+///
+/// etcd's v2 API has no conditional reads, so the node is always fetched. This only tells the
+/// caller whether it has already seen the value. A directory's modified index doesn't
+/// change when its children do.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the node to retrieve.
+/// * known_modified_index: The modified index of the node when it was last seen.
+///
+/// # Errors
+///
+/// Fails if the key doesn't exist.
+pub fn get_if_newer<C>(
+    client: &Client<C>,
+    key: &str,
+    known_modified_index: u64,
+) -> impl Future<Item = GetIfNewer, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    get(client, key, GetOptions::default()).map(move |response| {
+        match response.data.node.modified_index {
+            Some(index) if index <= known_modified_index => GetIfNewer::NotModified(response),
+            _ => GetIfNewer::Modified(response),
+        }
+    })
+}
+
+/// Creates a new key-value pair, or gets the existing node if the key already exists.
+///
+/// Creation is atomic, but the fallback retrieval is a separate request, so the node may have
+/// changed or been deleted in the meantime.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to create or retrieve.
+/// * default_value: The value for the node if it is created.
+/// * ttl: If given and the node is created, it will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the key already exists but was deleted before it could be retrieved.
+pub fn get_or_create<C>(
+    client: &Client<C>,
+    key: &str,
+    default_value: &str,
+    ttl: Option<u64>,
+) -> impl Future<Item = GetOrCreate, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let key = key.to_string();
+
+    create(&client, &key, default_value, ttl).then(move |result| match result {
+        Ok(response) => Either::A(Ok(GetOrCreate::Created(response)).into_future()),
+        Err(ref errors) if has_error_code(errors, NODE_EXIST) => {
+            Either::B(get(&client, &key, GetOptions::default()).map(GetOrCreate::Existing))
+        }
+        Err(errors) => Either::A(Err(errors).into_future()),
+    })
+}
+
+/// Restores the nodes in a snapshot produced by `kv::export`.
+///
+/// The nodes are written one at a time, so the import is not atomic: if it fails partway
+/// through, the nodes written so far are left in place. Directories that already exist are
+/// reused. TTLs are restored with the remaining time recorded in the snapshot. Entries whose keys
+/// are not under the snapshot's prefix are written to their original keys.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * snapshot: The snapshot to restore.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if a key in the snapshot already exists and `ImportOptions::overwrite` is false, or if
+/// any write fails. Succeeds with the number of key-value pairs written.
+pub fn import<C>(
+    client: &Client<C>,
+    snapshot: KeyspaceSnapshot,
+    options: ImportOptions,
+) -> impl Future<Item = usize, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let source = normalize_prefix(&snapshot.prefix);
+    let target = options
+        .prefix
+        .as_ref()
+        .map(|prefix| normalize_prefix(prefix))
+        .unwrap_or_else(|| source.clone());
+    let overwrite = options.overwrite;
+
+    futures::stream::iter_ok(snapshot.entries).fold(0, move |written, entry| {
+        let key = rebase_key(&entry.key, &source, &target).unwrap_or(entry.key);
+
+        let result: Box<dyn Future<Item = usize, Error = Vec<Error>> + Send> = if entry.dir {
+            Box::new(
+                create_dir(&client, &key, entry.ttl).then(move |result| match result {
+                    Ok(_) => Ok(written),
+                    Err(ref errors) if has_error_code(errors, NODE_EXIST) => Ok(written),
+                    Err(errors) => Err(errors),
+                }),
+            )
+        } else {
+            let value = entry.value.unwrap_or_default();
+
+            if overwrite {
+                Box::new(set(&client, &key, &value, entry.ttl).map(move |_| written + 1))
+            } else {
+                Box::new(create(&client, &key, &value, entry.ttl).map(move |_| written + 1))
+            }
+        };
+
+        result
+    })
+}
+
+/// Walks the descendants of a directory, yielding them in pages.
+///
+/// etcd's v2 API can't paginate a listing, so rather than reading the whole tree with one
+/// recursive request, each directory is listed with its own non-recursive request as the walk
+/// reaches it. That request still returns every child of the directory, with their values, no
+/// matter the page size, and the walk holds on to the children it hasn't yielded yet of each
+/// directory between the top one and the current node. Memory use therefore grows with the size
+/// of the directories on the current path rather than with the whole tree, and a single very
+/// large directory is read all at once. Nodes are yielded depth-first in alphabetical order, with
+/// each directory, without its children, preceding its descendants. Directories deleted during
+/// the walk are skipped.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * dir: The name of the directory to walk.
+/// * page_size: The most nodes to yield in each page.
+///
+/// # Errors
+///
+/// The stream ends with an error if the directory doesn't exist or if any listing fails.
+pub fn iter_dir<C>(
+    client: &Client<C>,
+    dir: &str,
+    page_size: usize,
+) -> impl Stream<Item = Vec<Node>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let page_size = page_size.max(1);
+
+    list_dir(&client, dir)
+        .map(move |children| {
+            let stack: Vec<Node> = children.into_iter().rev().collect();
+
+            stream::unfold(stack, move |stack| {
+                if stack.is_empty() {
+                    return None;
+                }
+
+                let client = client.clone();
+
+                Some(loop_fn((stack, vec![]), move |(mut stack, mut page)| {
+                    let node: Node = match stack.pop() {
+                        Some(node) => node,
+                        None => return Either::A(Ok(Loop::Break((page, stack))).into_future()),
+                    };
+
+                    let children = if node.dir == Some(true) {
+                        let key = node.key.clone().unwrap_or_default();
+
+                        Either::A(list_dir(&client, &key).then(|result| match result {
+                            Ok(children) => Ok(children),
+                            Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(vec![]),
+                            Err(errors) => Err(errors),
+                        }))
+                    } else {
+                        Either::B(Ok(vec![]).into_future())
+                    };
+
+                    Either::B(children.map(move |children| {
+                        stack.extend(children.into_iter().rev());
+                        page.push(node);
+
+                        if page.len() >= page_size {
+                            Loop::Break((page, stack))
+                        } else {
+                            Loop::Continue((stack, page))
+                        }
+                    }))
+                }))
+            })
+        })
+        .flatten_stream()
+}
+
+/// Gets the values of several nodes.
+///
+/// The requests are made concurrently, with a bounded number in flight at once. A failure to get
+/// one node doesn't affect the others.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * keys: The names of the nodes to retrieve.
+/// * options: Options to customize the behavior of each retrieval.
+///
+/// # Errors
+///
+/// Never fails. Succeeds with the result of each retrieval, in the same order as `keys`.
+pub fn multi_get<C>(
+    client: &Client<C>,
+    keys: &[&str],
+    options: GetOptions,
+) -> impl Future<Item = Vec<Result<Response<KeyValueInfo>, Vec<Error>>>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let requests: Vec<_> = keys
+        .iter()
+        .map(|key| get(client, key, options).then(Ok::<_, Vec<Error>>))
+        .collect();
+
+    futures::stream::iter_ok(requests)
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+}
+
+/// Sets the values of several key-value pairs.
+///
+/// The writes are made concurrently, with a bounded number in flight at once, and are not
+/// atomic. If `SetBatchOptions::rollback` is true and any write fails, each successful write is
+/// undone by restoring the key's previous value and TTL, or deleting it if it didn't exist. A key
+/// is only restored if it hasn't been changed again since it was written, so rollback is best
+/// effort.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * pairs: The names and new values of the key-value pairs to set.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if any of the writes fails. The error contains the result of every write and any
+/// failures to undo them.
+pub fn multi_set<C>(
+    client: &Client<C>,
+    pairs: &[(&str, &str)],
+    options: SetBatchOptions,
+) -> impl Future<Item = Vec<Response<KeyValueInfo>>, Error = SetBatchFailure> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let requests: Vec<_> = pairs
+        .iter()
+        .map(|&(key, value)| set(&client, key, value, options.ttl).then(Ok::<_, SetBatchFailure>))
+        .collect();
+
+    futures::stream::iter_ok(requests)
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .and_then(move |results| {
+            if results.iter().all(Result::is_ok) {
+                return Either::A(
+                    Ok(results.into_iter().filter_map(Result::ok).collect()).into_future(),
+                );
+            }
+
+            if !options.rollback {
+                return Either::A(
+                    Err(SetBatchFailure {
+                        results,
+                        rollback_errors: vec![],
+                    })
+                    .into_future(),
+                );
+            }
+
+            let restores: Vec<_> = results
+                .iter()
+                .filter_map(|result| result.as_ref().ok())
+                .map(|response| restore(&client, &response.data))
+                .collect();
+
+            Either::B(
+                futures::stream::iter_ok(restores)
+                    .buffered(BATCH_CONCURRENCY)
+                    .filter_map(|failure| failure)
+                    .collect()
+                    .then(move |rollback_errors| {
+                        Err(SetBatchFailure {
+                            results,
+                            rollback_errors: rollback_errors.unwrap_or_default(),
+                        })
+                    }),
+            )
+        })
+}
+
+/// Resets the TTL of an existing key-value pair without changing its value.
+///
+/// Unlike `kv::update`, this does not notify watchers of the key, which makes it suitable for
+/// heartbeats.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to refresh.
+/// * ttl: The node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the key does not exist.
+pub fn refresh<C>(
+    client: &Client<C>,
+    key: &str,
+    ttl: u64,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_set(
+        client,
+        key,
+        InternalSetOptions {
+            prev_exist: Some(true),
+            refresh: true,
+            ttl: Some(ttl),
+            ..Default::default()
+        },
+    )
+}
+
+/// Resets the TTL of an existing key-value pair to the given duration without changing its value.
+///
+/// This is the same as `kv::refresh`, with the duration rounded up to whole seconds.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to refresh.
+/// * ttl: The node will expire after this duration.
+///
+/// # Errors
+///
+/// Fails if the key does not exist.
+pub fn refresh_for<C>(
+    client: &Client<C>,
+    key: &str,
+    ttl: Duration,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    refresh(client, key, ttl_secs(ttl))
+}
+
+/// Moves a node and all of its descendants to another key.
+///
+/// The source is copied as with `kv::copy`, without replacing any existing keys, and then
+/// deleted. Each key-value pair is deleted with a compare-and-delete at the modified index it was
+/// copied at, and each directory only once it's empty, so a concurrent write to the source makes
+/// the rename fail rather than being lost. The copy is left in place if that happens. Hidden nodes
+/// are not moved, so a directory containing any can't be renamed.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * from: The name of the node to move.
+/// * to: The name of the node to create.
+///
+/// # Errors
+///
+/// Fails if the source doesn't exist, if a key at the destination already exists, or if any write
+/// or deletion fails. Succeeds with the number of key-value pairs moved.
+pub fn rename<C>(
+    client: &Client<C>,
+    from: &str,
+    to: &str,
+) -> impl Future<Item = usize, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let to = to.to_string();
+
+    get(
+        &client,
+        from,
+        GetOptions {
+            recursive: true,
+            strong_consistency: true,
+            ..Default::default()
+        },
+    )
+    .and_then(move |response| {
+        let node = response.data.node;
+        let options = ImportOptions {
+            overwrite: false,
+            prefix: Some(to),
+        };
+
+        import(&client, snapshot_of(&node), options).and_then(move |written| {
+            let mut nodes = vec![];
+
+            flatten_nodes(node, &mut nodes);
+
+            futures::stream::iter_ok(nodes.into_iter().rev())
+                .for_each(move |node| {
+                    let key = node.key.unwrap_or_default();
+
+                    if node.dir == Some(true) {
+                        Either::A(delete_dir(&client, &key).map(drop))
+                    } else {
+                        Either::B(
+                            compare_and_delete(&client, &key, None, node.modified_index).map(drop),
+                        )
+                    }
+                })
+                .map(move |_| written)
+        })
+    })
+}
+
+/// Sets the value of a key-value pair.
+///
+/// Any previous value and TTL will be replaced.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to set.
+/// * value: The new value for the key-value pair.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node is a directory.
+pub fn set<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &str,
+    ttl: Option<u64>,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_set(
+        client,
+        key,
+        InternalSetOptions {
+            ttl: ttl,
+            value: Some(value),
+            ..Default::default()
+        },
+    )
+}
+
+/// Sets the value of a key-value pair to the given bytes.
+///
+/// The bytes are encoded with the client's `ValueCodec`. Any previous value and TTL will be
+/// replaced.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to set.
+/// * value: The new value for the key-value pair.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node is a directory or if the bytes can't be encoded.
+pub fn set_bytes<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &[u8],
+    ttl: Option<u64>,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    match client.codec().encode(value) {
+        Ok(value) => Either::A(set(client, key, &value, ttl)),
+        Err(error) => Either::B(Err(vec![error]).into_future()),
+    }
+}
+
+/// Sets the key to an empty directory.
+///
+/// An existing key-value pair will be replaced, but an existing directory will not.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the directory to set.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node is an existing directory.
+pub fn set_dir<C>(
+    client: &Client<C>,
+    key: &str,
+    ttl: Option<u64>,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_set(
+        client,
+        key,
+        InternalSetOptions {
+            dir: Some(true),
+            ttl: ttl,
+            ..Default::default()
+        },
+    )
+}
+
+/// Sets the value of a key-value pair only if it differs from the current value.
+///
+/// The current value is read and, if it differs, the new value is written with a compare-and-swap
+/// on the modified index that was read, or created if the key doesn't exist. Skipping identical
+/// writes keeps watchers from being woken by values that didn't change. A TTL is only applied
+/// when a write is made, so an unchanged key's TTL is not extended; use `kv::refresh` for that.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the key-value pair to set.
+/// * value: The new value for the key-value pair.
+/// * ttl: If given and the value is written, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the key is a directory or is changed by another client between the read and the
+/// write. Succeeds with whether or not a write was made.
+pub fn set_if_changed<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &str,
+    ttl: Option<u64>,
+) -> impl Future<Item = bool, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let key = key.to_string();
+    let value = value.to_string();
+
+    let current = get(&client, &key, GetOptions::default()).then(|result| match result {
+        Ok(response) => Ok(Some(response.data.node)),
+        Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(None),
+        Err(errors) => Err(errors),
+    });
+
+    current.and_then(move |node| {
+        let write: Box<dyn Future<Item = bool, Error = Vec<Error>> + Send> = match node {
+            Some(ref node) if node.value.as_ref() == Some(&value) => {
+                Box::new(Ok(false).into_future())
+            }
+            Some(node) => Box::new(
+                compare_and_swap(&client, &key, &value, ttl, None, node.modified_index)
+                    .map(|_| true),
+            ),
+            None => Box::new(create(&client, &key, &value, ttl).map(|_| true)),
+        };
+
+        write
+    })
+}
+
+/// Sets the value of a key-value pair with the given options.
+///
+/// Any previous value and TTL will be replaced.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to set.
+/// * value: The new value for the key-value pair.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if the node is a directory, or if the conditions in `options.prev_exist`,
+/// `options.prev_index`, or `options.prev_value` don't match.
+pub fn set_with<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &str,
+    options: SetOptions,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_set(
+        client,
+        key,
+        InternalSetOptions {
+            conditions: conditions(options.prev_index, options.prev_value.as_deref()),
+            prev_exist: options.prev_exist,
+            ttl: options.ttl,
+            value: Some(value),
+            ..Default::default()
+        },
+    )
+}
+
+/// Updates an existing key-value pair.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to update.
+/// * value: The new value for the key-value pair.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the key does not exist.
+pub fn update<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &str,
+    ttl: Option<u64>,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_set(
+        client,
+        key,
+        InternalSetOptions {
+            prev_exist: Some(true),
+            ttl: ttl,
+            value: Some(value),
+            ..Default::default()
+        },
+    )
+}
+
+/// Updates a directory.
 ///
 /// If the directory already existed, only the TTL is updated. If the key was a key-value pair, its
 /// value is removed and its TTL is updated.
@@ -518,7 +1849,7 @@ where
     raw_set(
         client,
         key,
-        SetOptions {
+        InternalSetOptions {
             dir: Some(true),
             prev_exist: Some(true),
             ttl: ttl,
@@ -527,6 +1858,85 @@ where
     )
 }
 
+/// Updates a key-value pair by applying a function to its current value.
+///
+/// The current value is read, passed to `f`, and the result is written back with a
+/// compare-and-swap on the modified index that was read. If another client changed the key in
+/// the meantime, the whole cycle is retried with the new value.
+///
+/// `f` receives `None` if the key doesn't exist. If `f` returns `None`, the key is deleted if it
+/// exists. Any TTL on the key is removed, as with `kv::set`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the key-value pair to update.
+/// * max_attempts: The maximum number of read-modify-write cycles to attempt.
+/// * f: A function computing the new value for the key from its current value.
+///
+/// # Errors
+///
+/// Fails if the key is still being changed concurrently after `max_attempts` cycles, or if any
+/// request fails for another reason. Succeeds with `None` if nothing needed to be written.
+///
+/// # Panics
+///
+/// Panics if `max_attempts` is 0.
+pub fn update_fn<C, F>(
+    client: &Client<C>,
+    key: &str,
+    max_attempts: usize,
+    f: F,
+) -> impl Future<Item = Option<Response<KeyValueInfo>>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    F: Fn(Option<String>) -> Option<String> + Send + Sync + 'static,
+{
+    assert!(max_attempts > 0, "an update must make at least 1 attempt");
+
+    let client = client.clone();
+    let key = key.to_string();
+    let f = Arc::new(f);
+
+    loop_fn(1, move |attempt| {
+        let client = client.clone();
+        let key = key.clone();
+        let f = f.clone();
+
+        let current = get(&client, &key, GetOptions::default()).then(|result| match result {
+            Ok(response) => Ok(Some(response.data.node)),
+            Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(None),
+            Err(errors) => Err(errors),
+        });
+
+        let write = current.and_then(move |node| {
+            let modified_index = node.as_ref().and_then(|node| node.modified_index);
+            let value = node.and_then(|node| node.value);
+
+            match (modified_index, f(value)) {
+                (Some(index), Some(value)) => Either::A(Either::A(
+                    compare_and_swap(&client, &key, &value, None, None, Some(index)).map(Some),
+                )),
+                (None, Some(value)) => {
+                    Either::A(Either::B(create(&client, &key, &value, None).map(Some)))
+                }
+                (Some(index), None) => Either::B(Either::A(
+                    compare_and_delete(&client, &key, None, Some(index)).map(Some),
+                )),
+                (None, None) => Either::B(Either::B(Ok(None).into_future())),
+            }
+        });
+
+        write.then(move |result| match result {
+            Ok(response) => Ok(Loop::Break(response)),
+            Err(ref errors) if attempt < max_attempts && is_conflict(errors) => {
+                Ok(Loop::Continue(attempt + 1))
+            }
+            Err(errors) => Err(errors),
+        })
+    })
+}
+
 /// Watches a node for changes and returns the new value as soon as a change takes place.
 ///
 /// # Parameters
@@ -537,18 +1947,141 @@ where
 ///
 /// # Errors
 ///
-/// Fails if `options.index` is too old and has been flushed out of etcd's internal store of the
-/// most recent change events. In this case, the key should be queried for its latest
-/// "modified index" value and that should be used as the new `options.index` on a subsequent
-/// `watch`.
+/// Fails with `Error::IndexCleared` if `options.index` is too old and has been flushed out of
+/// etcd's internal store of the most recent change events. In this case, the key should be
+/// queried again, and the index after the error's `current_index` used as the new
+/// `options.index` on a subsequent `watch`.
 ///
-/// Fails if a timeout is specified and the duration lapses without a response from the etcd
-/// cluster.
+/// Fails with `Error::Timeout` if a timeout is specified and the duration lapses without a
+/// response from the etcd cluster.
 pub fn watch<C>(
     client: &Client<C>,
     key: &str,
     options: WatchOptions,
-) -> Box<dyn Future<Item = Response<KeyValueInfo>, Error = WatchError> + Send>
+) -> Box<dyn Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send>
+where
+    C: Clone + Connect,
+{
+    Box::new(
+        watch_once(client, key, options)
+            .map_err(|errors| errors.unwrap_or_else(|| vec![Error::Timeout])),
+    )
+}
+
+/// Watches a node for changes continuously, yielding each change as it takes place.
+///
+/// After each change, the next watch resumes from the index following the change's modified
+/// index, so no changes are missed between watches. If `options.timeout` is given but
+/// `options.index` isn't, the cluster's current index is read first and watching starts after it,
+/// so that changes made while a timed out watch is reissued aren't missed either.
+///
+/// To resume watching after a restart, save the `WatchItem::checkpoint` of each change once it has
+/// been handled, and start the next watch with `WatchOptions::from_checkpoint`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the node to watch.
+/// * options: Options to customize the behavior of the operation. If `options.timeout` is given,
+///   `WatchItem::Idle` is yielded each time the duration lapses without a change, and watching
+///   continues.
+///
+/// # Errors
+///
+/// The stream ends with an error under the same conditions as `kv::watch`, except for timeouts.
+pub fn watch_stream<C>(
+    client: &Client<C>,
+    key: &str,
+    options: WatchOptions,
+) -> impl Stream<Item = WatchItem, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let seed: Option<SeedFuture> = match (options.index, options.timeout) {
+        (None, Some(_)) => Some(Box::new(current_index(client, key))),
+        _ => None,
+    };
+
+    WatchStream {
+        client: client.clone(),
+        current: None,
+        done: false,
+        failure: None,
+        key: key.to_string(),
+        options,
+        pending: None,
+        seed,
+    }
+}
+
+/// Watches several nodes for changes concurrently, yielding each change along with the key it was
+/// watched by.
+///
+/// Each key is watched as by `kv::watch_stream`, which keeps track of the index to resume from for
+/// that key. Changes are yielded in the order they are received, so changes to different keys may
+/// not be in index order.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * keys: The names of the nodes to watch.
+/// * options: Options to customize the behavior of the operation, which apply to each key. If
+///   `options.timeout` is given, each watch is reissued when the duration lapses without a change,
+///   but nothing is yielded.
+///
+/// # Errors
+///
+/// The stream yields an error under the same conditions as `kv::watch_stream`, after which the
+/// failed key is no longer watched.
+pub fn watch_many<C>(
+    client: &Client<C>,
+    keys: &[&str],
+    options: WatchOptions,
+) -> impl Stream<Item = (String, Response<KeyValueInfo>), Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let mut merged: Box<
+        dyn Stream<Item = (String, Response<KeyValueInfo>), Error = Vec<Error>> + Send,
+    > = Box::new(stream::empty());
+
+    for key in keys {
+        let watched_key = key.to_string();
+
+        let changes = watch_stream(client, key, options).filter_map(move |item| match item {
+            WatchItem::Event(response) => Some((watched_key.clone(), *response)),
+            WatchItem::Idle => None,
+        });
+
+        merged = Box::new(merged.select(changes));
+    }
+
+    merged
+}
+
+/// Returns the cluster's etcd index, from the response to a request for the node, for
+/// `kv::watch_stream` to start watching after.
+fn current_index<C>(
+    client: &Client<C>,
+    key: &str,
+) -> impl Future<Item = Option<u64>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_get(client, key, InternalGetOptions::default()).then(|result| match result {
+        Ok(response) => Ok(response.cluster_info.etcd_index),
+        Err(errors) => match errors[..] {
+            [Error::Api(ref error)] if error.error_code == KEY_NOT_FOUND => Ok(Some(error.index)),
+            _ => Err(errors),
+        },
+    })
+}
+
+/// Makes a single watch request for `kv::watch` and `kv::watch_stream`.
+///
+/// Fails with `None` if `options.timeout` lapses, so that it can be told apart from requests to
+/// cluster members timing out.
+fn watch_once<C>(client: &Client<C>, key: &str, options: WatchOptions) -> WatchFuture
 where
     C: Clone + Connect,
 {
@@ -562,30 +2095,193 @@ where
             ..Default::default()
         },
     )
-    .map_err(|errors| WatchError::Other(errors));
+    .map_err(Some);
 
     if let Some(duration) = options.timeout {
-        Box::new(
-            Timeout::new(work, duration).map_err(|e| match e.into_inner() {
-                Some(we) => we,
-                None => WatchError::Timeout,
-            }),
-        )
+        Box::new(Timeout::new(work, duration).map_err(|error| error.into_inner().flatten()))
     } else {
         Box::new(work)
     }
 }
 
+/// Builds the comparison conditions for `kv::set_with` and `kv::delete_with`, if any are given.
+fn conditions(
+    modified_index: Option<u64>,
+    value: Option<&str>,
+) -> Option<ComparisonConditions<'_>> {
+    let conditions = ComparisonConditions {
+        modified_index,
+        value,
+    };
+
+    if conditions.is_empty() {
+        None
+    } else {
+        Some(conditions)
+    }
+}
+
+/// Converts a duration to a TTL in whole seconds, rounding up so that a node never expires sooner
+/// than requested.
+fn ttl_secs(duration: Duration) -> u64 {
+    if duration.subsec_nanos() > 0 {
+        duration.as_secs() + 1
+    } else {
+        duration.as_secs()
+    }
+}
+
 /// Constructs the full URL for an API call.
 fn build_url(endpoint: &Uri, path: &str) -> String {
-    format!("{}v2/keys{}", endpoint, path)
+    endpoint_url(endpoint, &format!("v2/keys{}", encode_key(path)))
+}
+
+/// Percent-encodes each segment of a key for use in a URL path, keeping the slashes between them.
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT_ENCODE_SET).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Appends the given nodes and their descendants to a snapshot, directories first.
+fn collect_entries(nodes: &[Node], entries: &mut Vec<SnapshotEntry>) {
+    for node in nodes {
+        let key = match node.key {
+            Some(ref key) => key.clone(),
+            None => continue,
+        };
+        let dir = node.dir == Some(true);
+
+        entries.push(SnapshotEntry {
+            dir,
+            key,
+            ttl: node.ttl.filter(|&ttl| ttl > 0).map(|ttl| ttl as u64),
+            value: if dir { None } else { node.value.clone() },
+        });
+
+        if let Some(ref children) = node.nodes {
+            collect_entries(children, entries);
+        }
+    }
+}
+
+/// Lists every child of a directory, without their descendants, in one request for
+/// `kv::iter_dir`.
+fn list_dir<C>(
+    client: &Client<C>,
+    dir: &str,
+) -> impl Future<Item = Vec<Node>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let options = GetOptions {
+        sort: true,
+        ..Default::default()
+    };
+
+    get(client, dir, options).map(|response| response.data.node.nodes.unwrap_or_default())
+}
+
+/// Flattens a tree of nodes, with each directory preceding its descendants.
+fn flatten_nodes(mut node: Node, nodes: &mut Vec<Node>) {
+    let children = node.nodes.take();
+
+    nodes.push(node);
+
+    for child in children.into_iter().flatten() {
+        flatten_nodes(child, nodes);
+    }
+}
+
+/// Builds a snapshot of a node and its descendants for `kv::copy` and `kv::rename`.
+fn snapshot_of(node: &Node) -> KeyspaceSnapshot {
+    let mut entries = vec![];
+
+    collect_entries(slice::from_ref(node), &mut entries);
+
+    KeyspaceSnapshot {
+        entries,
+        index: None,
+        prefix: node.key.clone().unwrap_or_default(),
+    }
+}
+
+/// Undoes a write made by `kv::multi_set`, returning the key and errors if it couldn't be undone.
+fn restore<C>(
+    client: &Client<C>,
+    info: &KeyValueInfo,
+) -> impl Future<Item = Option<(String, Vec<Error>)>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let key = info.node.key.clone().unwrap_or_default();
+    let index = info.node.modified_index;
+
+    let result = match info.prev_node {
+        Some(ref prev_node) => {
+            let value = prev_node.value.clone().unwrap_or_default();
+            let ttl = prev_node.ttl.filter(|&ttl| ttl > 0).map(|ttl| ttl as u64);
+
+            Either::A(compare_and_swap(client, &key, &value, ttl, None, index))
+        }
+        None => Either::B(compare_and_delete(client, &key, None, index)),
+    };
+
+    result.then(move |result| match result {
+        Ok(_) => Ok(None),
+        Err(errors) => Ok(Some((key, errors))),
+    })
+}
+
+/// Returns whether or not any of the errors is an API error with the given etcd error code.
+pub(crate) fn has_error_code(errors: &[Error], code: u64) -> bool {
+    errors.iter().any(|error| match *error {
+        Error::Api(ref error) => error.error_code == code,
+        _ => false,
+    })
+}
+
+/// Returns whether or not the errors indicate that a node was changed by another client between
+/// being read and being written.
+pub(crate) fn is_conflict(errors: &[Error]) -> bool {
+    has_error_code(errors, TEST_FAILED)
+        || has_error_code(errors, NODE_EXIST)
+        || has_error_code(errors, KEY_NOT_FOUND)
+}
+
+/// Gives a key prefix a leading slash and no trailing slash, so the root becomes "".
+pub(crate) fn normalize_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim_matches('/');
+
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// Moves a key from under one normalized prefix to under another, or returns `None` if the key is
+/// neither the prefix itself nor one of its descendants, e.g. `/application` for `/app`.
+fn rebase_key(key: &str, from: &str, to: &str) -> Option<String> {
+    if !key.starts_with(from) {
+        return None;
+    }
+
+    let rest = &key[from.len()..];
+
+    if rest.is_empty() || rest.starts_with('/') {
+        Some(format!("{}{}", to, rest))
+    } else {
+        None
+    }
 }
 
 /// Handles all delete operations.
 fn raw_delete<C>(
     client: &Client<C>,
     key: &str,
-    options: DeleteOptions<'_>,
+    options: InternalDeleteOptions<'_>,
 ) -> Box<dyn Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send>
 where
     C: Clone + Connect,
@@ -621,44 +2317,91 @@ where
 
     let http_client = client.http_client().clone();
     let key = key.to_string();
+    let written_key = key.clone();
 
-    let result = first_ok(client.endpoints().to_vec(), move |endpoint| {
-        let url = Url::parse_with_params(&build_url(endpoint, &key), query_pairs.clone())
-            .map_err(Error::from)
-            .into_future();
-
-        let uri = url.and_then(|url| {
-            Uri::from_str(url.as_str())
+    let result = first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |endpoint| {
+            let url = Url::parse_with_params(&build_url(endpoint, &key), query_pairs.clone())
                 .map_err(Error::from)
-                .into_future()
-        });
+                .into_future();
+
+            let uri = url.and_then(|url| {
+                Uri::from_str(url.as_str())
+                    .map_err(Error::from)
+                    .into_future()
+            });
+
+            let http_client = http_client.clone();
+            let access = AccessDenied {
+                key: Some(key.clone()),
+                user: http_client.username().map(str::to_owned),
+            };
+
+            let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
+
+            response.and_then(move |response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<KeyValueInfo>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        Err(Error::from_response(status, body, access))
+                    }
+                })
+            })
+        },
+    );
 
-        let http_client = http_client.clone();
+    Box::new(record_write(client, written_key, result))
+}
 
-        let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
+/// Records the etcd index of a successful write for `RequestOptions::read_your_writes`, and
+/// passes the write to the client's `MutationObserver`, if any.
+fn record_write<C, F>(
+    client: &Client<C>,
+    key: String,
+    write: F,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    F: Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send,
+{
+    let write_index = client.write_index().clone();
+    let observer = client.mutation_observer().cloned();
 
-        response.and_then(move |response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
+    write.map(move |response| {
+        if let Some(index) = response.cluster_info.etcd_index {
+            write_index.fetch_max(index, Ordering::SeqCst);
+        }
 
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<KeyValueInfo>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
-                    }
-                } else {
-                    match serde_json::from_slice::<ApiError>(body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
-                    }
-                }
-            })
-        })
-    });
+        if let Some(observer) = observer {
+            let data = &response.data;
+
+            observer.on_mutation(&Mutation {
+                key: data.node.key.as_deref().unwrap_or(&key),
+                action: data.action,
+                prev_value: data
+                    .prev_node
+                    .as_ref()
+                    .and_then(|node| node.value.as_deref()),
+                value: match data.action {
+                    Action::CompareAndDelete | Action::Delete | Action::Expire => None,
+                    _ => data.node.value.as_deref(),
+                },
+                etcd_index: response.cluster_info.etcd_index,
+            });
+        }
 
-    Box::new(result)
+        response
+    })
 }
 
 /// Handles all get operations.
@@ -686,51 +2429,109 @@ where
         query_pairs.insert("waitIndex", format!("{}", options.wait_index.unwrap()));
     }
 
-    let http_client = client.http_client().clone();
-    let key = key.to_string();
+    let mut request_options = client.request_options().clone();
 
-    first_ok(client.endpoints().to_vec(), move |endpoint| {
-        let url = Url::parse_with_params(&build_url(endpoint, &key), query_pairs.clone())
-            .map_err(Error::from)
-            .into_future();
-
-        let uri = url.and_then(|url| {
-            Uri::from_str(url.as_str())
-                .map_err(Error::from)
-                .into_future()
-        });
+    if options.wait {
+        // Watches wait for changes, so the request timeout would cut them short, and a hedged or
+        // concurrent request would just be a second watch.
+        request_options.timeout = request_options.watch_timeout;
+        request_options.total_timeout = None;
+        request_options.hedge_delay = None;
+        request_options.concurrent_attempts = 1;
+    } else if options.strong_consistency || request_options.strong_consistency {
+        query_pairs.insert("quorum", "true".to_owned());
+    }
 
-        let http_client = http_client.clone();
+    let http_client = client.http_client().clone();
+    let key = key.to_string();
+    let wait = options.wait;
+    let required_index = if request_options.read_your_writes && !options.wait {
+        Some(client.write_index().load(Ordering::SeqCst))
+    } else {
+        None
+    };
 
-        let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+    let latencies = client.latencies().clone();
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
+    hedged_first_ok(
+        if wait {
+            client.endpoints()
+        } else {
+            client.read_endpoints()
+        },
+        &request_options,
+        move |endpoint| {
+            let started = Instant::now();
+            let latencies = latencies.clone();
+            let measured_endpoint = endpoint.clone();
 
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<KeyValueInfo>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
-                    }
+            let url = Url::parse_with_params(&build_url(endpoint, &key), query_pairs.clone())
+                .map_err(Error::from)
+                .into_future();
+
+            let uri = url.and_then(|url| {
+                Uri::from_str(url.as_str())
+                    .map_err(Error::from)
+                    .into_future()
+            });
+
+            let http_client = http_client.clone();
+            let access = AccessDenied {
+                key: Some(key.clone()),
+                user: http_client.username().map(str::to_owned),
+            };
+
+            let response = uri.and_then(move |uri| {
+                if wait {
+                    Either::A(http_client.watch(uri))
                 } else {
-                    match serde_json::from_slice::<ApiError>(body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
-                    }
+                    Either::B(http_client.get(uri))
                 }
+            });
+
+            response.and_then(move |response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    let stale = match (required_index, cluster_info.etcd_index) {
+                        (Some(required), Some(index)) => index < required,
+                        _ => false,
+                    };
+
+                    if stale {
+                        Err(Error::StaleRead)
+                    } else if status == StatusCode::OK {
+                        if !wait {
+                            latencies.record(&measured_endpoint, started.elapsed());
+                        }
+
+                        match serde_json::from_slice::<KeyValueInfo>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        match Error::from_response(status, body, access) {
+                            Error::Api(ref error) if error.error_code == EVENT_INDEX_CLEARED => {
+                                Err(Error::IndexCleared {
+                                    current_index: cluster_info.etcd_index.unwrap_or(error.index),
+                                })
+                            }
+                            error => Err(error),
+                        }
+                    }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Handles all set operations.
 fn raw_set<C>(
     client: &Client<C>,
     key: &str,
-    options: SetOptions<'_>,
+    options: InternalSetOptions<'_>,
 ) -> Box<dyn Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send>
 where
     C: Clone + Connect,
@@ -753,6 +2554,14 @@ where
         http_options.push(("prevExist".to_owned(), prev_exist.to_string()));
     }
 
+    if options.refresh {
+        http_options.push(("refresh".to_owned(), "true".to_owned()));
+    }
+
+    if options.no_value_on_success {
+        http_options.push(("noValueOnSuccess".to_owned(), "true".to_owned()));
+    }
+
     if let Some(ref conditions) = options.conditions {
         if conditions.is_empty() {
             return Box::new(Err(vec![Error::InvalidConditions]).into_future());
@@ -769,47 +2578,181 @@ where
 
     let http_client = client.http_client().clone();
     let key = key.to_string();
+    let written_key = key.clone();
     let create_in_order = options.create_in_order;
 
-    let result = first_ok(client.endpoints().to_vec(), move |endpoint| {
-        let mut serializer = Serializer::new(String::new());
-        serializer.extend_pairs(http_options.clone());
-        let body = serializer.finish();
+    let result = first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |endpoint| {
+            let mut serializer = Serializer::new(String::new());
+            serializer.extend_pairs(http_options.clone());
+            let body = serializer.finish();
 
-        let url = build_url(endpoint, &key);
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
+            let url = build_url(endpoint, &key);
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
 
-        let http_client = http_client.clone();
+            let http_client = http_client.clone();
+            let access = AccessDenied {
+                key: Some(key.clone()),
+                user: http_client.username().map(str::to_owned),
+            };
 
-        let response = uri.and_then(move |uri| {
-            if create_in_order {
-                http_client.post(uri, body).map_err(Error::from)
-            } else {
-                http_client.put(uri, body).map_err(Error::from)
+            let response = uri.and_then(move |uri| {
+                if create_in_order {
+                    Either::A(http_client.post(uri, body))
+                } else {
+                    Either::B(http_client.put(uri, body))
+                }
+            });
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| match status {
+                    StatusCode::CREATED | StatusCode::OK => {
+                        match serde_json::from_slice::<KeyValueInfo>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    }
+                    _ => Err(Error::from_response(status, body, access)),
+                })
+            })
+        },
+    );
+
+    Box::new(record_write(client, written_key, result))
+}
+
+/// The stream returned by `kv::watch_stream`.
+#[must_use = "streams do nothing unless polled"]
+struct WatchStream<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    client: Client<C>,
+    current: Option<WatchFuture>,
+    done: bool,
+    /// An error to end the stream with once the pending change has been yielded.
+    failure: Option<Vec<Error>>,
+    key: String,
+    options: WatchOptions,
+    /// The latest change held back by `WatchOptions::coalesce_window`, and when to yield it.
+    pending: Option<(Response<KeyValueInfo>, Delay)>,
+    /// The request for the index to start watching after, if no index was given.
+    seed: Option<SeedFuture>,
+}
+
+impl<C> Stream for WatchStream<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    type Item = WatchItem;
+    type Error = Vec<Error>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+
+        if let Some(error) = self.failure.take() {
+            self.done = true;
+
+            return Err(error);
+        }
+
+        loop {
+            let deadline_passed = match self.pending {
+                Some((_, ref mut deadline)) => match deadline.poll() {
+                    Ok(Async::NotReady) => false,
+                    // A timer error means the deadline can't be waited for, so it's treated as
+                    // passed rather than holding the change back indefinitely.
+                    Ok(Async::Ready(())) | Err(_) => true,
+                },
+                None => false,
+            };
+
+            if deadline_passed {
+                if let Some((response, _)) = self.pending.take() {
+                    return Ok(Async::Ready(Some(WatchItem::Event(Box::new(response)))));
+                }
             }
-        });
 
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
+            if let Some(mut seed) = self.seed.take() {
+                match seed.poll() {
+                    Ok(Async::NotReady) => {
+                        self.seed = Some(seed);
+
+                        return Ok(Async::NotReady);
+                    }
+                    Ok(Async::Ready(index)) => self.options.index = index.map(|index| index + 1),
+                    Err(error) => {
+                        self.done = true;
 
-            body.and_then(move |ref body| match status {
-                StatusCode::CREATED | StatusCode::OK => {
-                    match serde_json::from_slice::<KeyValueInfo>(body) {
-                        Ok(data) => Ok(Response { data, cluster_info }),
-                        Err(error) => Err(Error::Serialization(error)),
+                        return Err(error);
                     }
                 }
-                _ => match serde_json::from_slice::<ApiError>(body) {
-                    Ok(error) => Err(Error::Api(error)),
-                    Err(error) => Err(Error::Serialization(error)),
-                },
-            })
-        })
-    });
+            }
+
+            let mut current = match self.current.take() {
+                Some(current) => current,
+                None => watch_once(&self.client, &self.key, self.options),
+            };
+
+            match current.poll() {
+                Ok(Async::NotReady) => {
+                    self.current = Some(current);
+
+                    return Ok(Async::NotReady);
+                }
+                Ok(Async::Ready(response)) => {
+                    if let Some(index) = response.data.node.modified_index {
+                        self.options.index = Some(index + 1);
+                    }
+
+                    if let Some(actions) = self.options.actions {
+                        if !actions.contains(&response.data.action) {
+                            continue;
+                        }
+                    }
+
+                    let window = match self.options.coalesce_window {
+                        Some(window) => window,
+                        None => {
+                            return Ok(Async::Ready(Some(WatchItem::Event(Box::new(response)))))
+                        }
+                    };
+
+                    match self.pending {
+                        Some((ref mut latest, _)) => *latest = response,
+                        None => {
+                            self.pending = Some((response, Delay::new(Instant::now() + window)))
+                        }
+                    }
+                }
+                Err(None) => {
+                    if self.pending.is_none() {
+                        return Ok(Async::Ready(Some(WatchItem::Idle)));
+                    }
+                }
+                Err(Some(error)) => match self.pending.take() {
+                    Some((response, _)) => {
+                        self.failure = Some(error);
+
+                        return Ok(Async::Ready(Some(WatchItem::Event(Box::new(response)))));
+                    }
+                    None => {
+                        self.done = true;
 
-    Box::new(result)
+                        return Err(error);
+                    }
+                },
+            }
+        }
+    }
 }
@@ -0,0 +1,193 @@
+//! A local copy of the nodes under a key, kept up to date by watching for changes.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use futures::future::Future;
+use futures::stream::Stream;
+use futures::sync::oneshot::{channel, Sender};
+use hyper::client::connect::Connect;
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::sync;
+
+use super::{get, watch_stream, Action, GetOptions, KeyValueInfo, WatchItem, WatchOptions};
+
+/// A local, read-only copy of the key-value pairs under a key.
+///
+/// A cache is created with `Cache::new`, which loads the key-value pairs with a single recursive
+/// read and then spawns a task onto the current Tokio executor that watches the key and applies
+/// each change to the copy. Reads from the cache don't make any requests.
+///
+/// The copy always reflects the state of etcd at a single index, given by `Cache::index`. If
+/// watching fails, e.g. because the cache fell too far behind etcd's event history, the cache
+/// stops being updated and `Cache::is_stale` returns true. A new cache must then be created.
+///
+/// Dropping the cache stops the watch.
+#[derive(Debug)]
+pub struct Cache {
+    stale: Arc<AtomicBool>,
+    state: Arc<RwLock<State>>,
+    stop: Option<Sender<()>>,
+}
+
+/// The contents of a cache.
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<String, String>,
+    index: u64,
+}
+
+impl Cache {
+    /// Loads the key-value pairs under a key and starts watching it for changes.
+    ///
+    /// The returned future must be run on a Tokio executor, which the watcher is spawned onto.
+    ///
+    /// # Parameters
+    ///
+    /// * client: A `Client` to use to make the API calls.
+    /// * prefix: The name of the node to cache.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the node doesn't exist.
+    pub fn new<C>(
+        client: &Client<C>,
+        prefix: &str,
+    ) -> impl Future<Item = Cache, Error = Vec<Error>> + Send
+    where
+        C: Clone + Connect + Sync + 'static,
+    {
+        let client = client.clone();
+        let prefix = prefix.to_string();
+        let options = GetOptions {
+            recursive: true,
+            strong_consistency: true,
+            ..Default::default()
+        };
+
+        get(&client, &prefix, options).map(move |response| {
+            let index = response.cluster_info.etcd_index.unwrap_or_default();
+            let state = Arc::new(RwLock::new(State {
+                entries: response.data.node.flatten().into_iter().collect(),
+                index,
+            }));
+            let stale = Arc::new(AtomicBool::new(false));
+            let (stop, stopped) = channel::<()>();
+
+            let watch_options = WatchOptions {
+                index: Some(index + 1),
+                recursive: true,
+                ..Default::default()
+            };
+            let watch_state = state.clone();
+            let watch_stale = stale.clone();
+
+            let watcher = watch_stream(&client, &prefix, watch_options)
+                .map_err(|_| ())
+                .for_each(move |item| {
+                    if let WatchItem::Event(response) = item {
+                        apply(&watch_state, response.data);
+                    }
+
+                    Ok(())
+                })
+                .then(move |_| {
+                    watch_stale.store(true, Ordering::SeqCst);
+
+                    Ok::<(), ()>(())
+                });
+
+            tokio::spawn(watcher.select2(stopped).then(|_| Ok(())));
+
+            Cache {
+                stale,
+                state,
+                stop: Some(stop),
+            }
+        })
+    }
+
+    /// Returns the value of a key, if it exists.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.read(|state| state.entries.get(key).cloned())
+    }
+
+    /// Returns the etcd index the cache reflects.
+    pub fn index(&self) -> u64 {
+        self.read(|state| state.index)
+    }
+
+    /// Returns whether or not the cache contains no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.read(|state| state.entries.is_empty())
+    }
+
+    /// Returns whether or not the cache has stopped receiving changes from etcd.
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of key-value pairs in the cache.
+    pub fn len(&self) -> usize {
+        self.read(|state| state.entries.len())
+    }
+
+    /// Returns a copy of all the key-value pairs in the cache.
+    pub fn to_map(&self) -> HashMap<String, String> {
+        self.read(|state| state.entries.clone())
+    }
+
+    /// Runs a function with read access to the contents of the cache.
+    fn read<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&State) -> T,
+    {
+        f(&sync::read(&self.state))
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+/// Applies a change from etcd to the contents of a cache.
+fn apply(state: &RwLock<State>, event: KeyValueInfo) {
+    let mut state = sync::write(state);
+    let node = event.node;
+
+    if let Some(index) = node.modified_index {
+        state.index = index;
+    }
+
+    let key = match node.key {
+        Some(key) => key,
+        None => return,
+    };
+
+    match event.action {
+        Action::CompareAndDelete | Action::Delete | Action::Expire => {
+            if node.dir == Some(true) {
+                let dir_prefix = format!("{}/", key);
+
+                state
+                    .entries
+                    .retain(|entry_key, _| !entry_key.starts_with(&dir_prefix));
+            } else {
+                state.entries.remove(&key);
+            }
+        }
+        Action::Get => {}
+        _ => {
+            if let Some(value) = node.value {
+                state.entries.insert(key, value);
+            }
+        }
+    }
+}
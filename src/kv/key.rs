@@ -0,0 +1,93 @@
+//! Validation and normalization of key names.
+
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::ops::Deref;
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// The prefix etcd reserves for its own data, which clients can't write to.
+const RESERVED_PREFIX: &str = "/_etcd";
+
+/// A validated, normalized key name.
+///
+/// A key is normalized by adding a leading slash if it's missing and collapsing repeated and
+/// trailing slashes, so `foo//bar/` becomes `/foo/bar`. Keys under etcd's reserved `/_etcd`
+/// directory are rejected.
+///
+/// `Key` dereferences to `str`, so a reference to it can be passed to any function in the `kv`
+/// module that takes a key.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Key(String);
+
+impl Key {
+    /// Validates and normalizes a key name.
+    ///
+    /// # Parameters
+    ///
+    /// * key: The name of the key.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidKey` if the key is empty or is under the reserved `/_etcd`
+    /// directory.
+    pub fn new(key: &str) -> Result<Self, Error> {
+        if key.is_empty() {
+            return Err(Error::InvalidKey("a key must not be empty".to_owned()));
+        }
+
+        let segments: Vec<&str> = key
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let normalized = format!("/{}", segments.join("/"));
+
+        if normalized == RESERVED_PREFIX || normalized.starts_with("/_etcd/") {
+            return Err(Error::InvalidKey(format!(
+                "the key {} is under etcd's reserved {} directory",
+                normalized, RESERVED_PREFIX
+            )));
+        }
+
+        Ok(Key(normalized))
+    }
+
+    /// Returns the normalized key name.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Key {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Key {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<Key> for String {
+    fn from(key: Key) -> String {
+        key.0
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(key: &str) -> Result<Self, Error> {
+        Key::new(key)
+    }
+}
@@ -0,0 +1,223 @@
+//! Storage of values too large for a single etcd request.
+//!
+//! etcd limits the size of requests, and therefore of values. The functions in this module split
+//! a value into chunks stored as separate key-value pairs, and reassemble them on read. A value
+//! stored at "/foo" is represented by a directory "/foo" containing the chunks "/foo/.chunks/0",
+//! "/foo/.chunks/1", and so on, and a manifest "/foo/.manifest" recording the number of chunks
+//! and a checksum of the whole value.
+//!
+//! Writes are not atomic. The manifest is written last, and reads verify the chunks against it,
+//! so a read that overlaps a write fails instead of returning a mix of old and new chunks.
+
+use futures::future::{Future, IntoFuture};
+use futures::stream::{iter_ok, Stream};
+use hyper::client::connect::Connect;
+use serde_derive::{Deserialize, Serialize};
+use serde_json;
+
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+use super::{create_dir, delete as delete_node, get as get_node, has_error_code, set as set_node};
+use super::{GetOptions, KeyValueInfo, KEY_NOT_FOUND};
+
+/// The maximum number of bytes in each chunk, which is well under etcd's default request limit
+/// of 1.5 MiB.
+const CHUNK_SIZE: usize = 512 * 1024;
+
+/// The record of the chunks making up a value.
+#[derive(Debug, Deserialize, Serialize)]
+struct Manifest {
+    /// A hex-encoded 64-bit FNV-1a hash of the value.
+    checksum: String,
+    /// The number of chunks.
+    chunks: usize,
+    /// The length of the value in bytes.
+    length: usize,
+}
+
+/// Deletes a value stored with `large::set`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the value to delete.
+///
+/// # Errors
+///
+/// Fails if the key doesn't exist.
+pub fn delete<C>(
+    client: &Client<C>,
+    key: &str,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    delete_node(client, key, true)
+}
+
+/// Gets a value stored with `large::set`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the value to retrieve.
+///
+/// # Errors
+///
+/// Fails with `Error::Codec` if the chunks are missing or don't match the manifest, e.g. because
+/// the value is being written concurrently. Fails if the key doesn't exist.
+pub fn get<C>(
+    client: &Client<C>,
+    key: &str,
+) -> impl Future<Item = Response<String>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let key = key.trim_end_matches('/').to_string();
+    let options = GetOptions {
+        recursive: true,
+        strong_consistency: true,
+        ..Default::default()
+    };
+
+    get_node(client, &key, options).and_then(move |response| {
+        match reassemble(&key, &response.data) {
+            Ok(data) => Ok(Response {
+                cluster_info: response.cluster_info,
+                data,
+            }),
+            Err(error) => Err(vec![error]),
+        }
+    })
+}
+
+/// Stores a value of any size by splitting it into chunks.
+///
+/// Any existing value or directory at the key is replaced.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the value to set.
+/// * value: The value to store.
+/// * ttl: If given, the value will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if any of the writes fails. Succeeds with the response from writing the manifest.
+pub fn set<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &str,
+    ttl: Option<u64>,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let key = key.trim_end_matches('/').to_string();
+
+    let chunks: Vec<String> = split(value, CHUNK_SIZE)
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+    let manifest = Manifest {
+        checksum: format!("{:016x}", fnv1a(value.as_bytes())),
+        chunks: chunks.len(),
+        length: value.len(),
+    };
+    let manifest = serde_json::to_string(&manifest).map_err(|error| vec![Error::from(error)]);
+
+    let chunks_client = client.clone();
+    let chunks_key = key.clone();
+    let manifest_client = client.clone();
+    let manifest_key = key.clone();
+
+    manifest.into_future().and_then(move |manifest| {
+        delete_node(&client, &key, true)
+            .then(|result| match result {
+                Ok(_) => Ok(()),
+                Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(()),
+                Err(errors) => Err(errors),
+            })
+            .and_then(move |_| create_dir(&client, &key, ttl))
+            .and_then(move |_| {
+                iter_ok(chunks.into_iter().enumerate()).for_each(move |(index, chunk)| {
+                    let chunk_key = format!("{}/.chunks/{}", chunks_key, index);
+
+                    set_node(&chunks_client, &chunk_key, &chunk, None).map(|_| ())
+                })
+            })
+            .and_then(move |_| {
+                let manifest_key = format!("{}/.manifest", manifest_key);
+
+                set_node(&manifest_client, &manifest_key, &manifest, None)
+            })
+    })
+}
+
+/// Splits a string into pieces of at most `size` bytes, without splitting any characters.
+fn split(value: &str, size: usize) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut rest = value;
+
+    while !rest.is_empty() {
+        let mut end = size.min(rest.len());
+
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let (piece, remainder) = rest.split_at(end);
+        pieces.push(piece);
+        rest = remainder;
+    }
+
+    pieces
+}
+
+/// Reassembles a value from the directory holding its chunks and manifest.
+fn reassemble(key: &str, info: &KeyValueInfo) -> Result<String, Error> {
+    let manifest_key = format!("{}/.manifest", key);
+    let manifest = info
+        .node
+        .find(&manifest_key)
+        .and_then(|node| node.value.as_ref())
+        .ok_or_else(|| Error::Codec(format!("{} has no manifest", key)))?;
+    let manifest: Manifest = serde_json::from_str(manifest)?;
+
+    let mut value = String::with_capacity(manifest.length);
+
+    for index in 0..manifest.chunks {
+        let chunk_key = format!("{}/.chunks/{}", key, index);
+        let chunk = info
+            .node
+            .find(&chunk_key)
+            .and_then(|node| node.value.as_ref())
+            .ok_or_else(|| Error::Codec(format!("{} is missing chunk {}", key, index)))?;
+
+        value.push_str(chunk);
+    }
+
+    if value.len() != manifest.length
+        || format!("{:016x}", fnv1a(value.as_bytes())) != manifest.checksum
+    {
+        return Err(Error::Codec(format!(
+            "the chunks of {} don't match its manifest",
+            key
+        )));
+    }
+
+    Ok(value)
+}
+
+/// Computes the 64-bit FNV-1a hash of the given bytes.
+///
+/// This detects chunks that are missing or from a different write. It is not a cryptographic
+/// hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
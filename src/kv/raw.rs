@@ -0,0 +1,83 @@
+//! Low-level access to etcd's key-value API.
+//!
+//! The functions in the `kv` module cover the common combinations of etcd's request parameters.
+//! The functions in this module accept every parameter the client supports, for combinations the
+//! higher-level functions don't offer, such as refreshing a TTL conditionally or creating a
+//! directory with a TTL only if it is at a given index. No validation beyond what etcd itself
+//! performs is done on the combination of options.
+
+use futures::future::Future;
+use hyper::client::connect::Connect;
+
+pub use crate::options::{ComparisonConditions, DeleteOptions, GetOptions, SetOptions};
+
+use super::{raw_delete, raw_get, raw_set, KeyValueInfo};
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+/// Deletes a node.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the node to delete.
+/// * options: The parameters for the request.
+///
+/// # Errors
+///
+/// Fails if `options.conditions` is given but empty, or if etcd rejects the request.
+pub fn delete<C>(
+    client: &Client<C>,
+    key: &str,
+    options: DeleteOptions<'_>,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_delete(client, key, options)
+}
+
+/// Gets a node, or waits for a change to it if `options.wait` is true.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the node to retrieve.
+/// * options: The parameters for the request.
+///
+/// # Errors
+///
+/// Fails if etcd rejects the request, e.g. because the key doesn't exist.
+pub fn get<C>(
+    client: &Client<C>,
+    key: &str,
+    options: GetOptions,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_get(client, key, options)
+}
+
+/// Creates, updates, or sets a node.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the node to write, or of the directory to create a node in if
+///   `options.create_in_order` is true.
+/// * options: The parameters for the request.
+///
+/// # Errors
+///
+/// Fails if `options.conditions` is given but empty, or if etcd rejects the request.
+pub fn set<C>(
+    client: &Client<C>,
+    key: &str,
+    options: SetOptions<'_>,
+) -> impl Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    raw_set(client, key, options)
+}
@@ -0,0 +1,242 @@
+//! Keys whose TTLs are kept alive for as long as a value is held.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::{loop_fn, Either, Future, IntoFuture, Loop, Shared};
+use futures::sync::oneshot::{channel, Receiver, Sender};
+use hyper::client::connect::Connect;
+use tokio::timer::Delay;
+
+use crate::client::Client;
+use crate::error::Error;
+
+use super::{compare_and_delete, has_error_code, refresh, set, KEY_NOT_FOUND};
+
+/// A key-value pair that is kept alive by refreshing its TTL in the background.
+///
+/// A session is created with `Session::new`, which sets the key and spawns a task onto the
+/// current Tokio executor that refreshes the key's TTL at a third of the TTL. This is useful for
+/// heartbeats, such as announcing that a service instance is running.
+///
+/// A refresh that fails is tried again after the client's `RequestOptions::retry_backoff`, for as
+/// long as the key's TTL allows. Once the key is missing or the TTL has passed without a
+/// successful refresh, the session is lost, which `Session::lost` reports.
+///
+/// When the session is dropped, the refresher is stopped and the key is deleted, unless its value
+/// has since been changed by another client. If the executor or the client is shut down first, the
+/// key is left to expire.
+#[derive(Debug)]
+pub struct Session<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    alive: Arc<AtomicBool>,
+    client: Client<C>,
+    key: String,
+    lost: Shared<Receiver<()>>,
+    stop: Option<Sender<bool>>,
+    value: String,
+}
+
+impl<C> Session<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    /// Sets a key-value pair and starts refreshing its TTL.
+    ///
+    /// The returned future must be run on a Tokio executor, which the refresher is spawned onto.
+    ///
+    /// # Parameters
+    ///
+    /// * client: A `Client` to use to make the API calls.
+    /// * key: The name of the key-value pair to set.
+    /// * value: The value for the key-value pair.
+    /// * ttl: The key will expire after this many seconds if it isn't refreshed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the key could not be set.
+    pub fn new(
+        client: &Client<C>,
+        key: &str,
+        value: &str,
+        ttl: u64,
+    ) -> impl Future<Item = Session<C>, Error = Vec<Error>> + Send {
+        let client = client.clone();
+        let key = key.to_string();
+        let value = value.to_string();
+
+        set(&client, &key, &value, Some(ttl))
+            .map(move |_| Session::keep_alive(&client, &key, &value, ttl))
+    }
+
+    /// Starts refreshing the TTL of a key that has already been set, e.g. one created with
+    /// `kv::create_in_order`.
+    ///
+    /// Must be called from a task running on a Tokio executor, which the refresher is spawned
+    /// onto.
+    pub(crate) fn keep_alive(client: &Client<C>, key: &str, value: &str, ttl: u64) -> Session<C> {
+        let alive = Arc::new(AtomicBool::new(true));
+        let (stop, stopped) = channel();
+        let (ended, lost) = channel();
+
+        tokio::spawn(refresher(
+            client.clone(),
+            key.to_string(),
+            value.to_string(),
+            ttl,
+            alive.clone(),
+            stopped,
+            ended,
+        ));
+
+        Session {
+            alive,
+            client: client.clone(),
+            key: key.to_string(),
+            lost: lost.shared(),
+            stop: Some(stop),
+            value: value.to_string(),
+        }
+    }
+
+    /// Returns whether or not the key is still being kept alive.
+    ///
+    /// This becomes false once the key has been found to be missing, e.g. because it was deleted
+    /// by another client, or once refreshing it has failed for longer than its TTL.
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    /// Returns a future that fails with `Error::LeaseLost` once the key is no longer kept alive,
+    /// so that the holder can stop acting on the assumption that it is.
+    ///
+    /// The future succeeds instead if the session is invalidated or dropped first.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::LeaseLost` if the key was found to be missing, e.g. because it was
+    /// deleted by another client, if refreshing it has failed for longer than its TTL, or if the
+    /// refresher stopped because the executor or the client was shut down.
+    pub fn lost(&self) -> impl Future<Item = (), Error = Error> + Send {
+        self.lost.clone().then(|result| match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::LeaseLost),
+        })
+    }
+
+    /// Returns the name of the key-value pair.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Stops refreshing the key and deletes it, unless its value has since been changed by
+    /// another client.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the key could not be deleted.
+    pub fn invalidate(mut self) -> impl Future<Item = (), Error = Vec<Error>> + Send {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(false);
+        }
+
+        self.alive.store(false, Ordering::SeqCst);
+
+        compare_and_delete(&self.client, &self.key, Some(&self.value), None).map(|_| ())
+    }
+}
+
+impl<C> Drop for Session<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(true);
+        }
+    }
+}
+
+/// Refreshes the key until it is lost or the session is stopped. If the session asks for it, the
+/// key is deleted when stopping. `ended` is only sent to when the session is stopped, so that
+/// `Session::lost` fails when it is dropped otherwise.
+fn refresher<C>(
+    client: Client<C>,
+    key: String,
+    value: String,
+    ttl: u64,
+    alive: Arc<AtomicBool>,
+    stopped: Receiver<bool>,
+    ended: Sender<()>,
+) -> impl Future<Item = (), Error = ()> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    let ttl_duration = Duration::from_secs(ttl);
+    let period = Duration::from_millis(ttl * 1000 / 3).max(Duration::from_millis(100));
+    let backoff = client.request_options().retry_backoff;
+    let refresh_client = client.clone();
+    let refresh_key = key.clone();
+
+    // Each iteration waits, then refreshes the key. The wait is `None` after a success, and the
+    // backoff after a failure, which never reaches past the time the key expires.
+    let refreshes = loop_fn(
+        (Instant::now(), None),
+        move |(last_success, wait): (Instant, Option<Duration>)| {
+            let now = Instant::now();
+            let expires = last_success + ttl_duration;
+            let delay = match wait {
+                Some(wait) if expires > now => wait.min(expires - now),
+                Some(_) => Duration::from_secs(0),
+                None => period,
+            };
+            let refresh_client = refresh_client.clone();
+            let refresh_key = refresh_key.clone();
+
+            Delay::new(now + delay).map_err(|_| ()).and_then(move |_| {
+                refresh(&refresh_client, &refresh_key, ttl).then(move |result| match result {
+                    Ok(_) => Ok(Loop::Continue((Instant::now(), None))),
+                    Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Err(()),
+                    Err(ref errors) if errors.iter().any(|e| matches!(e, Error::Cancelled)) => {
+                        Err(())
+                    }
+                    Err(_) if last_success.elapsed() >= ttl_duration => Err(()),
+                    Err(_) => {
+                        let wait = wait.map_or(backoff.initial, |wait| backoff.next(wait));
+
+                        Ok(Loop::Continue((last_success, Some(wait))))
+                    }
+                })
+            })
+        },
+    )
+    .map(|()| ());
+
+    refreshes.select2(stopped).then(move |result| {
+        alive.store(false, Ordering::SeqCst);
+
+        let delete = match result {
+            Ok(Either::B((delete, _))) => Some(delete),
+            Err(Either::B(_)) => Some(true),
+            _ => None,
+        };
+
+        match delete {
+            Some(delete) => {
+                let _ = ended.send(());
+
+                if delete {
+                    Either::A(
+                        compare_and_delete(&client, &key, Some(&value), None).then(|_| Ok(())),
+                    )
+                } else {
+                    Either::B(Ok(()).into_future())
+                }
+            }
+            None => Either::B(Ok(()).into_future()),
+        }
+    })
+}
@@ -0,0 +1,278 @@
+//! Reading large directories without buffering the whole response.
+
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+
+use futures::future::{Either, Future, IntoFuture};
+use futures::stream::Stream;
+use futures::{Async, Poll};
+use hyper::client::connect::Connect;
+use hyper::{Body, StatusCode, Uri};
+use serde_json;
+use url::Url;
+
+use super::{build_url, GetOptions, Node};
+use crate::client::Client;
+use crate::error::{AccessDenied, Error};
+use crate::first_ok::first_ok;
+
+/// Gets the children of a directory, yielding each one as soon as it has been received.
+///
+/// `kv::get` buffers the whole response before deserializing it, which for a recursive read of a
+/// large tree means holding both the raw body and the parsed nodes in memory. This function
+/// instead scans the body as it arrives and deserializes each child of the directory, along with
+/// its descendants if `options.recursive` is true, once it is complete. Only one child is held in
+/// memory at a time.
+///
+/// Nothing is yielded for a key-value pair or an empty directory. `GetOptions::include_hidden` is
+/// not supported. The client's request timeout only applies until the response headers are
+/// received.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the directory to retrieve.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// The stream ends with an error if the key doesn't exist, if the connection fails while the body
+/// is being received, or if a child can't be deserialized.
+pub fn get_streaming<C>(
+    client: &Client<C>,
+    key: &str,
+    options: GetOptions,
+) -> impl Stream<Item = Node, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let mut query_pairs = HashMap::new();
+
+    query_pairs.insert("recursive", format!("{}", options.recursive));
+    query_pairs.insert("sorted", format!("{}", options.sort));
+
+    if options.strong_consistency || client.request_options().strong_consistency {
+        query_pairs.insert("quorum", "true".to_owned());
+    }
+
+    let http_client = client.http_client().clone();
+    let key = key.to_string();
+
+    let body =
+        first_ok(
+            client.endpoints(),
+            client.request_options(),
+            move |endpoint| {
+                let url = Url::parse_with_params(&build_url(endpoint, &key), query_pairs.clone())
+                    .map_err(Error::from)
+                    .into_future();
+
+                let uri = url.and_then(|url| {
+                    Uri::from_str(url.as_str())
+                        .map_err(Error::from)
+                        .into_future()
+                });
+
+                let http_client = http_client.clone();
+                let access = AccessDenied {
+                    key: Some(key.clone()),
+                    user: http_client.username().map(str::to_owned),
+                };
+
+                let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+                response.and_then(move |response| {
+                    let status = response.status();
+
+                    if status == StatusCode::OK {
+                        Either::A(Ok(response.into_body()).into_future())
+                    } else {
+                        let body = response.into_body().concat2().map_err(Error::from);
+
+                        Either::B(body.and_then(move |ref body| {
+                            Err(Error::from_response(status, body, access))
+                        }))
+                    }
+                })
+            },
+        );
+
+    body.map(|body| NodeStream {
+        body,
+        done: false,
+        ready: VecDeque::new(),
+        splitter: NodeSplitter::default(),
+    })
+    .flatten_stream()
+}
+
+/// A stream of the children of a directory, deserialized from a response body as it arrives.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+struct NodeStream {
+    body: Body,
+    done: bool,
+    ready: VecDeque<Node>,
+    splitter: NodeSplitter,
+}
+
+impl Stream for NodeStream {
+    type Item = Node;
+    type Error = Vec<Error>;
+
+    fn poll(&mut self) -> Poll<Option<Node>, Vec<Error>> {
+        loop {
+            if let Some(node) = self.ready.pop_front() {
+                return Ok(Async::Ready(Some(node)));
+            }
+
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            match self.body.poll() {
+                Ok(Async::Ready(Some(chunk))) => {
+                    let nodes = self.splitter.push(&chunk).map_err(|error| vec![error])?;
+
+                    self.ready.extend(nodes);
+                }
+                Ok(Async::Ready(None)) => self.done = true,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(error) => {
+                    self.done = true;
+
+                    return Err(vec![Error::from(error)]);
+                }
+            }
+        }
+    }
+}
+
+/// Finds the children of the node in a response body as it arrives, so each can be deserialized
+/// as soon as it's complete.
+///
+/// The body is scanned one byte at a time, tracking just enough of the JSON structure to find the
+/// elements of the `node.nodes` array.
+#[derive(Debug, Default)]
+struct NodeSplitter {
+    /// The bytes that haven't been scanned yet or belong to an incomplete child.
+    buffer: Vec<u8>,
+    /// The position in `buffer` of the start of the child being received, if any.
+    child_start: Option<usize>,
+    /// Whether or not the next string is an object key.
+    expecting_key: bool,
+    /// Whether or not the previous byte was a backslash inside a string.
+    escaped: bool,
+    /// Whether or not the scan is inside a string.
+    in_string: bool,
+    /// The most recent key of the innermost object, if it's the root object or the node.
+    key: Option<Vec<u8>>,
+    /// The opening byte of each enclosing object or array, and the key it's the value of.
+    path: Vec<(u8, Option<Vec<u8>>)>,
+    /// The position in `buffer` of the next byte to scan.
+    position: usize,
+    /// The bytes of the key being scanned, if it's a key that needs to be recorded.
+    string: Option<Vec<u8>>,
+}
+
+impl NodeSplitter {
+    /// Scans the next chunk of the body, returning the children that were completed by it.
+    fn push(&mut self, chunk: &[u8]) -> Result<Vec<Node>, Error> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut nodes = vec![];
+
+        while self.position < self.buffer.len() {
+            let byte = self.buffer[self.position];
+
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+
+                    if let Some(string) = self.string.take() {
+                        self.key = Some(string);
+                    }
+                }
+
+                if self.in_string {
+                    if let Some(ref mut string) = self.string {
+                        string.push(byte);
+                    }
+                }
+            } else {
+                match byte {
+                    b'"' => {
+                        self.in_string = true;
+
+                        if self.expecting_key && self.path.len() <= 2 {
+                            self.string = Some(vec![]);
+                        }
+                    }
+                    b':' => self.expecting_key = false,
+                    b',' => {
+                        self.key = None;
+                        self.expecting_key = self.in_object();
+                    }
+                    b'{' | b'[' => {
+                        if byte == b'{' && self.in_children() {
+                            self.child_start = Some(self.position);
+                        }
+
+                        let key = if self.in_object() {
+                            self.key.take()
+                        } else {
+                            None
+                        };
+
+                        self.path.push((byte, key));
+                        self.expecting_key = byte == b'{';
+                    }
+                    b'}' | b']' => {
+                        self.path.pop();
+                        self.key = None;
+                        self.expecting_key = false;
+
+                        if byte == b'}' && self.in_children() {
+                            if let Some(start) = self.child_start.take() {
+                                nodes.push(serde_json::from_slice(
+                                    &self.buffer[start..=self.position],
+                                )?);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            self.position += 1;
+        }
+
+        let consumed = self.child_start.unwrap_or(self.position);
+
+        self.buffer.drain(..consumed);
+        self.position -= consumed;
+        self.child_start = self.child_start.map(|start| start - consumed);
+
+        Ok(nodes)
+    }
+
+    /// Returns whether or not the innermost enclosing value is an object.
+    fn in_object(&self) -> bool {
+        match self.path.last() {
+            Some(&(opening, _)) => opening == b'{',
+            None => false,
+        }
+    }
+
+    /// Returns whether or not the scan is directly inside the `node.nodes` array.
+    fn in_children(&self) -> bool {
+        self.path.len() == 3
+            && self.path[1].1.as_deref() == Some(&b"node"[..])
+            && self.path[2].0 == b'['
+            && self.path[2].1.as_deref() == Some(&b"nodes"[..])
+    }
+}
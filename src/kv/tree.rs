@@ -0,0 +1,425 @@
+//! Mapping Rust types to trees of keys, so that configuration schemas can live in code instead of
+//! in ad-hoc key names.
+//!
+//! A value is mapped to the keys under a prefix with Serde: each field of a struct, or entry of a
+//! map, becomes a key named after it, and nested structs and maps become directories. Strings are
+//! stored as they are, `None` is stored as a missing key, and other values, including sequences,
+//! are stored as JSON. For example, saving
+//! `Config { name: "api", port: 8080, tls: Some(Tls { cert: "/etc/api.pem" }) }` under `/config`
+//! sets `/config/name` to `api`, `/config/port` to `8080`, and `/config/tls/cert` to
+//! `/etc/api.pem`.
+//!
+//! Saving a value only writes the keys whose values changed. Keys under the prefix that the value
+//! doesn't have a field for are left alone. The writes are not atomic, so a value loaded while
+//! another is being saved may mix old and new fields.
+
+use std::collections::BTreeMap;
+
+use futures::future::{Either, Future, IntoFuture};
+use futures::stream::{iter_ok, Stream};
+use hyper::client::connect::Connect;
+use serde::de::value::MapDeserializer;
+use serde::de::{
+    DeserializeOwned,
+    Deserializer,
+    Error as DeError,
+    IntoDeserializer,
+    Unexpected,
+    Visitor,
+};
+use serde::{forward_to_deserialize_any, Serialize};
+use serde_json::{self, Error as SerializationError, Value};
+
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+use super::{
+    delete as delete_node,
+    get as get_node,
+    has_error_code,
+    normalize_prefix,
+    set as set_node,
+    GetOptions,
+    Node,
+    KEY_NOT_FOUND,
+};
+
+/// A change to one of the keys a value is mapped to, returned by `tree::diff` and `tree::save`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Change {
+    /// The key is set to a new value.
+    Set {
+        /// The name of the key.
+        key: String,
+        /// The new value.
+        value: String,
+    },
+    /// The key is deleted, because the field it is mapped to is `None`. If the field held a
+    /// struct or map, the key is a directory, and its children are deleted as well.
+    Delete {
+        /// The name of the key.
+        key: String,
+    },
+}
+
+impl Change {
+    /// Returns the name of the changed key.
+    pub fn key(&self) -> &str {
+        match *self {
+            Change::Set { ref key, .. } | Change::Delete { ref key } => key,
+        }
+    }
+}
+
+/// A tree of keys read from etcd, deserialized into a value by `tree::load`.
+#[derive(Debug)]
+enum Tree {
+    /// A key-value pair.
+    Leaf(String),
+    /// A directory, with the last segment of each child's key.
+    Dir(Vec<(String, Tree)>),
+}
+
+/// Makes the API calls for a list of changes, in order.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * changes: The changes to make, e.g. from `tree::diff`.
+///
+/// # Errors
+///
+/// Fails if any of the writes fails. Changes before the failed one have been made, and the ones
+/// after it have not. Deleting a key that doesn't exist is not an error.
+pub fn apply<C>(
+    client: &Client<C>,
+    changes: &[Change],
+) -> impl Future<Item = (), Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+
+    iter_ok(changes.to_vec()).for_each(move |change| match change {
+        Change::Set { key, value } => Either::A(set_node(&client, &key, &value, None).map(|_| ())),
+        Change::Delete { key } => {
+            Either::B(
+                delete_node(&client, &key, true).then(|result| match result {
+                    Ok(_) => Ok(()),
+                    Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(()),
+                    Err(errors) => Err(errors),
+                }),
+            )
+        }
+    })
+}
+
+/// Returns the changes that turn the keys an old value is mapped to into the keys a new value is
+/// mapped to, without making any API calls.
+///
+/// # Parameters
+///
+/// * prefix: The name of the directory the values are mapped to.
+/// * old: The value the keys currently hold.
+/// * new: The value the keys should hold.
+///
+/// # Errors
+///
+/// Fails with `Error::Codec` if either value can't be mapped to keys, e.g. because it is not a
+/// struct or map.
+pub fn diff<T>(prefix: &str, old: &T, new: &T) -> Result<Vec<Change>, Error>
+where
+    T: Serialize,
+{
+    let current = flatten(prefix, old)?
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key, value)))
+        .collect();
+
+    Ok(changes(&current, flatten(prefix, new)?))
+}
+
+/// Reads the keys under a prefix into a value.
+///
+/// Missing keys are deserialized as `None` if their field is an `Option`, or as the field's
+/// default if it has `#[serde(default)]`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * prefix: The name of the directory the value is mapped to.
+///
+/// # Errors
+///
+/// Fails if the directory doesn't exist, or with `Error::Serialization` if its keys can't be
+/// deserialized into the value.
+pub fn load<C, T>(
+    client: &Client<C>,
+    prefix: &str,
+) -> impl Future<Item = Response<T>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    T: DeserializeOwned + Send + 'static,
+{
+    let options = GetOptions {
+        recursive: true,
+        ..Default::default()
+    };
+
+    get_node(client, &normalize_prefix(prefix), options).and_then(|response| {
+        let cluster_info = response.cluster_info;
+        let tree = tree_of(&response.data.node);
+
+        match T::deserialize(tree) {
+            Ok(data) => Ok(Response { data, cluster_info }),
+            Err(error) => Err(vec![Error::Serialization(error)]),
+        }
+    })
+}
+
+/// Writes a value to the keys under a prefix, setting only the keys whose values differ from the
+/// ones in etcd, and deleting the keys of fields that are `None`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * prefix: The name of the directory to map the value to.
+/// * value: The value to write.
+///
+/// # Errors
+///
+/// Fails with `Error::Codec` if the value can't be mapped to keys, or under the same conditions
+/// as `tree::apply`. Succeeds with the changes that were made.
+pub fn save<C, T>(
+    client: &Client<C>,
+    prefix: &str,
+    value: &T,
+) -> impl Future<Item = Vec<Change>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    T: Serialize,
+{
+    let desired = match flatten(prefix, value) {
+        Ok(desired) => desired,
+        Err(error) => return Either::A(Err(vec![error]).into_future()),
+    };
+
+    let client = client.clone();
+    let options = GetOptions {
+        recursive: true,
+        ..Default::default()
+    };
+
+    let current =
+        get_node(&client, &normalize_prefix(prefix), options).then(|result| match result {
+            Ok(response) => Ok(response.data.node.flatten().into_iter().collect()),
+            Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(BTreeMap::new()),
+            Err(errors) => Err(errors),
+        });
+
+    Either::B(current.and_then(move |current| {
+        let changes = changes(&current, desired);
+
+        apply(&client, &changes).map(move |_| changes)
+    }))
+}
+
+/// Returns the changes that give each key its desired value, skipping keys that already have it.
+fn changes(
+    current: &BTreeMap<String, String>,
+    desired: BTreeMap<String, Option<String>>,
+) -> Vec<Change> {
+    desired
+        .into_iter()
+        .filter_map(|(key, value)| match (current.get(&key), value) {
+            (Some(current), Some(ref value)) if current == value => None,
+            (_, Some(value)) => Some(Change::Set { key, value }),
+            (_, None) if exists(current, &key) => Some(Change::Delete { key }),
+            (_, None) => None,
+        })
+        .collect()
+}
+
+/// Returns whether or not a key, or a directory with keys under it, exists.
+fn exists(current: &BTreeMap<String, String>, key: &str) -> bool {
+    let children = format!("{}/", key);
+
+    current.contains_key(key) || current.keys().any(|child| child.starts_with(&children))
+}
+
+/// Maps a value to the values of the keys under a prefix, where `None` means the key should not
+/// exist.
+fn flatten<T>(prefix: &str, value: &T) -> Result<BTreeMap<String, Option<String>>, Error>
+where
+    T: Serialize,
+{
+    let value = serde_json::to_value(value).map_err(|error| Error::Codec(error.to_string()))?;
+    let mut keys = BTreeMap::new();
+
+    match value {
+        Value::Object(fields) => {
+            let prefix = normalize_prefix(prefix);
+
+            for (field, value) in fields {
+                flatten_value(format!("{}/{}", prefix, field), value, &mut keys);
+            }
+
+            Ok(keys)
+        }
+        value => Err(Error::Codec(format!(
+            "only structs and maps can be mapped to keys, not {}",
+            value
+        ))),
+    }
+}
+
+/// Adds the keys a field is mapped to.
+fn flatten_value(key: String, value: Value, keys: &mut BTreeMap<String, Option<String>>) {
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields {
+                flatten_value(format!("{}/{}", key, field), value, keys);
+            }
+        }
+        Value::Null => {
+            keys.insert(key, None);
+        }
+        Value::String(value) => {
+            keys.insert(key, Some(value));
+        }
+        value => {
+            keys.insert(key, Some(value.to_string()));
+        }
+    }
+}
+
+/// Builds the tree of keys in a node.
+fn tree_of(node: &Node) -> Tree {
+    if node.dir != Some(true) {
+        return Tree::Leaf(node.value.clone().unwrap_or_default());
+    }
+
+    let children = node
+        .nodes
+        .iter()
+        .flatten()
+        .map(|child| {
+            let name = child
+                .key
+                .as_ref()
+                .and_then(|key| key.rsplit('/').next())
+                .unwrap_or("");
+
+            (name.to_owned(), tree_of(child))
+        })
+        .collect();
+
+    Tree::Dir(children)
+}
+
+impl Tree {
+    /// Converts the tree into JSON, for the types whose representation `Tree` doesn't handle
+    /// itself.
+    fn into_json(self) -> Value {
+        match self {
+            Tree::Leaf(value) => serde_json::from_str(&value).unwrap_or(Value::String(value)),
+            Tree::Dir(children) => Value::Object(
+                children
+                    .into_iter()
+                    .map(|(name, child)| (name, child.into_json()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, SerializationError> for Tree {
+    type Deserializer = Tree;
+
+    fn into_deserializer(self) -> Tree {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for Tree {
+    type Error = SerializationError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, SerializationError>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Tree::Leaf(value) => match serde_json::from_str::<Value>(&value) {
+                Ok(json) => json.deserialize_any(visitor),
+                Err(_) => visitor.visit_string(value),
+            },
+            Tree::Dir(children) => {
+                let mut map = MapDeserializer::new(children.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+
+                map.end()?;
+
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, SerializationError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, SerializationError>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Tree::Leaf(value) => visitor.visit_string(value),
+            Tree::Dir(_) => Err(DeError::invalid_type(Unexpected::Map, &visitor)),
+        }
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, SerializationError>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, SerializationError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerializationError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerializationError>
+    where
+        V: Visitor<'de>,
+    {
+        self.into_json().deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
@@ -0,0 +1,98 @@
+//! Tracking of how quickly each cluster member responds, for `EndpointSelection::LowestLatency`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::Uri;
+
+use crate::sync::lock;
+
+/// The weight given to each new measurement in a member's moving average.
+const SMOOTHING: f64 = 0.2;
+
+/// How much slower than the fastest member a member can be and still share the reads with it.
+const FAST_GROUP_FACTOR: f64 = 1.5;
+
+/// How long a member's average is used for after its latest measurement. After that, the member is
+/// ranked as if it hadn't been measured, so a member that was slow is tried again and gets the
+/// chance to show that it has recovered.
+const MEASUREMENT_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Moving averages of the latency of successful reads from each cluster member.
+#[derive(Debug, Default)]
+pub struct Latencies {
+    /// The average latency of each member, by endpoint, and when it was last measured.
+    averages: Mutex<HashMap<String, (Duration, Instant)>>,
+    /// A counter used to rotate reads between the fastest members.
+    next: AtomicUsize,
+}
+
+impl Latencies {
+    /// Returns the average latency of a member, if it has been measured within the measurements'
+    /// lifetime.
+    pub fn average(&self, endpoint: &Uri) -> Option<Duration> {
+        lock(&self.averages)
+            .get(&endpoint.to_string())
+            .filter(|&&(_, measured)| measured.elapsed() < MEASUREMENT_LIFETIME)
+            .map(|&(average, _)| average)
+    }
+
+    /// Orders the endpoints for a read.
+    ///
+    /// The members that haven't been measured yet, and those whose average is close to the
+    /// fastest, come first, rotated on each call so the reads are spread between them. The others
+    /// follow from fastest to slowest.
+    pub fn order(&self, endpoints: &[Uri]) -> Vec<Uri> {
+        let mut ranked = self.rank(endpoints);
+        let fastest = ranked.iter().filter_map(|&(_, average)| average).next();
+        let fast_group = ranked
+            .iter()
+            .take_while(|&&(_, average)| match (average, fastest) {
+                (Some(average), Some(fastest)) => average <= fastest.mul_f64(FAST_GROUP_FACTOR),
+                _ => true,
+            })
+            .count();
+
+        if fast_group > 1 {
+            let rotation = self.next.fetch_add(1, Ordering::Relaxed) % fast_group;
+
+            ranked[..fast_group].rotate_left(rotation);
+        }
+
+        ranked.into_iter().map(|(endpoint, _)| endpoint).collect()
+    }
+
+    /// Returns the endpoints with their average latencies, with the members that haven't been
+    /// measured first and the others from fastest to slowest.
+    pub fn rank(&self, endpoints: &[Uri]) -> Vec<(Uri, Option<Duration>)> {
+        let mut ranked: Vec<_> = endpoints
+            .iter()
+            .map(|endpoint| (endpoint.clone(), self.average(endpoint)))
+            .collect();
+
+        ranked.sort_by_key(|&(_, average)| average);
+
+        ranked
+    }
+
+    /// Adds the latency of a successful read from a member to its average.
+    ///
+    /// An average that has outlived the measurements' lifetime is started over, since it may
+    /// describe a member that has since recovered or slowed down.
+    pub fn record(&self, endpoint: &Uri, latency: Duration) {
+        let mut averages = lock(&self.averages);
+        let now = Instant::now();
+        let entry = averages
+            .entry(endpoint.to_string())
+            .or_insert((latency, now));
+
+        entry.0 = if now.duration_since(entry.1) < MEASUREMENT_LIFETIME {
+            entry.0.mul_f64(1.0 - SMOOTHING) + latency.mul_f64(SMOOTHING)
+        } else {
+            latency
+        };
+        entry.1 = now;
+    }
+}
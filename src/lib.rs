@@ -1,27 +1,34 @@
 //! Crate `etcd` provides a client for [etcd](https://github.com/coreos/etcd), a distributed
 //! key-value store from [CoreOS](https://coreos.com/).
 //!
-//! The client uses etcd's v2 API. Support for the v3 API is planned, and will be added via
-//! separate types for backwards compatibility and to support both APIs simultaneously.
+//! The client uses etcd's v2 API. Parts of the v3 API are available in the `v3` module, which uses
+//! the same client, so both APIs can be used simultaneously. The `any` module offers a key-value
+//! client that uses whichever of the two APIs the cluster supports.
 //!
 //! The client uses asynchronous I/O, backed by the `futures` and `tokio` crates, and requires
 //! both to be used alongside. Where possible, futures are returned using "impl Trait" instead of
 //! boxing.
 //!
-//! The client is tested against etcd 2.3.8.
+//! The client is tested against etcd 2.2 and 2.3, and against the v2 API of etcd 3.3.
 //!
 //! # Usage
 //!
 //! `Client` is an HTTP client required for all API calls. It can be constructed to use HTTP or
 //! HTTPS, and supports authenticating to the etcd cluster via HTTP basic authentication (username
 //! and password) and/or X.509 client certificates.
+//! Cluster members' hostnames can be resolved with a custom resolver; see the `dns` module.
 //!
 //! To get basic information about the health and versions of etcd running in a cluster, use the
 //! `Client::health` and `Client::versions` methods, respectively. All other API calls are made by
 //! passing a `Client` reference to the functions in the `auth`, `kv`, `members`, and `stats`
 //! modules. These modules contain functions for API calls to the authentication and authorization
 //! API, the primary key-value store API, the cluster membership API, and statistics API,
-//! respectively.
+//! respectively. The `mirror` module builds on the key-value API to replicate keys from one
+//! cluster to another, the `journal` module to keep a durable record of the changes to them, and
+//! the `bus` module to share one watch among many consumers. The `multi` module fails API calls
+//! over from a primary cluster to fallback clusters. The `semaphore` module limits how many
+//! clients across a fleet do something at once, the `sequence` module allocates cluster-wide
+//! unique IDs, and the `schedule` module runs scheduled jobs on exactly one of several instances.
 //!
 //! # Examples
 //!
@@ -66,22 +73,79 @@
 //!
 //! # Cargo features
 //!
-//! Crate `etcd` has one Cargo feature, `tls`, which adds HTTPS support via the `Client::https`
-//! constructor. This feature is enabled by default.
+//! Crate `etcd` has the following Cargo features:
+//!
+//! * tls: Adds HTTPS support via the `Client::https` constructor, and `Client::https_with_tls` for
+//!   certificates that can be reloaded. This feature is enabled by default.
+//! * chrono: Adds `kv::Node::expires_at`, which parses a node's expiration time.
+//! * compression: Adds the `codec::Compressed` codec, which compresses large values with gzip or
+//!   Zstandard.
+//! * config: Adds `ConfigSource`, a `config` crate source that reads the keys under a prefix.
+//! * encryption: Adds the `codec::Aes256Gcm` encryptor, for encrypting values with AES-256-GCM.
+//! * otel: Traces requests with OpenTelemetry spans; see `Client::with_trace_context`.
+//! * prometheus: Adds `stats::prometheus_export`, which renders cluster statistics in the
+//!   Prometheus text exposition format.
+//! * streaming: Adds `kv::get_streaming`, which deserializes the children of a large directory as
+//!   the response arrives instead of buffering it.
+//! * zeroize: Overwrites the client's copies of passwords and v3 API tokens with zeros.
 #![deny(missing_debug_implementations, missing_docs, warnings)]
 
-pub use crate::client::{BasicAuth, Client, ClusterInfo, Health, Response};
-pub use crate::error::{ApiError, Error};
-pub use crate::version::VersionInfo;
+pub use crate::cancellation::CancellationToken;
+pub use crate::client::{
+    Backoff,
+    BasicAuth,
+    Client,
+    ClusterInfo,
+    ConnectionOptions,
+    EndpointHealth,
+    EndpointSelection,
+    Health,
+    RequestOptions,
+    Response,
+};
+#[cfg(feature = "config")]
+pub use crate::config_source::ConfigSource;
+pub use crate::error::{AccessDenied, ApiError, CompareFailure, Error, RetryClass, RpcError};
+pub use crate::logging::DebugLogging;
+#[cfg(feature = "tls")]
+pub use crate::tls::{ReloadableHttpsConnector, TlsOptions};
+pub use crate::version::{ClusterVersions, Feature, VersionInfo};
 
+pub mod any;
 pub mod auth;
+pub mod bus;
+pub mod codec;
+pub mod dns;
+pub mod journal;
 pub mod kv;
 pub mod members;
+pub mod mirror;
+pub mod multi;
+pub mod prelude;
+pub mod schedule;
+pub mod semaphore;
+pub mod sequence;
 pub mod stats;
+pub mod v3;
 
+mod cancellation;
 mod client;
+#[cfg(feature = "config")]
+mod config_source;
 mod error;
 mod first_ok;
 mod http;
+mod latency;
+mod logging;
 mod options;
+#[cfg(feature = "otel")]
+mod otel;
+mod secret;
+mod sync;
+#[cfg(feature = "tls")]
+mod tls;
 mod version;
+
+/// The result of an operation on an etcd cluster: a response, or an error for each cluster member
+/// that was tried.
+pub type Result<T> = std::result::Result<Response<T>, Vec<Error>>;
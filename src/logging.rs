@@ -0,0 +1,242 @@
+//! Logging of the requests a client makes, enabled with `Client::with_debug_logging`.
+
+use std::sync::Arc;
+
+use http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use hyper::{Method, StatusCode, Uri};
+use serde_json::Value;
+use url::form_urlencoded::{parse, Serializer};
+use url::percent_encoding::percent_decode;
+use url::Url;
+
+/// The text logged in place of a secret.
+const REDACTED: &str = "[REDACTED]";
+
+/// Settings for logging every request a client makes.
+///
+/// Each request is logged with the `log` crate at the debug level once it completes, with its
+/// method, URL, headers, status, and latency, and optionally the request and response bodies.
+/// `Authorization` headers and `password` fields in JSON bodies are always redacted, as are the
+/// values of keys under any of `secret_prefixes`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DebugLogging {
+    /// The most bytes of each request and response body to log. Bodies are not logged if this is
+    /// zero. Logging response bodies requires receiving them in full before they are returned, so
+    /// this should stay zero for clients used with `kv::get_streaming`. The bodies of the v3
+    /// API's streaming and waiting responses, e.g. of `v3::kv::watch` and
+    /// `v3::election::observe`, are never logged.
+    pub max_body_bytes: usize,
+    /// Prefixes of keys whose values are secret, e.g. `/credentials`. Values of these keys in the
+    /// key-value API's URLs and bodies are redacted.
+    pub secret_prefixes: Vec<String>,
+}
+
+/// The details of a request to log once it completes.
+#[derive(Debug)]
+pub struct RequestLog {
+    body: Option<String>,
+    headers: String,
+    method: Method,
+    /// Whether or not the request operates on a secret key.
+    secret: bool,
+    settings: Arc<DebugLogging>,
+    url: String,
+}
+
+impl RequestLog {
+    /// Records the redacted details of a request.
+    pub fn new(
+        settings: &Arc<DebugLogging>,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap<HeaderValue>,
+        body: Option<&str>,
+    ) -> Self {
+        let secret = request_key(uri).is_some_and(|key| settings.is_secret(&key));
+
+        RequestLog {
+            body: body.map(|body| settings.redact_body(body.as_bytes(), secret)),
+            headers: redact_headers(headers),
+            method: method.clone(),
+            secret,
+            settings: settings.clone(),
+            url: redact_url(uri, secret),
+        }
+    }
+
+    /// Returns whether or not the response body must be received in full to be logged.
+    pub fn logs_bodies(&self) -> bool {
+        self.settings.max_body_bytes > 0
+    }
+
+    /// Logs a request that received a response.
+    pub fn response(&self, status: StatusCode, latency_ms: u128, body: Option<&[u8]>) {
+        let mut message = format!(
+            "{} {} [{}] -> {} in {} ms",
+            self.method, self.url, self.headers, status, latency_ms
+        );
+
+        if self.logs_bodies() {
+            if let Some(ref body) = self.body {
+                message.push_str(&format!(", request body: {}", body));
+            }
+
+            if let Some(body) = body {
+                let body = self.settings.redact_body(body, self.secret);
+
+                message.push_str(&format!(", response body: {}", body));
+            }
+        }
+
+        log::debug!("{}", message);
+    }
+
+    /// Logs a request that failed without a response.
+    pub fn failure(&self, latency_ms: u128, error: &dyn std::fmt::Display) {
+        log::debug!(
+            "{} {} [{}] -> failed in {} ms: {}",
+            self.method,
+            self.url,
+            self.headers,
+            latency_ms,
+            error
+        );
+    }
+}
+
+impl DebugLogging {
+    /// Returns whether or not a key's value is secret.
+    fn is_secret(&self, key: &str) -> bool {
+        self.secret_prefixes
+            .iter()
+            .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+
+    /// Redacts a request or response body and shortens it to `max_body_bytes`.
+    fn redact_body(&self, body: &[u8], secret: bool) -> String {
+        let redacted = match serde_json::from_slice::<Value>(body) {
+            Ok(mut value) => {
+                self.redact_json(&mut value, secret);
+
+                value.to_string()
+            }
+            Err(_) if secret => redact_form(body),
+            Err(_) => String::from_utf8_lossy(body).into_owned(),
+        };
+
+        truncate(redacted, self.max_body_bytes)
+    }
+
+    /// Redacts passwords, and the values and comparison failures of secret keys, in JSON.
+    fn redact_json(&self, value: &mut Value, secret: bool) {
+        match *value {
+            Value::Object(ref mut fields) => {
+                let secret_node = fields
+                    .get("key")
+                    .and_then(Value::as_str)
+                    .is_some_and(|key| self.is_secret(key));
+
+                for (name, field) in fields.iter_mut() {
+                    let redact = match name.as_str() {
+                        "password" => true,
+                        "value" => secret_node,
+                        "cause" => secret,
+                        _ => false,
+                    };
+
+                    if redact {
+                        *field = Value::String(REDACTED.to_owned());
+                    } else {
+                        self.redact_json(field, secret);
+                    }
+                }
+            }
+            Value::Array(ref mut items) => {
+                for item in items {
+                    self.redact_json(item, secret);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the key a request to the key-value API operates on, if it is one.
+fn request_key(uri: &Uri) -> Option<String> {
+    let path = uri.path();
+    let start = path.find("/v2/keys")? + "/v2/keys".len();
+    let key = percent_decode(&path.as_bytes()[start..])
+        .decode_utf8()
+        .ok()?;
+
+    Some(if key.is_empty() {
+        "/".to_owned()
+    } else {
+        key.into_owned()
+    })
+}
+
+/// Replaces the value of every header that carries credentials.
+fn redact_headers(headers: &HeaderMap<HeaderValue>) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            if name == AUTHORIZATION {
+                format!("{}: {}", name, REDACTED)
+            } else {
+                format!("{}: {}", name, value.to_str().unwrap_or("<binary>"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Replaces the values in a form-encoded request body to the key-value API.
+fn redact_form(body: &[u8]) -> String {
+    let pairs = parse(body).map(|(name, value)| match name.as_ref() {
+        "value" | "prevValue" => (name.into_owned(), REDACTED.to_owned()),
+        _ => (name.into_owned(), value.into_owned()),
+    });
+
+    Serializer::new(String::new()).extend_pairs(pairs).finish()
+}
+
+/// Replaces the value a request compares a secret key against in its URL.
+fn redact_url(uri: &Uri, secret: bool) -> String {
+    let mut url = match Url::parse(&uri.to_string()) {
+        Ok(url) => url,
+        Err(_) => return uri.to_string(),
+    };
+
+    if secret {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| match name.as_ref() {
+                "prevValue" => (name.into_owned(), REDACTED.to_owned()),
+                _ => (name.into_owned(), value.into_owned()),
+            })
+            .collect();
+
+        if !pairs.is_empty() {
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+    }
+
+    url.into_string()
+}
+
+/// Shortens text to at most the given number of bytes, marking where it was cut.
+fn truncate(mut text: String, max_bytes: usize) -> String {
+    if text.len() > max_bytes {
+        let mut end = max_bytes;
+
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        text.truncate(end);
+        text.push_str("...");
+    }
+
+    text
+}
@@ -2,23 +2,113 @@
 //!
 //! These API endpoints are used to manage cluster membership.
 
+use std::fmt::{Display, Error as FmtError, Formatter};
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+use futures::future::{loop_fn, Either, Loop};
 use futures::{Future, IntoFuture, Stream};
 use hyper::client::connect::Connect;
 use hyper::{StatusCode, Uri};
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
+use tokio::timer::{Delay, Timeout};
 
-use crate::client::{Client, ClusterInfo, Response};
-use crate::error::{ApiError, Error};
+use crate::client::{Client, ClusterInfo, Health, Response};
+use crate::error::{AccessDenied, Error};
 use crate::first_ok::first_ok;
+use crate::http::endpoint_url;
+
+/// The unique identifier of a cluster member.
+///
+/// The v2 API, `etcdctl member list`, and this type's `Display` and `FromStr` implementations
+/// represent IDs in hexadecimal, e.g. `8e9e05c52164694d`. The v3 API and `etcdctl -w json` use
+/// decimal integers instead; see `MemberId::from_decimal`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MemberId(pub u64);
+
+impl MemberId {
+    /// Parses an ID in hexadecimal, as used by the v2 API.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidMemberId` if the string isn't a hexadecimal 64-bit integer.
+    pub fn from_hex(id: &str) -> Result<Self, Error> {
+        u64::from_str_radix(id, 16)
+            .map(MemberId)
+            .map_err(|_| Error::InvalidMemberId(id.to_owned()))
+    }
+
+    /// Parses an ID in decimal, as used by the v3 API.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidMemberId` if the string isn't a decimal 64-bit integer.
+    pub fn from_decimal(id: &str) -> Result<Self, Error> {
+        id.parse()
+            .map(MemberId)
+            .map_err(|_| Error::InvalidMemberId(id.to_owned()))
+    }
+
+    /// Formats the ID in decimal, as used by the v3 API.
+    pub fn to_decimal(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl Display for MemberId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{:x}", self.0)
+    }
+}
+
+impl FromStr for MemberId {
+    type Err = Error;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        MemberId::from_hex(id)
+    }
+}
+
+impl From<u64> for MemberId {
+    fn from(id: u64) -> Self {
+        MemberId(id)
+    }
+}
+
+impl From<MemberId> for u64 {
+    fn from(id: MemberId) -> Self {
+        id.0
+    }
+}
+
+impl Serialize for MemberId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MemberId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let id = String::deserialize(deserializer)?;
+
+        MemberId::from_hex(&id).map_err(D::Error::custom)
+    }
+}
 
 /// An etcd server that is a member of a cluster.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct Member {
-    /// An internal identifier for the cluster member.
-    pub id: String,
+    /// The unique identifier of the cluster member.
+    pub id: MemberId,
     /// A human-readable name for the cluster member.
     pub name: String,
     /// URLs exposing this cluster member's peer API.
@@ -37,6 +127,13 @@ struct PeerUrls {
     peer_urls: Vec<String>,
 }
 
+/// The body of an unsuccessful response from the members API.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+struct ErrorResponse {
+    /// A human-friendly description of the error.
+    message: String,
+}
+
 /// A small wrapper around `Member` to match the response of `GET /v2/members`.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 struct ListResponse {
@@ -50,10 +147,27 @@ struct ListResponse {
 ///
 /// * client: A `Client` to use to make the API call.
 /// * peer_urls: URLs exposing this cluster member's peer API.
+///
+/// # Errors
+///
+/// Fails with `Error::MemberConflict` if another member already uses the peer URLs.
 pub fn add<C>(
     client: &Client<C>,
     peer_urls: Vec<String>,
-) -> Box<dyn Future<Item = Response<()>, Error = Vec<Error>>>
+) -> impl Future<Item = Response<()>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    add_member(client, peer_urls).map(|response| response.map(|_| ()))
+}
+
+/// Adds a new member to the cluster, returning it as etcd recorded it.
+///
+/// The new member has no name or client URLs until it has started and joined the cluster.
+fn add_member<C>(
+    client: &Client<C>,
+    peer_urls: Vec<String>,
+) -> impl Future<Item = Response<Member>, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
@@ -61,44 +175,163 @@ where
 
     let body = match serde_json::to_string(&peer_urls) {
         Ok(body) => body,
-        Err(error) => return Box::new(Err(vec![Error::Serialization(error)]).into_future()),
+        Err(error) => return Either::A(Err(vec![Error::Serialization(error)]).into_future()),
     };
 
     let http_client = client.http_client().clone();
 
-    let result = first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, "");
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let body = body.clone();
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.post(uri, body).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::CREATED {
-                    Ok(Response {
-                        data: (),
-                        cluster_info,
-                    })
-                } else {
-                    match serde_json::from_slice::<ApiError>(body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
+    let result = first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, "");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let body = body.clone();
+            let http_client = http_client.clone();
+            let access = AccessDenied {
+                key: None,
+                user: http_client.username().map(str::to_owned),
+            };
+
+            let response =
+                uri.and_then(move |uri| http_client.post(uri, body).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::CREATED {
+                        match serde_json::from_slice::<Member>(body) {
+                            Ok(data) => Ok(Response { data, cluster_info }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        Err(error_from_response(status, body, access))
                     }
-                }
+                })
             })
-        })
-    });
+        },
+    );
+
+    Either::B(result)
+}
+
+/// Adds a new member to the cluster and waits until it has started and reports itself as
+/// healthy, returning it as it is then.
+///
+/// The new member is looked up every `interval` once it has been added. Once it has client URLs,
+/// they are used to check its health. Failures to look it up that may succeed when tried again,
+/// e.g. because the cluster is electing a leader as the member joins, are waited out like
+/// failed health checks.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * peer_urls: URLs exposing this cluster member's peer API.
+/// * interval: How long to wait between checks of the new member.
+/// * timeout: How long to wait for the new member to become healthy after it has been added.
+///
+/// # Errors
+///
+/// Fails if the member couldn't be added or the members couldn't be listed while waiting because of
+/// an error that isn't retryable, with `Error::Timeout` if it didn't become healthy in time, or with
+/// `Error::UnexpectedStatus(StatusCode::NOT_FOUND)` if it was removed meanwhile. The member is
+/// left in the cluster if waiting fails.
+pub fn add_and_wait<C>(
+    client: &Client<C>,
+    peer_urls: Vec<String>,
+    interval: Duration,
+    timeout: Duration,
+) -> impl Future<Item = Response<Member>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+
+    add_member(&client, peer_urls).and_then(move |response| {
+        let id = response.data.id;
+
+        let healthy = loop_fn((), move |()| {
+            let client = client.clone();
+
+            let delay = Delay::new(Instant::now() + interval).map_err(|_| vec![Error::Timeout]);
+
+            delay.and_then(move |()| {
+                list(&client).then(move |result| {
+                    let response = match result {
+                        Ok(response) => response,
+                        Err(ref errors) if errors.iter().all(Error::is_retryable) => {
+                            return Either::B(Ok(Loop::Continue(())).into_future());
+                        }
+                        Err(errors) => return Either::B(Err(errors).into_future()),
+                    };
+                    let cluster_info = response.cluster_info;
+                    let member = match response.data.into_iter().find(|member| member.id == id) {
+                        Some(member) => member,
+                        None => {
+                            let error = Error::UnexpectedStatus(StatusCode::NOT_FOUND);
+
+                            return Either::B(Err(vec![error]).into_future());
+                        }
+                    };
+
+                    Either::A(
+                        is_healthy(&client, &member).then(move |result| match result {
+                            Ok(true) => Ok(Loop::Break(Response {
+                                data: member,
+                                cluster_info,
+                            })),
+                            // The member isn't serving requests until it has joined the cluster.
+                            Ok(false) | Err(_) => Ok(Loop::Continue(())),
+                        }),
+                    )
+                })
+            })
+        });
 
-    Box::new(result)
+        Timeout::new(healthy, timeout)
+            .map_err(|error| error.into_inner().unwrap_or_else(|| vec![Error::Timeout]))
+    })
+}
+
+/// Renders the members of the cluster in the form etcd's `--initial-cluster` flag takes, e.g.
+/// `infra0=http://10.0.0.1:2380,infra1=http://10.0.0.2:2380`.
+///
+/// Members that haven't started yet have no name, so they are left out. A member being added
+/// needs to append its own entry, e.g. `format!("{},{}={}", cluster, name, peer_url)`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+pub fn initial_cluster_string<C>(
+    client: &Client<C>,
+) -> impl Future<Item = Response<String>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    list(client).map(|response| {
+        let entries: Vec<String> = response
+            .data
+            .iter()
+            .filter(|member| !member.name.is_empty())
+            .flat_map(|member| {
+                member
+                    .peer_urls
+                    .iter()
+                    .map(move |url| format!("{}={}", member.name, url))
+            })
+            .collect();
+
+        Response {
+            data: entries.join(","),
+            cluster_info: response.cluster_info,
+        }
+    })
 }
 
 /// Deletes a member from the cluster.
@@ -109,43 +342,48 @@ where
 /// * id: The unique identifier of the member to delete.
 pub fn delete<C>(
     client: &Client<C>,
-    id: String,
+    id: MemberId,
 ) -> impl Future<Item = Response<()>, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, &format!("/{}", id));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::NO_CONTENT {
-                    Ok(Response {
-                        data: (),
-                        cluster_info,
-                    })
-                } else {
-                    match serde_json::from_slice::<ApiError>(body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, &format!("/{}", id));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+            let access = AccessDenied {
+                key: None,
+                user: http_client.username().map(str::to_owned),
+            };
+
+            let response = uri.and_then(move |uri| http_client.delete(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::NO_CONTENT {
+                        Ok(Response {
+                            data: (),
+                            cluster_info,
+                        })
+                    } else {
+                        Err(Error::from_response(status, body, access))
                     }
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
 /// Lists the members of the cluster.
@@ -161,53 +399,92 @@ where
 {
     let http_client = client.http_client().clone();
 
-    first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, "");
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::OK {
-                    match serde_json::from_slice::<ListResponse>(body) {
-                        Ok(data) => Ok(Response {
-                            data: data.members,
-                            cluster_info,
-                        }),
-                        Err(error) => Err(Error::Serialization(error)),
-                    }
-                } else {
-                    match serde_json::from_slice::<ApiError>(body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, "");
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let http_client = http_client.clone();
+            let access = AccessDenied {
+                key: None,
+                user: http_client.username().map(str::to_owned),
+            };
+
+            let response = uri.and_then(move |uri| http_client.get(uri).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::OK {
+                        match serde_json::from_slice::<ListResponse>(body) {
+                            Ok(data) => Ok(Response {
+                                data: data.members,
+                                cluster_info,
+                            }),
+                            Err(error) => Err(Error::Serialization(error)),
+                        }
+                    } else {
+                        Err(Error::from_response(status, body, access))
                     }
-                }
+                })
             })
-        })
-    })
+        },
+    )
 }
 
-/// Updates the peer URLs of a member of the cluster.
+/// Updates the peer URLs of a member of the cluster, returning the member as it is afterwards.
+///
+/// The member is fetched with a second API call once the update has been made.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
 /// * id: The unique identifier of the member to update.
 /// * peer_urls: URLs exposing this cluster member's peer API.
+///
+/// # Errors
+///
+/// Fails with `Error::MemberConflict` if another member already uses the peer URLs, and with
+/// `Error::UnexpectedStatus(StatusCode::NOT_FOUND)` if the member was removed before it could be
+/// fetched.
 pub fn update<C>(
     client: &Client<C>,
-    id: String,
+    id: MemberId,
     peer_urls: Vec<String>,
-) -> Box<dyn Future<Item = Response<()>, Error = Vec<Error>>>
+) -> impl Future<Item = Response<Member>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let list_client = client.clone();
+
+    update_peer_urls(client, id, peer_urls)
+        .and_then(move |_| list(&list_client))
+        .and_then(move |response| {
+            let cluster_info = response.cluster_info;
+
+            match response.data.into_iter().find(|member| member.id == id) {
+                Some(member) => Ok(Response {
+                    data: member,
+                    cluster_info,
+                }),
+                None => Err(vec![Error::UnexpectedStatus(StatusCode::NOT_FOUND)]),
+            }
+        })
+}
+
+/// Makes the API call to update the peer URLs of a member of the cluster.
+fn update_peer_urls<C>(
+    client: &Client<C>,
+    id: MemberId,
+    peer_urls: Vec<String>,
+) -> impl Future<Item = Response<()>, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
@@ -215,47 +492,94 @@ where
 
     let body = match serde_json::to_string(&peer_urls) {
         Ok(body) => body,
-        Err(error) => return Box::new(Err(vec![Error::Serialization(error)]).into_future()),
+        Err(error) => return Either::A(Err(vec![Error::Serialization(error)]).into_future()),
     };
 
     let http_client = client.http_client().clone();
 
-    let result = first_ok(client.endpoints().to_vec(), move |member| {
-        let url = build_url(member, &format!("/{}", id));
-        let uri = Uri::from_str(url.as_str())
-            .map_err(Error::from)
-            .into_future();
-
-        let body = body.clone();
-        let http_client = http_client.clone();
-
-        let response = uri.and_then(move |uri| http_client.put(uri, body).map_err(Error::from));
-
-        response.and_then(|response| {
-            let status = response.status();
-            let cluster_info = ClusterInfo::from(response.headers());
-            let body = response.into_body().concat2().map_err(Error::from);
-
-            body.and_then(move |ref body| {
-                if status == StatusCode::NO_CONTENT {
-                    Ok(Response {
-                        data: (),
-                        cluster_info,
-                    })
-                } else {
-                    match serde_json::from_slice::<ApiError>(body) {
-                        Ok(error) => Err(Error::Api(error)),
-                        Err(error) => Err(Error::Serialization(error)),
+    let result = first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |member| {
+            let url = build_url(member, &format!("/{}", id));
+            let uri = Uri::from_str(url.as_str())
+                .map_err(Error::from)
+                .into_future();
+
+            let body = body.clone();
+            let http_client = http_client.clone();
+            let access = AccessDenied {
+                key: None,
+                user: http_client.username().map(str::to_owned),
+            };
+
+            let response = uri.and_then(move |uri| http_client.put(uri, body).map_err(Error::from));
+
+            response.and_then(|response| {
+                let status = response.status();
+                let cluster_info = ClusterInfo::from(response.headers());
+                let body = response.into_body().concat2().map_err(Error::from);
+
+                body.and_then(move |ref body| {
+                    if status == StatusCode::NO_CONTENT {
+                        Ok(Response {
+                            data: (),
+                            cluster_info,
+                        })
+                    } else {
+                        Err(error_from_response(status, body, access))
                     }
-                }
+                })
             })
-        })
+        },
+    );
+
+    Either::B(result)
+}
+
+/// Checks whether or not a member reports itself as healthy at any of its client URLs.
+fn is_healthy<C>(
+    client: &Client<C>,
+    member: &Member,
+) -> impl Future<Item = bool, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let endpoints: Vec<Uri> = member
+        .client_urls
+        .iter()
+        .filter_map(|url| url.parse().ok())
+        .collect();
+
+    if endpoints.is_empty() {
+        return Either::A(Ok(false).into_future());
+    }
+
+    let request_client = client.clone();
+
+    let result = first_ok(endpoints, client.request_options(), move |endpoint| {
+        let url = endpoint_url(endpoint, "health");
+        let uri = url.parse().map_err(Error::from).into_future();
+
+        request_client.request::<_, Health>(uri)
     });
 
-    Box::new(result)
+    Either::B(result.map(|response| response.data.healthy))
+}
+
+/// Converts an unsuccessful response from the members API into an error.
+fn error_from_response(status: StatusCode, body: &[u8], access: AccessDenied) -> Error {
+    if status != StatusCode::CONFLICT {
+        return Error::from_response(status, body, access);
+    }
+
+    match serde_json::from_slice::<ErrorResponse>(body) {
+        Ok(error) => Error::MemberConflict(error.message),
+        Err(_) => Error::MemberConflict(String::from_utf8_lossy(body).into_owned()),
+    }
 }
 
 /// Constructs the full URL for an API call.
 fn build_url(endpoint: &Uri, path: &str) -> String {
-    format!("{}v2/members{}", endpoint, path)
+    endpoint_url(endpoint, &format!("v2/members{}", path))
 }
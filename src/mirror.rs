@@ -0,0 +1,190 @@
+//! Replication of keys from one etcd cluster to another.
+//!
+//! This is a client-side equivalent of etcd's `mirror-maker`: the nodes under a prefix in a source
+//! cluster are copied to a destination cluster, and every subsequent change to them is applied
+//! to the destination as well.
+
+use futures::future::{Either, Future, IntoFuture};
+use futures::stream::Stream;
+use hyper::client::connect::Connect;
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::kv::{
+    self,
+    has_error_code,
+    normalize_prefix,
+    Action,
+    ImportOptions,
+    KeyValueInfo,
+    WatchItem,
+    WatchOptions,
+    KEY_NOT_FOUND,
+    NODE_EXIST,
+};
+
+/// Options for customizing the behavior of `mirror::sync` and `mirror::mirror`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct MirrorOptions {
+    /// If given, nodes are written under this key in the destination cluster instead of under
+    /// the source prefix.
+    pub destination_prefix: Option<String>,
+    /// If given, `mirror::mirror` skips the initial sync and applies only the changes made after
+    /// this etcd index. This is usually the last index yielded by a previous mirror.
+    pub resume_from: Option<u64>,
+}
+
+/// Copies all the nodes under a prefix from one cluster to another.
+///
+/// Existing key-value pairs in the destination are replaced. Nodes in the destination that don't
+/// exist in the source are left in place.
+///
+/// # Parameters
+///
+/// * source: A `Client` for the cluster to copy from.
+/// * destination: A `Client` for the cluster to copy to.
+/// * prefix: The name of the node to copy.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if the prefix doesn't exist in the source cluster or if any write to the destination
+/// cluster fails. Succeeds with the source cluster's etcd index at the time the nodes were read.
+pub fn sync<C, D>(
+    source: &Client<C>,
+    destination: &Client<D>,
+    prefix: &str,
+    options: &MirrorOptions,
+) -> impl Future<Item = u64, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    D: Clone + Connect,
+{
+    let destination = destination.clone();
+    let import_options = ImportOptions {
+        overwrite: true,
+        prefix: options.destination_prefix.clone(),
+    };
+
+    kv::export(source, prefix).and_then(move |snapshot| {
+        let index = snapshot.index.unwrap_or_default();
+
+        kv::import(&destination, snapshot, import_options).map(move |_| index)
+    })
+}
+
+/// Continuously mirrors the nodes under a prefix from one cluster to another.
+///
+/// Unless `MirrorOptions::resume_from` is given, the nodes are first copied with `mirror::sync`.
+/// After that, the source cluster is watched and each change is applied to the destination.
+///
+/// The stream yields the source etcd index of each change once it has been applied. Storing the
+/// last one and passing it as `MirrorOptions::resume_from` lets a mirror pick up where a previous
+/// one stopped, as long as etcd still has the history for that index.
+///
+/// # Parameters
+///
+/// * source: A `Client` for the cluster to copy from.
+/// * destination: A `Client` for the cluster to copy to.
+/// * prefix: The name of the node to mirror.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// The stream ends with an error if the initial sync fails, if watching the source cluster fails,
+/// or if a change could not be applied to the destination cluster.
+pub fn mirror<C, D>(
+    source: &Client<C>,
+    destination: &Client<D>,
+    prefix: &str,
+    options: MirrorOptions,
+) -> impl Stream<Item = u64, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+    D: Clone + Connect + Sync + 'static,
+{
+    let source = source.clone();
+    let destination = destination.clone();
+    let source_prefix = normalize_prefix(prefix);
+    let destination_prefix = options
+        .destination_prefix
+        .as_ref()
+        .map(|prefix| normalize_prefix(prefix))
+        .unwrap_or_else(|| source_prefix.clone());
+
+    let start = match options.resume_from {
+        Some(index) => Either::A(Ok(index).into_future()),
+        None => Either::B(sync(&source, &destination, prefix, &options)),
+    };
+
+    start
+        .map(move |index| {
+            let watch_options = WatchOptions {
+                index: Some(index + 1),
+                recursive: true,
+                ..Default::default()
+            };
+            let key = if source_prefix.is_empty() {
+                "/"
+            } else {
+                source_prefix.as_str()
+            };
+
+            kv::watch_stream(&source, key, watch_options)
+                .filter_map(|item| match item {
+                    WatchItem::Event(response) => Some(response.data),
+                    WatchItem::Idle => None,
+                })
+                .and_then(move |event| {
+                    apply(&destination, &source_prefix, &destination_prefix, event)
+                })
+        })
+        .flatten_stream()
+}
+
+/// Applies a change from the source cluster to the destination cluster.
+fn apply<C>(
+    client: &Client<C>,
+    source_prefix: &str,
+    destination_prefix: &str,
+    event: KeyValueInfo,
+) -> Box<dyn Future<Item = u64, Error = Vec<Error>> + Send>
+where
+    C: Clone + Connect,
+{
+    let node = event.node;
+    let index = node.modified_index.unwrap_or_default();
+
+    let key = match node.key {
+        Some(ref key) if key.starts_with(source_prefix) => {
+            format!("{}{}", destination_prefix, &key[source_prefix.len()..])
+        }
+        _ => return Box::new(Ok(index).into_future()),
+    };
+    let dir = node.dir == Some(true);
+    let ttl = node.ttl.filter(|&ttl| ttl > 0).map(|ttl| ttl as u64);
+
+    match event.action {
+        Action::CompareAndDelete | Action::Delete | Action::Expire => Box::new(
+            kv::delete(client, &key, dir).then(move |result| match result {
+                Ok(_) => Ok(index),
+                Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(index),
+                Err(errors) => Err(errors),
+            }),
+        ),
+        Action::Get => Box::new(Ok(index).into_future()),
+        Action::Update if dir => Box::new(kv::update_dir(client, &key, ttl).map(move |_| index)),
+        _ if dir => Box::new(
+            kv::create_dir(client, &key, ttl).then(move |result| match result {
+                Ok(_) => Ok(index),
+                Err(ref errors) if has_error_code(errors, NODE_EXIST) => Ok(index),
+                Err(errors) => Err(errors),
+            }),
+        ),
+        _ => {
+            let value = node.value.unwrap_or_default();
+
+            Box::new(kv::set(client, &key, &value, ttl).map(move |_| index))
+        }
+    }
+}
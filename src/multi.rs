@@ -0,0 +1,216 @@
+//! Failing over between etcd clusters.
+//!
+//! `multi::Client` wraps the clients of a primary cluster and one or more fallback clusters, e.g.
+//! a standby cluster in another data center. API calls are made to the primary cluster, and only
+//! made to a fallback cluster when no member of the clusters before it could be reached. Errors
+//! from a cluster that could be reached, such as a key not being found, are returned as they are.
+//! Members that timed out only count as unreachable with `multi::Client::with_timeout_failover`.
+//!
+//! This module doesn't copy keys between the clusters. Use the `mirror` module to keep a fallback
+//! cluster up to date with the primary.
+
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::sync::Arc;
+
+use futures::future::{loop_fn, Future, IntoFuture, Loop};
+use hyper::client::connect::Connect;
+use log::warn;
+
+use crate::client::Client as V2Client;
+use crate::error::{Error, RetryClass};
+
+/// A function called when an API call fails over to another cluster.
+type FailoverHook = Arc<dyn Fn(&Failover<'_>) + Send + Sync>;
+
+/// The details of an API call failing over from one cluster to another, passed to the hook given
+/// to `multi::Client::with_failover_hook`.
+#[derive(Debug)]
+pub struct Failover<'a> {
+    /// The position of the cluster that couldn't be reached, where the primary cluster is 0 and
+    /// the fallback clusters follow in order.
+    pub from: usize,
+    /// The position of the cluster the API call is made to next.
+    pub to: usize,
+    /// The errors from the cluster that couldn't be reached.
+    pub errors: &'a [Error],
+}
+
+/// A client for a primary etcd cluster that fails over to fallback clusters.
+#[derive(Clone)]
+pub struct Client<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    clusters: Vec<V2Client<C>>,
+    /// Whether members that timed out count as unreachable.
+    fail_over_on_timeout: bool,
+    hook: Option<FailoverHook>,
+}
+
+impl<C> Client<C>
+where
+    C: Clone + Connect,
+{
+    /// Constructs a client for a primary cluster and the fallback clusters to try, in order, when
+    /// it can't be reached.
+    ///
+    /// # Parameters
+    ///
+    /// * primary: The `Client` to make API calls to the primary cluster with.
+    /// * fallbacks: The `Client`s to make API calls to the fallback clusters with.
+    pub fn new(primary: &V2Client<C>, fallbacks: &[V2Client<C>]) -> Self {
+        let mut clusters = Vec::with_capacity(fallbacks.len() + 1);
+
+        clusters.push(primary.clone());
+        clusters.extend(fallbacks.iter().cloned());
+
+        Client {
+            clusters,
+            fail_over_on_timeout: false,
+            hook: None,
+        }
+    }
+
+    /// Returns a client that calls the given function each time an API call fails over to
+    /// another cluster, e.g. to record metrics or alert an operator.
+    ///
+    /// The function is called on the thread that polls the API call, so it shouldn't block.
+    pub fn with_failover_hook<F>(&self, hook: F) -> Self
+    where
+        F: Fn(&Failover<'_>) + Send + Sync + 'static,
+    {
+        Client {
+            clusters: self.clusters.clone(),
+            fail_over_on_timeout: self.fail_over_on_timeout,
+            hook: Some(Arc::new(hook)),
+        }
+    }
+
+    /// Returns a client that also fails over when every member of a cluster that could be
+    /// connected to timed out.
+    ///
+    /// This is only safe for reads and idempotent writes: a member that timed out may still have
+    /// applied a write, which would then be applied to the next cluster as well.
+    pub fn with_timeout_failover(&self) -> Self {
+        Client {
+            clusters: self.clusters.clone(),
+            fail_over_on_timeout: true,
+            hook: self.hook.clone(),
+        }
+    }
+
+    /// Returns the `Client` for the primary cluster.
+    pub fn primary(&self) -> &V2Client<C> {
+        &self.clusters[0]
+    }
+
+    /// Returns the `Client`s for the primary cluster and the fallback clusters, in the order they
+    /// are tried.
+    pub fn clusters(&self) -> &[V2Client<C>] {
+        &self.clusters
+    }
+
+    /// Makes an API call to the first cluster that can be reached.
+    ///
+    /// `f` makes the API call with the `Client` for a cluster, e.g.
+    /// `|client| kv::get(client, "/foo", GetOptions::default())`. It is called with the primary
+    /// cluster's client first, and with the next cluster's client each time every error from the
+    /// call is one of not being able to connect to a cluster member, or, with
+    /// `multi::Client::with_timeout_failover`, of a member timing out.
+    ///
+    /// Without timeout failover, a write is only failed over if no member of the cluster could be
+    /// connected to, so none of them can have applied it.
+    ///
+    /// # Parameters
+    ///
+    /// * f: A function that makes the API call with a `Client`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the errors from the first cluster that could be reached, or with the errors
+    /// from every cluster if none could be reached.
+    pub fn call<F, R>(&self, f: F) -> impl Future<Item = R::Item, Error = Vec<Error>> + Send
+    where
+        F: Fn(&V2Client<C>) -> R + Send + 'static,
+        R: IntoFuture<Error = Vec<Error>>,
+        R::Future: Send + 'static,
+        R::Item: Send + 'static,
+    {
+        let clusters = self.clusters.clone();
+        let fail_over_on_timeout = self.fail_over_on_timeout;
+        let hook = self.hook.clone();
+
+        loop_fn(
+            (0, vec![]),
+            move |(position, mut errors): (usize, Vec<Error>)| {
+                let next = position + 1;
+                let last = next == clusters.len();
+                let hook = hook.clone();
+
+                f(&clusters[position])
+                    .into_future()
+                    .then(move |result| match result {
+                        Ok(item) => Ok(Loop::Break(item)),
+                        Err(cluster_errors) => {
+                            if !is_unreachable(&cluster_errors, fail_over_on_timeout) {
+                                return Err(cluster_errors);
+                            }
+
+                            if !last {
+                                warn!(
+                                    "failing over from cluster {} to cluster {}: {:?}",
+                                    position, next, cluster_errors
+                                );
+
+                                if let Some(hook) = hook {
+                                    hook(&Failover {
+                                        from: position,
+                                        to: next,
+                                        errors: &cluster_errors,
+                                    });
+                                }
+                            }
+
+                            errors.extend(cluster_errors);
+
+                            if last {
+                                Err(errors)
+                            } else {
+                                Ok(Loop::Continue((next, errors)))
+                            }
+                        }
+                    })
+            },
+        )
+    }
+}
+
+impl<C> Debug for Client<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        let endpoints: Vec<_> = self.clusters.iter().map(V2Client::endpoints).collect();
+
+        f.debug_struct("Client")
+            .field("clusters", &endpoints)
+            .field("fail_over_on_timeout", &self.fail_over_on_timeout)
+            .field("hook", &self.hook.is_some())
+            .finish()
+    }
+}
+
+/// Returns whether or not the errors from an API call show that no member of a cluster could be
+/// reached: each of them can be retried straight away without a member having responded, because
+/// the connection failed, or, if `timeouts` is true, because the member timed out.
+fn is_unreachable(errors: &[Error], timeouts: bool) -> bool {
+    !errors.is_empty()
+        && errors.iter().all(|error| {
+            error.retry_class() == RetryClass::Immediately
+                && match *error {
+                    Error::Http(ref error) | Error::Request { ref error, .. } => error.is_connect(),
+                    Error::Timeout => timeouts,
+                    _ => false,
+                }
+        })
+}
@@ -1,5 +1,8 @@
+//! Options for the low-level key-value operations, shared by the functions in `kv` and exposed
+//! publicly through `kv::raw`.
+
 /// Possible conditions for "compare and delete" and "compare and swap" operations.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct ComparisonConditions<'a> {
     /// The etcd modified index the key must have before the operation is performed.
     pub modified_index: Option<u64>,
@@ -15,7 +18,7 @@ impl<'a> ComparisonConditions<'a> {
 }
 
 /// Controls the various different ways a delete operation can be performed.
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct DeleteOptions<'a> {
     /// Conditions used for "compare and delete" operations.
     pub conditions: Option<ComparisonConditions<'a>>,
@@ -26,7 +29,7 @@ pub struct DeleteOptions<'a> {
 }
 
 /// Controls the various different ways a get operation can be performed.
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct GetOptions {
     /// Whether or not to use read linearization to avoid stale data.
     pub strong_consistency: bool,
@@ -41,7 +44,7 @@ pub struct GetOptions {
 }
 
 /// Controls the various different ways a create, update, or set operation can be performed.
-#[derive(Debug, Default)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct SetOptions<'a> {
     /// Conditions used for "compare and swap" operations.
     pub conditions: Option<ComparisonConditions<'a>>,
@@ -49,8 +52,14 @@ pub struct SetOptions<'a> {
     pub create_in_order: bool,
     /// Whether or not the key being operated on is or should be a directory.
     pub dir: Option<bool>,
+    /// Whether or not to leave the node out of a successful response, so large values aren't sent
+    /// back. The response's `KeyValueInfo::node` will be empty.
+    pub no_value_on_success: bool,
     /// Whether or not the key being operated on must already exist.
     pub prev_exist: Option<bool>,
+    /// Whether or not to only reset the TTL of an existing key, without changing its value or
+    /// notifying watchers.
+    pub refresh: bool,
     /// Time to live in seconds.
     pub ttl: Option<u64>,
     /// New value for the key.
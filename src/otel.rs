@@ -0,0 +1,171 @@
+//! OpenTelemetry spans for the requests a client makes, enabled with the "otel" Cargo feature.
+
+use std::fmt::{Debug, Error as FmtError, Formatter};
+
+use futures::Future;
+use http::header::HeaderValue;
+use hyper::{Body, Request, Response, StatusCode, Uri};
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::{Array, Context, KeyValue, StringValue, Value};
+
+use crate::error::Error;
+
+/// The name of the instrumentation scope the spans are recorded under.
+const TRACER_NAME: &str = "etcd";
+
+/// The attribute that records a request's `X-Request-Id` header, named as the semantic conventions
+/// name request header attributes.
+const REQUEST_ID_ATTRIBUTE: &str = "http.request.header.x-request-id";
+
+/// The W3C Trace Context header that identifies the span a request belongs to.
+const TRACEPARENT: &str = "traceparent";
+
+/// The W3C Trace Context header that carries vendor-specific trace data.
+const TRACESTATE: &str = "tracestate";
+
+/// A client span for one request to a cluster member.
+pub struct RequestSpan(BoxedSpan);
+
+impl RequestSpan {
+    /// Starts a span for a request and adds its trace context headers to the request.
+    ///
+    /// The span is a child of the span in `parent`, or of the current context's span if no parent
+    /// is given. Its attributes follow the OpenTelemetry semantic conventions for HTTP clients,
+    /// and include the ID the request is sent with in its `X-Request-Id` header.
+    pub fn start<B>(parent: Option<&Context>, request_id: &str, request: &mut Request<B>) -> Self {
+        let method = request.method().as_str().to_owned();
+        let uri = request.uri();
+        let mut attributes = vec![
+            KeyValue::new("http.request.method", method.clone()),
+            KeyValue::new("url.full", url_without_query(uri)),
+            KeyValue::new("db.system.name", "etcd"),
+            KeyValue::new(
+                REQUEST_ID_ATTRIBUTE,
+                Value::Array(Array::String(vec![StringValue::from(
+                    request_id.to_owned(),
+                )])),
+            ),
+        ];
+
+        if let Some(host) = uri.host() {
+            let host = host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_owned();
+
+            attributes.push(KeyValue::new("server.address", host));
+        }
+
+        let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+            Some("https") => 443,
+            _ => 80,
+        });
+        attributes.push(KeyValue::new("server.port", i64::from(port)));
+
+        let tracer = global::tracer(TRACER_NAME);
+        let builder = tracer
+            .span_builder(method)
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes);
+        let span = match parent {
+            Some(parent) => builder.start_with_context(&tracer, parent),
+            None => builder.start_with_context(&tracer, &Context::current()),
+        };
+
+        let span_context = span.span_context();
+
+        if span_context.is_valid() {
+            let traceparent = format!(
+                "00-{}-{}-{:02x}",
+                span_context.trace_id(),
+                span_context.span_id(),
+                span_context.trace_flags().to_u8()
+            );
+            let headers = request.headers_mut();
+
+            headers.insert(TRACEPARENT, HeaderValue::from_str(&traceparent).unwrap());
+
+            if let Ok(tracestate) = HeaderValue::from_str(&span_context.trace_state().header()) {
+                if !tracestate.is_empty() {
+                    headers.insert(TRACESTATE, tracestate);
+                }
+            }
+        }
+
+        RequestSpan(span)
+    }
+
+    /// Ends the span once the response to its request arrives, or the request fails.
+    pub fn end_with<F>(
+        self,
+        response: F,
+    ) -> impl Future<Item = Response<Body>, Error = Error> + Send
+    where
+        F: Future<Item = Response<Body>, Error = Error> + Send,
+    {
+        let mut span = self.0;
+
+        response.then(move |result| {
+            match result {
+                Ok(ref response) => {
+                    let status = response.status();
+
+                    span.set_attribute(KeyValue::new(
+                        "http.response.status_code",
+                        i64::from(status.as_u16()),
+                    ));
+
+                    if status.is_client_error() || status.is_server_error() {
+                        span.set_attribute(KeyValue::new("error.type", status.as_str().to_owned()));
+                        span.set_status(Status::error(status_description(status)));
+                    }
+                }
+                Err(ref error) => {
+                    span.set_attribute(KeyValue::new("error.type", error_type(error)));
+                    span.set_status(Status::error(error.to_string()));
+                }
+            }
+
+            span.end();
+
+            result
+        })
+    }
+}
+
+impl Debug for RequestSpan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("RequestSpan").finish()
+    }
+}
+
+/// Returns a request's URL without its query, which may contain the value a key is compared to.
+fn url_without_query(uri: &Uri) -> String {
+    format!(
+        "{}://{}{}",
+        uri.scheme_str().unwrap_or("http"),
+        uri.authority_part()
+            .map_or("", |authority| authority.as_str()),
+        uri.path()
+    )
+}
+
+/// Returns the description of an HTTP status for a span's status, e.g. "404 Not Found".
+fn status_description(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) => format!("{} {}", status.as_str(), reason),
+        None => status.as_str().to_owned(),
+    }
+}
+
+/// Returns the low-cardinality name of a request that failed without a response, for the
+/// `error.type` attribute.
+fn error_type(error: &Error) -> &'static str {
+    match *error {
+        Error::Cancelled => "cancelled",
+        Error::Http(_) | Error::Request { .. } => "http",
+        Error::Timeout => "timeout",
+        _ => "_OTHER",
+    }
+}
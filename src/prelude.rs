@@ -0,0 +1,17 @@
+//! Re-exports the types and traits most programs using this crate need.
+//!
+//! ```no_run
+//! use etcd::prelude::*;
+//! use tokio::runtime::Runtime;
+//!
+//! let client = Client::new(&["http://etcd.example.com:2379"], None).unwrap();
+//! let work = kv::get(&client, "/foo", GetOptions::default());
+//! let response: etcd::Result<KeyValueInfo> = Runtime::new().unwrap().block_on(work);
+//! ```
+
+pub use futures::{Future, Stream};
+
+pub use crate::client::{BasicAuth, Client, ConnectionOptions, RequestOptions, Response};
+pub use crate::codec::ValueCodec;
+pub use crate::error::{ApiError, Error};
+pub use crate::kv::{self, Action, GetOptions, KeyValueInfo, Node, WatchItem, WatchOptions};
@@ -0,0 +1,261 @@
+//! Running scheduled jobs on exactly one of several instances.
+//!
+//! Each run of a job is identified by its tick: the time it is scheduled for. The instances that
+//! run the job race to create a key for the tick, e.g. `/jobs/cleanup/2024-05-01T00:00:00`, and
+//! only the instance that creates it runs the job for that tick. The key is given a TTL so that
+//! old ticks don't pile up.
+//!
+//! The ticks come from the job's schedule: a function that returns the first tick after a given
+//! time. `schedule::every` makes a schedule whose ticks are multiples of an interval since the
+//! Unix epoch, and other schedules, such as crontab-style ones, can be written as functions. As
+//! long as a schedule only depends on the time it is given, every instance arrives at the same
+//! ticks while their clocks roughly agree.
+
+use std::fmt::Debug;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::future::{loop_fn, Either, Future, IntoFuture, Loop};
+use hyper::client::connect::Connect;
+use log::warn;
+use tokio::timer::Delay;
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::kv::{self, has_error_code, NODE_EXIST};
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Tries to claim a tick of a job for this instance.
+///
+/// Succeeds with true if this instance created the tick's key and should run the job, or false if
+/// another instance already has.
+///
+/// The TTL should be longer than the clocks of the instances differ by, so that a late instance
+/// doesn't claim a tick again after its key has expired.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * job: The name of the directory that holds the job's tick keys.
+/// * tick: The time the run of the job is scheduled for.
+/// * ttl: The tick's key will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the tick's key could not be created for another reason than already existing.
+pub fn claim<C>(
+    client: &Client<C>,
+    job: &str,
+    tick: SystemTime,
+    ttl: u64,
+) -> impl Future<Item = bool, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    kv::create(client, &tick_key(job, tick), "", Some(ttl)).then(|result| match result {
+        Ok(_) => Ok(true),
+        Err(ref errors) if has_error_code(errors, NODE_EXIST) => Ok(false),
+        Err(errors) => Err(errors),
+    })
+}
+
+/// Runs a job at each multiple of an interval since the Unix epoch that this instance claims, for
+/// as long as the returned future is polled.
+///
+/// This is `schedule::run_on_schedule` with the schedule returned by `schedule::every`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * job: The name of the directory that holds the job's tick keys.
+/// * interval: The time between ticks.
+/// * ttl: The number of seconds each tick's key lives for. See `schedule::claim`.
+/// * f: A function that runs the job for a tick.
+///
+/// # Panics
+///
+/// Panics if `interval` is shorter than a second, since tick keys have a resolution of a second.
+pub fn run<C, F, R>(
+    client: &Client<C>,
+    job: &str,
+    interval: Duration,
+    ttl: u64,
+    f: F,
+) -> impl Future<Item = (), Error = ()> + Send
+where
+    C: Clone + Connect,
+    F: FnMut(SystemTime) -> R + Send + 'static,
+    R: IntoFuture<Item = ()>,
+    R::Error: Debug,
+    R::Future: Send + 'static,
+{
+    run_on_schedule(client, job, every(interval), ttl, f)
+}
+
+/// Runs a job at each tick of a schedule that this instance claims, for as long as the returned
+/// future is polled.
+///
+/// The future waits for each tick, claims it with `schedule::claim`, and calls `f` with the tick
+/// if the claim succeeds, waiting for the future it returns before moving on. Ticks that pass
+/// while `f` runs are skipped. Failures to claim a tick and errors from `f` are logged, and the
+/// next tick is waited for; the future never finishes on its own.
+///
+/// The schedule's ticks must be at least a second apart, since tick keys have a resolution of a
+/// second.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * job: The name of the directory that holds the job's tick keys.
+/// * schedule: A function that returns the first tick after the time it is given.
+/// * ttl: The number of seconds each tick's key lives for. See `schedule::claim`.
+/// * f: A function that runs the job for a tick.
+pub fn run_on_schedule<C, S, F, R>(
+    client: &Client<C>,
+    job: &str,
+    schedule: S,
+    ttl: u64,
+    f: F,
+) -> impl Future<Item = (), Error = ()> + Send
+where
+    C: Clone + Connect,
+    S: Fn(SystemTime) -> SystemTime + Send + 'static,
+    F: FnMut(SystemTime) -> R + Send + 'static,
+    R: IntoFuture<Item = ()>,
+    R::Error: Debug,
+    R::Future: Send + 'static,
+{
+    let client = client.clone();
+    let job = job.to_string();
+
+    loop_fn((UNIX_EPOCH, f), move |(last_tick, mut f)| {
+        let now = SystemTime::now();
+        let tick = schedule(now.max(last_tick));
+        let wait = tick.duration_since(now).unwrap_or_default();
+
+        let claim_client = client.clone();
+        let claim_job = job.clone();
+        let log_job = job.clone();
+
+        let claimed = Delay::new(Instant::now() + wait)
+            .map_err(|_| vec![Error::Timeout])
+            .and_then(move |_| claim(&claim_client, &claim_job, tick, ttl))
+            .then(move |result| match result {
+                Ok(claimed) => Ok(claimed),
+                Err(errors) => {
+                    warn!(
+                        "failed to claim tick {} of job {}: {:?}",
+                        format_tick(tick),
+                        log_job,
+                        errors
+                    );
+
+                    Ok(false)
+                }
+            });
+
+        let log_job = job.clone();
+
+        claimed.and_then(move |claimed| {
+            if !claimed {
+                return Either::A(Ok(Loop::Continue((tick, f))).into_future());
+            }
+
+            Either::B(f(tick).into_future().then(move |result| {
+                if let Err(error) = result {
+                    warn!(
+                        "job {} failed at tick {}: {:?}",
+                        log_job,
+                        format_tick(tick),
+                        error
+                    );
+                }
+
+                Ok(Loop::Continue((tick, f)))
+            }))
+        })
+    })
+}
+
+/// Returns a schedule whose ticks are the multiples of an interval since the Unix epoch, for
+/// `schedule::run_on_schedule`.
+///
+/// # Parameters
+///
+/// * interval: The time between ticks.
+///
+/// # Panics
+///
+/// Panics if `interval` is shorter than a second, since tick keys have a resolution of a second.
+pub fn every(interval: Duration) -> impl Fn(SystemTime) -> SystemTime + Clone + Send + Sync {
+    assert!(
+        interval >= Duration::from_secs(1),
+        "a job's interval must be at least a second"
+    );
+
+    move |after| next_tick(after, interval)
+}
+
+/// Returns the key claimed for a tick of a job, e.g. `/jobs/cleanup/2024-05-01T00:00:00`.
+///
+/// # Parameters
+///
+/// * job: The name of the directory that holds the job's tick keys.
+/// * tick: The time the run of the job is scheduled for.
+pub fn tick_key(job: &str, tick: SystemTime) -> String {
+    format!("{}/{}", job.trim_end_matches('/'), format_tick(tick))
+}
+
+/// Returns the first multiple of the interval since the Unix epoch that is after the given time.
+fn next_tick(after: SystemTime, interval: Duration) -> SystemTime {
+    let elapsed = after
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let interval = interval.as_nanos();
+    let next = (elapsed / interval + 1) * interval;
+
+    UNIX_EPOCH + Duration::new((next / 1_000_000_000) as u64, (next % 1_000_000_000) as u32)
+}
+
+/// Formats a tick as an ISO 8601 date and time in UTC, to the second.
+fn format_tick(tick: SystemTime) -> String {
+    let seconds = tick
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+    let time = seconds % SECONDS_PER_DAY;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Converts a number of days since the Unix epoch into a year, month, and day in the proleptic
+/// Gregorian calendar, using Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
@@ -0,0 +1,63 @@
+//! Handling of passwords and tokens held in memory.
+//!
+//! The client keeps its own copies of credentials in `Secret`s, which overwrite them with zeros
+//! when they are dropped if the `zeroize` feature is enabled, and leave them out of their `Debug`
+//! output regardless.
+
+use std::fmt::{Debug, Error as FmtError, Formatter};
+
+use serde::{Serialize, Serializer};
+
+/// What a credential is replaced with in `Debug` output.
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// A password or token held by the client.
+#[derive(Clone)]
+pub(crate) struct Secret(String);
+
+impl Secret {
+    /// Takes ownership of a credential.
+    pub(crate) fn new(secret: String) -> Self {
+        Secret(secret)
+    }
+
+    /// Returns the credential.
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+/// Overwrites a credential's memory with zeros if the `zeroize` feature is enabled, leaving it
+/// empty.
+#[cfg(feature = "zeroize")]
+pub(crate) fn zeroize(secret: &mut String) {
+    zeroize::Zeroize::zeroize(secret);
+}
+
+/// Overwrites a credential's memory with zeros if the `zeroize` feature is enabled, leaving it
+/// empty.
+#[cfg(not(feature = "zeroize"))]
+pub(crate) fn zeroize(secret: &mut String) {
+    secret.clear();
+}
@@ -0,0 +1,155 @@
+//! A distributed semaphore, for limiting how many clients do something at once, e.g. how many
+//! instances of a job run concurrently across a fleet.
+//!
+//! A semaphore is a directory of in-order keys, one for each client that holds it or is waiting
+//! for it. The clients whose keys are among the first `limit` in the directory hold the semaphore,
+//! and the rest wait for keys ahead of theirs to be deleted. Each key's TTL is kept alive in the
+//! background, so a client that dies gives up its place once its key expires.
+//!
+//! Every client using a semaphore must agree on its limit. Each key's value is the limit the client
+//! acquired the semaphore with.
+
+use futures::future::{loop_fn, Either, Future, IntoFuture, Loop};
+use hyper::client::connect::Connect;
+
+use crate::client::Client;
+use crate::error::Error;
+use crate::kv::{self, GetOptions, Session, WatchOptions};
+
+/// A place among the holders of a distributed semaphore.
+///
+/// The semaphore is held until this value is dropped or `Semaphore::release` is called, which
+/// deletes the client's key so that the next waiting client can acquire it. If the executor or the
+/// client is shut down first, the key is left to expire.
+#[derive(Debug)]
+pub struct Semaphore<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    key: String,
+    session: Session<C>,
+}
+
+impl<C> Semaphore<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    /// Acquires a semaphore, waiting for as long as `limit` other clients hold it.
+    ///
+    /// The returned future must be run on a Tokio executor, which the refresher of the client's
+    /// key is spawned onto.
+    ///
+    /// # Parameters
+    ///
+    /// * client: A `Client` to use to make the API calls.
+    /// * key: The name of the directory that holds the semaphore's keys.
+    /// * limit: The most clients that may hold the semaphore at once.
+    /// * ttl: The client's key will expire after this many seconds if it isn't refreshed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the client's key could not be created or the semaphore could not be read, or with
+    /// `Error::LockLost` if the key expired or was deleted by another client while waiting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is 0.
+    pub fn acquire(
+        client: &Client<C>,
+        key: &str,
+        limit: usize,
+        ttl: u64,
+    ) -> impl Future<Item = Semaphore<C>, Error = Vec<Error>> + Send {
+        assert!(limit > 0, "a semaphore's limit must be at least 1");
+
+        let client = client.clone();
+        let key = key.to_string();
+        let value = limit.to_string();
+
+        kv::create_in_order(&client, &key, &value, Some(ttl)).and_then(move |response| {
+            let holder_key = response.data.node.key.unwrap_or_default();
+            let session = Session::keep_alive(&client, &holder_key, &value, ttl);
+
+            loop_fn(session, move |session| {
+                let watch_client = client.clone();
+                let watch_key = key.clone();
+                let options = GetOptions {
+                    sort: true,
+                    strong_consistency: true,
+                    ..Default::default()
+                };
+
+                kv::get(&client, &key, options).and_then(move |response| {
+                    let position = response
+                        .data
+                        .node
+                        .nodes
+                        .unwrap_or_default()
+                        .iter()
+                        .position(|node| node.key.as_deref() == Some(session.key()));
+
+                    match position {
+                        Some(position) if position < limit => {
+                            Either::A(Ok(Loop::Break((watch_key, session))).into_future())
+                        }
+                        Some(_) => {
+                            let options = WatchOptions {
+                                index: response.cluster_info.etcd_index.map(|index| index + 1),
+                                recursive: true,
+                                ..Default::default()
+                            };
+
+                            // Any change to the directory may have freed a place, so its keys are
+                            // read again. If changes were missed, the directory is read again too.
+                            Either::B(kv::watch(&watch_client, &watch_key, options).then(
+                                move |result| match result {
+                                    Ok(_) => Ok(Loop::Continue(session)),
+                                    Err(ref errors) if is_index_cleared(errors) => {
+                                        Ok(Loop::Continue(session))
+                                    }
+                                    Err(errors) => Err(errors),
+                                },
+                            ))
+                        }
+                        None => Either::A(Err(vec![Error::LockLost]).into_future()),
+                    }
+                })
+            })
+            .map(|(key, session)| Semaphore { key, session })
+        })
+    }
+
+    /// Returns the name of the directory that holds the semaphore's keys.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the name of the client's key in the semaphore's directory.
+    pub fn holder_key(&self) -> &str {
+        self.session.key()
+    }
+
+    /// Returns whether or not the client's key is still being kept alive.
+    ///
+    /// This becomes false once the key has been found to be missing, or once refreshing it has
+    /// failed for longer than its TTL, after which another client may acquire the client's place.
+    pub fn is_held(&self) -> bool {
+        self.session.is_alive()
+    }
+
+    /// Releases the semaphore, deleting the client's key.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the key could not be deleted, in which case it is left to expire.
+    pub fn release(self) -> impl Future<Item = (), Error = Vec<Error>> + Send {
+        self.session.invalidate()
+    }
+}
+
+/// Returns whether or not the errors show that changes the watch was asked for were missed.
+fn is_index_cleared(errors: &[Error]) -> bool {
+    errors
+        .iter()
+        .any(|error| matches!(error, Error::IndexCleared { .. }))
+}
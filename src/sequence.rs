@@ -0,0 +1,208 @@
+//! Cluster-wide unique IDs, allocated from a counter key.
+//!
+//! The counter key holds the last ID allocated, in decimal, and is advanced with a
+//! compare-and-swap, so no ID is handed out twice. IDs start at 1, and a missing counter key
+//! counts as 0.
+//!
+//! `sequence::next` makes two API calls for each ID. A `Sequence` instead reserves a window of IDs
+//! at a time and hands them out locally, at the cost of IDs from different clients interleaving
+//! rather than increasing in the order they were handed out, and of the unused part of a window
+//! being skipped when the `Sequence` is dropped.
+
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use futures::future::{loop_fn, Either, Future, IntoFuture, Loop};
+use hyper::client::connect::Connect;
+
+use crate::client::{Client, Response};
+use crate::error::Error;
+use crate::kv::{self, has_error_code, is_conflict, GetOptions, KeyValueInfo, KEY_NOT_FOUND};
+use crate::sync::lock;
+
+/// The maximum number of read-modify-write cycles made to advance the counter key before giving
+/// up because other clients keep advancing it first.
+const MAX_ATTEMPTS: usize = 32;
+
+/// A handle to a sequence that reserves IDs in windows, reducing the API calls made for each ID.
+///
+/// Clones share the same reserved window.
+#[derive(Clone, Debug)]
+pub struct Sequence<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    client: Client<C>,
+    key: String,
+    reserved: Arc<Mutex<Range<u64>>>,
+    window: u64,
+}
+
+impl<C> Sequence<C>
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    /// Constructs a handle to a sequence. No API calls are made until the first ID is requested.
+    ///
+    /// # Parameters
+    ///
+    /// * client: A `Client` to use to make the API calls.
+    /// * key: The name of the counter key.
+    /// * window: The number of IDs to reserve at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    pub fn new(client: &Client<C>, key: &str, window: u64) -> Self {
+        assert!(window > 0, "a sequence's window must be at least 1");
+
+        Sequence {
+            client: client.clone(),
+            key: key.to_string(),
+            reserved: Arc::new(Mutex::new(0..0)),
+            window,
+        }
+    }
+
+    /// Returns the name of the counter key.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the next ID, reserving a new window of IDs if the current one has been used up.
+    ///
+    /// IDs are unique across the cluster, and increase for as long as one call to this method
+    /// finishes before the next is made.
+    ///
+    /// # Errors
+    ///
+    /// Fails under the same conditions as `sequence::reserve`.
+    pub fn next(&self) -> impl Future<Item = u64, Error = Vec<Error>> + Send {
+        if let Some(id) = lock(&self.reserved).next() {
+            return Either::A(Ok(id).into_future());
+        }
+
+        let reserved = self.reserved.clone();
+
+        Either::B(
+            reserve(&self.client, &self.key, self.window).map(move |mut range| {
+                let id = range.next().unwrap_or_default();
+                let mut reserved = lock(&reserved);
+
+                // A window reserved by a concurrent call may have been stored already. Only the
+                // later window is kept, so that IDs keep increasing.
+                if range.start > reserved.start {
+                    *reserved = range;
+                }
+
+                id
+            }),
+        )
+    }
+}
+
+/// Returns the next ID of a sequence.
+///
+/// IDs are unique across the cluster, and each one is greater than every ID allocated before it
+/// by `sequence::next` or `sequence::reserve`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the counter key.
+///
+/// # Errors
+///
+/// Fails under the same conditions as `sequence::reserve`.
+pub fn next<C>(client: &Client<C>, key: &str) -> impl Future<Item = u64, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    reserve(client, key, 1).map(|range| range.start)
+}
+
+/// Reserves a range of IDs of a sequence, which no other call will return.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the counter key.
+/// * count: The number of IDs to reserve.
+///
+/// # Errors
+///
+/// Fails if the counter key is still being advanced by other clients after several attempts, if
+/// any request fails for another reason, with `Error::InvalidSequence` if the counter key's value
+/// isn't a number, or with `Error::SequenceExhausted` if the sequence has run out of IDs.
+pub fn reserve<C>(
+    client: &Client<C>,
+    key: &str,
+    count: u64,
+) -> impl Future<Item = Range<u64>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+    let key = key.to_string();
+
+    loop_fn(1, move |attempt| {
+        let client = client.clone();
+        let key = key.clone();
+
+        let current = kv::get(&client, &key, GetOptions::default()).then(|result| match result {
+            Ok(response) => Ok(Some(response.data.node)),
+            Err(ref errors) if has_error_code(errors, KEY_NOT_FOUND) => Ok(None),
+            Err(errors) => Err(errors),
+        });
+
+        let write = current.and_then(move |node| {
+            let last = match node.as_ref().and_then(|node| node.value.as_ref()) {
+                Some(value) => match value.parse::<u64>() {
+                    Ok(last) => last,
+                    Err(_) => {
+                        let error = Error::InvalidSequence {
+                            key,
+                            value: value.clone(),
+                        };
+
+                        return Either::A(Err(vec![error]).into_future());
+                    }
+                },
+                None => 0,
+            };
+
+            let end = match last.checked_add(count) {
+                Some(end) => end,
+                None => {
+                    return Either::A(Err(vec![Error::SequenceExhausted(key)]).into_future());
+                }
+            };
+
+            let value = end.to_string();
+            let modified_index = node.and_then(|node| node.modified_index);
+
+            let write: Box<dyn Future<Item = Response<KeyValueInfo>, Error = Vec<Error>> + Send> =
+                match modified_index {
+                    Some(index) => Box::new(kv::compare_and_swap(
+                        &client,
+                        &key,
+                        &value,
+                        None,
+                        None,
+                        Some(index),
+                    )),
+                    None => Box::new(kv::create(&client, &key, &value, None)),
+                };
+
+            Either::B(write.map(move |_| last + 1..end + 1))
+        });
+
+        write.then(move |result| match result {
+            Ok(range) => Ok(Loop::Break(range)),
+            Err(ref errors) if attempt < MAX_ATTEMPTS && is_conflict(errors) => {
+                Ok(Loop::Continue(attempt + 1))
+            }
+            Err(errors) => Err(errors),
+        })
+    })
+}
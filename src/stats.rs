@@ -2,14 +2,25 @@
 
 use std::collections::HashMap;
 
+use futures::future::Either;
 use futures::stream::futures_unordered;
 use futures::{Future, IntoFuture, Stream};
 use hyper::client::connect::Connect;
 use hyper::Uri;
+use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::client::{Client, Response};
 use crate::error::Error;
+use crate::first_ok::first_ok;
+use crate::http::endpoint_url;
+use crate::members::{self, MemberId};
+
+#[cfg(feature = "prometheus")]
+pub use self::prometheus::prometheus_export;
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
 
 /// Statistics about an etcd cluster leader.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -156,33 +167,60 @@ pub struct StoreStats {
 
 /// Returns statistics about the leader member of a cluster.
 ///
-/// Fails if JSON decoding fails, which suggests a bug in our schema.
+/// Only the leader serves these statistics, so each cluster member the client was initialized
+/// with is tried in turn. If none of them is the leader, the leader is looked up using the
+/// statistics and membership APIs, and the request is made to its client URLs instead.
+///
+/// # Errors
+///
+/// Fails if the leader could not be reached, or if JSON decoding fails, which suggests a bug in
+/// our schema.
 pub fn leader_stats<C>(
     client: &Client<C>,
-) -> impl Future<Item = Response<LeaderStats>, Error = Error> + Send
+) -> impl Future<Item = Response<LeaderStats>, Error = Vec<Error>> + Send
 where
     C: Clone + Connect,
 {
-    let url = build_url(&client.endpoints()[0], "v2/stats/leader");
-    let uri = url.parse().map_err(Error::from).into_future();
+    let fallback_client = client.clone();
+
+    request_first_ok(client, client.endpoints(), "v2/stats/leader").or_else(move |mut errors| {
+        leader_endpoints(&fallback_client).then(move |result| match result {
+            Ok(ref endpoints) if endpoints.is_empty() => Either::A(Err(errors).into_future()),
+            Ok(endpoints) => Either::B(
+                request_first_ok(&fallback_client, endpoints, "v2/stats/leader").map_err(
+                    move |leader_errors| {
+                        errors.extend(leader_errors);
+                        errors
+                    },
+                ),
+            ),
+            Err(lookup_errors) => {
+                errors.extend(lookup_errors);
 
-    client.request(uri)
+                Either::A(Err(errors).into_future())
+            }
+        })
+    })
 }
 
 /// Returns statistics about each cluster member the client was initialized with.
 ///
+/// Each item is paired with the endpoint of the member that produced it.
+///
 /// Fails if JSON decoding fails, which suggests a bug in our schema.
 pub fn self_stats<C>(
     client: &Client<C>,
-) -> impl Stream<Item = Response<SelfStats>, Error = Error> + Send
+) -> impl Stream<Item = (Uri, Response<SelfStats>), Error = Error> + Send
 where
     C: Clone + Connect,
 {
-    let futures = client.endpoints().iter().map(|endpoint| {
+    let futures = client.endpoints().into_iter().map(|endpoint| {
         let url = build_url(&endpoint, "v2/stats/self");
         let uri = url.parse().map_err(Error::from).into_future();
 
-        client.request(uri)
+        client
+            .request(uri)
+            .map(move |response| (endpoint, response))
     });
 
     futures_unordered(futures)
@@ -191,24 +229,74 @@ where
 /// Returns statistics about operations handled by each etcd member the client was initialized
 /// with.
 ///
+/// Each item is paired with the endpoint of the member that produced it.
+///
 /// Fails if JSON decoding fails, which suggests a bug in our schema.
 pub fn store_stats<C>(
     client: &Client<C>,
-) -> impl Stream<Item = Response<StoreStats>, Error = Error> + Send
+) -> impl Stream<Item = (Uri, Response<StoreStats>), Error = Error> + Send
 where
     C: Clone + Connect,
 {
-    let futures = client.endpoints().iter().map(|endpoint| {
+    let futures = client.endpoints().into_iter().map(|endpoint| {
         let url = build_url(&endpoint, "v2/stats/store");
         let uri = url.parse().map_err(Error::from).into_future();
 
-        client.request(uri)
+        client
+            .request(uri)
+            .map(move |response| (endpoint, response))
     });
 
     futures_unordered(futures)
 }
 
+/// Finds the client URLs of the cluster's current leader.
+fn leader_endpoints<C>(
+    client: &Client<C>,
+) -> impl Future<Item = Vec<Uri>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let members_client = client.clone();
+
+    request_first_ok::<C, SelfStats>(client, client.endpoints(), "v2/stats/self").and_then(
+        move |response| {
+            let leader_id = response.data.leader_info.id.parse::<MemberId>().ok();
+
+            members::list(&members_client).map(move |response| {
+                response
+                    .data
+                    .into_iter()
+                    .filter(|member| Some(member.id) == leader_id)
+                    .flat_map(|member| member.client_urls)
+                    .filter_map(|url| url.parse().ok())
+                    .collect()
+            })
+        },
+    )
+}
+
+/// Makes a GET request to the given path on each endpoint in turn until one succeeds.
+fn request_first_ok<C, T>(
+    client: &Client<C>,
+    endpoints: Vec<Uri>,
+    path: &'static str,
+) -> impl Future<Item = Response<T>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    T: DeserializeOwned + Send + 'static,
+{
+    let request_client = client.clone();
+
+    first_ok(endpoints, client.request_options(), move |endpoint| {
+        let url = build_url(endpoint, path);
+        let uri = url.parse().map_err(Error::from).into_future();
+
+        request_client.request(uri)
+    })
+}
+
 /// Constructs the full URL for an API call.
 fn build_url(endpoint: &Uri, path: &str) -> String {
-    format!("{}{}", endpoint, path)
+    endpoint_url(endpoint, path)
 }
@@ -0,0 +1,301 @@
+//! Rendering of etcd statistics in the Prometheus text exposition format.
+
+use std::fmt::Write;
+
+use futures::{Future, Stream};
+use hyper::client::connect::Connect;
+use hyper::Uri;
+
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+use super::{leader_stats, self_stats, store_stats, LeaderStats, SelfStats, StoreStats};
+
+/// A set of labels for a single sample.
+type Labels = Vec<(&'static str, String)>;
+
+/// Gathers statistics from the cluster and renders them in the Prometheus text exposition format.
+///
+/// Self and store statistics are gathered from each cluster member the client was initialized
+/// with, and labeled with the member's endpoint. Leader statistics are gathered from the leader.
+/// Members that can't be reached are left out, so the output can be served to a Prometheus
+/// scraper as is, even when part of the cluster is down.
+///
+/// This function requires the `prometheus` Cargo feature.
+///
+/// # Errors
+///
+/// Fails if no statistics could be gathered at all.
+pub fn prometheus_export<C>(
+    client: &Client<C>,
+) -> impl Future<Item = String, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let leader = leader_stats(client).then(Ok::<_, Vec<Error>>);
+    let members = self_stats(client).then(Ok::<_, Vec<Error>>).collect();
+    let stores = store_stats(client).then(Ok::<_, Vec<Error>>).collect();
+
+    leader
+        .join3(members, stores)
+        .and_then(|(leader, members, stores)| {
+            let mut errors = vec![];
+
+            let leader = match leader {
+                Ok(response) => Some(response),
+                Err(leader_errors) => {
+                    errors.extend(leader_errors);
+                    None
+                }
+            };
+            let members: Vec<_> = members
+                .into_iter()
+                .filter_map(|result| result.map_err(|error| errors.push(error)).ok())
+                .collect();
+            let stores: Vec<_> = stores
+                .into_iter()
+                .filter_map(|result| result.map_err(|error| errors.push(error)).ok())
+                .collect();
+
+            if leader.is_none() && members.is_empty() && stores.is_empty() {
+                return Err(errors);
+            }
+
+            Ok(render(leader.as_ref(), &members, &stores))
+        })
+}
+
+/// Renders the gathered statistics.
+fn render(
+    leader: Option<&Response<LeaderStats>>,
+    members: &[(Uri, Response<SelfStats>)],
+    stores: &[(Uri, Response<StoreStats>)],
+) -> String {
+    let mut output = String::new();
+
+    if let Some(leader) = leader {
+        render_leader(&mut output, &leader.data);
+    }
+
+    render_members(&mut output, members);
+    render_stores(&mut output, stores);
+
+    output
+}
+
+/// Renders the leader's statistics about its followers.
+fn render_leader(output: &mut String, stats: &LeaderStats) {
+    let mut followers: Vec<_> = stats.followers.iter().collect();
+    followers.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut requests = vec![];
+    let mut latencies = vec![];
+
+    for (id, follower) in followers {
+        let labels = vec![("leader", stats.leader.clone()), ("follower", id.clone())];
+
+        for &(result, count) in &[
+            ("success", follower.counts.success),
+            ("fail", follower.counts.fail),
+        ] {
+            let mut labels = labels.clone();
+            labels.push(("result", result.to_owned()));
+            requests.push((labels, count as f64));
+        }
+
+        for &(stat, value) in &[
+            ("current", follower.latency.current),
+            ("average", follower.latency.average),
+            ("minimum", follower.latency.minimum),
+            ("maximum", follower.latency.maximum),
+            ("standard_deviation", follower.latency.standard_deviation),
+        ] {
+            let mut labels = labels.clone();
+            labels.push(("stat", stat.to_owned()));
+            latencies.push((labels, value));
+        }
+    }
+
+    family(
+        output,
+        "etcd_follower_raft_requests_total",
+        "counter",
+        "Raft RPC requests from the leader to each follower.",
+        &requests,
+    );
+    family(
+        output,
+        "etcd_follower_latency_milliseconds",
+        "gauge",
+        "Latency of Raft RPC requests from the leader to each follower.",
+        &latencies,
+    );
+}
+
+/// Renders each member's statistics about itself.
+fn render_members(output: &mut String, members: &[(Uri, Response<SelfStats>)]) {
+    let mut is_leader = vec![];
+    let mut received = vec![];
+    let mut sent = vec![];
+    let mut received_bandwidth = vec![];
+    let mut sent_bandwidth = vec![];
+
+    for (endpoint, response) in members {
+        let stats = &response.data;
+        let labels = vec![
+            ("endpoint", endpoint.to_string()),
+            ("id", stats.id.clone()),
+            ("name", stats.name.clone()),
+        ];
+
+        is_leader.push((labels.clone(), flag(stats.state == "StateLeader")));
+        received.push((labels.clone(), stats.received_append_request_count as f64));
+        sent.push((labels.clone(), stats.sent_append_request_count as f64));
+
+        if let Some(rate) = stats.received_bandwidth_rate {
+            received_bandwidth.push((labels.clone(), rate));
+        }
+
+        if let Some(rate) = stats.sent_bandwidth_rate {
+            sent_bandwidth.push((labels, rate));
+        }
+    }
+
+    family(
+        output,
+        "etcd_member_is_leader",
+        "gauge",
+        "Whether or not the member is the cluster leader.",
+        &is_leader,
+    );
+    family(
+        output,
+        "etcd_member_received_append_requests_total",
+        "counter",
+        "Raft append requests received by the member.",
+        &received,
+    );
+    family(
+        output,
+        "etcd_member_sent_append_requests_total",
+        "counter",
+        "Raft append requests sent by the member.",
+        &sent,
+    );
+    family(
+        output,
+        "etcd_member_received_bandwidth_bytes_per_second",
+        "gauge",
+        "Rate of Raft data received by the member.",
+        &received_bandwidth,
+    );
+    family(
+        output,
+        "etcd_member_sent_bandwidth_bytes_per_second",
+        "gauge",
+        "Rate of Raft data sent by the member.",
+        &sent_bandwidth,
+    );
+}
+
+/// Renders each member's statistics about the operations it handled.
+fn render_stores(output: &mut String, stores: &[(Uri, Response<StoreStats>)]) {
+    let mut operations = vec![];
+    let mut expires = vec![];
+    let mut watchers = vec![];
+
+    for (endpoint, response) in stores {
+        let stats = &response.data;
+        let endpoint = endpoint.to_string();
+
+        for &(operation, success, fail) in &[
+            (
+                "compare_and_delete",
+                stats.compare_and_delete_success,
+                stats.compare_and_delete_fail,
+            ),
+            (
+                "compare_and_swap",
+                stats.compare_and_swap_success,
+                stats.compare_and_swap_fail,
+            ),
+            ("create", stats.create_success, stats.create_fail),
+            ("delete", stats.delete_success, stats.delete_fail),
+            ("get", stats.get_success, stats.get_fail),
+            ("set", stats.set_success, stats.set_fail),
+            ("update", stats.update_success, stats.update_fail),
+        ] {
+            for &(result, count) in &[("success", success), ("fail", fail)] {
+                let labels = vec![
+                    ("endpoint", endpoint.clone()),
+                    ("operation", operation.to_owned()),
+                    ("result", result.to_owned()),
+                ];
+
+                operations.push((labels, count as f64));
+            }
+        }
+
+        let labels = vec![("endpoint", endpoint)];
+        expires.push((labels.clone(), stats.expire_count as f64));
+        watchers.push((labels, stats.watchers as f64));
+    }
+
+    family(
+        output,
+        "etcd_store_operations_total",
+        "counter",
+        "Key-value store operations handled by the member.",
+        &operations,
+    );
+    family(
+        output,
+        "etcd_store_expires_total",
+        "counter",
+        "Keys expired by the member.",
+        &expires,
+    );
+    family(
+        output,
+        "etcd_store_watchers",
+        "gauge",
+        "Watchers currently registered with the member.",
+        &watchers,
+    );
+}
+
+/// Renders a metric family, if it has any samples.
+fn family(output: &mut String, name: &str, kind: &str, help: &str, samples: &[(Labels, f64)]) {
+    if samples.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+
+    for (labels, value) in samples {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+            .collect();
+
+        let _ = writeln!(output, "{}{{{}}} {}", name, labels.join(","), value);
+    }
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Converts a boolean into a sample value.
+fn flag(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
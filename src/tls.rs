@@ -0,0 +1,229 @@
+//! TLS settings that can be replaced while a client is in use.
+
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use hyper::client::connect::{Connect, Destination, HttpConnector};
+use hyper_tls::{HttpsConnecting, HttpsConnector, MaybeHttpsStream};
+use log::warn;
+use native_tls::{Certificate, Identity, TlsConnector};
+use tokio::net::TcpStream;
+
+use crate::error::Error;
+use crate::sync;
+
+/// The certificates a client constructed with `Client::https_with_tls` uses for TLS.
+///
+/// Use `Client::reload_tls` to replace them, e.g. when certificates are rotated.
+#[derive(Clone, Default)]
+pub struct TlsOptions {
+    /// Certificates of certificate authorities to trust, in addition to the system's.
+    pub root_certificates: Vec<Certificate>,
+    /// The certificate and private key to authenticate to cluster members with, if any.
+    pub identity: Option<Identity>,
+    /// Whether or not to accept any certificate cluster members present, including self-signed,
+    /// expired and mismatched ones.
+    ///
+    /// This leaves connections open to interception, so it should only be used with development
+    /// and test clusters. Prefer adding the cluster's CA certificate to `root_certificates`.
+    pub danger_accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+    /// Constructs TLS options from PEM-encoded certificates and keys.
+    ///
+    /// # Parameters
+    ///
+    /// * ca_certificates: The certificates of certificate authorities to trust, one or more.
+    /// * certificate_and_key: A client certificate and its PKCS #8 private key.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Tls` if a certificate or key isn't valid.
+    pub fn from_pem(
+        ca_certificates: Option<&[u8]>,
+        certificate_and_key: Option<(&[u8], &[u8])>,
+    ) -> Result<Self, Error> {
+        let mut options = TlsOptions::default();
+
+        if let Some(pem) = ca_certificates {
+            for certificate in pem_certificates(pem) {
+                options
+                    .root_certificates
+                    .push(Certificate::from_pem(certificate)?);
+            }
+        }
+
+        if let Some((certificate, key)) = certificate_and_key {
+            options.identity = Some(Identity::from_pkcs8(certificate, key)?);
+        }
+
+        Ok(options)
+    }
+
+    /// Loads TLS options from PEM files, such as those written by certificate managers.
+    ///
+    /// # Parameters
+    ///
+    /// * ca_certificates: A file containing the certificates of certificate authorities to trust.
+    /// * certificate_and_key: Files containing a client certificate and its PKCS #8 private key.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::Io` if a file can't be read, or with `Error::Tls` if it isn't valid.
+    pub fn from_pem_files<P>(
+        ca_certificates: Option<P>,
+        certificate_and_key: Option<(P, P)>,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let ca_certificates = match ca_certificates {
+            Some(path) => Some(fs::read(path)?),
+            None => None,
+        };
+        let certificate_and_key = match certificate_and_key {
+            Some((certificate, key)) => Some((fs::read(certificate)?, fs::read(key)?)),
+            None => None,
+        };
+
+        TlsOptions::from_pem(
+            ca_certificates.as_deref(),
+            certificate_and_key
+                .as_ref()
+                .map(|(certificate, key)| (certificate.as_slice(), key.as_slice())),
+        )
+    }
+
+    /// Builds a TLS connector with these options, which asks for HTTP/2 with ALPN if `http2` is
+    /// true.
+    pub(crate) fn tls_connector(&self, http2: bool) -> Result<TlsConnector, Error> {
+        let mut builder = TlsConnector::builder();
+
+        if http2 {
+            builder.request_alpns(&["h2"]);
+        }
+
+        for certificate in &self.root_certificates {
+            builder.add_root_certificate(certificate.clone());
+        }
+
+        if let Some(ref identity) = self.identity {
+            builder.identity(identity.clone());
+        }
+
+        if self.danger_accept_invalid_certs {
+            warn!("TLS certificates of cluster members will not be verified");
+
+            builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Splits a PEM bundle into its certificates, each including its begin and end lines.
+///
+/// Anything that isn't a certificate is left out. If there are no certificates, the whole bundle
+/// is returned, so that parsing it reports the problem.
+fn pem_certificates(pem: &[u8]) -> Vec<&[u8]> {
+    const BEGIN: &[u8] = b"-----BEGIN CERTIFICATE-----";
+    const END: &[u8] = b"-----END CERTIFICATE-----";
+
+    let mut certificates = vec![];
+    let mut rest = pem;
+
+    while let Some(start) = find(rest, BEGIN) {
+        let end = match find(&rest[start..], END) {
+            Some(end) => start + end + END.len(),
+            None => break,
+        };
+
+        certificates.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+
+    if certificates.is_empty() {
+        certificates.push(pem);
+    }
+
+    certificates
+}
+
+/// Returns the position of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+impl Debug for TlsOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("TlsOptions")
+            .field("root_certificates", &self.root_certificates.len())
+            .field("identity", &self.identity.is_some())
+            .field(
+                "danger_accept_invalid_certs",
+                &self.danger_accept_invalid_certs,
+            )
+            .finish()
+    }
+}
+
+/// An HTTPS connector whose TLS settings can be replaced, used by clients constructed with
+/// `Client::https_with_tls`.
+#[derive(Clone)]
+pub struct ReloadableHttpsConnector {
+    current: Arc<RwLock<HttpsConnector<HttpConnector>>>,
+    http: HttpConnector,
+    /// Whether or not to ask for HTTP/2 with ALPN, for `ConnectionOptions::http2_only`.
+    http2: bool,
+}
+
+impl ReloadableHttpsConnector {
+    /// Constructs a connector that makes TCP connections with `http` and secures them with the
+    /// given options, asking for HTTP/2 with ALPN if `http2` is true.
+    pub(crate) fn new(
+        mut http: HttpConnector,
+        options: &TlsOptions,
+        http2: bool,
+    ) -> Result<Self, Error> {
+        http.enforce_http(false);
+
+        let current = HttpsConnector::from((http.clone(), options.tls_connector(http2)?));
+
+        Ok(ReloadableHttpsConnector {
+            current: Arc::new(RwLock::new(current)),
+            http,
+            http2,
+        })
+    }
+
+    /// Replaces the TLS settings new connections are made with.
+    pub(crate) fn reload(&self, options: &TlsOptions) -> Result<(), Error> {
+        let connector =
+            HttpsConnector::from((self.http.clone(), options.tls_connector(self.http2)?));
+
+        *sync::write(&self.current) = connector;
+
+        Ok(())
+    }
+}
+
+impl Connect for ReloadableHttpsConnector {
+    type Transport = MaybeHttpsStream<TcpStream>;
+    type Error = std::io::Error;
+    type Future = HttpsConnecting<TcpStream>;
+
+    fn connect(&self, destination: Destination) -> Self::Future {
+        sync::read(&self.current).connect(destination)
+    }
+}
+
+impl Debug for ReloadableHttpsConnector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("ReloadableHttpsConnector").finish()
+    }
+}
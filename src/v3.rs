@@ -0,0 +1,762 @@
+//! etcd's v3 API.
+//!
+//! etcd serves the v3 API over gRPC, and also through a JSON gateway on the same client URLs. The
+//! functions in this module use the gateway, so they work with the same `Client` as the v2 API.
+//! The gateway's path prefix has changed between etcd versions, from `/v3alpha` in etcd 3.2 to
+//! `/v3beta` in 3.3 and `/v3` in 3.4, so each request tries the newest prefix first and falls back
+//! to older ones if the cluster member doesn't serve it.
+//!
+//! The v3 API requires etcd 3.2 or later, with the v3 API enabled. See `Feature::V3Api`.
+//!
+//! Each call must complete within the client's `RequestOptions::timeout`, except for calls that
+//! wait for something to happen, such as `v3::lock::lock`, which use `RequestOptions::watch_timeout`
+//! instead. `Client::with_timeout` gives a client with a different deadline for particular calls.
+//! Streams, such as `v3::kv::watch`, are re-established when their connection fails.
+//!
+//! The v3 API keeps its own key space, separate from the v2 API's, so keys written with `etcd::kv`
+//! are not visible here and vice versa.
+
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::mem;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use futures::future::{loop_fn, Either, Future, IntoFuture, Loop};
+use futures::stream::{futures_unordered, Stream};
+use futures::{Async, Poll};
+use hyper::client::connect::Connect;
+use hyper::{Body, StatusCode, Uri};
+use serde::de::{DeserializeOwned, Error as DeError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+use tokio::timer::Delay;
+
+use crate::client::{with_timeout, Backoff, Client, ClusterInfo, RequestOptions, Response};
+use crate::error::{Error, RpcError};
+use crate::first_ok::first_ok;
+use crate::http::{endpoint_url, HttpClient};
+use crate::secret::Secret;
+
+pub mod auth;
+pub mod election;
+pub mod kv;
+pub mod lock;
+pub mod maintenance;
+
+/// The path prefixes the gRPC gateway has been served under, newest first.
+const PREFIXES: [&str; 3] = ["v3", "v3beta", "v3alpha"];
+
+/// Information about the cluster included in every v3 API response.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct ResponseHeader {
+    /// The ID of the cluster.
+    #[serde(default, with = "int64")]
+    pub cluster_id: u64,
+    /// The ID of the cluster member that sent the response.
+    #[serde(default, with = "int64")]
+    pub member_id: u64,
+    /// The revision of the key-value store when the request was applied.
+    #[serde(default, with = "int64")]
+    pub revision: i64,
+    /// The Raft term when the request was applied.
+    #[serde(default, with = "int64")]
+    pub raft_term: u64,
+}
+
+/// A key and its value in the v3 key-value store.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct KeyValue {
+    /// The key.
+    #[serde(default, with = "bytes")]
+    pub key: Vec<u8>,
+    /// The revision of the key-value store when the key was last created.
+    #[serde(default, with = "int64")]
+    pub create_revision: i64,
+    /// The revision of the key-value store when the key was last modified.
+    #[serde(default, with = "int64")]
+    pub mod_revision: i64,
+    /// The number of times the key has been modified since it was last created.
+    #[serde(default, with = "int64")]
+    pub version: i64,
+    /// The value.
+    #[serde(default, with = "bytes")]
+    pub value: Vec<u8>,
+    /// The ID of the lease attached to the key, or zero if there is none.
+    #[serde(default, with = "int64")]
+    pub lease: i64,
+}
+
+/// The body of an unsuccessful response from the gRPC gateway.
+///
+/// Older versions of the gateway put the message in `error`, newer ones in `message`. In streaming
+/// responses, the error is an object under `error` with the code in `grpc_code`.
+#[derive(Debug, Deserialize)]
+struct GatewayError {
+    #[serde(alias = "grpc_code", default)]
+    code: u32,
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    message: String,
+}
+
+impl From<GatewayError> for RpcError {
+    fn from(error: GatewayError) -> Self {
+        RpcError {
+            code: error.code,
+            message: if error.message.is_empty() {
+                error.error
+            } else {
+                error.message
+            },
+        }
+    }
+}
+
+/// The body of a request for a token.
+#[derive(Debug, Serialize)]
+struct AuthenticateRequest {
+    name: String,
+    password: Secret,
+}
+
+/// The body of a response with a token.
+#[derive(Debug, Deserialize)]
+struct AuthenticateResponse {
+    token: String,
+}
+
+/// A response body consisting only of a `ResponseHeader`.
+#[derive(Debug, Deserialize)]
+struct HeaderOnly {
+    header: ResponseHeader,
+}
+
+/// Makes a request to a v3 API endpoint, e.g. `maintenance/status`, on a single cluster member.
+///
+/// The request fails with `Error::Timeout` if it doesn't complete within the client's timeout.
+fn request<C, B, T>(
+    client: &Client<C>,
+    endpoint: &Uri,
+    path: &'static str,
+    body: &B,
+) -> impl Future<Item = Response<T>, Error = Error> + Send
+where
+    C: Clone + Connect,
+    B: Serialize,
+    T: DeserializeOwned + Send + 'static,
+{
+    let response = call(client.http_client(), endpoint, path, body, false);
+
+    with_timeout(response, client.request_options().timeout)
+}
+
+/// Makes a request to a v3 API endpoint on each cluster member in turn until one succeeds.
+fn request_first_ok<C, B, T>(
+    client: &Client<C>,
+    path: &'static str,
+    body: B,
+) -> impl Future<Item = Response<T>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    B: Serialize + Send + 'static,
+    T: DeserializeOwned + Send + 'static,
+{
+    let http_client = client.http_client().clone();
+
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |endpoint| call(&http_client, endpoint, path, &body, false),
+    )
+}
+
+/// Makes a request to a v3 API endpoint that waits for something to happen, e.g. for a lock to be
+/// acquired, on each cluster member in turn until one succeeds.
+///
+/// Like a watch, each attempt is limited by the client's watch timeout rather than its request
+/// timeout.
+fn wait_first_ok<C, B, T>(
+    client: &Client<C>,
+    path: &'static str,
+    body: B,
+) -> impl Future<Item = Response<T>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    B: Serialize + Send + 'static,
+    T: DeserializeOwned + Send + 'static,
+{
+    let http_client = client.http_client().clone();
+
+    first_ok(
+        client.endpoints(),
+        &waiting_options(client),
+        move |endpoint| call(&http_client, endpoint, path, &body, true),
+    )
+}
+
+/// Makes a request to a v3 API endpoint that streams its response, e.g. `election/observe`, on
+/// each cluster member in turn until one starts responding, and yields each message it streams.
+///
+/// The client's watch timeout limits how long each member may take to start responding, but not
+/// how long the response may go on for.
+fn stream_first_ok<C, B, T>(
+    client: &Client<C>,
+    path: &'static str,
+    body: B,
+) -> impl Stream<Item = Response<T>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+    B: Serialize + Send + 'static,
+    T: DeserializeOwned + Send + 'static,
+{
+    let http_client = client.http_client().clone();
+
+    first_ok(
+        client.endpoints(),
+        &waiting_options(client),
+        move |endpoint| open(&http_client, endpoint, path, &body, true),
+    )
+    .map(|(cluster_info, body)| {
+        Messages {
+            body,
+            buffer: Vec::new(),
+            cluster_info,
+            data: PhantomData,
+            done: false,
+        }
+        .map_err(|error| vec![error])
+    })
+    .flatten_stream()
+}
+
+/// Makes a request to a v3 API endpoint on every cluster member, pairing each response with the
+/// endpoint of the member that produced it.
+fn request_each<C, B, T>(
+    client: &Client<C>,
+    path: &'static str,
+    body: &B,
+) -> impl Stream<Item = (Uri, Response<T>), Error = Error> + Send
+where
+    C: Clone + Connect,
+    B: Serialize,
+    T: DeserializeOwned + Send + 'static,
+{
+    let futures = client.endpoints().into_iter().map(|endpoint| {
+        request(client, &endpoint, path, body).map(move |response| (endpoint, response))
+    });
+
+    futures_unordered(futures)
+}
+
+/// Returns the client's request options with the watch timeout in place of the request timeout,
+/// for requests that wait for something to happen.
+fn waiting_options<C>(client: &Client<C>) -> RequestOptions
+where
+    C: Clone + Connect,
+{
+    let mut options = client.request_options().clone();
+    options.timeout = options.watch_timeout;
+    options.total_timeout = None;
+    options.hedge_delay = None;
+
+    options
+}
+
+/// Makes a request to a v3 API endpoint on a single cluster member and decodes its response.
+fn call<C, B, T>(
+    http_client: &HttpClient<C>,
+    endpoint: &Uri,
+    path: &'static str,
+    body: &B,
+    wait: bool,
+) -> impl Future<Item = Response<T>, Error = Error> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+    B: Serialize,
+    T: DeserializeOwned + Send + 'static,
+{
+    open(http_client, endpoint, path, body, wait).and_then(decode)
+}
+
+/// Makes a request to a v3 API endpoint on a single cluster member, returning the response's
+/// cluster information and its unread body once the request has succeeded.
+///
+/// If the client has credentials, the request is authenticated with a token issued for them,
+/// which is requested first if the client doesn't have one yet. If etcd no longer accepts the
+/// token, e.g. because it has expired, a new one is requested and the request is made again.
+///
+/// Once etcd has reported that authentication is not enabled, requests are made without a token
+/// and without requesting one, until etcd refuses a request for lacking one.
+fn open<C, B>(
+    http_client: &HttpClient<C>,
+    endpoint: &Uri,
+    path: &'static str,
+    body: &B,
+    wait: bool,
+) -> Box<dyn Future<Item = (ClusterInfo, Body), Error = Error> + Send>
+where
+    C: Clone + Connect + Sync + 'static,
+    B: Serialize,
+{
+    let body = match serde_json::to_string(body) {
+        Ok(body) => body,
+        Err(error) => return Box::new(Err(Error::Serialization(error)).into_future()),
+    };
+    let http_client = http_client.clone();
+    let endpoint = endpoint.clone();
+
+    match (http_client.basic_auth().is_some(), http_client.v3_token()) {
+        (false, _) => Box::new(send(&http_client, &endpoint, path, body, None, wait)),
+        (true, None) if http_client.v3_auth_disabled() => {
+            let retry_body = body.clone();
+
+            Box::new(
+                send(&http_client, &endpoint, path, body, None, wait).or_else(move |error| {
+                    if is_token_required(&error) {
+                        http_client.set_v3_auth_disabled(false);
+
+                        Either::A(authenticate_and_send(
+                            http_client,
+                            endpoint,
+                            path,
+                            retry_body,
+                            wait,
+                        ))
+                    } else {
+                        Either::B(Err(error).into_future())
+                    }
+                }),
+            )
+        }
+        (true, None) => Box::new(authenticate_and_send(
+            http_client,
+            endpoint,
+            path,
+            body,
+            wait,
+        )),
+        (true, Some(token)) => {
+            let retry_body = body.clone();
+
+            Box::new(
+                send(&http_client, &endpoint, path, body, Some(token), wait).or_else(
+                    move |error| {
+                        if is_invalid_token(&error) {
+                            http_client.set_v3_token(None);
+
+                            Either::A(authenticate_and_send(
+                                http_client,
+                                endpoint,
+                                path,
+                                retry_body,
+                                wait,
+                            ))
+                        } else {
+                            Either::B(Err(error).into_future())
+                        }
+                    },
+                ),
+            )
+        }
+    }
+}
+
+/// Requests a token for the client's credentials, stores it in the client, and then makes a
+/// request with it.
+///
+/// If authentication is not enabled in the cluster, the client records that, and the request is
+/// made without a token.
+fn authenticate_and_send<C>(
+    http_client: HttpClient<C>,
+    endpoint: Uri,
+    path: &'static str,
+    body: String,
+    wait: bool,
+) -> impl Future<Item = (ClusterInfo, Body), Error = Error> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    let credentials = match http_client.basic_auth() {
+        Some(basic_auth) => AuthenticateRequest {
+            name: basic_auth.username.clone(),
+            password: basic_auth.password.clone(),
+        },
+        None => return Either::A(send(&http_client, &endpoint, path, body, None, wait)),
+    };
+
+    let token =
+        authenticate(&http_client, &endpoint, &credentials).then(move |result| match result {
+            Ok(response) => {
+                http_client.set_v3_token(Some(response.data.clone()));
+
+                Either::A(send(
+                    &http_client,
+                    &endpoint,
+                    path,
+                    body,
+                    Some(response.data),
+                    wait,
+                ))
+            }
+            Err(ref error) if is_auth_not_enabled(error) => {
+                http_client.set_v3_auth_disabled(true);
+
+                Either::A(send(&http_client, &endpoint, path, body, None, wait))
+            }
+            Err(error) => Either::B(Err(error).into_future()),
+        });
+
+    Either::B(token)
+}
+
+/// Requests a token for the given credentials from a single cluster member.
+fn authenticate<C>(
+    http_client: &HttpClient<C>,
+    endpoint: &Uri,
+    credentials: &AuthenticateRequest,
+) -> impl Future<Item = Response<String>, Error = Error> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    let body = match serde_json::to_string(credentials) {
+        Ok(body) => body,
+        Err(error) => return Either::A(Err(Error::Serialization(error)).into_future()),
+    };
+
+    Either::B(
+        send(
+            http_client,
+            endpoint,
+            "auth/authenticate",
+            body,
+            None,
+            false,
+        )
+        .and_then(decode)
+        .map(|response: Response<AuthenticateResponse>| response.map(|data| data.token)),
+    )
+}
+
+/// Sends a request to a v3 API endpoint on a single cluster member, with the given token if any,
+/// returning the response's cluster information and its unread body once the request has
+/// succeeded.
+fn send<C>(
+    http_client: &HttpClient<C>,
+    endpoint: &Uri,
+    path: &'static str,
+    body: String,
+    token: Option<String>,
+    wait: bool,
+) -> impl Future<Item = (ClusterInfo, Body), Error = Error> + Send
+where
+    C: Clone + Connect + Sync + 'static,
+{
+    let http_client = http_client.clone();
+    let endpoint = endpoint.clone();
+
+    loop_fn(0, move |position| {
+        let url = endpoint_url(&endpoint, &format!("{}/{}", PREFIXES[position], path));
+        let uri = Uri::from_str(&url).map_err(Error::from).into_future();
+        let http_client = http_client.clone();
+        let body = body.clone();
+        let token = token.clone();
+
+        uri.and_then(move |uri| {
+            if wait {
+                Either::A(http_client.watch_json(uri, body, token))
+            } else {
+                Either::B(http_client.post_json(uri, body, token))
+            }
+        })
+        .and_then(move |response| {
+            let status = response.status();
+            let cluster_info = ClusterInfo::from(response.headers());
+
+            if status == StatusCode::OK {
+                return Either::A(
+                    Ok(Loop::Break((cluster_info, response.into_body()))).into_future(),
+                );
+            }
+
+            Either::B(
+                response
+                    .into_body()
+                    .concat2()
+                    .map_err(Error::from)
+                    .and_then(move |body| {
+                        match serde_json::from_slice::<GatewayError>(&body) {
+                            Ok(error) => Err(Error::Rpc(error.into())),
+                            // A member that doesn't serve the prefix responds with a plain text
+                            // 404, while a call that fails with gRPC's "not found" status has a
+                            // JSON body.
+                            Err(_)
+                                if status == StatusCode::NOT_FOUND
+                                    && position + 1 < PREFIXES.len() =>
+                            {
+                                Ok(Loop::Continue(position + 1))
+                            }
+                            Err(_) => Err(Error::UnexpectedStatus(status)),
+                        }
+                    }),
+            )
+        })
+    })
+}
+
+/// Receives the body of a successful response in full and decodes it.
+fn decode<T>(
+    (cluster_info, body): (ClusterInfo, Body),
+) -> impl Future<Item = Response<T>, Error = Error> + Send
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    body.concat2().map_err(Error::from).and_then(move |body| {
+        match serde_json::from_slice::<T>(&body) {
+            Ok(data) => Ok(Response { data, cluster_info }),
+            Err(error) => Err(Error::Serialization(error)),
+        }
+    })
+}
+
+/// Returns whether or not an error means etcd no longer accepts the client's token.
+fn is_invalid_token(error: &Error) -> bool {
+    match *error {
+        Error::Rpc(ref error) => error.message.contains("invalid auth token"),
+        _ => false,
+    }
+}
+
+/// Returns whether or not an error means a request needs a token, because authentication has
+/// been enabled in the cluster.
+fn is_token_required(error: &Error) -> bool {
+    match *error {
+        Error::Rpc(ref error) => error.message.contains("user name is empty"),
+        _ => false,
+    }
+}
+
+/// Returns whether or not an error means authentication is not enabled in the cluster.
+fn is_auth_not_enabled(error: &Error) -> bool {
+    match *error {
+        Error::Rpc(ref error) => error.message.contains("authentication is not enabled"),
+        _ => false,
+    }
+}
+
+/// The messages of a streaming response from the gRPC gateway, which are separated by newlines.
+struct Messages<T> {
+    body: Body,
+    buffer: Vec<u8>,
+    cluster_info: ClusterInfo,
+    data: PhantomData<T>,
+    done: bool,
+}
+
+/// A message of a streaming response from the gRPC gateway.
+#[derive(Debug, Deserialize)]
+struct Message<T> {
+    error: Option<GatewayError>,
+    result: Option<T>,
+}
+
+impl<T> Messages<T>
+where
+    T: DeserializeOwned,
+{
+    /// Decodes a message, returning `None` if it has neither a result nor an error.
+    fn decode(&self, message: &[u8]) -> Result<Option<Response<T>>, Error> {
+        match serde_json::from_slice::<Message<T>>(message) {
+            Ok(Message {
+                error: Some(error), ..
+            }) => Err(Error::Rpc(error.into())),
+            Ok(Message {
+                result: Some(data), ..
+            }) => Ok(Some(Response {
+                data,
+                cluster_info: self.cluster_info.clone(),
+            })),
+            Ok(_) => Ok(None),
+            Err(error) => Err(Error::Serialization(error)),
+        }
+    }
+}
+
+impl<T> Stream for Messages<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Response<T>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Response<T>>, Error> {
+        loop {
+            let message = match self.buffer.iter().position(|&byte| byte == b'\n') {
+                Some(end) => self.buffer.drain(..=end).collect::<Vec<_>>(),
+                None if self.done => {
+                    if self.buffer.iter().all(u8::is_ascii_whitespace) {
+                        return Ok(Async::Ready(None));
+                    }
+
+                    mem::take(&mut self.buffer)
+                }
+                None => {
+                    match self.body.poll()? {
+                        Async::Ready(Some(chunk)) => self.buffer.extend_from_slice(&chunk),
+                        Async::Ready(None) => self.done = true,
+                        Async::NotReady => return Ok(Async::NotReady),
+                    }
+
+                    continue;
+                }
+            };
+
+            if message.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            if let Some(response) = self.decode(&message)? {
+                return Ok(Async::Ready(Some(response)));
+            }
+        }
+    }
+}
+
+/// A v3 API stream that is re-established when it fails with transient errors, e.g. because a
+/// cluster member couldn't be reached or stopped responding, waiting between attempts as
+/// `RequestOptions::reconnect_backoff` allows.
+///
+/// Each attempt is made by calling `connect` with the last item yielded, if any, so it can resume
+/// where the previous attempt left off.
+struct Reconnecting<T, F> {
+    backoff: Backoff,
+    connect: F,
+    current: Option<Box<dyn Stream<Item = T, Error = Vec<Error>> + Send>>,
+    delay: Option<Delay>,
+    last: Option<T>,
+    wait: Duration,
+}
+
+impl<T, F> Reconnecting<T, F>
+where
+    F: FnMut(Option<&T>) -> Box<dyn Stream<Item = T, Error = Vec<Error>> + Send>,
+{
+    /// Constructs a stream that connects with `connect`, waiting between attempts as the client's
+    /// options allow.
+    fn new<C>(client: &Client<C>, connect: F) -> Self
+    where
+        C: Clone + Connect,
+    {
+        let backoff = client.request_options().reconnect_backoff;
+
+        Reconnecting {
+            backoff,
+            connect,
+            current: None,
+            delay: None,
+            last: None,
+            wait: backoff.initial,
+        }
+    }
+}
+
+impl<T, F> Stream for Reconnecting<T, F>
+where
+    T: Clone,
+    F: FnMut(Option<&T>) -> Box<dyn Stream<Item = T, Error = Vec<Error>> + Send>,
+{
+    type Item = T;
+    type Error = Vec<Error>;
+
+    fn poll(&mut self) -> Poll<Option<T>, Vec<Error>> {
+        loop {
+            if let Some(ref mut delay) = self.delay {
+                if let Ok(Async::NotReady) = delay.poll() {
+                    return Ok(Async::NotReady);
+                }
+            }
+
+            self.delay = None;
+
+            let last = self.last.as_ref();
+            let connect = &mut self.connect;
+            let current = self.current.get_or_insert_with(|| connect(last));
+
+            match current.poll() {
+                Ok(Async::Ready(Some(item))) => {
+                    self.last = Some(item.clone());
+                    self.wait = self.backoff.initial;
+
+                    return Ok(Async::Ready(Some(item)));
+                }
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(ref errors) if errors.iter().all(Error::is_transient) => {
+                    self.current = None;
+                    self.delay = Some(Delay::new(Instant::now() + self.wait));
+                    self.wait = self.backoff.next(self.wait);
+                }
+                Err(errors) => return Err(errors),
+            }
+        }
+    }
+}
+
+/// Serializes 64-bit integers as strings, the way the gRPC gateway does, and deserializes them
+/// from either strings or numbers.
+mod int64 {
+    use super::*;
+
+    /// Serializes an integer as a string.
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Display,
+        S: Serializer,
+    {
+        serializer.collect_str(value)
+    }
+
+    /// Deserializes an integer from a string or a number.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: Deserialize<'de> + FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr<T> {
+            Number(T),
+            String(String),
+        }
+
+        match Repr::<T>::deserialize(deserializer)? {
+            Repr::Number(value) => Ok(value),
+            Repr::String(value) => value.parse().map_err(D::Error::custom),
+        }
+    }
+}
+
+/// Serializes bytes as standard Base64, the way the gRPC gateway does.
+mod bytes {
+    use super::*;
+
+    /// Serializes bytes as a Base64 string.
+    pub fn serialize<S>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(value))
+    }
+
+    /// Deserializes bytes from a Base64 string.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+
+        base64::decode(&value).map_err(D::Error::custom)
+    }
+}
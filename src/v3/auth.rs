@@ -0,0 +1,52 @@
+//! etcd's v3 authentication API.
+//!
+//! The v3 API authenticates requests with tokens rather than with a username and password. A
+//! client with credentials, given to `Client::new` or `Client::with_basic_auth`, requests a token
+//! for them before its first v3 API call and sends it with each call after that. When etcd stops
+//! accepting the token, e.g. because it has expired, a new one is requested and the call is made
+//! again. Clients derived from one another share the token until their credentials change.
+
+use futures::Future;
+use hyper::client::connect::Connect;
+
+use super::AuthenticateRequest;
+use crate::client::{Client, Response};
+use crate::error::Error;
+use crate::first_ok::first_ok;
+use crate::secret::Secret;
+
+/// Requests a token for the given user from the v3 API.
+///
+/// This is not needed for the functions in the `v3` module, which request tokens for the client's
+/// credentials automatically. It is useful for passing a token to other programs, or for checking
+/// a user's password.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * name: The name of the user.
+/// * password: The user's password.
+///
+/// # Errors
+///
+/// Fails if the password is wrong or authentication is not enabled in the cluster.
+pub fn authenticate<C>(
+    client: &Client<C>,
+    name: &str,
+    password: &str,
+) -> impl Future<Item = Response<String>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let http_client = client.http_client().clone();
+    let credentials = AuthenticateRequest {
+        name: name.to_owned(),
+        password: Secret::new(password.to_owned()),
+    };
+
+    first_ok(
+        client.endpoints(),
+        client.request_options(),
+        move |endpoint| super::authenticate(&http_client, endpoint, &credentials),
+    )
+}
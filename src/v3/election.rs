@@ -0,0 +1,280 @@
+//! etcd's v3 election API, for electing a leader among a group of clients.
+//!
+//! Candidates campaign to become the leader of an election, waiting until the current leader, if
+//! any, resigns or its lease expires. The leader can publish a value, e.g. its address, which
+//! other clients can read with `v3::election::leader` or follow with `v3::election::observe`.
+//! Clients that only need to know who the leader is, e.g. to route requests to it, can follow
+//! changes of leader with `v3::election::observe_only`.
+
+use futures::{Future, Stream};
+use hyper::client::connect::Connect;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+    bytes,
+    int64,
+    request_first_ok,
+    stream_first_ok,
+    wait_first_ok,
+    HeaderOnly,
+    KeyValue,
+    Reconnecting,
+    ResponseHeader,
+};
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+/// Represents leadership of an election, returned by `v3::election::campaign`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct LeaderKey {
+    /// The name of the election.
+    #[serde(default, with = "bytes")]
+    pub name: Vec<u8>,
+    /// The key in the v3 key-value store that holds the leader's value. It exists for as long as
+    /// the leader holds leadership.
+    #[serde(default, with = "bytes")]
+    pub key: Vec<u8>,
+    /// The creation revision of `key`, which increases each time leadership changes hands.
+    #[serde(default, with = "int64")]
+    pub rev: i64,
+    /// The ID of the lease attached to leadership.
+    #[serde(default, with = "int64")]
+    pub lease: i64,
+}
+
+/// The body of a request to the campaign endpoint.
+#[derive(Debug, Serialize)]
+struct CampaignRequest {
+    #[serde(with = "bytes")]
+    name: Vec<u8>,
+    #[serde(with = "int64")]
+    lease: i64,
+    #[serde(with = "bytes")]
+    value: Vec<u8>,
+}
+
+/// The body of a response from the campaign endpoint.
+#[derive(Debug, Deserialize)]
+struct CampaignResponse {
+    #[serde(default)]
+    leader: LeaderKey,
+}
+
+/// The body of a request to the proclaim endpoint.
+#[derive(Debug, Serialize)]
+struct ProclaimRequest {
+    leader: LeaderKey,
+    #[serde(with = "bytes")]
+    value: Vec<u8>,
+}
+
+/// The body of a request to the leader and observe endpoints.
+#[derive(Clone, Debug, Serialize)]
+struct LeaderRequest {
+    #[serde(with = "bytes")]
+    name: Vec<u8>,
+}
+
+/// The body of a response from the leader and observe endpoints.
+#[derive(Debug, Deserialize)]
+struct LeaderResponse {
+    #[serde(default)]
+    kv: KeyValue,
+}
+
+/// The body of a request to the resign endpoint.
+#[derive(Debug, Serialize)]
+struct ResignRequest {
+    leader: LeaderKey,
+}
+
+/// Campaigns to become the leader of an election, waiting for as long as another candidate is
+/// the leader.
+///
+/// Like a watch, this waits for as long as the client's watch timeout allows, rather than its
+/// request timeout.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * name: The name of the election.
+/// * lease: The ID of a lease to attach to leadership, which ends it when the lease expires.
+/// * value: The initial value to publish once elected.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached, if the lease has expired, or with
+/// `Error::Timeout` if the client's watch timeout lapses before the campaign succeeds.
+pub fn campaign<C>(
+    client: &Client<C>,
+    name: &str,
+    lease: i64,
+    value: &[u8],
+) -> impl Future<Item = Response<LeaderKey>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = CampaignRequest {
+        name: name.as_bytes().to_vec(),
+        lease,
+        value: value.to_vec(),
+    };
+
+    wait_first_ok(client, "election/campaign", body)
+        .map(|response: Response<CampaignResponse>| response.map(|data| data.leader))
+}
+
+/// Publishes a new value as the leader of an election.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * leader: The leadership returned by `v3::election::campaign`.
+/// * value: The value to publish.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached, or if leadership has been lost.
+pub fn proclaim<C>(
+    client: &Client<C>,
+    leader: &LeaderKey,
+    value: &[u8],
+) -> impl Future<Item = Response<ResponseHeader>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = ProclaimRequest {
+        leader: leader.clone(),
+        value: value.to_vec(),
+    };
+
+    request_first_ok(client, "election/proclaim", body)
+        .map(|response: Response<HeaderOnly>| response.map(|data| data.header))
+}
+
+/// Returns the key and the value published by the current leader of an election.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * name: The name of the election.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached, or if the election has no leader.
+pub fn leader<C>(
+    client: &Client<C>,
+    name: &str,
+) -> impl Future<Item = Response<KeyValue>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = LeaderRequest {
+        name: name.as_bytes().to_vec(),
+    };
+
+    request_first_ok(client, "election/leader", body)
+        .map(|response: Response<LeaderResponse>| response.map(|data| data.kv))
+}
+
+/// Follows the leadership of an election, yielding the key and the value published by the
+/// leader each time either changes, starting with the current leader.
+///
+/// The stream continues until it is dropped or an error occurs. If a cluster member can't be
+/// reached or stops responding, the stream is re-established after waiting as the client's
+/// `RequestOptions::reconnect_backoff` allows, and yields the current leader again. Because the
+/// stream's response never completes, it can't be used with a client that logs response bodies.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * name: The name of the election.
+///
+/// # Errors
+///
+/// The stream ends with an error if etcd rejects the request.
+pub fn observe<C>(
+    client: &Client<C>,
+    name: &str,
+) -> impl Stream<Item = Response<KeyValue>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = LeaderRequest {
+        name: name.as_bytes().to_vec(),
+    };
+
+    let observe_client = client.clone();
+
+    Reconnecting::new(client, move |_: Option<&Response<KeyValue>>| {
+        Box::new(
+            stream_first_ok(&observe_client, "election/observe", body.clone())
+                .map(|response: Response<LeaderResponse>| response.map(|data| data.kv)),
+        )
+    })
+}
+
+/// Follows the leadership of an election without campaigning, yielding the key and the value
+/// published by the leader each time leadership passes to another candidate, starting with the
+/// current leader.
+///
+/// Unlike `v3::election::observe`, new values published by the same leader are skipped, as is the
+/// current leader when the stream is re-established after a cluster member can't be reached.
+/// Leaders are told apart by their key and its creation revision, so a candidate that wins the
+/// election again after losing it is yielded again. Nothing is yielded while the election has no
+/// leader.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * name: The name of the election.
+///
+/// # Errors
+///
+/// The stream ends with an error if etcd rejects the request.
+pub fn observe_only<C>(
+    client: &Client<C>,
+    name: &str,
+) -> impl Stream<Item = Response<KeyValue>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let mut current: Option<(Vec<u8>, i64)> = None;
+
+    observe(client, name).filter(move |response| {
+        let leader = (response.data.key.clone(), response.data.create_revision);
+
+        if current.as_ref() == Some(&leader) {
+            false
+        } else {
+            current = Some(leader);
+
+            true
+        }
+    })
+}
+
+/// Gives up leadership of an election, so that another candidate can be elected.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * leader: The leadership returned by `v3::election::campaign`.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn resign<C>(
+    client: &Client<C>,
+    leader: &LeaderKey,
+) -> impl Future<Item = Response<ResponseHeader>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = ResignRequest {
+        leader: leader.clone(),
+    };
+
+    request_first_ok(client, "election/resign", body)
+        .map(|response: Response<HeaderOnly>| response.map(|data| data.header))
+}
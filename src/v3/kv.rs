@@ -0,0 +1,521 @@
+//! etcd's v3 key-value API.
+//!
+//! Keys and values in the v3 API are bytes. The functions in this module take keys as strings, and
+//! return them as bytes in `KeyValue`.
+//!
+//! etcd keeps every revision of the v3 key-value store until it is compacted. A cluster that
+//! isn't compacted automatically, e.g. with etcd's `--auto-compaction-retention` flag, grows until
+//! it runs out of space, so clients that manage retention themselves should compact it
+//! periodically with `v3::kv::compact`. `RevisionHistory` helps to find the revision to compact
+//! to when revisions should be kept for a period of time.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use futures::stream::{iter_ok, Stream};
+use futures::Future;
+use hyper::client::connect::Connect;
+use serde_derive::{Deserialize, Serialize};
+
+use super::lock::FencingToken;
+use super::{
+    bytes,
+    int64,
+    request_first_ok,
+    stream_first_ok,
+    HeaderOnly,
+    KeyValue,
+    Reconnecting,
+    ResponseHeader,
+};
+use crate::client::{Client, Response};
+use crate::error::{Error, RpcError};
+
+/// The kind of change to a key in an `Event`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum EventType {
+    /// The key was created or modified.
+    #[default]
+    #[serde(rename = "PUT")]
+    Put,
+    /// The key was deleted.
+    #[serde(rename = "DELETE")]
+    Delete,
+}
+
+/// A change to a key, returned by `v3::kv::watch`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Event {
+    /// The kind of change.
+    #[serde(default, rename = "type")]
+    pub kind: EventType,
+    /// The key and its value after the change. After a deletion, the value is empty and
+    /// `mod_revision` is the revision of the deletion.
+    #[serde(default)]
+    pub kv: KeyValue,
+}
+
+/// The body of a request to the range endpoint.
+#[derive(Debug, Serialize)]
+struct RangeRequest {
+    #[serde(with = "bytes")]
+    key: Vec<u8>,
+    count_only: bool,
+}
+
+/// The body of a response from the range endpoint.
+#[derive(Debug, Deserialize)]
+struct RangeResponse {
+    #[serde(default)]
+    kvs: Vec<KeyValue>,
+}
+
+/// The body of a request to the put endpoint.
+#[derive(Debug, Serialize)]
+struct PutRequest {
+    #[serde(with = "bytes")]
+    key: Vec<u8>,
+    #[serde(with = "bytes")]
+    value: Vec<u8>,
+}
+
+/// The body of a request to the transaction endpoint.
+#[derive(Debug, Serialize)]
+struct TxnRequest {
+    compare: Vec<Compare>,
+    success: Vec<RequestOp>,
+}
+
+/// A condition of a transaction on the creation revision of a key.
+#[derive(Debug, Serialize)]
+struct Compare {
+    result: &'static str,
+    target: &'static str,
+    #[serde(with = "bytes")]
+    key: Vec<u8>,
+    #[serde(with = "int64")]
+    create_revision: i64,
+}
+
+/// An operation made by a transaction.
+#[derive(Debug, Serialize)]
+struct RequestOp {
+    request_put: PutRequest,
+}
+
+/// The body of a response from the transaction endpoint.
+#[derive(Debug, Deserialize)]
+struct TxnResponse {
+    #[serde(default)]
+    header: ResponseHeader,
+    #[serde(default)]
+    succeeded: bool,
+}
+
+/// The body of a request to the delete range endpoint.
+#[derive(Debug, Serialize)]
+struct DeleteRangeRequest {
+    #[serde(with = "bytes")]
+    key: Vec<u8>,
+}
+
+/// The body of a response from the delete range endpoint.
+#[derive(Debug, Deserialize)]
+struct DeleteRangeResponse {
+    #[serde(default, with = "int64")]
+    deleted: i64,
+}
+
+/// The body of a request to the watch endpoint.
+#[derive(Debug, Serialize)]
+struct WatchRequest {
+    create_request: WatchCreateRequest,
+}
+
+/// A request to start watching a key.
+#[derive(Debug, Serialize)]
+struct WatchCreateRequest {
+    #[serde(with = "bytes")]
+    key: Vec<u8>,
+    #[serde(with = "int64")]
+    start_revision: i64,
+}
+
+/// A message streamed from the watch endpoint.
+#[derive(Clone, Debug, Deserialize)]
+struct WatchResponse {
+    #[serde(default)]
+    header: ResponseHeader,
+    #[serde(default)]
+    canceled: bool,
+    #[serde(default)]
+    cancel_reason: String,
+    #[serde(default, with = "int64")]
+    compact_revision: i64,
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+/// The body of a request to the compaction endpoint.
+#[derive(Debug, Serialize)]
+struct CompactionRequest {
+    #[serde(with = "int64")]
+    revision: i64,
+    physical: bool,
+}
+
+/// A record of the revisions of the v3 key-value store over time, for finding the revision to
+/// compact to in order to keep revisions for a period of time.
+///
+/// Record the current revision periodically, e.g. with the result of `v3::kv::current_revision`,
+/// and compact to the revision returned by `RevisionHistory::revision_older_than`. The shorter the
+/// period, the more precisely the retention period is kept.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RevisionHistory {
+    max_age: Duration,
+    samples: VecDeque<(SystemTime, i64)>,
+}
+
+impl RevisionHistory {
+    /// Constructs an empty history that keeps revisions for at least `max_age`, the longest age
+    /// that will be passed to `RevisionHistory::revision_older_than`.
+    pub fn new(max_age: Duration) -> Self {
+        RevisionHistory {
+            max_age,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Records the revision of the key-value store at the current time.
+    pub fn record(&mut self, revision: i64) {
+        self.record_at(SystemTime::now(), revision);
+    }
+
+    /// Records the revision of the key-value store at the given time.
+    ///
+    /// Records for times earlier than the latest one are ignored. Records that are older than
+    /// the history's maximum age and no longer needed are forgotten.
+    pub fn record_at(&mut self, time: SystemTime, revision: i64) {
+        if let Some(&(latest, _)) = self.samples.back() {
+            if time < latest {
+                return;
+            }
+        }
+
+        self.samples.push_back((time, revision));
+
+        if let Some(cutoff) = time.checked_sub(self.max_age) {
+            while self.samples.len() > 1 && self.samples[1].0 <= cutoff {
+                self.samples.pop_front();
+            }
+        }
+    }
+
+    /// Returns the latest recorded revision that is at least `age` old, or `None` if none is.
+    pub fn revision_older_than(&self, age: Duration) -> Option<i64> {
+        SystemTime::now()
+            .checked_sub(age)
+            .and_then(|time| self.revision_at(time))
+    }
+
+    /// Returns the latest revision recorded at or before the given time, or `None` if none was.
+    pub fn revision_at(&self, time: SystemTime) -> Option<i64> {
+        self.samples
+            .iter()
+            .rev()
+            .find(|&&(recorded, _)| recorded <= time)
+            .map(|&(_, revision)| revision)
+    }
+}
+
+/// Compacts the key-value store, discarding the revisions before the given one.
+///
+/// Watches and reads can't start from compacted revisions. Compacting frees the space used by
+/// old revisions for reuse, but doesn't shrink the database file, which requires
+/// `v3::maintenance::defragment`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * revision: The oldest revision to keep.
+/// * physical: Whether or not to wait until the compaction has been applied to the database.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached, or if the revision has already been compacted
+/// or is newer than the current revision.
+pub fn compact<C>(
+    client: &Client<C>,
+    revision: i64,
+    physical: bool,
+) -> impl Future<Item = Response<ResponseHeader>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = CompactionRequest { revision, physical };
+
+    request_first_ok(client, "kv/compaction", body)
+        .map(|response: Response<HeaderOnly>| response.map(|data| data.header))
+}
+
+/// Returns the current revision of the key-value store.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn current_revision<C>(
+    client: &Client<C>,
+) -> impl Future<Item = Response<i64>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    // Any read returns the current revision in its header, so this counts the keys named by a
+    // single zero byte rather than reading any values.
+    let body = RangeRequest {
+        key: vec![0],
+        count_only: true,
+    };
+
+    request_first_ok(client, "kv/range", body)
+        .map(|response: Response<HeaderOnly>| response.map(|data| data.header.revision))
+}
+
+/// Deletes a key.
+///
+/// Returns the number of keys deleted, which is zero if the key didn't exist.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The key to delete.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn delete<C>(
+    client: &Client<C>,
+    key: &str,
+) -> impl Future<Item = Response<i64>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = DeleteRangeRequest {
+        key: key.as_bytes().to_vec(),
+    };
+
+    request_first_ok(client, "kv/deleterange", body)
+        .map(|response: Response<DeleteRangeResponse>| response.map(|data| data.deleted))
+}
+
+/// Gets a key and its value, or `None` if the key doesn't exist.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The key to get.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn get<C>(
+    client: &Client<C>,
+    key: &str,
+) -> impl Future<Item = Response<Option<KeyValue>>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = RangeRequest {
+        key: key.as_bytes().to_vec(),
+        count_only: false,
+    };
+
+    request_first_ok(client, "kv/range", body)
+        .map(|response: Response<RangeResponse>| response.map(|data| data.kvs.into_iter().next()))
+}
+
+/// Sets the value of a key, creating it if it doesn't exist.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The key to set.
+/// * value: The new value.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn put<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &[u8],
+) -> impl Future<Item = Response<ResponseHeader>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = PutRequest {
+        key: key.as_bytes().to_vec(),
+        value: value.to_vec(),
+    };
+
+    request_first_ok(client, "kv/put", body)
+        .map(|response: Response<HeaderOnly>| response.map(|data| data.header))
+}
+
+/// Sets the value of a key like `v3::kv::put`, but only if a lock is still held by the client
+/// that acquired it with `v3::lock::lock_with_fence`.
+///
+/// The write is made in a transaction that compares the creation revision of the key representing
+/// ownership of the lock with the token, so it is refused if the lock has been released or has
+/// passed to another client, even if the client making the write hasn't noticed.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The key to set.
+/// * value: The new value.
+/// * token: The fencing token of the lock that protects the key.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached, or with `Error::LockLost` if the lock is no
+/// longer held.
+pub fn put_with_fence<C>(
+    client: &Client<C>,
+    key: &str,
+    value: &[u8],
+    token: &FencingToken,
+) -> impl Future<Item = Response<ResponseHeader>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = TxnRequest {
+        compare: vec![Compare {
+            result: "EQUAL",
+            target: "CREATE",
+            key: token.key().to_vec(),
+            create_revision: token.value(),
+        }],
+        success: vec![RequestOp {
+            request_put: PutRequest {
+                key: key.as_bytes().to_vec(),
+                value: value.to_vec(),
+            },
+        }],
+    };
+
+    request_first_ok(client, "kv/txn", body).and_then(|response: Response<TxnResponse>| {
+        if response.data.succeeded {
+            Ok(response.map(|data| data.header))
+        } else {
+            Err(vec![Error::LockLost])
+        }
+    })
+}
+
+/// Watches a key for changes, yielding each change as it takes place.
+///
+/// The stream continues until it is dropped or an error occurs. If a cluster member can't be
+/// reached or stops responding, the watch is re-established after waiting as the client's
+/// `RequestOptions::reconnect_backoff` allows, resuming after the last change it yielded. Because
+/// the stream's response never completes, it can't be used with a client that logs response
+/// bodies.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The key to watch.
+/// * start_revision: The revision to start watching from, or the next revision if `None`.
+///
+/// Starting from an earlier revision yields the changes made since then first, so a watch can
+/// resume from the revision after the last change it yielded without missing any.
+///
+/// # Errors
+///
+/// The stream ends with an error if etcd rejects the watch, e.g. because `start_revision` has been
+/// compacted.
+pub fn watch<C>(
+    client: &Client<C>,
+    key: &str,
+    start_revision: Option<i64>,
+) -> impl Stream<Item = Response<Event>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let watch_client = client.clone();
+    let key = key.to_owned();
+
+    Reconnecting::new(client, move |last: Option<&Response<WatchResponse>>| {
+        let resume_revision = match last.map(|response| &response.data) {
+            Some(WatchResponse { events, .. }) if !events.is_empty() => {
+                events.last().map(|event| event.kv.mod_revision + 1)
+            }
+            // Before any changes, a watch that started from the next revision resumes from the
+            // one after the revision it was created at.
+            Some(WatchResponse { header, .. }) if start_revision.is_none() => {
+                Some(header.revision + 1)
+            }
+            _ => start_revision,
+        };
+
+        Box::new(watch_from(&watch_client, &key, resume_revision))
+    })
+    .map(|response| {
+        let cluster_info = response.cluster_info;
+
+        iter_ok(response.data.events.into_iter().map(move |data| Response {
+            data,
+            cluster_info: cluster_info.clone(),
+        }))
+    })
+    .flatten()
+}
+
+/// Watches a key for changes on the first cluster member that can be reached, yielding each
+/// message etcd sends until the connection fails.
+fn watch_from<C>(
+    client: &Client<C>,
+    key: &str,
+    start_revision: Option<i64>,
+) -> impl Stream<Item = Response<WatchResponse>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = WatchRequest {
+        create_request: WatchCreateRequest {
+            key: key.as_bytes().to_vec(),
+            start_revision: start_revision.unwrap_or(0),
+        },
+    };
+
+    stream_first_ok(client, "watch", body).and_then(|response: Response<WatchResponse>| {
+        if response.data.canceled {
+            Err(vec![Error::Rpc(canceled(response.data))])
+        } else {
+            Ok(response)
+        }
+    })
+}
+
+/// Describes why etcd canceled a watch.
+fn canceled(response: WatchResponse) -> RpcError {
+    if response.compact_revision > 0 {
+        RpcError {
+            // gRPC's "out of range" status, which etcd uses for compacted revisions.
+            code: 11,
+            message: format!(
+                "etcdserver: mvcc: required revision has been compacted, the oldest revision is {}",
+                response.compact_revision
+            ),
+        }
+    } else {
+        RpcError {
+            // gRPC's "cancelled" status.
+            code: 1,
+            message: if response.cancel_reason.is_empty() {
+                "etcdserver: watch canceled".to_owned()
+            } else {
+                response.cancel_reason
+            },
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! etcd's v3 lock API, for distributed locks held by the cluster.
+//!
+//! A lock is held until it is released with `v3::lock::unlock`, or until the lease it was acquired
+//! with expires. Acquiring a lock with a lease keeps a client that dies while holding it from
+//! holding it forever.
+//!
+//! A client can lose a lock without noticing, e.g. when its lease expires during a long pause, and
+//! go on to write to a resource the lock protects after another client has acquired it. Acquiring
+//! a lock with `v3::lock::lock_with_fence` returns a `FencingToken` to guard against this.
+
+use std::str;
+
+use futures::future::Either;
+use futures::{Future, IntoFuture};
+use hyper::client::connect::Connect;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{bytes, int64, kv, request_first_ok, wait_first_ok, HeaderOnly, ResponseHeader};
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+/// A number that increases each time a lock changes hands, returned by
+/// `v3::lock::lock_with_fence`.
+///
+/// A resource the lock protects can refuse writes that carry a lower token than the highest it has
+/// seen, since they come from a client that has lost the lock. Writes to the v3 key-value store can
+/// instead be made with `v3::kv::put_with_fence`, which refuses them if the lock is no longer held.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct FencingToken {
+    key: Vec<u8>,
+    revision: i64,
+}
+
+impl FencingToken {
+    /// Returns the token's number: the revision at which the key representing ownership of the
+    /// lock was created.
+    pub fn value(&self) -> i64 {
+        self.revision
+    }
+
+    /// Returns the key that represents ownership of the lock, which is needed to release it.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+/// The body of a request to the lock endpoint.
+#[derive(Debug, Serialize)]
+struct LockRequest {
+    #[serde(with = "bytes")]
+    name: Vec<u8>,
+    #[serde(with = "int64")]
+    lease: i64,
+}
+
+/// The body of a response from the lock endpoint.
+#[derive(Debug, Deserialize)]
+struct LockResponse {
+    #[serde(default, with = "bytes")]
+    key: Vec<u8>,
+}
+
+/// The body of a request to the unlock endpoint.
+#[derive(Debug, Serialize)]
+struct UnlockRequest {
+    #[serde(with = "bytes")]
+    key: Vec<u8>,
+}
+
+/// Acquires a lock, waiting for as long as it is held by someone else. Returns the key that
+/// represents ownership of the lock, which is needed to release it.
+///
+/// The key exists in the v3 key-value store for as long as the lock is held, and its creation
+/// revision increases each time the lock changes hands, so it can be used as a fencing token.
+///
+/// Like a watch, this waits for as long as the client's watch timeout allows, rather than its
+/// request timeout.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * name: The name of the lock.
+/// * lease: The ID of a lease to attach to the lock, which releases it when the lease expires.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached, if the lease has expired, or with
+/// `Error::Timeout` if the client's watch timeout lapses before the lock is acquired.
+pub fn lock<C>(
+    client: &Client<C>,
+    name: &str,
+    lease: Option<i64>,
+) -> impl Future<Item = Response<Vec<u8>>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = LockRequest {
+        name: name.as_bytes().to_vec(),
+        lease: lease.unwrap_or(0),
+    };
+
+    wait_first_ok(client, "lock/lock", body)
+        .map(|response: Response<LockResponse>| response.map(|data| data.key))
+}
+
+/// Acquires a lock like `v3::lock::lock`, returning a fencing token for it.
+///
+/// The token is read from the key that represents ownership of the lock once the lock has been
+/// acquired, which takes a second API call.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * name: The name of the lock.
+/// * lease: The ID of a lease to attach to the lock, which releases it when the lease expires.
+///
+/// # Errors
+///
+/// Fails like `v3::lock::lock`, or with `Error::LockLost` if the lock was released before its
+/// token could be read.
+pub fn lock_with_fence<C>(
+    client: &Client<C>,
+    name: &str,
+    lease: Option<i64>,
+) -> impl Future<Item = Response<FencingToken>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let client = client.clone();
+
+    lock(&client, name, lease).and_then(move |response| {
+        // Lock keys are the name of the lock followed by the lease ID in hexadecimal.
+        let key = match str::from_utf8(&response.data) {
+            Ok(key) => key.to_owned(),
+            Err(error) => {
+                return Either::A(Err(vec![Error::Codec(error.to_string())]).into_future())
+            }
+        };
+
+        Either::B(kv::get(&client, &key).and_then(|response| {
+            let cluster_info = response.cluster_info;
+
+            match response.data {
+                Some(kv) => Ok(Response {
+                    data: FencingToken {
+                        key: kv.key,
+                        revision: kv.create_revision,
+                    },
+                    cluster_info,
+                }),
+                None => Err(vec![Error::LockLost]),
+            }
+        }))
+    })
+}
+
+/// Releases a lock.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The key returned by `v3::lock::lock` when the lock was acquired.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn unlock<C>(
+    client: &Client<C>,
+    key: &[u8],
+) -> impl Future<Item = Response<ResponseHeader>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = UnlockRequest { key: key.to_vec() };
+
+    request_first_ok(client, "lock/unlock", body)
+        .map(|response: Response<HeaderOnly>| response.map(|data| data.header))
+}
@@ -0,0 +1,250 @@
+//! etcd's v3 maintenance API, for checking on and looking after cluster members.
+
+use futures::{Future, Stream};
+use hyper::client::connect::Connect;
+use hyper::Uri;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{int64, request, request_each, request_first_ok, HeaderOnly, ResponseHeader};
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+/// The status of a cluster member, returned by `v3::maintenance::status`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Status {
+    /// Information about the cluster.
+    #[serde(default)]
+    pub header: ResponseHeader,
+    /// Problems the member has detected, e.g. a lack of space. Requires etcd 3.4.
+    #[serde(default)]
+    pub errors: Vec<String>,
+    /// The size of the member's database file in bytes, including free space.
+    #[serde(default, rename = "dbSize", with = "int64")]
+    pub db_size: u64,
+    /// The number of bytes of the member's database file that are in use. This is less than
+    /// `db_size` by the space a defragmentation would reclaim. Requires etcd 3.4.
+    #[serde(default, rename = "dbSizeInUse", with = "int64")]
+    pub db_size_in_use: u64,
+    /// Whether or not the member is a learner, which replicates the log without voting. Requires
+    /// etcd 3.4.
+    #[serde(default, rename = "isLearner")]
+    pub is_learner: bool,
+    /// The ID of the member the member believes to be the leader, or zero if there is none.
+    #[serde(default, with = "int64")]
+    pub leader: u64,
+    /// The index of the last Raft log entry the member has applied. Requires etcd 3.4.
+    #[serde(default, rename = "raftAppliedIndex", with = "int64")]
+    pub raft_applied_index: u64,
+    /// The index of the last Raft log entry the member has committed.
+    #[serde(default, rename = "raftIndex", with = "int64")]
+    pub raft_index: u64,
+    /// The member's Raft term.
+    #[serde(default, rename = "raftTerm", with = "int64")]
+    pub raft_term: u64,
+    /// The version of etcd the member is running.
+    #[serde(default)]
+    pub version: String,
+}
+
+impl Status {
+    /// Returns whether or not the member is the leader of the cluster.
+    pub fn is_leader(&self) -> bool {
+        self.leader != 0 && self.leader == self.header.member_id
+    }
+}
+
+/// A hash of a cluster member's key-value store, returned by `v3::maintenance::hash_kv`.
+///
+/// Members that agree on the hash for a revision have the same data. A mismatch indicates
+/// corruption.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct HashKv {
+    /// Information about the cluster.
+    #[serde(default)]
+    pub header: ResponseHeader,
+    /// The revision the store had been compacted to when the hash was computed. Hashes are only
+    /// comparable between members with the same compact revision.
+    #[serde(default, with = "int64")]
+    pub compact_revision: i64,
+    /// The hash of the keys and values up to the requested revision.
+    #[serde(default)]
+    pub hash: u32,
+}
+
+/// A kind of alarm raised by a cluster member.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum AlarmType {
+    /// No alarm.
+    #[default]
+    #[serde(rename = "NONE")]
+    None,
+    /// The member's database has exceeded its space quota, so it only accepts reads and deletes.
+    #[serde(rename = "NOSPACE")]
+    NoSpace,
+    /// The member's data has been found to differ from the rest of the cluster's.
+    #[serde(rename = "CORRUPT")]
+    Corrupt,
+}
+
+/// An alarm raised by a cluster member, returned by `v3::maintenance::alarm_list`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Alarm {
+    /// The kind of alarm.
+    #[serde(default)]
+    pub alarm: AlarmType,
+    /// The ID of the member that raised the alarm.
+    #[serde(default, rename = "memberID", with = "int64")]
+    pub member_id: u64,
+}
+
+/// The body of a request to the alarm endpoint.
+#[derive(Debug, Serialize)]
+struct AlarmRequest {
+    action: &'static str,
+    alarm: AlarmType,
+    #[serde(rename = "memberID", with = "int64")]
+    member_id: u64,
+}
+
+/// The body of a response from the alarm endpoint.
+#[derive(Debug, Deserialize)]
+struct AlarmResponse {
+    #[serde(default)]
+    alarms: Vec<Alarm>,
+}
+
+/// The body of a request to the hash endpoint.
+#[derive(Debug, Serialize)]
+struct HashKvRequest {
+    #[serde(with = "int64")]
+    revision: i64,
+}
+
+/// An empty request body.
+#[derive(Debug, Serialize)]
+struct Empty {}
+
+/// Returns the alarms raised by members of the cluster.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn alarm_list<C>(
+    client: &Client<C>,
+) -> impl Future<Item = Response<Vec<Alarm>>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = AlarmRequest {
+        action: "GET",
+        alarm: AlarmType::None,
+        member_id: 0,
+    };
+
+    request_first_ok(client, "maintenance/alarm", body)
+        .map(|response: Response<AlarmResponse>| response.map(|data| data.alarms))
+}
+
+/// Disarms an alarm once the problem that raised it has been dealt with, e.g. by compacting and
+/// defragmenting after a `AlarmType::NoSpace` alarm. Returns the alarms that were disarmed.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * alarm: The alarm to disarm, as returned by `v3::maintenance::alarm_list`.
+///
+/// # Errors
+///
+/// Fails if no cluster member could be reached.
+pub fn alarm_disarm<C>(
+    client: &Client<C>,
+    alarm: Alarm,
+) -> impl Future<Item = Response<Vec<Alarm>>, Error = Vec<Error>> + Send
+where
+    C: Clone + Connect,
+{
+    let body = AlarmRequest {
+        action: "DEACTIVATE",
+        alarm: alarm.alarm,
+        member_id: alarm.member_id,
+    };
+
+    request_first_ok(client, "maintenance/alarm", body)
+        .map(|response: Response<AlarmResponse>| response.map(|data| data.alarms))
+}
+
+/// Releases the free space in a cluster member's database file back to the file system.
+///
+/// The member can't serve requests while it is being defragmented, so members should be
+/// defragmented one at a time. Defragmenting a large database can take longer than the client's
+/// timeout, so a client with a longer timeout may be needed.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * endpoint: The endpoint of the member to defragment.
+///
+/// # Errors
+///
+/// Fails if the member could not be reached or the defragmentation failed.
+pub fn defragment<C>(
+    client: &Client<C>,
+    endpoint: &Uri,
+) -> impl Future<Item = Response<ResponseHeader>, Error = Error> + Send
+where
+    C: Clone + Connect,
+{
+    request(client, endpoint, "maintenance/defragment", &Empty {})
+        .map(|response: Response<HeaderOnly>| response.map(|data| data.header))
+}
+
+/// Returns a hash of the key-value store of each cluster member the client was initialized with.
+///
+/// Each item is paired with the endpoint of the member that produced it.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * revision: The revision to hash the store up to, or the latest revision if `None`.
+///
+/// Without a revision, members that are behind may hash different revisions, so their hashes
+/// won't match even if they aren't corrupt.
+///
+/// etcd's gateway serves this call and the older whole-store hash at the same path, and some
+/// versions route it to the older call. Their members ignore `revision` and report a
+/// `compact_revision` of zero.
+///
+/// # Errors
+///
+/// Fails if a member could not be reached, or if JSON decoding fails, which suggests a bug in our
+/// schema.
+pub fn hash_kv<C>(
+    client: &Client<C>,
+    revision: Option<i64>,
+) -> impl Stream<Item = (Uri, Response<HashKv>), Error = Error> + Send
+where
+    C: Clone + Connect,
+{
+    let body = HashKvRequest {
+        revision: revision.unwrap_or(0),
+    };
+
+    request_each(client, "maintenance/hash", &body)
+}
+
+/// Returns the status of each cluster member the client was initialized with.
+///
+/// Each item is paired with the endpoint of the member that produced it.
+///
+/// # Errors
+///
+/// Fails if a member could not be reached, or if JSON decoding fails, which suggests a bug in our
+/// schema.
+pub fn status<C>(
+    client: &Client<C>,
+) -> impl Stream<Item = (Uri, Response<Status>), Error = Error> + Send
+where
+    C: Clone + Connect,
+{
+    request_each(client, "maintenance/status", &Empty {})
+}
@@ -1,5 +1,8 @@
 //! Types for the version endpoint.
 
+use std::collections::HashMap;
+
+use hyper::Uri;
 use serde_derive::{Deserialize, Serialize};
 
 /// Information about the versions of etcd running in a cluster.
@@ -14,3 +17,77 @@ pub struct VersionInfo {
     #[serde(rename = "etcdserver")]
     pub server_version: String,
 }
+
+/// The versions of etcd running on each member of a cluster.
+///
+/// This value is returned by `Client::cluster_versions`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ClusterVersions {
+    /// The version information from each member that responded, keyed by the member's endpoint.
+    pub members: HashMap<Uri, VersionInfo>,
+}
+
+impl ClusterVersions {
+    /// Returns the lowest server version among the members, which bounds the features the cluster
+    /// can be relied upon to support.
+    ///
+    /// Returns `None` if there are no members or none of their versions could be parsed.
+    pub fn min_server_version(&self) -> Option<&str> {
+        self.members
+            .values()
+            .filter_map(|info| {
+                parse_version(&info.server_version).map(|version| (version, &info.server_version))
+            })
+            .min()
+            .map(|(_, version)| version.as_str())
+    }
+
+    /// Returns whether or not every member supports the given feature.
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.min_server_version()
+            .and_then(parse_version)
+            .map(|(major, minor, _)| (major, minor) >= feature.min_version())
+            .unwrap_or(false)
+    }
+}
+
+/// A capability of etcd that is only available in some versions.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Feature {
+    /// The authentication and authorization API, used by the `auth` module. Requires etcd 2.1.
+    Auth,
+    /// Resetting a key's TTL without notifying watchers, used by `kv::refresh`. Requires etcd
+    /// 2.3.
+    RefreshTtl,
+    /// The v3 API. Requires etcd 3.0.
+    V3Api,
+}
+
+impl Feature {
+    /// Returns the major and minor version of etcd that introduced this feature.
+    pub fn min_version(self) -> (u64, u64) {
+        match self {
+            Feature::Auth => (2, 1),
+            Feature::RefreshTtl => (2, 3),
+            Feature::V3Api => (3, 0),
+        }
+    }
+}
+
+/// Parses a version string like "2.3.8" into its major, minor, and patch numbers.
+///
+/// Anything after the patch number, such as a pre-release suffix, is ignored.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts
+        .next()?
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()?;
+
+    Some((major, minor, patch))
+}
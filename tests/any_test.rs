@@ -0,0 +1,119 @@
+use etcd::any::{self, Api, EventType, KeyValue};
+use etcd::Client;
+use futures::Stream;
+use tokio::runtime::Runtime;
+
+use crate::test::serve_statuses;
+
+mod test;
+
+#[test]
+fn detects_v3() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "",
+                r#"{"etcdserver":"3.4.0","etcdcluster":"3.4.0"}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"header":{},"kvs":[{"key":"Zm9v","value":"YmFy","mod_revision":"9"}],"count":"1"}"#,
+            ),
+            ("200 OK", "", r#"{"header":{"revision":"10"}}"#),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let client = runtime.block_on(any::Client::detect(&client)).unwrap();
+
+    assert_eq!(client.api(), Api::V3);
+
+    let kv = runtime.block_on(client.get("foo")).unwrap();
+
+    assert_eq!(
+        kv,
+        Some(KeyValue {
+            key: "foo".to_owned(),
+            value: "bar".to_owned(),
+            revision: 9,
+        })
+    );
+
+    assert!(!runtime.block_on(client.delete("foo")).unwrap());
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("GET /version"));
+    assert!(requests[1].starts_with("POST /v3/kv/range"));
+    assert!(requests[2].starts_with("POST /v3/kv/deleterange"));
+}
+
+#[test]
+fn detects_v2() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "",
+                r#"{"etcdserver":"2.3.8","etcdcluster":"2.3.0"}"#,
+            ),
+            (
+                "404 Not Found",
+                "X-Etcd-Index: 7\r\n",
+                r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":7}"#,
+            ),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let client = runtime.block_on(any::Client::detect(&client)).unwrap();
+
+    assert_eq!(client.api(), Api::V2);
+    assert_eq!(runtime.block_on(client.get("foo")).unwrap(), None);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[1].starts_with("GET /v2/keys/foo"));
+}
+
+#[test]
+fn watch_v3() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "200 OK",
+            "",
+            concat!(
+                r#"{"result":{"header":{},"created":true}}"#,
+                "\n",
+                r#"{"result":{"header":{},"events":[{"kv":{"key":"Zm9v","value":"YmFy","mod_revision":"11"}},{"type":"DELETE","kv":{"key":"Zm9v","mod_revision":"12"}}]}}"#,
+                "\n",
+            ),
+        )],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let client = any::Client::with_api(&client, Api::V3);
+
+    let work = client.watch("foo", Some(11)).collect();
+    let events = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].kind, EventType::Set);
+    assert_eq!(events[0].kv.value, "bar");
+    assert_eq!(events[1].kind, EventType::Delete);
+    assert_eq!(events[1].kv.revision, 12);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/watch"));
+    assert!(requests[0].ends_with(r#"{"create_request":{"key":"Zm9v","start_revision":"11"}}"#));
+}
@@ -1,5 +1,6 @@
-use etcd::auth::{self, AuthChange, NewUser, Role, RoleUpdate, UserUpdate};
-use etcd::{BasicAuth, Client};
+use etcd::auth::{self, AuthChange, Credentials, NewUser, Role, RoleUpdate, User, UserUpdate};
+use etcd::kv;
+use etcd::{AccessDenied, BasicAuth, Client, Error};
 use futures::future::Future;
 use tokio::runtime::Runtime;
 
@@ -8,6 +9,9 @@ fn auth() {
     let client = Client::new(&["http://etcd:2379"], None).unwrap();
     let client_2 = client.clone();
     let client_3 = client.clone();
+    let client_4 = client.clone();
+    let client_5 = client.clone();
+    let client_6 = client.clone();
 
     let basic_auth = BasicAuth {
         username: "root".into(),
@@ -23,6 +27,7 @@ fn auth() {
     let authed_client_7 = authed_client.clone();
     let authed_client_8 = authed_client.clone();
     let authed_client_9 = authed_client.clone();
+    let authed_client_10 = authed_client.clone();
 
     let root_user = NewUser::new("root", "secret");
 
@@ -56,6 +61,22 @@ fn auth() {
             .then(move |res| {
                 res.unwrap();
 
+                kv::set(&client_6, "/test/forbidden", "bar", None)
+            })
+            .then(move |res| {
+                let errors = res.unwrap_err();
+
+                match errors[0] {
+                    Error::Unauthorized(ref access) => assert_eq!(
+                        *access,
+                        AccessDenied {
+                            key: Some("/test/forbidden".to_owned()),
+                            user: None,
+                        }
+                    ),
+                    ref error => panic!("expected Error::Unauthorized, got {:?}", error),
+                }
+
                 let mut rkt_role = Role::new("rkt");
 
                 rkt_role.grant_kv_read_permission("/rkt/*");
@@ -93,6 +114,31 @@ fn auth() {
             .then(move |res| {
                 res.unwrap();
 
+                let rkt_auth = BasicAuth {
+                    username: "rkt".into(),
+                    password: "secret2".into(),
+                };
+
+                auth::verify_credentials(&client_4, rkt_auth)
+            })
+            .then(move |res| {
+                assert_eq!(res.unwrap(), Credentials::Valid);
+
+                let rkt_auth = BasicAuth {
+                    username: "rkt".into(),
+                    password: "secret".into(),
+                };
+
+                auth::verify_credentials(&client_5, rkt_auth)
+            })
+            .then(move |res| {
+                assert_eq!(res.unwrap(), Credentials::Unauthorized);
+
+                auth::change_password(&authed_client_10, "rkt", "secret3")
+            })
+            .then(move |res| {
+                res.unwrap();
+
                 auth::get_role(&authed_client_5, "rkt")
             })
             .then(move |res| {
@@ -137,3 +183,33 @@ fn auth() {
         .expect("failed to create Tokio runtime")
         .block_on(work);
 }
+
+#[test]
+fn prefix_role() {
+    for prefix in &["rkt", "/rkt", "/rkt/", "/rkt/*"] {
+        let role = Role::for_prefix("rkt", prefix);
+
+        assert_eq!(role.kv_read_permissions(), ["/rkt", "/rkt/*"]);
+        assert_eq!(role.kv_write_permissions(), ["/rkt/*"]);
+    }
+
+    let role = Role::for_prefix("everything", "/");
+
+    assert_eq!(role.kv_read_permissions(), ["/", "/*"]);
+    assert_eq!(role.kv_write_permissions(), ["/*"]);
+}
+
+#[test]
+fn user_role_names() {
+    let names: User = serde_json::from_str(r#"{"user":"rkt","roles":["rkt"]}"#).unwrap();
+    assert_eq!(names.role_names(), ["rkt"]);
+
+    let objects: User = serde_json::from_str(
+        r#"{"user":"rkt","roles":[{"role":"rkt","permissions":{"kv":{"read":["/rkt/*"]}}}]}"#,
+    )
+    .unwrap();
+    assert_eq!(objects.role_names(), ["rkt"]);
+
+    let missing: User = serde_json::from_str(r#"{"user":"rkt","roles":null}"#).unwrap();
+    assert!(missing.role_names().is_empty());
+}
@@ -0,0 +1,69 @@
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use etcd::bus;
+use etcd::kv;
+use futures::future::{lazy, Future};
+use futures::stream::Stream;
+use futures::sync::oneshot::channel;
+
+use crate::test::TestClient;
+
+mod test;
+
+#[test]
+fn subscribe() {
+    let (tx, rx) = channel();
+
+    let child = spawn(move || {
+        let mut client = TestClient::no_destructor();
+        let inner_client = client.clone();
+
+        let work = rx.then(move |_| {
+            sleep(Duration::from_millis(100));
+
+            kv::set(&inner_client, "/test/foo", "bar", None)
+        });
+
+        client.run(work);
+    });
+
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = lazy(move || {
+        let bus = bus::subscribe(&inner_client, "/test");
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+
+        assert_eq!(bus.subscriber_count(), 2);
+
+        tx.send(()).unwrap();
+
+        first
+            .into_future()
+            .join(second.into_future())
+            .map_err(|_| ())
+            .and_then(move |((first, _), (second, _))| {
+                // A subscription ends without a change if watching failed.
+                let responses = first.into_iter().chain(second).collect::<Vec<_>>();
+
+                if responses.len() < 2 {
+                    return Err(());
+                }
+
+                for response in responses {
+                    assert_eq!(response.data.node.key, Some("/test/foo".to_owned()));
+                    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+                }
+
+                assert!(!bus.is_stale());
+
+                Ok(())
+            })
+    });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
@@ -1,6 +1,41 @@
+use std::error::Error as StdError;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "encryption")]
+use etcd::codec::Aes256Gcm;
+use etcd::codec::{Base64, Encrypted, Encryptor, Utf8, ValueCodec};
+#[cfg(feature = "compression")]
+use etcd::codec::{Compressed, Compression};
+use etcd::dns::{CachingResolver, Name, Resolve};
+use etcd::kv::{self, GetOptions};
+use etcd::{
+    BasicAuth,
+    Client,
+    ClusterInfo,
+    ClusterVersions,
+    ConnectionOptions,
+    DebugLogging,
+    EndpointHealth,
+    Error,
+    Feature,
+    Health,
+    Response,
+    TlsOptions,
+    VersionInfo,
+};
+use futures::future::{self, FutureResult};
 use futures::{Future, Stream};
+use hyper::service::service_fn_ok;
+use hyper::{Body, Request, Response as HyperResponse, Server, Uri};
+use tokio::runtime::Runtime;
+use tokio::timer::Delay;
 
-use crate::test::TestClient;
+use crate::test::{etcd_version, serve, serve_once, serve_statuses, TestClient};
 
 mod test;
 
@@ -8,9 +43,17 @@ mod test;
 fn health() {
     let mut client = TestClient::no_destructor();
 
-    let work = client.health().collect().and_then(|responses| {
-        for response in responses {
-            assert_eq!(response.data.health, "true");
+    let work = client.health().collect().and_then(|outcomes| {
+        for outcome in outcomes {
+            assert_eq!(outcome.endpoint().host(), Some("etcd"));
+
+            match outcome {
+                EndpointHealth::Responded(_, response) => {
+                    assert_eq!(response.data.health, "true");
+                    assert!(response.data.healthy);
+                }
+                EndpointHealth::Failed(_, _) => return Err(()),
+            }
         }
 
         Ok(())
@@ -18,14 +61,418 @@ fn health() {
 
     client.run(work);
 }
+
+#[test]
+fn health_with_unreachable_member() {
+    let (port, server) = serve_once("127.0.0.1:0", r#"{"health":"true"}"#);
+    let healthy_endpoint = format!("http://127.0.0.1:{}", port);
+    let closed_endpoint = format!(
+        "http://{}",
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    );
+    let client = Client::new(&[&healthy_endpoint, &closed_endpoint], None).unwrap();
+
+    let mut runtime = Runtime::new().unwrap();
+    let mut outcomes = runtime.block_on(client.health().collect()).unwrap();
+    outcomes.sort_by_key(|outcome| outcome.endpoint().to_string());
+
+    assert_eq!(outcomes.len(), 2);
+    assert!(outcomes.iter().any(EndpointHealth::is_healthy));
+
+    for outcome in outcomes {
+        match outcome {
+            EndpointHealth::Responded(endpoint, response) => {
+                assert_eq!(endpoint.port_part().map(|port| port.as_u16()), Some(port));
+                assert!(response.data.healthy);
+            }
+            EndpointHealth::Failed(endpoint, _) => {
+                assert_eq!(endpoint.to_string().trim_end_matches('/'), closed_endpoint);
+            }
+        }
+    }
+
+    server.join().unwrap();
+    runtime.shutdown_now();
+}
+
+#[test]
+fn new_with_connection_options() {
+    let options = ConnectionOptions {
+        connect_timeout: Some(Duration::from_secs(5)),
+        max_idle_per_host: Some(2),
+        idle_timeout: Some(Duration::from_secs(30)),
+        tcp_keepalive: Some(Duration::from_secs(60)),
+        tcp_nodelay: true,
+        ..ConnectionOptions::default()
+    };
+    let client = Client::new_with(&["http://etcd:2379"], None, options).unwrap();
+
+    let work = client.health().collect().and_then(|outcomes| {
+        for outcome in outcomes {
+            match outcome {
+                EndpointHealth::Responded(_, response) => assert!(response.data.healthy),
+                EndpointHealth::Failed(_, _) => return Err(()),
+            }
+        }
+
+        Ok(())
+    });
+
+    let _ = Runtime::new().unwrap().block_on(work);
+}
+
+#[test]
+fn http2_prior_knowledge() {
+    let mut runtime = Runtime::new().unwrap();
+
+    // Each response's value is the HTTP version the request was made with.
+    let server = Server::bind(&([127, 0, 0, 1], 0).into())
+        .http2_only(true)
+        .serve(|| {
+            service_fn_ok(|request: Request<Body>| {
+                HyperResponse::new(Body::from(format!(
+                    r#"{{"action":"get","node":{{"key":"/foo","value":"{:?}","modifiedIndex":1,"createdIndex":1}}}}"#,
+                    request.version()
+                )))
+            })
+        });
+    let endpoint = format!("http://{}", server.local_addr());
+    runtime.spawn(server.map_err(|_| ()));
+
+    let options = ConnectionOptions {
+        http2_only: true,
+        ..ConnectionOptions::default()
+    };
+    let client = Client::new_with(&[&endpoint], None, options).unwrap();
+
+    let response = runtime
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.data.node.value, Some("HTTP/2.0".to_owned()));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn http2_over_tls() {
+    let options = ConnectionOptions {
+        http2_only: true,
+        ..ConnectionOptions::default()
+    };
+
+    Client::https_with(&["https://etcd:2379"], None, options).unwrap();
+
+    let client =
+        Client::https_with_tls(&["https://etcd:2379"], None, options, TlsOptions::default())
+            .unwrap();
+
+    client.reload_tls(TlsOptions::default()).unwrap();
+}
+
+#[test]
+fn request_ids() {
+    let node = r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#;
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            ("200 OK", "", node),
+            ("200 OK", "", node),
+            ("200 OK", "", node),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let correlated = client.with_correlation_id("checkout 42\n");
+    let mut runtime = Runtime::new().unwrap();
+
+    for client in &[&client, &client, &correlated] {
+        runtime
+            .block_on(kv::get(client, "/foo", GetOptions::default()))
+            .unwrap();
+    }
+
+    let request_ids: Vec<String> = server
+        .join()
+        .unwrap()
+        .iter()
+        .map(|request| {
+            request
+                .lines()
+                .find_map(|line| line.strip_prefix("x-request-id: "))
+                .expect("expected an X-Request-Id header")
+                .to_owned()
+        })
+        .collect();
+
+    assert_ne!(request_ids[0], request_ids[1]);
+    assert_eq!(request_ids[2], "checkout 42_");
+
+    // Nothing listens on port 1, so the request fails without a response.
+    let unreachable = Client::new(&["http://127.0.0.1:1"], None)
+        .unwrap()
+        .with_correlation_id("checkout 43");
+    let errors = runtime
+        .block_on(kv::get(&unreachable, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    match errors[..] {
+        [ref error @ Error::Request { .. }] => {
+            assert_eq!(error.request_id(), Some("checkout 43"));
+            assert!(error.source().is_some());
+            assert!(error.http_error().unwrap().is_connect());
+        }
+        ref errors => panic!("expected one Error::Request, got {:?}", errors),
+    }
+
+    runtime.shutdown_now();
+}
+
+/// A resolver that resolves every name to the loopback address and counts its lookups.
+#[derive(Clone, Debug, Default)]
+struct LoopbackResolver {
+    lookups: Arc<AtomicUsize>,
+}
+
+impl Resolve for LoopbackResolver {
+    type Addrs = std::vec::IntoIter<IpAddr>;
+    type Future = FutureResult<Self::Addrs, io::Error>;
+
+    fn resolve(&self, _: Name) -> Self::Future {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+
+        future::ok(vec![IpAddr::V4(Ipv4Addr::LOCALHOST)].into_iter())
+    }
+}
+
+#[test]
+fn new_with_resolver() {
+    let node = r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#;
+    let (port, server) = serve("127.0.0.1:0", vec![("", node), ("", node)]);
+
+    let resolver = LoopbackResolver::default();
+    let lookups = resolver.lookups.clone();
+    let options = ConnectionOptions {
+        // Each request opens a new connection, and so resolves the hostname.
+        max_idle_per_host: Some(0),
+        ..ConnectionOptions::default()
+    };
+    let client = Client::new_with_resolver(
+        &[&format!("http://etcd.example.invalid:{}", port)],
+        None,
+        options,
+        CachingResolver::new(resolver, Duration::from_secs(60)),
+    )
+    .unwrap();
+
+    let mut runtime = Runtime::new().unwrap();
+
+    for _ in 0..2 {
+        let response = runtime
+            .block_on(kv::get(&client, "/foo", GetOptions::default()))
+            .unwrap();
+
+        assert_eq!(response.data.node.value, Some("bar".to_owned()));
+    }
+
+    // The second lookup is answered from the cache.
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    assert_eq!(server.join().unwrap().len(), 2);
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn basic_auth_debug() {
+    let basic_auth = BasicAuth {
+        username: "root".to_owned(),
+        password: "hunter2".to_owned(),
+    };
+
+    let debug = format!("{:?}", basic_auth);
+
+    assert!(debug.contains("root"));
+    assert!(!debug.contains("hunter2"));
+
+    let client = Client::new(&["http://etcd:2379"], Some(basic_auth.clone())).unwrap();
+
+    assert!(!format!("{:?}", client).contains("hunter2"));
+
+    let BasicAuth { username, password } = basic_auth;
+
+    assert_eq!(username, "root");
+    assert_eq!(password, "hunter2");
+}
+
+#[test]
+fn reload_tls() {
+    let client = Client::https_with_tls(
+        &["https://etcd:2379"],
+        None,
+        ConnectionOptions::default(),
+        TlsOptions::default(),
+    )
+    .unwrap();
+    let derived = client.with_timeout(Duration::from_secs(1));
+
+    derived.reload_tls(TlsOptions::default()).unwrap();
+
+    match TlsOptions::from_pem_files(Some("/nonexistent/ca.pem"), None) {
+        Err(Error::Io(_)) => {}
+        result => panic!("expected Error::Io, got {:?}", result),
+    }
+}
+
+#[test]
+fn danger_accept_invalid_certs() {
+    let tls = TlsOptions {
+        danger_accept_invalid_certs: true,
+        ..Default::default()
+    };
+
+    assert!(format!("{:?}", tls).contains("danger_accept_invalid_certs: true"));
+
+    Client::https_with_tls(
+        &["https://etcd:2379"],
+        None,
+        ConnectionOptions::default(),
+        tls,
+    )
+    .unwrap();
+}
+
+#[test]
+fn tls_options_from_pem() {
+    let options = TlsOptions::from_pem(None, None).unwrap();
+
+    assert!(options.root_certificates.is_empty());
+    assert!(options.identity.is_none());
+
+    let invalid =
+        b"-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydGlmaWNhdGU=\n-----END CERTIFICATE-----\n";
+
+    match TlsOptions::from_pem(Some(&invalid[..]), None) {
+        Err(Error::Tls(_)) => {}
+        result => panic!("expected Error::Tls, got {:?}", result),
+    }
+
+    match TlsOptions::from_pem(None, Some((&b"certificate"[..], &b"key"[..]))) {
+        Err(Error::Tls(_)) => {}
+        result => panic!("expected Error::Tls, got {:?}", result),
+    }
+}
+
+#[test]
+fn response_accessors() {
+    let response = Response {
+        cluster_info: ClusterInfo {
+            etcd_index: Some(4),
+            ..Default::default()
+        },
+        data: vec!["foo".to_owned(), "bar".to_owned()],
+    };
+
+    assert_eq!(response.len(), 2);
+
+    let response = response.map(|data| data.join(","));
+
+    assert_eq!(response.cluster_info.etcd_index, Some(4));
+    assert_eq!(response.into_data(), "foo,bar");
+}
+
+#[test]
+fn debug_logging() {
+    let (port, server) = serve(
+        "127.0.0.1:0",
+        vec![
+            (
+                "X-Etcd-Index: 1\r\n",
+                r#"{"action":"set","node":{"key":"/secrets/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+            ),
+            (
+                "X-Etcd-Index: 1\r\n",
+                r#"{"action":"get","node":{"key":"/secrets/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+            ),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None)
+        .unwrap()
+        .with_basic_auth(BasicAuth {
+            username: "root".to_owned(),
+            password: "secret".to_owned(),
+        })
+        .with_debug_logging(DebugLogging {
+            max_body_bytes: 16,
+            secret_prefixes: vec!["/secrets".to_owned()],
+        });
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/secrets/foo", "bar", None)
+        .and_then(move |_| kv::get(&inner_client, "/secrets/foo", GetOptions::default()));
+    let response = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("PUT /v2/keys/secrets/foo"));
+    assert!(requests[1].starts_with("GET /v2/keys/secrets/foo"));
+}
+
+#[test]
+fn max_in_flight_per_endpoint() {
+    // A server that accepts connections but never responds, keeping requests in flight.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    spawn(move || {
+        let connections: Vec<_> = listener.incoming().collect();
+
+        drop(connections);
+    });
+
+    let options = ConnectionOptions {
+        max_in_flight_per_endpoint: Some(1),
+        queue_timeout: Some(Duration::from_millis(100)),
+        ..ConnectionOptions::default()
+    };
+    let client = Client::new_with(&[&endpoint], None, options).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    runtime.spawn(kv::get(&client, "/foo", GetOptions::default()).then(|_| Ok(())));
+
+    let work = Delay::new(Instant::now() + Duration::from_millis(50))
+        .then(move |_| kv::get(&client, "/foo", GetOptions::default()));
+
+    match runtime.block_on(work) {
+        Err(ref errors) => match errors[..] {
+            [Error::Timeout] => {}
+            _ => panic!("expected Error::Timeout, got {:?}", errors),
+        },
+        Ok(response) => panic!("expected Error::Timeout, got {:?}", response),
+    }
+
+    runtime.shutdown_now();
+}
+
 #[test]
 fn versions() {
     let mut client = TestClient::no_destructor();
+    let (major, minor, patch) = etcd_version();
 
-    let work = client.versions().collect().and_then(|responses| {
-        for response in responses {
-            assert_eq!(response.data.cluster_version, "2.3.0");
-            assert_eq!(response.data.server_version, "2.3.8");
+    let work = client.versions().collect().and_then(move |responses| {
+        for (endpoint, response) in responses {
+            assert_eq!(endpoint.host(), Some("etcd"));
+            assert_eq!(
+                response.data.cluster_version,
+                format!("{}.{}.0", major, minor)
+            );
+            assert_eq!(
+                response.data.server_version,
+                format!("{}.{}.{}", major, minor, patch)
+            );
         }
 
         Ok(())
@@ -33,3 +480,267 @@ fn versions() {
 
     client.run(work);
 }
+
+#[test]
+fn cluster_info_labels() {
+    let cluster_info = ClusterInfo {
+        cluster_id: Some("7e27652122e8b2ae".to_owned()),
+        etcd_index: Some(42),
+        raft_index: Some(100),
+        raft_term: Some(3),
+        ..ClusterInfo::default()
+    };
+
+    assert_eq!(
+        cluster_info.labels(),
+        vec![
+            ("cluster_id", "7e27652122e8b2ae".to_owned()),
+            ("raft_term", "3".to_owned()),
+        ]
+    );
+
+    let cluster_info = ClusterInfo {
+        cluster_id: None,
+        etcd_index: None,
+        raft_index: None,
+        raft_term: Some(3),
+        ..ClusterInfo::default()
+    };
+
+    assert_eq!(cluster_info.labels(), vec![("raft_term", "3".to_owned())]);
+}
+
+#[test]
+fn codecs() {
+    let bytes = [0, 159, 146, 150, 255];
+
+    let encoded = Base64.encode(&bytes).unwrap();
+    assert_eq!(encoded, "AJ+Slv8=");
+    assert_eq!(Base64.decode(&encoded).unwrap(), bytes.to_vec());
+    assert!(Base64.decode("not base64!").is_err());
+
+    assert!(Utf8.encode(&bytes).is_err());
+    assert_eq!(Utf8.encode(b"bar").unwrap(), "bar");
+    assert_eq!(Utf8.decode("bar").unwrap(), b"bar".to_vec());
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_codec() {
+    let large = vec![b'a'; 1024];
+
+    for &compression in &[Compression::Gzip, Compression::Zstd] {
+        let codec = Compressed::new(Base64, compression, 64);
+
+        let encoded = codec.encode(&large).unwrap();
+        assert!(encoded.len() < large.len());
+        assert!(encoded.starts_with("gzip:") || encoded.starts_with("zstd:"));
+        assert_eq!(codec.decode(&encoded).unwrap(), large);
+
+        let encoded = codec.encode(b"small").unwrap();
+        assert_eq!(encoded, Base64.encode(b"small").unwrap());
+        assert_eq!(codec.decode(&encoded).unwrap(), b"small".to_vec());
+    }
+
+    let codec = Compressed::new(Utf8, Compression::Gzip, 64);
+    let encoded = codec.encode(b"gzip:not compressed").unwrap();
+    assert_eq!(encoded, "raw:gzip:not compressed");
+    assert_eq!(
+        codec.decode(&encoded).unwrap(),
+        b"gzip:not compressed".to_vec()
+    );
+}
+
+#[derive(Debug)]
+struct Xor(u8);
+
+impl Encryptor for Xor {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(plaintext.iter().map(|byte| byte ^ self.0).collect())
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        self.encrypt(ciphertext)
+    }
+}
+
+#[test]
+fn encrypted_codec() {
+    let codec = Encrypted::new(Xor(0xff), Base64);
+
+    let encoded = codec.encode(b"bar").unwrap();
+    assert_eq!(encoded, Base64.encode(&[!b'b', !b'a', !b'r']).unwrap());
+    assert_eq!(codec.decode(&encoded).unwrap(), b"bar".to_vec());
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn aes_256_gcm() {
+    let encryptor = Aes256Gcm::new(&[7; 32]);
+
+    let first = encryptor.encrypt(b"secret").unwrap();
+    let second = encryptor.encrypt(b"secret").unwrap();
+    assert_ne!(first, second);
+    assert_eq!(encryptor.decrypt(&first).unwrap(), b"secret".to_vec());
+
+    let mut tampered = first.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert!(encryptor.decrypt(&tampered).is_err());
+
+    assert!(Aes256Gcm::new(&[8; 32]).decrypt(&first).is_err());
+    assert!(encryptor.decrypt(&[0; 4]).is_err());
+}
+
+#[test]
+fn is_cluster_healthy() {
+    let mut client = TestClient::no_destructor();
+
+    let work = client.is_cluster_healthy().and_then(|healthy| {
+        assert!(healthy);
+
+        Ok(())
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn health_deserialization() {
+    let health: Health = serde_json::from_str(r#"{"health":"true"}"#).unwrap();
+    assert!(health.healthy);
+
+    let health: Health = serde_json::from_str(r#"{"health":"false"}"#).unwrap();
+    assert!(!health.healthy);
+    assert_eq!(health.health, "false");
+
+    assert_eq!(
+        serde_json::to_string(&health).unwrap(),
+        r#"{"health":"false"}"#
+    );
+}
+
+#[test]
+fn cluster_versions() {
+    let mut client = TestClient::no_destructor();
+    let inner_client = client.clone();
+
+    let work = client.cluster_versions().and_then(move |versions| {
+        assert_eq!(versions.members.len(), 1);
+        assert!(versions.min_server_version().is_some());
+
+        inner_client
+            .supports(Feature::RefreshTtl)
+            .map(|supported| assert!(supported))
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn connect() {
+    let version = r#"{"etcdserver":"3.3.10","etcdcluster":"3.3.0"}"#;
+    let (first_port, first_server) = serve_once("127.0.0.1:0", version);
+    let (second_port, second_server) = serve_once("127.0.0.1:0", version);
+    let first_endpoint = format!("http://127.0.0.1:{}", first_port);
+    let second_endpoint = format!("http://127.0.0.1:{}", second_port);
+
+    let mut runtime = Runtime::new().unwrap();
+    runtime
+        .block_on(Client::new_connected(
+            &[&first_endpoint, &second_endpoint],
+            None,
+        ))
+        .unwrap();
+
+    assert!(first_server.join().unwrap().starts_with("GET /version "));
+    assert!(second_server.join().unwrap().starts_with("GET /version "));
+
+    // Every member must be reachable, unlike with `Client::cluster_versions`.
+    let (port, server) = serve_once("127.0.0.1:0", version);
+    let closed_endpoint = format!(
+        "http://{}",
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    );
+    let client = Client::new(
+        &[&format!("http://127.0.0.1:{}", port), &closed_endpoint],
+        None,
+    )
+    .unwrap();
+
+    let errors = runtime.block_on(client.connect()).unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    server.join().unwrap();
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn add_and_remove_endpoints() {
+    let closed_endpoint = format!(
+        "http://{}",
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    );
+    let client = Client::new(&[&closed_endpoint], None).unwrap();
+    let derived = client.with_timeout(Duration::from_secs(1));
+
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"etcdserver":"3.3.10","etcdcluster":"3.3.0"}"#,
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+
+    client.add_endpoint(&endpoint).unwrap();
+    client.add_endpoint(&endpoint).unwrap();
+
+    assert_eq!(derived.endpoints().len(), 2);
+    assert!(derived.remove_endpoint(&closed_endpoint).unwrap());
+    assert!(!derived.remove_endpoint(&closed_endpoint).unwrap());
+    assert_eq!(client.endpoints(), vec![endpoint.parse::<Uri>().unwrap()]);
+
+    match client.remove_endpoint(&endpoint) {
+        Err(Error::NoEndpoints) => {}
+        result => panic!("expected Error::NoEndpoints, got {:?}", result),
+    }
+
+    let mut runtime = Runtime::new().unwrap();
+    let versions = runtime.block_on(client.cluster_versions()).unwrap();
+
+    assert_eq!(versions.members.len(), 1);
+    assert!(server.join().unwrap().starts_with("GET /version "));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn cluster_versions_supports() {
+    let mut versions = ClusterVersions::default();
+    assert_eq!(versions.min_server_version(), None);
+    assert!(!versions.supports(Feature::Auth));
+
+    for &(endpoint, server_version) in &[
+        ("http://a:2379", "2.3.8"),
+        ("http://b:2379", "2.2.10"),
+        ("http://c:2379", "2.10.0-alpha.0"),
+    ] {
+        versions.members.insert(
+            endpoint.parse().unwrap(),
+            VersionInfo {
+                cluster_version: "2.2.0".to_owned(),
+                server_version: server_version.to_owned(),
+            },
+        );
+    }
+
+    assert_eq!(versions.min_server_version(), Some("2.2.10"));
+    assert!(versions.supports(Feature::Auth));
+    assert!(!versions.supports(Feature::RefreshTtl));
+    assert!(!versions.supports(Feature::V3Api));
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "config")]
+
+use config::Config;
+use etcd::{Client, ConfigSource};
+
+use crate::test::{serve_once, serve_statuses};
+
+mod test;
+
+#[test]
+fn collects_keys_under_prefix() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/app","dir":true,"nodes":[{"key":"/app/name","value":"api","modifiedIndex":2,"createdIndex":2},{"key":"/app/database","dir":true,"nodes":[{"key":"/app/database/url","value":"postgres://db","modifiedIndex":3,"createdIndex":3},{"key":"/app/database/pool","value":"8","modifiedIndex":4,"createdIndex":4}],"modifiedIndex":3,"createdIndex":3}],"modifiedIndex":2,"createdIndex":2}}"#,
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let settings = Config::builder()
+        .set_default("name", "default")
+        .unwrap()
+        .add_source(ConfigSource::new(&client, "/app/"))
+        .build()
+        .unwrap();
+
+    assert_eq!(settings.get::<String>("name").unwrap(), "api");
+    assert_eq!(
+        settings.get::<String>("database.url").unwrap(),
+        "postgres://db"
+    );
+    assert_eq!(settings.get::<u32>("database.pool").unwrap(), 8);
+
+    let request = server.join().unwrap();
+
+    assert!(request.starts_with("GET /v2/keys/app?"));
+    assert!(request.contains("recursive=true"));
+}
+
+#[test]
+fn missing_prefix() {
+    let not_found = r#"{"errorCode":100,"message":"Key not found","cause":"/app","index":7}"#;
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            ("404 Not Found", "", not_found),
+            ("404 Not Found", "", not_found),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let settings = Config::builder()
+        .add_source(ConfigSource::new(&client, "/app"))
+        .build()
+        .unwrap();
+
+    assert!(settings.get::<String>("name").is_err());
+
+    let result = Config::builder()
+        .add_source(ConfigSource::new(&client, "/app").required(true))
+        .build();
+
+    assert!(result.is_err());
+    assert_eq!(server.join().unwrap().len(), 2);
+}
@@ -0,0 +1,36 @@
+use etcd::kv::{self, GetOptions};
+use etcd::{members, Client};
+use tokio::runtime::Runtime;
+
+use crate::test::serve_once;
+
+mod test;
+
+#[test]
+fn kv_with_ipv6_endpoint() {
+    let (port, server) = serve_once(
+        "[::1]:0",
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+    );
+    let endpoint = format!("http://[::1]:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = kv::get(&client, "/foo", GetOptions::default());
+    let response = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+    assert!(server.join().unwrap().starts_with("GET /v2/keys/foo?"));
+}
+
+#[test]
+fn members_with_ipv6_endpoint_and_path_prefix() {
+    let (port, server) = serve_once("[::1]:0", r#"{"members":[]}"#);
+    let endpoint = format!("http://[::1]:{}/etcd", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = members::list(&client);
+    let response = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert!(response.data.is_empty());
+    assert_eq!(server.join().unwrap(), "GET /etcd/v2/members HTTP/1.1");
+}
@@ -0,0 +1,106 @@
+use std::env::temp_dir;
+use std::fs::{remove_file, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+
+use etcd::journal::{self, FileJournal, JournalEntry, JournalSink};
+use etcd::kv::{self, Action, KeyValueInfo, Node, WatchOptions};
+use futures::future::Future;
+use futures::stream::Stream;
+
+use crate::test::TestClient;
+
+mod test;
+
+/// Returns a path for a journal file that doesn't exist yet.
+fn journal_path(name: &str) -> PathBuf {
+    let path = temp_dir().join(format!("etcd-journal-{}-{}", process::id(), name));
+
+    let _ = remove_file(&path);
+
+    path
+}
+
+fn entry(index: u64, value: &str) -> JournalEntry {
+    JournalEntry {
+        index,
+        event: KeyValueInfo {
+            action: Action::Set,
+            node: Node {
+                key: Some("/test/foo".to_owned()),
+                modified_index: Some(index),
+                value: Some(value.to_owned()),
+                ..Default::default()
+            },
+            prev_node: None,
+        },
+    }
+}
+
+#[test]
+fn file_journal() {
+    let path = journal_path("file");
+
+    let mut journal = FileJournal::open(&path).unwrap();
+    journal.append(&entry(1, "bar")).unwrap();
+    drop(journal);
+
+    let mut journal = FileJournal::open(&path).unwrap();
+    journal.append(&entry(2, "baz")).unwrap();
+    drop(journal);
+
+    // A line cut short by a crash is ignored.
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"{\"index\":3,")
+        .unwrap();
+
+    let entries = FileJournal::read(&path).unwrap();
+
+    assert_eq!(entries, vec![entry(1, "bar"), entry(2, "baz")]);
+    assert_eq!(entries[1].checkpoint().index, 3);
+
+    remove_file(&path).unwrap();
+}
+
+#[test]
+fn record() {
+    let path = journal_path("record");
+    let read_path = path.clone();
+
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/test/foo", "bar", None).and_then(move |res| {
+        let index = res.data.node.modified_index.unwrap();
+        let options = WatchOptions {
+            index: Some(index),
+            ..Default::default()
+        };
+
+        journal::record(
+            &inner_client,
+            "/test",
+            FileJournal::open(&path).unwrap(),
+            options,
+        )
+        .into_future()
+        .map_err(|(errors, _)| errors)
+        .and_then(move |(entry, _)| {
+            let entry = entry.unwrap();
+
+            assert_eq!(entry.index, index);
+            assert_eq!(entry.event.node.value, Some("bar".to_owned()));
+            assert_eq!(FileJournal::read(&read_path).unwrap(), vec![entry]);
+
+            remove_file(&read_path).unwrap();
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
@@ -1,12 +1,58 @@
+use std::error::Error as StdError;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
 use std::thread::{sleep, spawn};
-use std::time::Duration;
-
-use etcd::kv::{self, Action, GetOptions, KeyValueInfo, WatchError, WatchOptions};
-use etcd::{Error, Response};
+use std::time::{Duration, Instant};
+
+use etcd::codec::Utf8;
+use etcd::kv::{
+    self,
+    raw,
+    tree::{self, Change},
+    Action,
+    Cache,
+    CopyOptions,
+    DeleteOptions,
+    GetIfNewer,
+    GetOptions,
+    GetOrCreate,
+    ImportOptions,
+    Key,
+    KeyValueInfo,
+    KeyspaceSnapshot,
+    Mutation,
+    MutationObserver,
+    Node,
+    Session,
+    SetBatchOptions,
+    SetOptions,
+    SnapshotEntry,
+    WatchCheckpoint,
+    WatchItem,
+    WatchOptions,
+};
+use etcd::{
+    AccessDenied,
+    ApiError,
+    Backoff,
+    CancellationToken,
+    Client,
+    CompareFailure,
+    EndpointSelection,
+    Error,
+    RequestOptions,
+    Response,
+    RetryClass,
+};
 use futures::future::{join_all, Future};
+use futures::stream::Stream;
 use futures::sync::oneshot::channel;
+use hyper::StatusCode;
+use serde_derive::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+use tokio::timer::Delay;
 
-use crate::test::TestClient;
+use crate::test::{serve, serve_once, serve_statuses, TestClient};
 
 mod test;
 
@@ -232,6 +278,233 @@ fn compare_and_swap() {
     client.run(work);
 }
 
+#[test]
+fn compare_and_swap_failure_details() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |res| {
+        let index = res.data.node.modified_index.unwrap();
+
+        kv::compare_and_swap(
+            &inner_client,
+            "/test/foo",
+            "baz",
+            None,
+            Some("qux"),
+            Some(index + 1),
+        )
+        .then(move |result| {
+            match result {
+                Ok(_) => panic!("expected EtcdError due to failed comparison"),
+                Err(errors) => match errors[0] {
+                    Error::Api(ref error) => {
+                        let failure = error.compare_failure().unwrap();
+
+                        assert_eq!(failure.expected_value, Some("qux".to_owned()));
+                        assert_eq!(failure.actual_value, Some("bar".to_owned()));
+                        assert_eq!(failure.expected_index, Some(index + 1));
+                        assert_eq!(failure.actual_index, Some(index));
+                    }
+                    _ => panic!("expected EtcdError due to failed comparison"),
+                },
+            }
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn compare_failure() {
+    let mut error = ApiError {
+        cause: Some("[3 != 4]".to_owned()),
+        error_code: 101,
+        index: 4,
+        message: "Compare failed".to_owned(),
+    };
+
+    assert_eq!(
+        error.compare_failure(),
+        Some(CompareFailure {
+            expected_index: Some(3),
+            actual_index: Some(4),
+            ..Default::default()
+        })
+    );
+
+    error.cause = Some("[bar != baz]".to_owned());
+
+    assert_eq!(
+        error.compare_failure(),
+        Some(CompareFailure {
+            expected_value: Some("bar".to_owned()),
+            actual_value: Some("baz".to_owned()),
+            ..Default::default()
+        })
+    );
+
+    error.error_code = 100;
+
+    assert_eq!(error.compare_failure(), None);
+}
+
+#[test]
+fn error_source() {
+    let error = Error::Api(ApiError {
+        cause: Some("/test/foo".to_owned()),
+        error_code: 100,
+        index: 4,
+        message: "Key not found".to_owned(),
+    });
+
+    assert_eq!(error.to_string(), "Key not found");
+    assert!(error.source().is_none());
+    assert!(Error::Timeout.source().is_none());
+}
+
+#[test]
+fn error_classification() {
+    let api_error = |error_code| {
+        Error::Api(ApiError {
+            cause: None,
+            error_code,
+            index: 4,
+            message: "".to_owned(),
+        })
+    };
+
+    assert!(Error::Timeout.is_transient());
+    assert!(api_error(301).is_transient());
+    assert!(Error::UnexpectedStatus(StatusCode::BAD_GATEWAY).is_transient());
+    assert!(!Error::StaleRead.is_transient());
+    assert!(Error::StaleRead.is_retryable());
+
+    assert!(!api_error(100).is_retryable());
+    assert!(!Error::UnexpectedStatus(StatusCode::BAD_REQUEST).is_retryable());
+    assert!(!Error::PermissionDenied(AccessDenied::default()).is_retryable());
+    assert!(!Error::Cancelled.is_retryable());
+
+    assert_eq!(api_error(300).retry_class(), RetryClass::AfterBackoff);
+    assert_eq!(api_error(400).retry_class(), RetryClass::Immediately);
+    assert_eq!(api_error(101).retry_class(), RetryClass::Never);
+    assert_eq!(
+        Error::UnexpectedStatus(StatusCode::SERVICE_UNAVAILABLE).retry_class(),
+        RetryClass::AfterBackoff
+    );
+    assert_eq!(Error::StaleRead.retry_class(), RetryClass::Immediately);
+}
+
+#[test]
+fn retryable_error_tries_next_member() {
+    let (first_port, first_server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "500 Internal Server Error",
+            "",
+            r#"{"errorCode":301,"message":"During Leader Election","index":4}"#,
+        )],
+    );
+    let (second_port, second_server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+    );
+    let first_endpoint = format!("http://127.0.0.1:{}", first_port);
+    let second_endpoint = format!("http://127.0.0.1:{}", second_port);
+    let client = Client::new(&[&first_endpoint, &second_endpoint], None).unwrap();
+
+    let response = Runtime::new()
+        .unwrap()
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.data.node.value.unwrap(), "bar");
+    assert_eq!(first_server.join().unwrap().len(), 1);
+    assert!(second_server
+        .join()
+        .unwrap()
+        .starts_with("GET /v2/keys/foo?"));
+}
+
+#[test]
+fn leader_election_is_retried_after_backoff() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "500 Internal Server Error",
+                "",
+                r#"{"errorCode":301,"message":"During Leader Election","index":4}"#,
+            ),
+            (
+                "500 Internal Server Error",
+                "",
+                r#"{"errorCode":301,"message":"During Leader Election","index":4}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 5\r\n",
+                r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            retries: 2,
+            retry_backoff: Backoff {
+                initial: Duration::from_millis(100),
+                max: Duration::from_secs(1),
+            },
+            ..Default::default()
+        });
+
+    let started = Instant::now();
+    let response = Runtime::new()
+        .unwrap()
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.data.node.value.unwrap(), "bar");
+    // The waits before the second and third attempts are 100 and 200 milliseconds.
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert_eq!(server.join().unwrap().len(), 3);
+}
+
+#[test]
+fn permanent_error_is_not_retried() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "404 Not Found",
+            "X-Etcd-Index: 4\r\n",
+            r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":4}"#,
+        )],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    // Nothing listens on port 1, so trying the second member would fail with `Error::Http`.
+    let client = Client::new(&[&endpoint, "http://127.0.0.1:1"], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            retries: 2,
+            ..Default::default()
+        });
+
+    let errors = Runtime::new()
+        .unwrap()
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    match errors[..] {
+        [Error::Api(ref error)] => assert_eq!(error.error_code, 100),
+        ref errors => panic!("expected one Error::Api, got {:?}", errors),
+    }
+
+    assert_eq!(server.join().unwrap().len(), 1);
+}
+
 #[test]
 fn compare_and_swap_requires_conditions() {
     let mut client = TestClient::new();
@@ -258,6 +531,35 @@ fn compare_and_swap_requires_conditions() {
     client.run(work);
 }
 
+#[test]
+fn exists() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::exists(&inner_client, "/test/foo").and_then(|exists| {
+            assert!(exists);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn exists_missing_key() {
+    let mut client = TestClient::new();
+
+    let work = kv::exists(&client, "/test/missing").and_then(|exists| {
+        assert!(!exists);
+
+        Ok(())
+    });
+
+    client.run(work);
+}
+
 #[test]
 fn get() {
     let mut client = TestClient::new();
@@ -266,6 +568,7 @@ fn get() {
     let work = kv::create(&client, "/test/foo", "bar", Some(60)).and_then(move |_| {
         kv::get(&inner_client, "/test/foo", GetOptions::default()).and_then(|res| {
             assert_eq!(res.data.action, Action::Get);
+            assert!(res.cluster_info.headers.contains_key("date"));
 
             let node = res.data.node;
 
@@ -280,34 +583,57 @@ fn get() {
 }
 
 #[test]
-fn get_non_recursive() {
+fn get_unicode_key() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work = join_all(vec![
-        kv::set(&client, "/test/dir/baz", "blah", None),
-        kv::set(&client, "/test/foo", "bar", None),
-    ])
-    .and_then(move |_| {
-        kv::get(
-            &inner_client,
-            "/test",
-            GetOptions {
-                sort: true,
-                ..Default::default()
-            },
-        )
-        .and_then(|res| {
+    let work = kv::create(&client, "/test/ünicode key?#", "bar", None).and_then(move |_| {
+        kv::get(&inner_client, "/test/ünicode key?#", GetOptions::default()).and_then(|res| {
             let node = res.data.node;
 
-            assert_eq!(node.dir.unwrap(), true);
+            assert_eq!(node.key.unwrap(), "/test/ünicode key?#");
+            assert_eq!(node.value.unwrap(), "bar");
 
-            let nodes = node.nodes.unwrap();
+            Ok(())
+        })
+    });
 
-            assert_eq!(nodes[0].clone().key.unwrap(), "/test/dir");
-            assert_eq!(nodes[0].clone().dir.unwrap(), true);
-            assert_eq!(nodes[1].clone().key.unwrap(), "/test/foo");
-            assert_eq!(nodes[1].clone().value.unwrap(), "bar");
+    client.run(work);
+}
+
+#[test]
+fn get_percent_encodes_key() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/test/ünicode key?#","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = kv::get(&client, "/test/ünicode key?#", GetOptions::default());
+    let response = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(
+        response.data.node.key,
+        Some("/test/ünicode key?#".to_owned())
+    );
+    assert!(server
+        .join()
+        .unwrap()
+        .starts_with("GET /v2/keys/test/%C3%BCnicode%20key%3F%23?"));
+}
+
+#[test]
+fn get_with_key() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+    let key = Key::new("test//foo/").unwrap();
+
+    assert_eq!(key.as_str(), "/test/foo");
+
+    let work = kv::create(&client, &key, "bar", None).and_then(move |_| {
+        kv::get(&inner_client, &key, GetOptions::default()).and_then(|res| {
+            assert_eq!(res.data.node.key.unwrap(), "/test/foo");
 
             Ok(())
         })
@@ -317,27 +643,49 @@ fn get_non_recursive() {
 }
 
 #[test]
-fn get_recursive() {
+fn key_validation() {
+    assert_eq!(Key::new("/").unwrap().as_str(), "/");
+    assert_eq!(Key::new("foo").unwrap().as_str(), "/foo");
+    assert_eq!("/_etcdfoo".parse::<Key>().unwrap().as_str(), "/_etcdfoo");
+
+    match Key::new("") {
+        Err(Error::InvalidKey(_)) => {}
+        other => panic!("expected Error::InvalidKey, got {:?}", other),
+    }
+
+    match Key::new("//_etcd/machines") {
+        Err(Error::InvalidKey(_)) => {}
+        other => panic!("expected Error::InvalidKey, got {:?}", other),
+    }
+}
+
+#[test]
+fn get_include_hidden() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work = kv::set(&client, "/test/dir/baz", "blah", None).and_then(move |_| {
+    let work = join_all(vec![
+        kv::set(&client, "/test/foo", "bar", None),
+        kv::set(&client, "/test/_hidden", "baz", None),
+    ])
+    .and_then(move |_| {
         kv::get(
             &inner_client,
             "/test",
             GetOptions {
-                recursive: true,
-                sort: true,
+                include_hidden: &["_hidden", "_missing"],
                 ..Default::default()
             },
         )
         .and_then(|res| {
             let nodes = res.data.node.nodes.unwrap();
 
-            assert_eq!(
-                nodes[0].clone().nodes.unwrap()[0].clone().value.unwrap(),
-                "blah"
-            );
+            assert_eq!(nodes.len(), 2);
+            assert_eq!(nodes[0].clone().key.unwrap(), "/test/foo");
+            assert!(!nodes[0].is_hidden());
+            assert_eq!(nodes[1].clone().key.unwrap(), "/test/_hidden");
+            assert_eq!(nodes[1].clone().value.unwrap(), "baz");
+            assert!(nodes[1].is_hidden());
 
             Ok(())
         })
@@ -347,20 +695,22 @@ fn get_recursive() {
 }
 
 #[test]
-fn get_root() {
+fn get_include_hidden_of_key_value_pair() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work = kv::create(&client, "/test/foo", "bar", Some(60)).and_then(move |_| {
-        kv::get(&inner_client, "/", GetOptions::default()).and_then(|res| {
-            assert_eq!(res.data.action, Action::Get);
-
-            let node = res.data.node;
-
-            assert!(node.created_index.is_none());
-            assert!(node.modified_index.is_none());
-            assert_eq!(node.nodes.unwrap().len(), 1);
-            assert_eq!(node.dir.unwrap(), true);
+    let work = kv::set(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::get(
+            &inner_client,
+            "/test/foo",
+            GetOptions {
+                include_hidden: &["_hidden"],
+                ..Default::default()
+            },
+        )
+        .and_then(|res| {
+            assert_eq!(res.data.node.value.unwrap(), "bar");
+            assert_eq!(res.data.node.nodes, None);
 
             Ok(())
         })
@@ -370,90 +720,935 @@ fn get_root() {
 }
 
 #[test]
-fn https() {
-    let mut client = TestClient::https(true);
-
-    let work = kv::set(&client, "/test/foo", "bar", Some(60));
-
-    client.run(work);
-}
+fn get_include_hidden_sorted() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
 
-#[test]
-fn https_without_valid_client_certificate() {
-    let mut client = TestClient::https(false);
+    let work = join_all(vec![
+        kv::set(&client, "/test/foo", "bar", None),
+        kv::set(&client, "/test/bar", "foo", None),
+        kv::set(&client, "/test/_hidden", "baz", None),
+    ])
+    .and_then(move |_| {
+        kv::get(
+            &inner_client,
+            "/test",
+            GetOptions {
+                include_hidden: &["_hidden"],
+                sort: true,
+                ..Default::default()
+            },
+        )
+        .and_then(|res| {
+            let keys: Vec<_> = res
+                .data
+                .node
+                .nodes
+                .unwrap()
+                .into_iter()
+                .map(|node| node.key.unwrap())
+                .collect();
 
-    let work: Box<dyn Future<Item = (), Error = ()> + Send> =
-        Box::new(kv::set(&client, "/test/foo", "bar", Some(60)).then(|res| {
-            assert!(res.is_err());
+            assert_eq!(keys, vec!["/test/_hidden", "/test/bar", "/test/foo"]);
 
             Ok(())
-        }));
+        })
+    });
 
     client.run(work);
 }
 
 #[test]
-fn set() {
+fn get_if_newer() {
     let mut client = TestClient::new();
+    let inner_client = client.clone();
 
-    let work = kv::set(&client, "/test/foo", "baz", None).and_then(|res| {
-        assert_eq!(res.data.action, Action::Set);
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |res| {
+        let index = res.data.node.modified_index.unwrap();
+        let newer_client = inner_client.clone();
 
-        let node = res.data.node;
+        kv::get_if_newer(&inner_client, "/test/foo", index).and_then(move |result| {
+            match result {
+                GetIfNewer::NotModified(_) => {}
+                other => panic!("expected GetIfNewer::NotModified, got {:?}", other),
+            }
 
-        assert_eq!(node.value.unwrap(), "baz");
-        assert!(node.ttl.is_none());
+            kv::get_if_newer(&newer_client, "/test/foo", index - 1).and_then(|result| {
+                match result {
+                    GetIfNewer::Modified(res) => {
+                        assert_eq!(res.data.node.value, Some("bar".to_owned()))
+                    }
+                    other => panic!("expected GetIfNewer::Modified, got {:?}", other),
+                }
 
-        Ok(())
+                Ok(())
+            })
+        })
     });
 
     client.run(work);
 }
 
 #[test]
-fn set_dir() {
-    let mut client = TestClient::new();
+fn read_your_writes() {
+    let node = r#"{"action":"set","node":{"key":"/foo","value":"bar","modifiedIndex":10,"createdIndex":10}}"#;
+    let (stale_port, stale_server) = serve(
+        "127.0.0.1:0",
+        vec![
+            ("X-Etcd-Index: 10\r\n", node),
+            ("X-Etcd-Index: 5\r\n", node),
+        ],
+    );
+    let (fresh_port, fresh_server) = serve("127.0.0.1:0", vec![("X-Etcd-Index: 10\r\n", node)]);
+    let stale_endpoint = format!("http://127.0.0.1:{}", stale_port);
+    let fresh_endpoint = format!("http://127.0.0.1:{}", fresh_port);
+    let client = Client::new(&[&stale_endpoint, &fresh_endpoint], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            read_your_writes: true,
+            ..Default::default()
+        });
     let inner_client = client.clone();
 
-    let work = kv::set_dir(&client, "/test", None).and_then(move |_| {
-        kv::set_dir(&inner_client, "/test", None)
-            .then(|result| match result {
-                Ok(_) => panic!("set_dir should fail on an existing dir"),
-                Err(_) => Ok(()),
-            })
-            .and_then(move |_| {
-                kv::set(&inner_client, "/test/foo", "bar", None)
-                    .and_then(move |_| kv::set_dir(&inner_client, "/test/foo", None))
-            })
-    });
+    let work = kv::set(&client, "/foo", "bar", None)
+        .and_then(move |_| kv::get(&inner_client, "/foo", GetOptions::default()));
+    let response = Runtime::new().unwrap().block_on(work).unwrap();
 
-    client.run(work);
+    assert_eq!(response.cluster_info.etcd_index, Some(10));
+    assert!(stale_server.join().unwrap()[1].starts_with("GET /v2/keys/foo?"));
+    assert!(fresh_server.join().unwrap()[0].starts_with("GET /v2/keys/foo?"));
 }
 
-#[test]
-fn update() {
-    let mut client = TestClient::new();
-    let inner_client = client.clone();
-
-    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
-        kv::update(&inner_client, "/test/foo", "blah", Some(30)).and_then(|res| {
-            assert_eq!(res.data.action, Action::Update);
+/// A `MutationObserver` that records the writes it observes.
+#[derive(Clone, Debug, Default)]
+struct RecordingObserver {
+    mutations: Arc<Mutex<Vec<(String, Action, Option<String>, Option<String>, Option<u64>)>>>,
+}
 
-            let node = res.data.node;
+impl MutationObserver for RecordingObserver {
+    fn on_mutation(&self, mutation: &Mutation<'_>) {
+        self.mutations.lock().unwrap().push((
+            mutation.key.to_owned(),
+            mutation.action,
+            mutation.prev_value.map(str::to_owned),
+            mutation.value.map(str::to_owned),
+            mutation.etcd_index,
+        ));
+    }
+}
 
-            assert_eq!(node.value.unwrap(), "blah");
-            assert_eq!(node.ttl.unwrap(), 30);
+#[test]
+fn mutation_observer() {
+    let (port, server) = serve(
+        "127.0.0.1:0",
+        vec![
+            (
+                "X-Etcd-Index: 11\r\n",
+                r#"{"action":"set","node":{"key":"/foo","value":"baz","modifiedIndex":11,"createdIndex":11},"prevNode":{"key":"/foo","value":"bar","modifiedIndex":10,"createdIndex":10}}"#,
+            ),
+            (
+                "X-Etcd-Index: 12\r\n",
+                r#"{"action":"delete","node":{"key":"/foo","modifiedIndex":12,"createdIndex":11},"prevNode":{"key":"/foo","value":"baz","modifiedIndex":11,"createdIndex":11}}"#,
+            ),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let observer = RecordingObserver::default();
+    let client = Client::new(&[&endpoint], None)
+        .unwrap()
+        .with_mutation_observer(observer.clone())
+        .with_timeout(Duration::from_secs(5));
+    let inner_client = client.clone();
 
-            Ok(())
-        })
-    });
+    let work = kv::set(&client, "/foo", "baz", None)
+        .and_then(move |_| kv::delete(&inner_client, "/foo", false));
+    Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(
+        *observer.mutations.lock().unwrap(),
+        vec![
+            (
+                "/foo".to_owned(),
+                Action::Set,
+                Some("bar".to_owned()),
+                Some("baz".to_owned()),
+                Some(11)
+            ),
+            (
+                "/foo".to_owned(),
+                Action::Delete,
+                Some("baz".to_owned()),
+                None,
+                Some(12)
+            ),
+        ]
+    );
 
-    client.run(work);
+    server.join().unwrap();
 }
 
+#[cfg(feature = "streaming")]
 #[test]
-fn update_requires_existing_key() {
-    let mut client = TestClient::no_destructor();
+fn get_streaming() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/test","dir":true,"nodes":[
+            {"key":"/test/dir","dir":true,"nodes":[{"key":"/test/dir/baz","value":"{\"[\"}"}]},
+            {"key":"/test/foo","value":"bar"}
+        ]}}"#,
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = kv::get_streaming(
+        &client,
+        "/test",
+        GetOptions {
+            recursive: true,
+            ..Default::default()
+        },
+    )
+    .collect();
+    let nodes = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(nodes[0].key, Some("/test/dir".to_owned()));
+    assert_eq!(
+        nodes[0].flatten(),
+        vec![("/test/dir/baz".to_owned(), "{\"[\"}".to_owned())]
+    );
+    assert_eq!(nodes[1].value, Some("bar".to_owned()));
+    assert!(server.join().unwrap().starts_with("GET /v2/keys/test?"));
+}
+
+#[test]
+fn hedge_delay() {
+    // A server that accepts connections but never responds.
+    let slow_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let slow_endpoint = format!("http://{}", slow_listener.local_addr().unwrap());
+    spawn(move || {
+        let connections: Vec<_> = slow_listener.incoming().collect();
+
+        drop(connections);
+    });
+
+    let (fast_port, fast_server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+    );
+    let fast_endpoint = format!("http://127.0.0.1:{}", fast_port);
+    let client = Client::new(&[&slow_endpoint, &fast_endpoint], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            hedge_delay: Some(Duration::from_millis(50)),
+            ..Default::default()
+        });
+
+    let mut runtime = Runtime::new().unwrap();
+    let response = runtime
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+    assert!(fast_server.join().unwrap().starts_with("GET /v2/keys/foo?"));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn hedge_delay_after_permanent_error() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "404 Not Found",
+            "X-Etcd-Index: 5\r\n",
+            r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":5}"#,
+        )],
+    );
+
+    // A server that accepts connections but never responds.
+    let slow_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let slow_endpoint = format!("http://{}", slow_listener.local_addr().unwrap());
+    spawn(move || {
+        let connections: Vec<_> = slow_listener.incoming().collect();
+
+        drop(connections);
+    });
+
+    let client = Client::new(
+        &[&format!("http://127.0.0.1:{}", port), &slow_endpoint],
+        None,
+    )
+    .unwrap()
+    .with_options(RequestOptions {
+        hedge_delay: Some(Duration::from_secs(1)),
+        ..Default::default()
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    let started = Instant::now();
+    let errors = runtime
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    // The error is returned without waiting for the hedge delay.
+    assert!(started.elapsed() < Duration::from_millis(500));
+    match errors[..] {
+        [Error::Api(ref error)] => assert_eq!(error.error_code, 100),
+        ref errors => panic!("expected one Error::Api, got {:?}", errors),
+    }
+    assert_eq!(server.join().unwrap().len(), 1);
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn hedge_delay_with_total_timeout() {
+    // Servers that accept connections but never respond.
+    let endpoints: Vec<String> = (0..2)
+        .map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            spawn(move || {
+                let connections: Vec<_> = listener.incoming().collect();
+
+                drop(connections);
+            });
+
+            endpoint
+        })
+        .collect();
+    let endpoints: Vec<&str> = endpoints.iter().map(String::as_str).collect();
+    let client = Client::new(&endpoints, None)
+        .unwrap()
+        .with_options(RequestOptions {
+            hedge_delay: Some(Duration::from_millis(600)),
+            total_timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        });
+
+    let mut runtime = Runtime::new().unwrap();
+    let started = Instant::now();
+    let errors = runtime
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    // The hedge shares the total timeout with the first attempts rather than starting its own
+    // when the hedge delay has passed, which would take 1.6 seconds.
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert!(started.elapsed() < Duration::from_millis(1400));
+
+    for error in errors {
+        match error {
+            Error::Timeout => {}
+            _ => panic!("expected Error::Timeout"),
+        }
+    }
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn concurrent_attempts() {
+    // A server that accepts connections but never responds.
+    let slow_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let slow_endpoint = format!("http://{}", slow_listener.local_addr().unwrap());
+    spawn(move || {
+        let connections: Vec<_> = slow_listener.incoming().collect();
+
+        drop(connections);
+    });
+
+    // An endpoint with nothing listening, which fails straight away.
+    let closed_endpoint = format!(
+        "http://{}",
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    );
+
+    let (fast_port, fast_server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+    );
+    let fast_endpoint = format!("http://127.0.0.1:{}", fast_port);
+    let client = Client::new(&[&slow_endpoint, &closed_endpoint, &fast_endpoint], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            concurrent_attempts: 2,
+            ..Default::default()
+        });
+
+    let mut runtime = Runtime::new().unwrap();
+    let response = runtime
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+    assert!(fast_server.join().unwrap().starts_with("GET /v2/keys/foo?"));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn total_timeout() {
+    // Servers that accept connections but never respond.
+    let endpoints: Vec<String> = (0..3)
+        .map(|_| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            spawn(move || {
+                let connections: Vec<_> = listener.incoming().collect();
+
+                drop(connections);
+            });
+
+            endpoint
+        })
+        .collect();
+    let endpoints: Vec<&str> = endpoints.iter().map(String::as_str).collect();
+    let client = Client::new(&endpoints, None)
+        .unwrap()
+        .with_options(RequestOptions {
+            retries: 2,
+            timeout: Some(Duration::from_secs(10)),
+            total_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        });
+
+    let mut runtime = Runtime::new().unwrap();
+    let started = Instant::now();
+    let errors = runtime
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(!errors.is_empty());
+
+    for error in errors {
+        match error {
+            Error::Timeout => {}
+            _ => panic!("expected Error::Timeout"),
+        }
+    }
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn total_timeout_with_unlimited_retries() {
+    // A server that accepts connections but never responds.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    spawn(move || {
+        let connections: Vec<_> = listener.incoming().collect();
+
+        drop(connections);
+    });
+    let client = Client::new(&[&endpoint, &endpoint], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            retries: usize::MAX,
+            total_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        });
+
+    let mut runtime = Runtime::new().unwrap();
+    let started = Instant::now();
+    let errors = runtime
+        .block_on(kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    // Each attempt gets a share of the total timeout long enough to succeed rather than a
+    // vanishing fraction of it, and the retries stop once the total timeout has lapsed.
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(errors.len() > 1);
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn lowest_latency_endpoint_selection() {
+    let node = r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#;
+    let (first_port, first_server) = serve_once("127.0.0.1:0", node);
+    let (second_port, second_server) = serve_once("127.0.0.1:0", node);
+    let first_endpoint = format!("http://127.0.0.1:{}", first_port);
+    let second_endpoint = format!("http://127.0.0.1:{}", second_port);
+    let client = Client::new(&[&first_endpoint, &second_endpoint], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            endpoint_selection: EndpointSelection::LowestLatency,
+            ..Default::default()
+        });
+    let inner_client = client.clone();
+
+    // Members that haven't been measured are tried first, so each read goes to a different one.
+    let work = kv::get(&client, "/foo", GetOptions::default())
+        .and_then(move |_| kv::get(&inner_client, "/foo", GetOptions::default()));
+    Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert!(first_server
+        .join()
+        .unwrap()
+        .starts_with("GET /v2/keys/foo?"));
+    assert!(second_server
+        .join()
+        .unwrap()
+        .starts_with("GET /v2/keys/foo?"));
+
+    let latencies = client.endpoint_latencies();
+
+    assert_eq!(latencies.len(), 2);
+    assert!(latencies.iter().all(|&(_, latency)| latency.is_some()));
+}
+
+#[test]
+fn get_non_recursive() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = join_all(vec![
+        kv::set(&client, "/test/dir/baz", "blah", None),
+        kv::set(&client, "/test/foo", "bar", None),
+    ])
+    .and_then(move |_| {
+        kv::get(
+            &inner_client,
+            "/test",
+            GetOptions {
+                sort: true,
+                ..Default::default()
+            },
+        )
+        .and_then(|res| {
+            let node = res.data.node;
+
+            assert_eq!(node.dir.unwrap(), true);
+
+            let nodes = node.nodes.unwrap();
+
+            assert_eq!(nodes[0].clone().key.unwrap(), "/test/dir");
+            assert_eq!(nodes[0].clone().dir.unwrap(), true);
+            assert_eq!(nodes[1].clone().key.unwrap(), "/test/foo");
+            assert_eq!(nodes[1].clone().value.unwrap(), "bar");
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn get_recursive() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/test/dir/baz", "blah", None).and_then(move |_| {
+        kv::get(
+            &inner_client,
+            "/test",
+            GetOptions {
+                recursive: true,
+                sort: true,
+                ..Default::default()
+            },
+        )
+        .and_then(|res| {
+            let nodes = res.data.node.nodes.unwrap();
+
+            assert_eq!(
+                nodes[0].clone().nodes.unwrap()[0].clone().value.unwrap(),
+                "blah"
+            );
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn get_root() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", Some(60)).and_then(move |_| {
+        kv::get(&inner_client, "/", GetOptions::default()).and_then(|res| {
+            assert_eq!(res.data.action, Action::Get);
+
+            let node = res.data.node;
+
+            assert!(node.created_index.is_none());
+            assert!(node.modified_index.is_none());
+            assert_eq!(node.nodes.unwrap().len(), 1);
+            assert_eq!(node.dir.unwrap(), true);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn node_tree() {
+    let node: Node = serde_json::from_str(
+        r#"{
+            "key": "/test",
+            "dir": true,
+            "nodes": [
+                {"key": "/test/a", "value": "1"},
+                {
+                    "key": "/test/b",
+                    "dir": true,
+                    "nodes": [
+                        {"key": "/test/b/c", "value": "2"},
+                        {"key": "/test/b/d", "dir": true}
+                    ]
+                },
+                {"key": "/test/e", "value": "3"}
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let pairs: Vec<(&str, &str)> = node.iter().collect();
+
+    assert_eq!(
+        pairs,
+        vec![("/test/a", "1"), ("/test/b/c", "2"), ("/test/e", "3")]
+    );
+    assert_eq!(node.flatten()[1], ("/test/b/c".to_owned(), "2".to_owned()));
+
+    assert_eq!(node.find("/test").unwrap().key, node.key);
+    assert_eq!(node.find("/test/b/c").unwrap().value, Some("2".to_owned()));
+    assert_eq!(node.find("/test/b/d").unwrap().dir, Some(true));
+    assert!(node.find("/test/b/e").is_none());
+}
+
+#[test]
+fn get_or_create() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::get_or_create(&client, "/test/foo", "bar", None).and_then(move |result| {
+        match result {
+            GetOrCreate::Created(res) => assert_eq!(res.data.node.value.unwrap(), "bar"),
+            GetOrCreate::Existing(_) => panic!("expected GetOrCreate::Created"),
+        }
+
+        kv::get_or_create(&inner_client, "/test/foo", "baz", None).and_then(|result| {
+            match result {
+                GetOrCreate::Existing(res) => {
+                    assert_eq!(res.data.action, Action::Get);
+                    assert_eq!(res.data.node.value.unwrap(), "bar");
+                }
+                GetOrCreate::Created(_) => panic!("expected GetOrCreate::Existing"),
+            }
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn https() {
+    let mut client = TestClient::https(true);
+
+    let work = kv::set(&client, "/test/foo", "bar", Some(60));
+
+    client.run(work);
+}
+
+#[test]
+fn https_without_valid_client_certificate() {
+    let mut client = TestClient::https(false);
+
+    let work: Box<dyn Future<Item = (), Error = ()> + Send> =
+        Box::new(kv::set(&client, "/test/foo", "bar", Some(60)).then(|res| {
+            assert!(res.is_err());
+
+            Ok(())
+        }));
+
+    client.run(work);
+}
+
+#[test]
+fn copy() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = join_all(vec![
+        kv::set(&client, "/test/src/foo", "bar", None),
+        kv::set(&client, "/test/src/dir/baz", "blah", None),
+    ])
+    .and_then(move |_| {
+        let options = CopyOptions {
+            recursive: true,
+            ..Default::default()
+        };
+
+        kv::copy(&inner_client, "/test/src", "/test/dest", options).and_then(move |written| {
+            assert_eq!(written, 2);
+
+            join_all(vec![
+                kv::get(&inner_client, "/test/src/foo", GetOptions::default()),
+                kv::get(&inner_client, "/test/dest/foo", GetOptions::default()),
+                kv::get(&inner_client, "/test/dest/dir/baz", GetOptions::default()),
+            ])
+            .and_then(|responses| {
+                assert_eq!(responses[0].data.node.value, Some("bar".to_owned()));
+                assert_eq!(responses[1].data.node.value, Some("bar".to_owned()));
+                assert_eq!(responses[2].data.node.value, Some("blah".to_owned()));
+
+                Ok(())
+            })
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn rename() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = join_all(vec![
+        kv::set(&client, "/test/src/foo", "bar", None),
+        kv::set(&client, "/test/src/dir/baz", "blah", None),
+    ])
+    .and_then(move |_| {
+        kv::rename(&inner_client, "/test/src", "/test/dest").and_then(move |moved| {
+            assert_eq!(moved, 2);
+
+            kv::exists(&inner_client, "/test/src").and_then(move |exists| {
+                assert!(!exists);
+
+                kv::get(&inner_client, "/test/dest/dir/baz", GetOptions::default()).and_then(
+                    |res| {
+                        assert_eq!(res.data.node.value, Some("blah".to_owned()));
+
+                        Ok(())
+                    },
+                )
+            })
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn set_if_changed() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set_if_changed(&client, "/test/foo", "bar", None).and_then(move |written| {
+        assert!(written);
+
+        let client = inner_client.clone();
+
+        kv::set_if_changed(&inner_client, "/test/foo", "bar", None).and_then(move |written| {
+            assert!(!written);
+
+            kv::set_if_changed(&client, "/test/foo", "baz", None).and_then(|written| {
+                assert!(written);
+
+                Ok(())
+            })
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn iter_dir() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = join_all(vec![
+        kv::set(&client, "/test/a", "1", None),
+        kv::set(&client, "/test/dir/b", "2", None),
+        kv::set(&client, "/test/dir/sub/c", "3", None),
+        kv::set(&client, "/test/z", "4", None),
+    ])
+    .and_then(move |_| {
+        kv::iter_dir(&inner_client, "/test", 2)
+            .collect()
+            .and_then(|pages| {
+                assert!(pages.iter().all(|page| page.len() == 2));
+
+                let keys: Vec<String> = pages
+                    .into_iter()
+                    .flatten()
+                    .map(|node| node.key.unwrap())
+                    .collect();
+
+                assert_eq!(
+                    keys,
+                    vec![
+                        "/test/a",
+                        "/test/dir",
+                        "/test/dir/b",
+                        "/test/dir/sub",
+                        "/test/dir/sub/c",
+                        "/test/z",
+                    ]
+                );
+
+                Ok(())
+            })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn set() {
+    let mut client = TestClient::new();
+
+    let work = kv::set(&client, "/test/foo", "baz", None).and_then(|res| {
+        assert_eq!(res.data.action, Action::Set);
+
+        let node = res.data.node;
+
+        assert_eq!(node.value.unwrap(), "baz");
+        assert!(node.ttl.is_none());
+
+        Ok(())
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn set_with() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let options = SetOptions::new().ttl(100).prev_exist(false);
+
+    let work = kv::set_with(&client, "/test/foo", "bar", options).and_then(move |res| {
+        assert_eq!(res.data.action, Action::Create);
+        assert_eq!(res.data.node.ttl, Some(100));
+
+        let options = SetOptions::new().prev_exist(false);
+
+        kv::set_with(&inner_client, "/test/foo", "baz", options).then(|result| match result {
+            Ok(_) => panic!("expected EtcdError due to existing key"),
+            Err(ref errors) => match errors[0] {
+                Error::Api(ref error) => {
+                    assert_eq!(error.message, "Key already exists");
+
+                    Ok(())
+                }
+                _ => panic!("expected EtcdError due to existing key"),
+            },
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn option_builders() {
+    assert_eq!(
+        GetOptions::new().recursive().sorted().quorum(),
+        GetOptions {
+            recursive: true,
+            sort: true,
+            strong_consistency: true,
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        WatchOptions::new()
+            .recursive()
+            .index(4)
+            .timeout(Duration::from_secs(1)),
+        WatchOptions {
+            index: Some(4),
+            recursive: true,
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        SetOptions::new().ttl(60).prev_exist(true).prev_index(4),
+        SetOptions {
+            prev_exist: Some(true),
+            prev_index: Some(4),
+            ttl: Some(60),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        SetOptions::new().expire_after(Duration::from_millis(1500)),
+        SetOptions::new().ttl(2)
+    );
+    assert_eq!(
+        DeleteOptions::new().recursive().prev_value("bar"),
+        DeleteOptions {
+            prev_value: Some("bar".to_owned()),
+            recursive: true,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn set_dir() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set_dir(&client, "/test", None).and_then(move |_| {
+        kv::set_dir(&inner_client, "/test", None)
+            .then(|result| match result {
+                Ok(_) => panic!("set_dir should fail on an existing dir"),
+                Err(_) => Ok(()),
+            })
+            .and_then(move |_| {
+                kv::set(&inner_client, "/test/foo", "bar", None)
+                    .and_then(move |_| kv::set_dir(&inner_client, "/test/foo", None))
+            })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn update() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::update(&inner_client, "/test/foo", "blah", Some(30)).and_then(|res| {
+            assert_eq!(res.data.action, Action::Update);
+
+            let node = res.data.node;
+
+            assert_eq!(node.value.unwrap(), "blah");
+            assert_eq!(node.ttl.unwrap(), 30);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn update_requires_existing_key() {
+    let mut client = TestClient::no_destructor();
 
     let work = kv::update(&client, "/test/foo", "bar", None).then(|result| {
         match result {
@@ -461,25 +1656,879 @@ fn update_requires_existing_key() {
                 Error::Api(ref error) => assert_eq!(error.message, "Key not found"),
                 _ => panic!("expected EtcdError due to missing key"),
             },
-            _ => panic!("expected EtcdError due to missing key"),
+            _ => panic!("expected EtcdError due to missing key"),
+        }
+
+        let result: Result<(), ()> = Ok(());
+
+        result
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn update_dir() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create_dir(&client, "/test", None).and_then(move |_| {
+        kv::update_dir(&inner_client, "/test", Some(60)).and_then(|res| {
+            assert_eq!(res.data.node.ttl.unwrap(), 60);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn update_dir_replaces_key() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::update_dir(&inner_client, "/test/foo", Some(60)).and_then(|res| {
+            let node = res.data.node;
+
+            assert_eq!(node.value.unwrap(), "");
+            assert_eq!(node.ttl.unwrap(), 60);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn update_dir_requires_existing_dir() {
+    let mut client = TestClient::no_destructor();
+
+    let work: Box<dyn Future<Item = (), Error = ()> + Send> =
+        Box::new(kv::update_dir(&client, "/test", None).then(|res| {
+            assert!(res.is_err());
+
+            Ok(())
+        }));
+
+    client.run(work);
+}
+
+#[test]
+fn update_fn() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let increment = |value: Option<String>| {
+        let count: u64 = value.map(|value| value.parse().unwrap()).unwrap_or(0);
+
+        Some((count + 1).to_string())
+    };
+
+    let work = kv::update_fn(&client, "/test/counter", 3, increment).and_then(move |res| {
+        assert_eq!(res.unwrap().data.node.value.unwrap(), "1");
+
+        kv::update_fn(&inner_client, "/test/counter", 3, increment).and_then(|res| {
+            let res = res.unwrap();
+
+            assert_eq!(res.data.action, Action::CompareAndSwap);
+            assert_eq!(res.data.node.value.unwrap(), "2");
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn update_fn_delete() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::update_fn(&inner_client, "/test/foo", 3, |_| None).and_then(|res| {
+            assert_eq!(res.unwrap().data.action, Action::CompareAndDelete);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn delete() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::delete(&inner_client, "/test/foo", false).and_then(|res| {
+            assert_eq!(res.data.action, Action::Delete);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn create_dir() {
+    let mut client = TestClient::new();
+
+    let work = kv::create_dir(&client, "/test/dir", None).and_then(|res| {
+        assert_eq!(res.data.action, Action::Create);
+
+        let node = res.data.node;
+
+        assert!(node.dir.is_some());
+        assert!(node.value.is_none());
+
+        Ok(())
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn delete_dir() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create_dir(&client, "/test/dir", None).and_then(move |_| {
+        kv::delete_dir(&inner_client, "/test/dir").and_then(|res| {
+            assert_eq!(res.data.action, Action::Delete);
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn delete_with() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        let options = DeleteOptions::new().prev_value("baz");
+
+        kv::delete_with(&inner_client, "/test/foo", options).then(move |result| {
+            match result {
+                Ok(_) => panic!("expected a compare failure"),
+                Err(ref errors) => match errors[0] {
+                    Error::Api(ref error) => assert_eq!(error.error_code, 101),
+                    _ => panic!("expected EtcdError due to mismatched value"),
+                },
+            }
+
+            let options = DeleteOptions::new().prev_value("bar");
+
+            kv::delete_with(&inner_client, "/test/foo", options).and_then(|res| {
+                assert_eq!(res.data.action, Action::CompareAndDelete);
+
+                Ok(())
+            })
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn watch() {
+    let (tx, rx) = channel();
+
+    let child = spawn(move || {
+        let mut client = TestClient::no_destructor();
+        let inner_client = client.clone();
+
+        let work = rx.then(move |_| kv::set(&inner_client, "/test/foo", "baz", None));
+
+        client.run(work);
+    });
+
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        tx.send(()).unwrap();
+
+        kv::watch(&inner_client, "/test/foo", WatchOptions::default()).and_then(|res| {
+            assert_eq!(res.data.node.value.unwrap(), "baz");
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
+
+#[test]
+fn watch_cancel() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work: Box<dyn Future<Item = (), Error = ()> + Send> = Box::new(
+        kv::create(&client, "/test/foo", "bar", None)
+            .and_then(move |_| {
+                kv::watch(
+                    &inner_client,
+                    "/test/foo",
+                    WatchOptions {
+                        timeout: Some(Duration::from_millis(1)),
+                        ..Default::default()
+                    },
+                )
+            })
+            .then(|res| match res {
+                Ok(_) => panic!("expected Error::Timeout"),
+                Err(ref errors) => match errors[..] {
+                    [Error::Timeout] => Ok(()),
+                    _ => panic!("expected Error::Timeout, got {:?}", errors),
+                },
+            }),
+    );
+
+    client.run(work);
+}
+
+#[test]
+fn watch_index() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/test/foo", "bar", None).and_then(move |res| {
+        let index = res.data.node.modified_index;
+
+        kv::watch(
+            &inner_client,
+            "/test/foo",
+            WatchOptions {
+                index: index,
+                ..Default::default()
+            },
+        )
+        .and_then(move |res| {
+            let node = res.data.node;
+
+            assert_eq!(node.modified_index, index);
+            assert_eq!(node.value.unwrap(), "bar");
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn watch_checkpoint() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/test/foo", "1", None)
+        .and_then(move |res| {
+            let options = WatchOptions::from_cluster_info(&res.cluster_info);
+
+            let set_client = inner_client.clone();
+
+            kv::set(&inner_client, "/test/foo", "2", None)
+                .and_then(move |_| kv::set(&set_client, "/test/foo", "3", None))
+                .and_then(move |_| {
+                    kv::watch_stream(&inner_client, "/test/foo", options)
+                        .into_future()
+                        .map_err(|(errors, _)| errors)
+                        .and_then(move |(item, _)| {
+                            let item = item.unwrap();
+
+                            match item {
+                                WatchItem::Event(ref res) => {
+                                    assert_eq!(res.data.node.value, Some("2".to_owned()))
+                                }
+                                WatchItem::Idle => panic!("expected WatchItem::Event"),
+                            }
+                            assert!(item.etcd_index().is_some());
+
+                            // Checkpoints survive a round trip through storage.
+                            let saved = serde_json::to_string(&item.checkpoint().unwrap()).unwrap();
+                            let checkpoint: WatchCheckpoint = serde_json::from_str(&saved).unwrap();
+
+                            kv::watch(
+                                &inner_client,
+                                "/test/foo",
+                                WatchOptions::from_checkpoint(checkpoint),
+                            )
+                        })
+                })
+        })
+        .and_then(|res| {
+            assert_eq!(res.data.node.value, Some("3".to_owned()));
+
+            Ok(())
+        });
+
+    client.run(work);
+}
+
+#[test]
+fn watch_recursive() {
+    let (tx, rx) = channel();
+
+    let child = spawn(move || {
+        let mut client = TestClient::no_destructor();
+        let inner_client = client.clone();
+
+        let work = rx.then(move |_| {
+            let duration = Duration::from_millis(100);
+            sleep(duration);
+            kv::set(&inner_client, "/test/foo/bar", "baz", None)
+        });
+
+        client.run(work);
+    });
+
+    let mut client = TestClient::new();
+
+    tx.send(()).unwrap();
+
+    let work = kv::watch(
+        &client,
+        "/test",
+        WatchOptions {
+            recursive: true,
+            timeout: Some(Duration::from_millis(1000)),
+            ..Default::default()
+        },
+    )
+    .and_then(|res| {
+        let node = res.data.node;
+
+        assert_eq!(node.key.unwrap(), "/test/foo/bar");
+        assert_eq!(node.value.unwrap(), "baz");
+
+        Ok(())
+    });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
+
+#[test]
+fn watch_stream() {
+    let (tx, rx) = channel();
+
+    let child = spawn(move || {
+        let mut client = TestClient::no_destructor();
+        let inner_client = client.clone();
+        let inner_client_2 = client.clone();
+
+        let work = rx.then(move |_| {
+            let duration = Duration::from_millis(100);
+            sleep(duration);
+            kv::set(&inner_client, "/test/foo", "baz", None)
+                .and_then(move |_| kv::set(&inner_client_2, "/test/foo", "qux", None))
+        });
+
+        client.run(work);
+    });
+
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |res| {
+        tx.send(()).unwrap();
+
+        let options = WatchOptions {
+            index: res.data.node.modified_index.map(|index| index + 1),
+            ..Default::default()
+        };
+
+        kv::watch_stream(&inner_client, "/test/foo", options)
+            .take(2)
+            .collect()
+            .and_then(|items| {
+                let values: Vec<String> = items
+                    .into_iter()
+                    .map(|item| match item {
+                        WatchItem::Event(res) => res.data.node.value.unwrap(),
+                        WatchItem::Idle => panic!("expected WatchItem::Event"),
+                    })
+                    .collect();
+
+                assert_eq!(values, vec!["baz".to_owned(), "qux".to_owned()]);
+
+                Ok(())
+            })
+    });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
+
+#[test]
+fn watch_stream_actions() {
+    let (tx, rx) = channel();
+
+    let child = spawn(move || {
+        let mut client = TestClient::no_destructor();
+        let inner_client = client.clone();
+        let inner_client_2 = client.clone();
+
+        let work = rx.then(move |_| {
+            let duration = Duration::from_millis(100);
+            sleep(duration);
+            kv::set(&inner_client, "/test/foo", "baz", None)
+                .and_then(move |_| kv::delete(&inner_client_2, "/test/foo", false))
+        });
+
+        client.run(work);
+    });
+
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |res| {
+        tx.send(()).unwrap();
+
+        let options = WatchOptions {
+            actions: Some(&[Action::Delete, Action::Expire]),
+            index: res.data.node.modified_index.map(|index| index + 1),
+            ..Default::default()
+        };
+
+        kv::watch_stream(&inner_client, "/test/foo", options)
+            .take(1)
+            .collect()
+            .and_then(|items| {
+                match items[0] {
+                    WatchItem::Event(ref res) => assert_eq!(res.data.action, Action::Delete),
+                    WatchItem::Idle => panic!("expected WatchItem::Event"),
+                }
+
+                Ok(())
+            })
+    });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
+
+#[test]
+fn watch_stream_coalesce_window() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+    let inner_client_2 = client.clone();
+    let inner_client_3 = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None)
+        .and_then(move |res| {
+            let index = res.data.node.modified_index.map(|index| index + 1);
+
+            kv::set(&inner_client, "/test/foo", "baz", None)
+                .and_then(move |_| kv::set(&inner_client_2, "/test/foo", "qux", None))
+                .map(move |_| index)
+        })
+        .and_then(move |index| {
+            let options = WatchOptions {
+                coalesce_window: Some(Duration::from_millis(500)),
+                index,
+                ..Default::default()
+            };
+
+            kv::watch_stream(&inner_client_3, "/test/foo", options)
+                .take(1)
+                .collect()
+                .and_then(|items| {
+                    match items[0] {
+                        WatchItem::Event(ref res) => {
+                            assert_eq!(res.data.node.value, Some("qux".to_owned()))
+                        }
+                        WatchItem::Idle => panic!("expected WatchItem::Event"),
+                    }
+
+                    Ok(())
+                })
+        });
+
+    client.run(work);
+}
+
+#[test]
+fn watch_stream_idle() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::watch_stream(
+            &inner_client,
+            "/test/foo",
+            WatchOptions {
+                timeout: Some(Duration::from_millis(1)),
+                ..Default::default()
+            },
+        )
+        .take(2)
+        .collect()
+        .and_then(|items| {
+            for item in items {
+                match item {
+                    WatchItem::Idle => {}
+                    WatchItem::Event(_) => panic!("expected WatchItem::Idle"),
+                }
+            }
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn watch_stream_starts_after_current_index() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "404 Not Found",
+                "X-Etcd-Index: 7\r\n",
+                r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":7}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 7\r\n",
+                r#"{"action":"set","node":{"key":"/foo","value":"bar","modifiedIndex":9,"createdIndex":9}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let options = WatchOptions {
+        timeout: Some(Duration::from_secs(5)),
+        ..Default::default()
+    };
+
+    let items = Runtime::new()
+        .unwrap()
+        .block_on(kv::watch_stream(&client, "/foo", options).take(1).collect())
+        .unwrap();
+
+    match items[..] {
+        [WatchItem::Event(ref response)] => assert_eq!(response.data.node.modified_index, Some(9)),
+        ref items => panic!("expected one WatchItem::Event, got {:?}", items),
+    }
+
+    let requests = server.join().unwrap();
+
+    // Changes made while a timed out watch is reissued are caught by starting after index 7.
+    assert!(!requests[0].contains("wait=true"));
+    assert!(requests[1].contains("waitIndex=8"));
+}
+
+#[test]
+fn watch_many() {
+    let (tx, rx) = channel();
+
+    let child = spawn(move || {
+        let mut client = TestClient::no_destructor();
+        let inner_client = client.clone();
+        let inner_client_2 = client.clone();
+
+        let work = rx.then(move |_| {
+            let duration = Duration::from_millis(100);
+            sleep(duration);
+            kv::set(&inner_client, "/test/foo", "baz", None)
+                .and_then(move |_| kv::set(&inner_client_2, "/test/bar", "qux", None))
+        });
+
+        client.run(work);
+    });
+
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |res| {
+        tx.send(()).unwrap();
+
+        let options = WatchOptions {
+            index: res.data.node.modified_index.map(|index| index + 1),
+            ..Default::default()
+        };
+
+        kv::watch_many(&inner_client, &["/test/foo", "/test/bar"], options)
+            .take(2)
+            .collect()
+            .and_then(|items| {
+                let mut changes: Vec<(String, String)> = items
+                    .into_iter()
+                    .map(|(key, res)| (key, res.data.node.value.unwrap()))
+                    .collect();
+
+                changes.sort();
+
+                assert_eq!(
+                    changes,
+                    vec![
+                        ("/test/bar".to_owned(), "qux".to_owned()),
+                        ("/test/foo".to_owned(), "baz".to_owned()),
+                    ]
+                );
+
+                Ok(())
+            })
+    });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
+
+#[test]
+fn with_timeout() {
+    let mut client = TestClient::new();
+    let scoped = client.with_timeout(Duration::from_nanos(1));
+
+    let work = kv::get(&scoped, "/test/foo", GetOptions::default()).then(|result| {
+        match result {
+            Ok(_) => panic!("expected the request to time out"),
+            Err(errors) => {
+                assert!(!errors.is_empty());
+
+                for error in errors {
+                    match error {
+                        Error::Timeout => {}
+                        _ => panic!("expected Error::Timeout"),
+                    }
+                }
+            }
+        }
+
+        Ok::<(), ()>(())
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn watch_timeout() {
+    let mut client = TestClient::new();
+    let scoped = client.with_options(RequestOptions {
+        watch_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    });
+
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::watch(&scoped, "/test/foo", WatchOptions::default()).then(|result| {
+            for error in result.unwrap_err() {
+                match error {
+                    Error::Timeout => {}
+                    _ => panic!("expected Error::Timeout"),
+                }
+            }
+
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn watch_index_cleared() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "400 Bad Request",
+            "X-Etcd-Index: 2000\r\n",
+            r#"{"errorCode":401,"message":"The event in requested index is outdated and cleared","cause":"the requested history has been cleared [1008/4]","index":2007}"#,
+        )],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let options = WatchOptions {
+        index: Some(4),
+        ..Default::default()
+    };
+
+    let errors = Runtime::new()
+        .unwrap()
+        .block_on(kv::watch(&client, "/foo", options))
+        .unwrap_err();
+
+    match errors[..] {
+        [Error::IndexCleared {
+            current_index: 2000,
+        }] => {}
+        ref errors => panic!("expected Error::IndexCleared, got {:?}", errors),
+    }
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("GET /v2/keys/foo?"));
+    assert!(requests[0].contains("waitIndex=4"));
+}
+
+#[test]
+fn cancellation_token_before_request() {
+    let mut client = TestClient::new();
+    let token = CancellationToken::new();
+    let cancellable = client.with_cancellation(token.clone());
+
+    token.cancel();
+
+    let work = kv::get(&cancellable, "/test/foo", GetOptions::default()).then(|result| {
+        match result.unwrap_err()[..] {
+            [Error::Cancelled] => {}
+            ref errors => panic!("expected Error::Cancelled, got {:?}", errors),
         }
 
-        let result: Result<(), ()> = Ok(());
+        Ok::<(), ()>(())
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn cancellation_token_aborts_watch() {
+    let mut client = TestClient::new();
+    let token = CancellationToken::new();
+    let cancellable = client.with_cancellation(token.clone());
+
+    let child = spawn(move || {
+        sleep(Duration::from_millis(100));
+
+        token.cancel();
+    });
+
+    let work = kv::create(&client, "/test/foo", "bar", None)
+        .and_then(move |_| kv::watch(&cancellable, "/test/foo", WatchOptions::default()))
+        .then(|result| {
+            match result {
+                Err(ref errors) => match errors[..] {
+                    [Error::Cancelled] => {}
+                    _ => panic!("expected Error::Cancelled, got {:?}", errors),
+                },
+                other => panic!("expected Error::Cancelled, got {:?}", other),
+            }
+
+            Ok::<(), ()>(())
+        });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
 
-        result
+#[test]
+fn shutdown() {
+    let mut client = TestClient::new();
+    let derived = client.with_timeout(Duration::from_secs(5));
+
+    let work = client.shutdown().then(move |_| {
+        assert!(derived.is_shut_down());
+
+        kv::get(&derived, "/test/foo", GetOptions::default()).then(|result| {
+            match result.unwrap_err()[..] {
+                [Error::Cancelled] => {}
+                ref errors => panic!("expected Error::Cancelled, got {:?}", errors),
+            }
+
+            Ok::<(), ()>(())
+        })
     });
 
     client.run(work);
 }
 
 #[test]
-fn update_dir() {
+fn export_and_import() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work = kv::create_dir(&client, "/test", None).and_then(move |_| {
-        kv::update_dir(&inner_client, "/test", Some(60)).and_then(|res| {
-            assert_eq!(res.data.node.ttl.unwrap(), 60);
+    let work = kv::set(&client, "/test/source/foo", "bar", None)
+        .and_then(move |_| {
+            kv::set(&inner_client, "/test/source/dir/baz", "qux", Some(60)).and_then(move |_| {
+                kv::export(&inner_client, "/test/source/").and_then(move |snapshot| {
+                    assert_eq!(snapshot.prefix, "/test/source");
+                    assert!(snapshot.index.is_some());
+                    assert_eq!(snapshot.entries.len(), 3);
+                    assert_eq!(snapshot.entries[0].key, "/test/source/dir");
+                    assert!(snapshot.entries[0].dir);
+                    assert_eq!(snapshot.entries[1].key, "/test/source/dir/baz");
+                    assert_eq!(snapshot.entries[1].value, Some("qux".to_owned()));
+                    assert!(snapshot.entries[1].ttl.is_some());
+                    assert_eq!(snapshot.entries[2].key, "/test/source/foo");
+                    assert_eq!(snapshot.entries[2].ttl, None);
+
+                    let options = ImportOptions {
+                        prefix: Some("/test/target".to_owned()),
+                        ..Default::default()
+                    };
+
+                    kv::import(&inner_client, snapshot, options).and_then(move |written| {
+                        assert_eq!(written, 2);
+
+                        kv::get(&inner_client, "/test/target/dir/baz", GetOptions::default())
+                    })
+                })
+            })
+        })
+        .and_then(|res| {
+            assert_eq!(res.data.node.value.unwrap(), "qux");
+            assert!(res.data.node.ttl.is_some());
+
+            Ok(())
+        });
+
+    client.run(work);
+}
+
+#[test]
+fn import_keeps_keys_outside_prefix() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let snapshot = KeyspaceSnapshot {
+        entries: vec![
+            SnapshotEntry {
+                dir: false,
+                key: "/test/app/foo".to_owned(),
+                ttl: None,
+                value: Some("bar".to_owned()),
+            },
+            SnapshotEntry {
+                dir: false,
+                key: "/test/application".to_owned(),
+                ttl: None,
+                value: Some("baz".to_owned()),
+            },
+        ],
+        index: None,
+        prefix: "/test/app".to_owned(),
+    };
+    let options = ImportOptions {
+        prefix: Some("/test/target".to_owned()),
+        ..Default::default()
+    };
+
+    let work = kv::import(&client, snapshot, options).and_then(move |written| {
+        assert_eq!(written, 2);
+
+        join_all(vec![
+            kv::get(&inner_client, "/test/target/foo", GetOptions::default()),
+            kv::get(&inner_client, "/test/application", GetOptions::default()),
+        ])
+        .and_then(|responses| {
+            assert_eq!(responses[0].data.node.value, Some("bar".to_owned()));
+            assert_eq!(responses[1].data.node.value, Some("baz".to_owned()));
 
             Ok(())
         })
@@ -489,16 +2538,24 @@ fn update_dir() {
 }
 
 #[test]
-fn update_dir_replaces_key() {
+fn multi_get() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work = kv::set(&client, "/test/foo", "bar", None).and_then(move |_| {
-        kv::update_dir(&inner_client, "/test/foo", Some(60)).and_then(|res| {
-            let node = res.data.node;
-
-            assert_eq!(node.value.unwrap(), "");
-            assert_eq!(node.ttl.unwrap(), 60);
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        kv::multi_get(
+            &inner_client,
+            &["/test/foo", "/test/missing", "/test/foo"],
+            GetOptions::default(),
+        )
+        .and_then(|results| {
+            assert_eq!(results.len(), 3);
+            assert_eq!(
+                results[0].as_ref().unwrap().data.node.value,
+                Some("bar".to_owned())
+            );
+            assert!(results[1].is_err());
+            assert!(results[2].is_ok());
 
             Ok(())
         })
@@ -508,27 +2565,101 @@ fn update_dir_replaces_key() {
 }
 
 #[test]
-fn update_dir_requires_existing_dir() {
-    let mut client = TestClient::no_destructor();
+fn multi_set() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
 
-    let work: Box<dyn Future<Item = (), Error = ()> + Send> =
-        Box::new(kv::update_dir(&client, "/test", None).then(|res| {
-            assert!(res.is_err());
+    let work = kv::multi_set(
+        &client,
+        &[("/test/foo", "bar"), ("/test/baz", "qux")],
+        SetBatchOptions::default(),
+    )
+    .map_err(|failure| failure.rollback_errors)
+    .and_then(move |responses| {
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].data.node.key, Some("/test/foo".to_owned()));
+        assert_eq!(responses[1].data.node.key, Some("/test/baz".to_owned()));
 
-            Ok(())
-        }));
+        kv::get(&inner_client, "/test/baz", GetOptions::default())
+            .map_err(|errors| vec![("/test/baz".to_owned(), errors)])
+            .and_then(|res| {
+                assert_eq!(res.data.node.value.unwrap(), "qux");
+
+                Ok(())
+            })
+    });
 
     client.run(work);
 }
 
 #[test]
-fn delete() {
+fn multi_set_rollback() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
+    let inner_client_2 = client.clone();
 
-    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
-        kv::delete(&inner_client, "/test/foo", false).and_then(|res| {
-            assert_eq!(res.data.action, Action::Delete);
+    let work = kv::create(&client, "/test/foo", "bar", None)
+        .join(kv::create_dir(&client, "/test/dir", None))
+        .and_then(move |_| {
+            let options = SetBatchOptions {
+                rollback: true,
+                ..Default::default()
+            };
+
+            kv::multi_set(
+                &inner_client,
+                &[
+                    ("/test/foo", "new"),
+                    ("/test/baz", "qux"),
+                    ("/test/dir", "x"),
+                ],
+                options,
+            )
+            .then(|result| {
+                let failure = match result {
+                    Ok(_) => panic!("expected the batch to fail"),
+                    Err(failure) => failure,
+                };
+
+                assert!(failure.results[0].is_ok());
+                assert!(failure.results[1].is_ok());
+                assert!(failure.results[2].is_err());
+                assert!(failure.rollback_errors.is_empty());
+
+                Ok(())
+            })
+        })
+        .and_then(move |_| {
+            kv::get(&inner_client_2, "/test/foo", GetOptions::default()).and_then(move |res| {
+                assert_eq!(res.data.node.value.unwrap(), "bar");
+
+                kv::exists(&inner_client_2, "/test/baz").map(|exists| assert!(!exists))
+            })
+        });
+
+    client.run(work);
+}
+
+#[test]
+fn raw_set_refresh_with_conditions() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::create(&client, "/test/foo", "bar", Some(60)).and_then(move |res| {
+        let options = raw::SetOptions {
+            conditions: Some(raw::ComparisonConditions {
+                modified_index: res.data.node.modified_index,
+                value: None,
+            }),
+            prev_exist: Some(true),
+            refresh: true,
+            ttl: Some(120),
+            ..Default::default()
+        };
+
+        raw::set(&inner_client, "/test/foo", options).and_then(|res| {
+            assert_eq!(res.data.node.value.unwrap(), "bar");
+            assert!(res.data.node.ttl.unwrap() > 60);
 
             Ok(())
         })
@@ -538,16 +2669,18 @@ fn delete() {
 }
 
 #[test]
-fn create_dir() {
+fn raw_set_no_value_on_success() {
     let mut client = TestClient::new();
 
-    let work = kv::create_dir(&client, "/test/dir", None).and_then(|res| {
-        assert_eq!(res.data.action, Action::Create);
-
-        let node = res.data.node;
+    let options = raw::SetOptions {
+        no_value_on_success: true,
+        value: Some("bar"),
+        ..Default::default()
+    };
 
-        assert!(node.dir.is_some());
-        assert!(node.value.is_none());
+    let work = raw::set(&client, "/test/foo", options).and_then(|res| {
+        assert_eq!(res.data.action, Action::Set);
+        assert_eq!(res.data.node, Node::default());
 
         Ok(())
     });
@@ -556,13 +2689,14 @@ fn create_dir() {
 }
 
 #[test]
-fn delete_dir() {
+fn refresh() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work = kv::create_dir(&client, "/test/dir", None).and_then(move |_| {
-        kv::delete_dir(&inner_client, "/test/dir").and_then(|res| {
-            assert_eq!(res.data.action, Action::Delete);
+    let work = kv::create(&client, "/test/foo", "bar", Some(10)).and_then(move |_| {
+        kv::refresh(&inner_client, "/test/foo", 60).and_then(|res| {
+            assert_eq!(res.data.node.value.unwrap(), "bar");
+            assert!(res.data.node.ttl.unwrap() > 10);
 
             Ok(())
         })
@@ -572,137 +2706,365 @@ fn delete_dir() {
 }
 
 #[test]
-fn watch() {
-    let (tx, rx) = channel();
+fn refresh_for() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
 
-    let child = spawn(move || {
-        let mut client = TestClient::no_destructor();
-        let inner_client = client.clone();
+    let work = kv::create(&client, "/test/foo", "bar", Some(10)).and_then(move |_| {
+        kv::refresh_for(&inner_client, "/test/foo", Duration::from_millis(59_500)).and_then(|res| {
+            let ttl = res.data.node.time_to_live().unwrap();
 
-        let work = rx.then(move |_| kv::set(&inner_client, "/test/foo", "baz", None));
+            assert!(ttl > Duration::from_secs(10));
+            assert!(ttl <= Duration::from_secs(60));
 
-        client.run(work);
+            Ok(())
+        })
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn node_time_to_live() {
+    let node = Node {
+        expiration: Some("2013-12-04T12:01:21.874888581-08:00".to_owned()),
+        ttl: Some(30),
+        ..Default::default()
+    };
+
+    assert_eq!(node.time_to_live(), Some(Duration::from_secs(30)));
+    assert_eq!(Node::default().time_to_live(), None);
+
+    #[cfg(feature = "chrono")]
+    {
+        use chrono::{TimeZone, Timelike, Utc};
+
+        let expires_at = node.expires_at().unwrap();
+
+        assert_eq!(
+            expires_at.with_nanosecond(0).unwrap(),
+            Utc.with_ymd_and_hms(2013, 12, 4, 20, 1, 21).unwrap()
+        );
+        assert_eq!(expires_at.nanosecond(), 874_888_581);
+    }
+}
+
+#[test]
+fn refresh_requires_existing_key() {
+    let mut client = TestClient::new();
+
+    let work = kv::refresh(&client, "/test/foo", 60).then(|result| {
+        assert!(result.is_err());
+
+        Ok::<(), ()>(())
     });
 
+    client.run(work);
+}
+
+#[test]
+fn session() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work = kv::create(&client, "/test/foo", "bar", None)
-        .map_err(|errors| WatchError::Other(errors))
-        .and_then(move |_| {
-            tx.send(()).unwrap();
+    let work = Session::new(&client, "/test/foo", "bar", 1).and_then(move |session| {
+        assert!(session.is_alive());
+        assert_eq!(session.key(), "/test/foo");
+
+        Delay::new(Instant::now() + Duration::from_secs(2))
+            .map_err(|_| vec![])
+            .and_then(move |_| {
+                assert!(session.is_alive());
 
-            kv::watch(&inner_client, "/test/foo", WatchOptions::default()).and_then(|res| {
-                assert_eq!(res.data.node.value.unwrap(), "baz");
+                kv::exists(&inner_client, "/test/foo").and_then(move |exists| {
+                    assert!(exists);
 
-                Ok(())
+                    session.invalidate().and_then(move |_| {
+                        kv::exists(&inner_client, "/test/foo").map(|exists| assert!(!exists))
+                    })
+                })
             })
-        });
+    });
 
     client.run(work);
+}
 
-    child.join().ok().unwrap();
+#[test]
+fn session_lost() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "201 Created",
+                "X-Etcd-Index: 5\r\n",
+                r#"{"action":"set","node":{"key":"/foo","value":"bar","ttl":1,"modifiedIndex":5,"createdIndex":5}}"#,
+            ),
+            (
+                "404 Not Found",
+                "X-Etcd-Index: 6\r\n",
+                r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":6}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let work = Session::new(&client, "/foo", "bar", 1).and_then(|session| {
+        session.lost().then(move |result| {
+            match result {
+                Err(Error::LeaseLost) => {}
+                ref result => panic!("expected Error::LeaseLost, got {:?}", result),
+            }
+
+            assert!(!session.is_alive());
+
+            Ok(())
+        })
+    });
+
+    Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(server.join().unwrap().len(), 2);
 }
 
 #[test]
-fn watch_cancel() {
+fn cache() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
 
-    let work: Box<dyn Future<Item = (), Error = ()> + Send> = Box::new(
-        kv::create(&client, "/test/foo", "bar", None)
-            .map_err(|errors| WatchError::Other(errors))
-            .and_then(move |_| {
-                kv::watch(
-                    &inner_client,
-                    "/test/foo",
-                    WatchOptions {
-                        timeout: Some(Duration::from_millis(1)),
-                        ..Default::default()
-                    },
-                )
+    let work = kv::create(&client, "/test/foo", "bar", None).and_then(move |_| {
+        Cache::new(&inner_client, "/test").and_then(move |cache| {
+            assert_eq!(cache.get("/test/foo"), Some("bar".to_owned()));
+            assert_eq!(cache.len(), 1);
+            assert!(!cache.is_stale());
+
+            let index = cache.index();
+
+            kv::set(&inner_client, "/test/foo", "baz", None).and_then(move |_| {
+                Delay::new(Instant::now() + Duration::from_millis(200))
+                    .map_err(|_| vec![])
+                    .and_then(move |_| {
+                        assert_eq!(cache.get("/test/foo"), Some("baz".to_owned()));
+                        assert!(cache.index() > index);
+
+                        Ok(())
+                    })
             })
-            .then(|res| match res {
-                Ok(_) => panic!("expected WatchError::Timeout"),
-                Err(WatchError::Timeout) => Ok(()),
-                Err(_) => panic!("expected WatchError::Timeout"),
-            }),
-    );
+        })
+    });
 
     client.run(work);
 }
 
 #[test]
-fn watch_index() {
+fn set_bytes_and_get_bytes() {
     let mut client = TestClient::new();
     let inner_client = client.clone();
+    let bytes = vec![0, 159, 146, 150, 255];
 
-    let work = kv::set(&client, "/test/foo", "bar", None)
-        .map_err(|errors| WatchError::Other(errors))
-        .and_then(move |res| {
-            let index = res.data.node.modified_index;
-
-            kv::watch(
-                &inner_client,
-                "/test/foo",
-                WatchOptions {
-                    index: index,
-                    ..Default::default()
-                },
-            )
-            .and_then(move |res| {
-                let node = res.data.node;
+    let work = kv::set_bytes(&client, "/test/foo", &bytes, None).and_then(move |res| {
+        assert_eq!(res.data.node.value.unwrap(), "AJ+Slv8=");
 
-                assert_eq!(node.modified_index, index);
-                assert_eq!(node.value.unwrap(), "bar");
+        kv::get_bytes(&inner_client, "/test/foo", GetOptions::default()).and_then(move |res| {
+            assert_eq!(res.data, bytes);
 
-                Ok(())
-            })
-        });
+            Ok(())
+        })
+    });
 
     client.run(work);
 }
 
 #[test]
-fn watch_recursive() {
-    let (tx, rx) = channel();
-
-    let child = spawn(move || {
-        let mut client = TestClient::no_destructor();
-        let inner_client = client.clone();
+fn set_bytes_with_utf8_codec() {
+    let mut client = TestClient::new();
+    let utf8_client = client.with_codec(Utf8);
 
-        let work = rx.then(move |_| {
-            let duration = Duration::from_millis(100);
-            sleep(duration);
-            kv::set(&inner_client, "/test/foo/bar", "baz", None)
-        });
+    let work = kv::set_bytes(&utf8_client, "/test/foo", &[0, 159, 146, 150], None).then(|result| {
+        match result {
+            Ok(_) => panic!("expected invalid UTF-8 to be rejected"),
+            Err(errors) => match errors[0] {
+                Error::Codec(_) => {}
+                _ => panic!("expected Error::Codec"),
+            },
+        }
 
-        client.run(work);
+        Ok::<(), ()>(())
     });
 
+    client.run(work);
+}
+
+#[test]
+fn large_set_and_get() {
     let mut client = TestClient::new();
+    let inner_client = client.clone();
+    let value: String = "héllo wörld ".repeat(100_000);
+    let expected = value.clone();
 
-    tx.send(()).unwrap();
+    let work = kv::large::set(&client, "/test/foo", &value, None).and_then(move |_| {
+        kv::large::get(&inner_client, "/test/foo").and_then(move |res| {
+            assert_eq!(res.data, expected);
 
-    let work = kv::watch(
-        &client,
-        "/test",
-        WatchOptions {
-            recursive: true,
-            timeout: Some(Duration::from_millis(1000)),
-            ..Default::default()
-        },
-    )
-    .and_then(|res| {
-        let node = res.data.node;
+            kv::get(&inner_client, "/test/foo/.chunks/2", GetOptions::default()).map(|_| ())
+        })
+    });
 
-        assert_eq!(node.key.unwrap(), "/test/foo/bar");
-        assert_eq!(node.value.unwrap(), "baz");
+    client.run(work);
+}
 
-        Ok(())
+#[test]
+fn large_get_with_missing_chunk() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+    let value: String = "x".repeat(600 * 1024);
+
+    let work = kv::large::set(&client, "/test/foo", &value, None).and_then(move |_| {
+        kv::delete(&inner_client, "/test/foo/.chunks/1", false).and_then(move |_| {
+            kv::large::get(&inner_client, "/test/foo").then(|result| {
+                match result {
+                    Ok(_) => panic!("expected a missing chunk to be detected"),
+                    Err(errors) => match errors[0] {
+                        Error::Codec(_) => {}
+                        _ => panic!("expected Error::Codec"),
+                    },
+                }
+
+                Ok(())
+            })
+        })
     });
 
     client.run(work);
+}
 
-    child.join().ok().unwrap();
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct TreeConfig {
+    name: String,
+    port: u16,
+    hosts: Vec<String>,
+    tls: Option<TreeTls>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct TreeTls {
+    cert: String,
+}
+
+#[test]
+fn tree_diff() {
+    let old = TreeConfig {
+        name: "api".to_owned(),
+        port: 8080,
+        hosts: vec!["a".to_owned(), "b".to_owned()],
+        tls: Some(TreeTls {
+            cert: "/etc/api.pem".to_owned(),
+        }),
+    };
+    let new = TreeConfig {
+        name: "api".to_owned(),
+        port: 8081,
+        hosts: vec!["a".to_owned(), "b".to_owned()],
+        tls: None,
+    };
+
+    assert_eq!(
+        tree::diff("/config/", &old, &new).unwrap(),
+        vec![
+            Change::Set {
+                key: "/config/port".to_owned(),
+                value: "8081".to_owned(),
+            },
+            Change::Delete {
+                key: "/config/tls".to_owned(),
+            },
+        ]
+    );
+    assert!(tree::diff("/config", &new, &new).unwrap().is_empty());
+    assert!(tree::diff("/config", &"api", &"api").is_err());
+}
+
+#[test]
+fn tree_load() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/config","dir":true,"nodes":[{"key":"/config/name","value":"8080","modifiedIndex":2,"createdIndex":2},{"key":"/config/port","value":"8080","modifiedIndex":3,"createdIndex":3},{"key":"/config/hosts","value":"[\"a\",\"b\"]","modifiedIndex":4,"createdIndex":4},{"key":"/config/tls","dir":true,"nodes":[{"key":"/config/tls/cert","value":"/etc/api.pem","modifiedIndex":5,"createdIndex":5}],"modifiedIndex":5,"createdIndex":5},{"key":"/config/unused","value":"x","modifiedIndex":6,"createdIndex":6}],"modifiedIndex":2,"createdIndex":2}}"#,
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let config: TreeConfig = Runtime::new()
+        .unwrap()
+        .block_on(tree::load(&client, "/config"))
+        .unwrap()
+        .data;
+
+    assert_eq!(
+        config,
+        TreeConfig {
+            name: "8080".to_owned(),
+            port: 8080,
+            hosts: vec!["a".to_owned(), "b".to_owned()],
+            tls: Some(TreeTls {
+                cert: "/etc/api.pem".to_owned(),
+            }),
+        }
+    );
+    let request = server.join().unwrap();
+
+    assert!(request.starts_with("GET /v2/keys/config?"));
+    assert!(request.contains("recursive=true"));
+}
+
+#[test]
+fn tree_save() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "X-Etcd-Index: 6\r\n",
+                r#"{"action":"get","node":{"key":"/config","dir":true,"nodes":[{"key":"/config/name","value":"api","modifiedIndex":2,"createdIndex":2},{"key":"/config/port","value":"8080","modifiedIndex":3,"createdIndex":3},{"key":"/config/tls","dir":true,"nodes":[{"key":"/config/tls/cert","value":"/etc/api.pem","modifiedIndex":5,"createdIndex":5}],"modifiedIndex":5,"createdIndex":5}],"modifiedIndex":2,"createdIndex":2}}"#,
+            ),
+            (
+                "201 Created",
+                "X-Etcd-Index: 7\r\n",
+                r#"{"action":"set","node":{"key":"/config/hosts","value":"[]","modifiedIndex":7,"createdIndex":7}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 8\r\n",
+                r#"{"action":"set","node":{"key":"/config/port","value":"8081","modifiedIndex":8,"createdIndex":3}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 9\r\n",
+                r#"{"action":"delete","node":{"key":"/config/tls","dir":true,"modifiedIndex":9,"createdIndex":5}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let config = TreeConfig {
+        name: "api".to_owned(),
+        port: 8081,
+        hosts: vec![],
+        tls: None,
+    };
+
+    let changes = Runtime::new()
+        .unwrap()
+        .block_on(tree::save(&client, "/config", &config))
+        .unwrap();
+
+    assert_eq!(
+        changes.iter().map(Change::key).collect::<Vec<_>>(),
+        vec!["/config/hosts", "/config/port", "/config/tls"]
+    );
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[1].starts_with("PUT /v2/keys/config/hosts"));
+    assert!(requests[1].ends_with("value=%5B%5D"));
+    assert!(requests[2].starts_with("PUT /v2/keys/config/port"));
+    assert!(requests[2].ends_with("value=8081"));
+    assert!(requests[3].starts_with("DELETE /v2/keys/config/tls?"));
+    assert!(requests[3].contains("recursive=true"));
 }
@@ -1,7 +1,11 @@
-use etcd::members;
+use etcd::members::{self, MemberId};
+use etcd::{Client, Error};
+use std::time::Duration;
+
 use futures::future::Future;
+use tokio::runtime::Runtime;
 
-use crate::test::TestClient;
+use crate::test::{serve_once, serve_statuses, TestClient};
 
 mod test;
 
@@ -20,3 +24,168 @@ fn list() {
 
     client.run(work);
 }
+
+#[test]
+fn update() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            ("204 No Content", "", ""),
+            (
+                "200 OK",
+                "",
+                r#"{"members":[{"id":"a","name":"first","peerURLs":["http://10.0.0.1:2380"],"clientURLs":[]},{"id":"b","name":"second","peerURLs":["http://10.0.0.3:2380"],"clientURLs":["http://10.0.0.2:2379"]}]}"#,
+            ),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let response = runtime
+        .block_on(members::update(
+            &client,
+            MemberId(0xb),
+            vec!["http://10.0.0.3:2380".to_owned()],
+        ))
+        .unwrap();
+
+    assert_eq!(response.data.id, MemberId(0xb));
+    assert_eq!(response.data.name, "second");
+    assert_eq!(response.data.peer_urls, vec!["http://10.0.0.3:2380"]);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("PUT /v2/members/b "));
+    assert!(requests[1].starts_with("GET /v2/members "));
+}
+
+#[test]
+fn update_conflict() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "409 Conflict",
+            "",
+            r#"{"message":"etcdserver: peerURL exists"}"#,
+        )],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let errors = runtime
+        .block_on(members::update(
+            &client,
+            MemberId(0xb),
+            vec!["http://10.0.0.1:2380".to_owned()],
+        ))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::MemberConflict(ref message) => assert_eq!(message, "etcdserver: peerURL exists"),
+        ref error => panic!("expected Error::MemberConflict, got {:?}", error),
+    }
+
+    server.join().unwrap();
+}
+
+#[test]
+fn member_id() {
+    let id: MemberId = "8e9e05c52164694d".parse().unwrap();
+
+    assert_eq!(id, MemberId(0x8e9e05c52164694d));
+    assert_eq!(id.to_string(), "8e9e05c52164694d");
+    assert_eq!(id.to_decimal(), "10276657743932975437");
+    assert_eq!(MemberId::from_decimal("10276657743932975437").unwrap(), id);
+
+    assert_eq!(serde_json::to_string(&id).unwrap(), r#""8e9e05c52164694d""#);
+    assert_eq!(
+        serde_json::from_str::<MemberId>(r#""8e9e05c52164694d""#).unwrap(),
+        id
+    );
+
+    match MemberId::from_hex("not an id") {
+        Err(Error::InvalidMemberId(ref invalid)) => assert_eq!(invalid, "not an id"),
+        result => panic!("expected Error::InvalidMemberId, got {:?}", result),
+    }
+}
+
+#[test]
+fn initial_cluster_string() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"members":[{"id":"a","name":"infra0","peerURLs":["http://10.0.0.1:2380","http://10.0.1.1:2380"],"clientURLs":[]},{"id":"b","name":"","peerURLs":["http://10.0.0.2:2380"],"clientURLs":[]},{"id":"c","name":"infra2","peerURLs":["http://10.0.0.3:2380"],"clientURLs":[]}]}"#,
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let response = runtime
+        .block_on(members::initial_cluster_string(&client))
+        .unwrap();
+
+    assert_eq!(
+        response.data,
+        "infra0=http://10.0.0.1:2380,infra0=http://10.0.1.1:2380,infra2=http://10.0.0.3:2380"
+    );
+
+    server.join().unwrap();
+}
+
+#[test]
+fn add_and_wait() {
+    let (member_port, member_server) = serve_once("127.0.0.1:0", r#"{"health":"true"}"#);
+    let started: &'static str = Box::leak(
+        format!(
+            r#"{{"members":[{{"id":"b","name":"infra1","peerURLs":["http://10.0.0.2:2380"],"clientURLs":["http://127.0.0.1:{}"]}}]}}"#,
+            member_port
+        )
+        .into_boxed_str(),
+    );
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "201 Created",
+                "",
+                r#"{"id":"b","name":"","peerURLs":["http://10.0.0.2:2380"],"clientURLs":[]}"#,
+            ),
+            // The cluster may elect a leader while the new member joins.
+            (
+                "500 Internal Server Error",
+                "",
+                r#"{"errorCode":301,"message":"During Leader Election","index":4}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"members":[{"id":"b","name":"","peerURLs":["http://10.0.0.2:2380"],"clientURLs":[]}]}"#,
+            ),
+            ("200 OK", "", started),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let response = runtime
+        .block_on(members::add_and_wait(
+            &client,
+            vec!["http://10.0.0.2:2380".to_owned()],
+            Duration::from_millis(10),
+            Duration::from_secs(5),
+        ))
+        .unwrap();
+
+    assert_eq!(response.data.id, MemberId(0xb));
+    assert_eq!(response.data.name, "infra1");
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v2/members "));
+    assert!(requests[1].starts_with("GET /v2/members "));
+    assert!(requests[2].starts_with("GET /v2/members "));
+    assert!(requests[3].starts_with("GET /v2/members "));
+    assert!(member_server.join().unwrap().starts_with("GET /health "));
+}
@@ -0,0 +1,96 @@
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use etcd::kv::{self, GetOptions};
+use etcd::mirror::{self, MirrorOptions};
+use futures::future::Future;
+use futures::stream::Stream;
+use futures::sync::oneshot::channel;
+
+use crate::test::TestClient;
+
+mod test;
+
+#[test]
+fn sync() {
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/test/source/foo", "bar", None).and_then(move |_| {
+        let options = MirrorOptions {
+            destination_prefix: Some("/test/destination".to_owned()),
+            ..Default::default()
+        };
+
+        mirror::sync(&inner_client, &inner_client, "/test/source", &options).and_then(
+            move |index| {
+                assert!(index > 0);
+
+                kv::get(
+                    &inner_client,
+                    "/test/destination/foo",
+                    GetOptions::default(),
+                )
+                .and_then(|res| {
+                    assert_eq!(res.data.node.value.unwrap(), "bar");
+
+                    Ok(())
+                })
+            },
+        )
+    });
+
+    client.run(work);
+}
+
+#[test]
+fn mirror() {
+    let (tx, rx) = channel();
+
+    let child = spawn(move || {
+        let mut client = TestClient::no_destructor();
+        let inner_client = client.clone();
+
+        let work = rx.then(move |_| {
+            sleep(Duration::from_millis(100));
+
+            kv::set(&inner_client, "/test/source/foo", "baz", None)
+        });
+
+        client.run(work);
+    });
+
+    let mut client = TestClient::new();
+    let inner_client = client.clone();
+
+    let work = kv::set(&client, "/test/source/foo", "bar", None).and_then(move |res| {
+        tx.send(()).unwrap();
+
+        let options = MirrorOptions {
+            destination_prefix: Some("/test/destination".to_owned()),
+            resume_from: res.data.node.modified_index,
+        };
+
+        mirror::mirror(&inner_client, &inner_client, "/test/source", options)
+            .into_future()
+            .map_err(|(errors, _)| errors)
+            .and_then(move |(index, _)| {
+                assert!(index.is_some());
+
+                kv::get(
+                    &inner_client,
+                    "/test/destination/foo",
+                    GetOptions::default(),
+                )
+                .and_then(|res| {
+                    assert_eq!(res.data.node.value.unwrap(), "baz");
+
+                    Ok(())
+                })
+            })
+    });
+
+    client.run(work);
+
+    child.join().ok().unwrap();
+}
@@ -0,0 +1,151 @@
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::Duration;
+
+use etcd::kv::{self, GetOptions};
+use etcd::{multi, Client, Error, RequestOptions};
+use hyper::client::HttpConnector;
+use tokio::runtime::Runtime;
+
+use crate::test::{serve_once, serve_statuses};
+
+mod test;
+
+/// Returns the URL of a port that nothing listens on.
+fn closed_endpoint() -> String {
+    format!(
+        "http://{}",
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    )
+}
+
+/// Returns the URL of a server that accepts connections but never responds.
+fn unresponsive_endpoint() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+
+    spawn(move || {
+        let connections: Vec<_> = listener.incoming().collect();
+
+        drop(connections);
+    });
+
+    endpoint
+}
+
+/// Returns a client for a cluster whose only member never responds.
+fn unresponsive_client() -> Client<HttpConnector> {
+    Client::new(&[&unresponsive_endpoint()], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        })
+}
+
+#[test]
+fn fails_over_when_unreachable() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":7,"createdIndex":7}}"#,
+    );
+    let primary = Client::new(&[&closed_endpoint()], None).unwrap();
+    let fallback = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let failovers = Arc::new(AtomicUsize::new(0));
+    let hook_failovers = failovers.clone();
+
+    let client = multi::Client::new(&primary, &[fallback]).with_failover_hook(move |failover| {
+        assert_eq!((failover.from, failover.to), (0, 1));
+        assert!(!failover.errors.is_empty());
+
+        hook_failovers.fetch_add(1, Ordering::SeqCst);
+    });
+
+    let mut runtime = Runtime::new().unwrap();
+    let response = runtime
+        .block_on(client.call(|client| kv::get(client, "/foo", GetOptions::default())))
+        .unwrap();
+
+    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+    assert_eq!(failovers.load(Ordering::SeqCst), 1);
+    assert!(server.join().unwrap().starts_with("GET /v2/keys/foo"));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn returns_errors_from_reachable_cluster() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "404 Not Found",
+            "",
+            r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":7}"#,
+        )],
+    );
+    let primary = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let fallback = Client::new(&[&closed_endpoint()], None).unwrap();
+
+    let client = multi::Client::new(&primary, &[fallback])
+        .with_failover_hook(|_| panic!("failed over from a reachable cluster"));
+
+    let mut runtime = Runtime::new().unwrap();
+    let errors = runtime
+        .block_on(client.call(|client| kv::get(client, "/foo", GetOptions::default())))
+        .unwrap_err();
+
+    match errors[..] {
+        [Error::Api(ref error)] => assert_eq!(error.error_code, 100),
+        ref errors => panic!("expected a single API error, got {:?}", errors),
+    }
+
+    server.join().unwrap();
+    runtime.shutdown_now();
+}
+
+#[test]
+fn does_not_fail_over_on_timeout() {
+    let fallback = Client::new(&[&closed_endpoint()], None).unwrap();
+
+    let client = multi::Client::new(&unresponsive_client(), &[fallback])
+        .with_failover_hook(|_| panic!("failed over after a timeout"));
+
+    let mut runtime = Runtime::new().unwrap();
+    let errors = runtime
+        .block_on(client.call(|client| kv::get(client, "/foo", GetOptions::default())))
+        .unwrap_err();
+
+    match errors[..] {
+        [Error::Timeout] => {}
+        ref errors => panic!("expected a single timeout, got {:?}", errors),
+    }
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn fails_over_on_timeout_when_enabled() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":7,"createdIndex":7}}"#,
+    );
+    let fallback = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let client = multi::Client::new(&unresponsive_client(), &[fallback]).with_timeout_failover();
+
+    let mut runtime = Runtime::new().unwrap();
+    let response = runtime
+        .block_on(client.call(|client| kv::get(client, "/foo", GetOptions::default())))
+        .unwrap();
+
+    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+    assert!(server.join().unwrap().starts_with("GET /v2/keys/foo"));
+
+    runtime.shutdown_now();
+}
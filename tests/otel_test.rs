@@ -0,0 +1,50 @@
+#![cfg(feature = "otel")]
+
+use std::str::FromStr;
+
+use etcd::kv::{self, GetOptions};
+use etcd::Client;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::Context;
+use tokio::runtime::Runtime;
+
+use crate::test::serve_statuses;
+
+mod test;
+
+#[test]
+fn propagates_trace_context() {
+    let node = r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#;
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![("200 OK", "", node), ("200 OK", "", node)],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let context = Context::new().with_remote_span_context(SpanContext::new(
+        TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+        SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::from_str("congo=t61rcWkgMzE").unwrap(),
+    ));
+    let traced = client.with_trace_context(&context);
+    let mut runtime = Runtime::new().unwrap();
+
+    for client in &[&traced, &client] {
+        runtime
+            .block_on(kv::get(client, "/foo", GetOptions::default()))
+            .unwrap();
+    }
+
+    let requests = server.join().unwrap();
+
+    // Without an SDK, the global tracer's spans carry their parent's span context.
+    assert!(requests[0]
+        .contains("traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01\r\n"));
+    assert!(requests[0].contains("tracestate: congo=t61rcWkgMzE\r\n"));
+
+    // Without a span in the current context, there is no trace to continue.
+    assert!(!requests[1].contains("traceparent"));
+
+    runtime.shutdown_now();
+}
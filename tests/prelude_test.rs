@@ -0,0 +1,25 @@
+use etcd::prelude::*;
+use tokio::runtime::Runtime;
+
+use crate::test::serve_once;
+
+mod test;
+
+#[test]
+fn prelude() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = kv::get(&client, "/foo", GetOptions::default());
+    let result: etcd::Result<KeyValueInfo> = Runtime::new().unwrap().block_on(work);
+    let response = result.unwrap();
+
+    assert_eq!(response.data.action, Action::Get);
+    assert_eq!(response.data.node.value, Some("bar".to_owned()));
+
+    assert!(server.join().unwrap().starts_with("GET /v2/keys/foo"));
+}
@@ -0,0 +1,169 @@
+use std::sync::mpsc::channel;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use etcd::schedule;
+use etcd::Client;
+use tokio::runtime::Runtime;
+
+use crate::test::serve_statuses;
+
+mod test;
+
+#[test]
+fn tick_key() {
+    let tick = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+
+    assert_eq!(
+        schedule::tick_key("/jobs/cleanup", tick(0)),
+        "/jobs/cleanup/1970-01-01T00:00:00"
+    );
+    assert_eq!(
+        schedule::tick_key("/jobs/cleanup/", tick(1_714_521_600)),
+        "/jobs/cleanup/2024-05-01T00:00:00"
+    );
+    assert_eq!(
+        schedule::tick_key("/jobs/cleanup", tick(1_709_251_199)),
+        "/jobs/cleanup/2024-02-29T23:59:59"
+    );
+    assert_eq!(
+        schedule::tick_key("/jobs/cleanup", tick(951_868_800 + 3_723)),
+        "/jobs/cleanup/2000-03-01T01:02:03"
+    );
+}
+
+#[test]
+fn claim() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "201 Created",
+                "X-Etcd-Index: 7\r\n",
+                r#"{"action":"create","node":{"key":"/jobs/cleanup/2024-05-01T00:00:00","value":"","expiration":"2024-05-01T00:10:00Z","ttl":600,"modifiedIndex":7,"createdIndex":7}}"#,
+            ),
+            (
+                "412 Precondition Failed",
+                "",
+                r#"{"errorCode":105,"message":"Key already exists","cause":"/jobs/cleanup/2024-05-01T00:00:00","index":7}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let tick = UNIX_EPOCH + Duration::from_secs(1_714_521_600);
+    let mut runtime = Runtime::new().unwrap();
+
+    assert!(runtime
+        .block_on(schedule::claim(&client, "/jobs/cleanup", tick, 600))
+        .unwrap());
+    assert!(!runtime
+        .block_on(schedule::claim(&client, "/jobs/cleanup", tick, 600))
+        .unwrap());
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("PUT /v2/keys/jobs/cleanup/2024-05-01T00:00:00"));
+    assert!(requests[0].ends_with("value=&ttl=600&prevExist=false"));
+}
+
+#[test]
+fn run_calls_function_for_claimed_ticks() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "201 Created",
+            "X-Etcd-Index: 7\r\n",
+            r#"{"action":"create","node":{"key":"/jobs/cleanup/tick","value":"","ttl":60,"modifiedIndex":7,"createdIndex":7}}"#,
+        )],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let (sender, receiver) = channel();
+    let mut runtime = Runtime::new().unwrap();
+
+    runtime.spawn(schedule::run(
+        &client,
+        "/jobs/cleanup",
+        Duration::from_secs(1),
+        60,
+        move |tick| {
+            let _ = sender.send(tick);
+
+            Ok::<(), ()>(())
+        },
+    ));
+
+    let tick = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    let elapsed = tick.duration_since(UNIX_EPOCH).unwrap();
+
+    assert_eq!(elapsed.subsec_nanos(), 0);
+    assert!(tick <= SystemTime::now());
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with(&format!(
+        "PUT /v2/keys/{}",
+        schedule::tick_key("jobs/cleanup", tick)
+    )));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn run_on_schedule_calls_function_at_scheduled_ticks() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "201 Created",
+            "X-Etcd-Index: 7\r\n",
+            r#"{"action":"create","node":{"key":"/jobs/cleanup/tick","value":"","ttl":60,"modifiedIndex":7,"createdIndex":7}}"#,
+        )],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let (sender, receiver) = channel();
+    let mut runtime = Runtime::new().unwrap();
+    let even_seconds = |after: SystemTime| {
+        let seconds = after.duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        UNIX_EPOCH + Duration::from_secs((seconds / 2 + 1) * 2)
+    };
+
+    runtime.spawn(schedule::run_on_schedule(
+        &client,
+        "/jobs/cleanup",
+        even_seconds,
+        60,
+        move |tick| {
+            let _ = sender.send(tick);
+
+            Ok::<(), ()>(())
+        },
+    ));
+
+    let tick = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    let elapsed = tick.duration_since(UNIX_EPOCH).unwrap();
+
+    assert_eq!(elapsed.subsec_nanos(), 0);
+    assert_eq!(elapsed.as_secs() % 2, 0);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with(&format!(
+        "PUT /v2/keys/{}",
+        schedule::tick_key("jobs/cleanup", tick)
+    )));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn every_returns_multiples_of_interval() {
+    let schedule = schedule::every(Duration::from_secs(60));
+
+    assert_eq!(
+        schedule(UNIX_EPOCH + Duration::from_secs(90)),
+        UNIX_EPOCH + Duration::from_secs(120)
+    );
+    assert_eq!(
+        schedule(UNIX_EPOCH + Duration::from_secs(120)),
+        UNIX_EPOCH + Duration::from_secs(180)
+    );
+}
@@ -0,0 +1,97 @@
+use etcd::semaphore::Semaphore;
+use etcd::{Client, Error};
+use tokio::runtime::Runtime;
+
+use crate::test::serve_statuses;
+
+mod test;
+
+#[test]
+fn acquire_waits_for_holders() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "201 Created",
+                "X-Etcd-Index: 12\r\n",
+                r#"{"action":"create","node":{"key":"/jobs/00000000000000000012","value":"1","ttl":60,"modifiedIndex":12,"createdIndex":12}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 12\r\n",
+                r#"{"action":"get","node":{"key":"/jobs","dir":true,"nodes":[{"key":"/jobs/00000000000000000011","value":"1","modifiedIndex":11,"createdIndex":11},{"key":"/jobs/00000000000000000012","value":"1","modifiedIndex":12,"createdIndex":12}],"modifiedIndex":11,"createdIndex":11}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 13\r\n",
+                r#"{"action":"delete","node":{"key":"/jobs/00000000000000000011","modifiedIndex":13,"createdIndex":11}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 13\r\n",
+                r#"{"action":"get","node":{"key":"/jobs","dir":true,"nodes":[{"key":"/jobs/00000000000000000012","value":"1","modifiedIndex":12,"createdIndex":12}],"modifiedIndex":11,"createdIndex":11}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 14\r\n",
+                r#"{"action":"compareAndDelete","node":{"key":"/jobs/00000000000000000012","modifiedIndex":14,"createdIndex":12}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let semaphore = runtime
+        .block_on(Semaphore::acquire(&client, "/jobs", 1, 60))
+        .unwrap();
+
+    assert_eq!(semaphore.key(), "/jobs");
+    assert_eq!(semaphore.holder_key(), "/jobs/00000000000000000012");
+    assert!(semaphore.is_held());
+
+    runtime.block_on(semaphore.release()).unwrap();
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v2/keys/jobs"));
+    assert!(requests[0].ends_with("value=1&ttl=60"));
+    assert!(requests[1].starts_with("GET /v2/keys/jobs?"));
+    assert!(requests[2].starts_with("GET /v2/keys/jobs?"));
+    assert!(requests[2].contains("waitIndex=13"));
+    assert!(requests[3].starts_with("GET /v2/keys/jobs?"));
+    assert!(requests[4].starts_with("DELETE /v2/keys/jobs/00000000000000000012?prevValue=1"));
+
+    runtime.shutdown_now();
+}
+
+#[test]
+fn acquire_fails_when_key_is_lost() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "201 Created",
+                "X-Etcd-Index: 12\r\n",
+                r#"{"action":"create","node":{"key":"/jobs/00000000000000000012","value":"2","ttl":60,"modifiedIndex":12,"createdIndex":12}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 13\r\n",
+                r#"{"action":"get","node":{"key":"/jobs","dir":true,"nodes":[{"key":"/jobs/00000000000000000011","value":"2","modifiedIndex":11,"createdIndex":11}],"modifiedIndex":11,"createdIndex":11}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    match runtime.block_on(Semaphore::acquire(&client, "/jobs", 2, 60)) {
+        Err(ref errors) => match errors[..] {
+            [Error::LockLost] => {}
+            ref errors => panic!("expected LockLost, got {:?}", errors),
+        },
+        Ok(semaphore) => panic!("expected the key to be lost, got {:?}", semaphore),
+    }
+
+    server.join().unwrap();
+    runtime.shutdown_now();
+}
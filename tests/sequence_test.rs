@@ -0,0 +1,150 @@
+use etcd::sequence::{self, Sequence};
+use etcd::{Client, Error};
+use tokio::runtime::Runtime;
+
+use crate::test::serve_statuses;
+
+mod test;
+
+#[test]
+fn next_creates_counter() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "404 Not Found",
+                "",
+                r#"{"errorCode":100,"message":"Key not found","cause":"/ids","index":8}"#,
+            ),
+            (
+                "201 Created",
+                "X-Etcd-Index: 9\r\n",
+                r#"{"action":"create","node":{"key":"/ids","value":"1","modifiedIndex":9,"createdIndex":9}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let id = Runtime::new()
+        .unwrap()
+        .block_on(sequence::next(&client, "/ids"))
+        .unwrap();
+
+    assert_eq!(id, 1);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[1].starts_with("PUT /v2/keys/ids"));
+    assert!(requests[1].ends_with("value=1&prevExist=false"));
+}
+
+#[test]
+fn reserve_retries_conflicts() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "X-Etcd-Index: 9\r\n",
+                r#"{"action":"get","node":{"key":"/ids","value":"4","modifiedIndex":9,"createdIndex":1}}"#,
+            ),
+            (
+                "412 Precondition Failed",
+                "",
+                r#"{"errorCode":101,"message":"Compare failed","cause":"[9 != 10]","index":10}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 10\r\n",
+                r#"{"action":"get","node":{"key":"/ids","value":"5","modifiedIndex":10,"createdIndex":1}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 11\r\n",
+                r#"{"action":"compareAndSwap","node":{"key":"/ids","value":"15","modifiedIndex":11,"createdIndex":1}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    let range = Runtime::new()
+        .unwrap()
+        .block_on(sequence::reserve(&client, "/ids", 10))
+        .unwrap();
+
+    assert_eq!(range, 6..16);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[1].ends_with("value=14&prevIndex=9"));
+    assert!(requests[3].starts_with("PUT /v2/keys/ids"));
+    assert!(requests[3].ends_with("value=15&prevIndex=10"));
+}
+
+#[test]
+fn reserve_rejects_invalid_counter() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "200 OK",
+            "X-Etcd-Index: 9\r\n",
+            r#"{"action":"get","node":{"key":"/ids","value":"bar","modifiedIndex":9,"createdIndex":1}}"#,
+        )],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+
+    match Runtime::new()
+        .unwrap()
+        .block_on(sequence::reserve(&client, "/ids", 1))
+    {
+        Err(ref errors) => match errors[..] {
+            [Error::InvalidSequence { ref key, ref value }] => {
+                assert_eq!(key, "/ids");
+                assert_eq!(value, "bar");
+            }
+            ref errors => panic!("expected an invalid sequence error, got {:?}", errors),
+        },
+        Ok(range) => panic!("expected an error, got {:?}", range),
+    }
+
+    server.join().unwrap();
+}
+
+#[test]
+fn sequence_reserves_windows() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "X-Etcd-Index: 9\r\n",
+                r#"{"action":"get","node":{"key":"/ids","value":"4","modifiedIndex":9,"createdIndex":1}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 10\r\n",
+                r#"{"action":"compareAndSwap","node":{"key":"/ids","value":"7","modifiedIndex":10,"createdIndex":1}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 12\r\n",
+                r#"{"action":"get","node":{"key":"/ids","value":"9","modifiedIndex":12,"createdIndex":1}}"#,
+            ),
+            (
+                "200 OK",
+                "X-Etcd-Index: 13\r\n",
+                r#"{"action":"compareAndSwap","node":{"key":"/ids","value":"12","modifiedIndex":13,"createdIndex":1}}"#,
+            ),
+        ],
+    );
+    let client = Client::new(&[&format!("http://127.0.0.1:{}", port)], None).unwrap();
+    let sequence = Sequence::new(&client, "/ids", 3);
+    let mut runtime = Runtime::new().unwrap();
+
+    let ids: Vec<u64> = (0..4)
+        .map(|_| runtime.block_on(sequence.next()).unwrap())
+        .collect();
+
+    assert_eq!(ids, vec![5, 6, 7, 10]);
+    assert_eq!(server.join().unwrap().len(), 4);
+}
@@ -31,3 +31,18 @@ fn store_stats() {
 
     client.run(work);
 }
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn prometheus_export() {
+    let mut client = TestClient::no_destructor();
+
+    let work = stats::prometheus_export(&client).and_then(|output| {
+        assert!(output.contains("# TYPE etcd_store_operations_total counter\n"));
+        assert!(output.contains("etcd_store_watchers{endpoint=\"http://etcd:2379/\"}"));
+
+        Ok(())
+    });
+
+    client.run(work);
+}
@@ -1,6 +1,9 @@
+use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::net::TcpListener;
 use std::ops::Deref;
+use std::thread::{spawn, JoinHandle};
 
 use etcd::{kv, Client};
 use futures::Future;
@@ -10,6 +13,150 @@ use hyper_tls::HttpsConnector;
 use native_tls::{Certificate, Identity, TlsConnector};
 use tokio::runtime::Runtime;
 
+/// The version of etcd used when `ETCD_VERSION` is not set.
+const DEFAULT_ETCD_VERSION: &str = "2.3.8";
+
+/// Returns the major, minor, and patch version of the etcd cluster the tests are running against.
+///
+/// This is read from the `ETCD_VERSION` environment variable, which is also used to choose the
+/// etcd image in docker-compose.yml.
+#[allow(dead_code)]
+pub fn etcd_version() -> (u64, u64, u64) {
+    let version = env::var("ETCD_VERSION").unwrap_or_else(|_| DEFAULT_ETCD_VERSION.to_owned());
+    let mut parts = version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().expect("ETCD_VERSION must be a version number"));
+
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+/// Returns whether or not the tests are running against at least the given etcd version.
+#[allow(dead_code)]
+pub fn etcd_version_at_least(major: u64, minor: u64) -> bool {
+    let (actual_major, actual_minor, _) = etcd_version();
+
+    (actual_major, actual_minor) >= (major, minor)
+}
+
+/// Starts a server at the given address that answers a single request with the given JSON body.
+/// Returns the port and a handle that yields the request line the server received.
+#[allow(dead_code)]
+pub fn serve_once(address: &str, body: &'static str) -> (u16, JoinHandle<String>) {
+    let (port, handle) = serve(address, vec![("", body)]);
+
+    (port, spawn(move || handle.join().unwrap().remove(0)))
+}
+
+/// Starts a server at the given address that answers one request with each of the given extra
+/// headers and JSON bodies, in order. Returns the port and a handle that yields the request lines
+/// the server received.
+#[allow(dead_code)]
+pub fn serve(
+    address: &str,
+    responses: Vec<(&'static str, &'static str)>,
+) -> (u16, JoinHandle<Vec<String>>) {
+    let responses = responses
+        .into_iter()
+        .map(|(headers, body)| ("200 OK", headers, body))
+        .collect();
+    let (port, handle) = serve_statuses(address, responses);
+
+    (
+        port,
+        spawn(move || {
+            handle
+                .join()
+                .unwrap()
+                .iter()
+                .map(|request| request.lines().next().unwrap_or_default().to_owned())
+                .collect()
+        }),
+    )
+}
+
+/// Like `serve`, but answers each request with the given HTTP status as well, e.g. `404 Not
+/// Found`, and yields the whole of each request the server received.
+#[allow(dead_code)]
+pub fn serve_statuses(
+    address: &str,
+    responses: Vec<(&'static str, &'static str, &'static str)>,
+) -> (u16, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind(address).expect("failed to bind the test server");
+    let port = listener.local_addr().unwrap().port();
+
+    let handle = spawn(move || {
+        let mut requests = Vec::with_capacity(responses.len());
+
+        for (status, headers, body) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+
+                if read == 0 {
+                    break;
+                }
+
+                request.extend_from_slice(&buffer[..read]);
+            }
+
+            let request = String::from_utf8(request).unwrap();
+            let header_length = request
+                .find("\r\n\r\n")
+                .map_or(request.len(), |end| end + 4);
+            let content_length = request
+                .lines()
+                .filter_map(|line| {
+                    let (name, value) = line.split_at(line.find(':')?);
+
+                    if name.eq_ignore_ascii_case("content-length") {
+                        value[1..].trim().parse::<usize>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap_or(0);
+            let mut remaining = (header_length + content_length).saturating_sub(request.len());
+
+            while remaining > 0 {
+                let read = stream.read(&mut buffer).unwrap();
+
+                if read == 0 {
+                    break;
+                }
+
+                remaining = remaining.saturating_sub(read);
+            }
+
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n{}\r\n{}",
+                status,
+                body.len(),
+                headers,
+                body
+            )
+            .unwrap();
+
+            requests.push(request);
+        }
+
+        requests
+    });
+
+    (port, handle)
+}
+
 /// Wrapper around Client that automatically cleans up etcd after each test.
 pub struct TestClient<C>
 where
@@ -0,0 +1,643 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::channel;
+use std::thread::spawn;
+use std::time::{Duration, SystemTime};
+
+use etcd::v3::election::{self, LeaderKey};
+use etcd::v3::kv::{self, RevisionHistory};
+use etcd::v3::maintenance::{self, Alarm, AlarmType};
+use etcd::v3::{auth, lock};
+use etcd::{Backoff, BasicAuth, Client, DebugLogging, Error, RequestOptions};
+use futures::{Future, Stream};
+use tokio::runtime::Runtime;
+use tokio::timer::Timeout;
+
+use crate::test::{serve_once, serve_statuses};
+
+mod test;
+
+#[test]
+fn status() {
+    let (port, server) = serve_once(
+        "127.0.0.1:0",
+        r#"{"header":{"cluster_id":"14841639068965178418","member_id":"10276657743932975437","revision":"5","raft_term":"2"},"version":"3.3.25","dbSize":"24576","leader":"10276657743932975437","raftIndex":"9","raftTerm":"2"}"#,
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = maintenance::status(&client).collect();
+    let responses = Runtime::new().unwrap().block_on(work).unwrap();
+    let status = &responses[0].1.data;
+
+    assert_eq!(status.header.cluster_id, 14_841_639_068_965_178_418);
+    assert_eq!(status.header.revision, 5);
+    assert_eq!(status.version, "3.3.25");
+    assert_eq!(status.db_size, 24576);
+    assert_eq!(status.raft_index, 9);
+    assert_eq!(status.db_size_in_use, 0);
+    assert!(status.is_leader());
+
+    assert!(server
+        .join()
+        .unwrap()
+        .starts_with("POST /v3/maintenance/status"));
+}
+
+#[test]
+fn falls_back_to_older_gateway_prefixes() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            ("404 Not Found", "", "404 page not found"),
+            ("404 Not Found", "", "404 page not found"),
+            (
+                "200 OK",
+                "",
+                r#"{"header":{"revision":"5"},"alarms":[{"memberID":"42","alarm":"NOSPACE"}]}"#,
+            ),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = maintenance::alarm_list(&client);
+    let response = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(
+        response.data,
+        vec![Alarm {
+            alarm: AlarmType::NoSpace,
+            member_id: 42,
+        }]
+    );
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/maintenance/alarm"));
+    assert!(requests[1].starts_with("POST /v3beta/maintenance/alarm"));
+    assert!(requests[2].starts_with("POST /v3alpha/maintenance/alarm"));
+}
+
+#[test]
+fn rpc_error() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "404 Not Found",
+            "",
+            r#"{"error":"etcdserver: member not found","code":5}"#,
+        )],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = maintenance::defragment(&client, &endpoint.parse().unwrap());
+
+    match Runtime::new().unwrap().block_on(work) {
+        Err(Error::Rpc(ref error)) => {
+            assert_eq!(error.code, 5);
+            assert_eq!(error.message, "etcdserver: member not found");
+        }
+        result => panic!("expected Error::Rpc, got {:?}", result),
+    }
+
+    assert_eq!(server.join().unwrap().len(), 1);
+}
+
+#[test]
+fn authenticates_with_tokens() {
+    let status = r#"{"header":{"member_id":"1"},"version":"3.4.0","leader":"1"}"#;
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            ("200 OK", "", r#"{"header":{},"token":"first"}"#),
+            ("200 OK", "", status),
+            (
+                "401 Unauthorized",
+                "",
+                r#"{"error":"etcdserver: invalid auth token","code":16}"#,
+            ),
+            ("200 OK", "", r#"{"header":{},"token":"second"}"#),
+            ("200 OK", "", status),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(
+        &[&endpoint],
+        Some(BasicAuth {
+            username: "root".to_owned(),
+            password: "secret".to_owned(),
+        }),
+    )
+    .unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    for _ in 0..2 {
+        let responses = runtime
+            .block_on(maintenance::status(&client).collect())
+            .unwrap();
+
+        assert!(responses[0].1.data.is_leader());
+    }
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/auth/authenticate"));
+    assert!(!requests[0].contains("authorization"));
+    assert!(requests[1].contains("authorization: first\r\n"));
+    assert!(requests[2].contains("authorization: first\r\n"));
+    assert!(requests[3].starts_with("POST /v3/auth/authenticate"));
+    assert!(requests[4].starts_with("POST /v3/maintenance/status"));
+    assert!(requests[4].contains("authorization: second\r\n"));
+}
+
+#[test]
+fn skips_tokens_when_authentication_is_disabled() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "400 Bad Request",
+                "",
+                r#"{"error":"etcdserver: authentication is not enabled","code":9}"#,
+            ),
+            ("200 OK", "", r#"{"header":{"revision":"3"}}"#),
+            ("200 OK", "", r#"{"header":{"revision":"4"}}"#),
+            (
+                "400 Bad Request",
+                "",
+                r#"{"error":"etcdserver: user name is empty","code":3}"#,
+            ),
+            ("200 OK", "", r#"{"header":{},"token":"first"}"#),
+            ("200 OK", "", r#"{"header":{"revision":"5"}}"#),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None)
+        .unwrap()
+        .with_basic_auth(BasicAuth {
+            username: "root".to_owned(),
+            password: "secret".to_owned(),
+        });
+    let mut runtime = Runtime::new().unwrap();
+
+    for revision in 3..6 {
+        let work = maintenance::defragment(&client, &endpoint.parse().unwrap());
+        let response = runtime.block_on(work).unwrap();
+
+        assert_eq!(response.data.revision, revision);
+    }
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/auth/authenticate"));
+    assert!(requests[1].starts_with("POST /v3/maintenance/defragment"));
+    assert!(!requests[1].contains("authorization"));
+    assert!(requests[2].starts_with("POST /v3/maintenance/defragment"));
+    assert!(!requests[2].contains("authorization"));
+    assert!(requests[3].starts_with("POST /v3/maintenance/defragment"));
+    assert!(requests[4].starts_with("POST /v3/auth/authenticate"));
+    assert!(requests[5].contains("authorization: first\r\n"));
+}
+
+#[test]
+fn authenticate() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![("200 OK", "", r#"{"header":{},"token":"abc.123"}"#)],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = auth::authenticate(&client, "root", "secret");
+    let response = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(response.data, "abc.123");
+    assert!(server.join().unwrap()[0].starts_with("POST /v3/auth/authenticate"));
+}
+
+#[test]
+fn lock_and_unlock() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "",
+                r#"{"header":{"revision":"7"},"key":"am9icy82OTRk"}"#,
+            ),
+            ("200 OK", "", r#"{"header":{"revision":"8"}}"#),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let key = runtime
+        .block_on(lock::lock(&client, "jobs", Some(0x694d)))
+        .unwrap()
+        .data;
+
+    assert_eq!(key, b"jobs/694d");
+
+    let header = runtime.block_on(lock::unlock(&client, &key)).unwrap().data;
+
+    assert_eq!(header.revision, 8);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/lock/lock"));
+    assert!(requests[0].ends_with(r#"{"name":"am9icw==","lease":"26957"}"#));
+    assert!(requests[1].starts_with("POST /v3/lock/unlock"));
+    assert!(requests[1].ends_with(r#"{"key":"am9icy82OTRk"}"#));
+}
+
+#[test]
+fn lock_with_fence() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "",
+                r#"{"header":{"revision":"7"},"key":"am9icy82OTRk"}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"header":{"revision":"7"},"kvs":[{"key":"am9icy82OTRk","create_revision":"7","mod_revision":"7","version":"1","lease":"26957"}],"count":"1"}"#,
+            ),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let token = Runtime::new()
+        .unwrap()
+        .block_on(lock::lock_with_fence(&client, "jobs", Some(0x694d)))
+        .unwrap()
+        .data;
+
+    assert_eq!(token.value(), 7);
+    assert_eq!(token.key(), b"jobs/694d");
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/lock/lock"));
+    assert!(requests[1].starts_with("POST /v3/kv/range"));
+    assert!(requests[1].contains(r#""key":"am9icy82OTRk""#));
+}
+
+#[test]
+fn put_with_fence() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "",
+                r#"{"header":{"revision":"7"},"key":"am9icy82OTRk"}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"header":{"revision":"7"},"kvs":[{"key":"am9icy82OTRk","create_revision":"7","mod_revision":"7","version":"1"}],"count":"1"}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"header":{"revision":"8"},"succeeded":true}"#,
+            ),
+            ("200 OK", "", r#"{"header":{"revision":"9"}}"#),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let token = runtime
+        .block_on(lock::lock_with_fence(&client, "jobs", None))
+        .unwrap()
+        .data;
+
+    let header = runtime
+        .block_on(kv::put_with_fence(&client, "foo", b"bar", &token))
+        .unwrap()
+        .data;
+
+    assert_eq!(header.revision, 8);
+
+    match runtime.block_on(kv::put_with_fence(&client, "foo", b"baz", &token)) {
+        Err(ref errors) => match errors[..] {
+            [Error::LockLost] => {}
+            ref errors => panic!("expected LockLost, got {:?}", errors),
+        },
+        Ok(response) => panic!("expected the write to be refused, got {:?}", response),
+    }
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[2].starts_with("POST /v3/kv/txn"));
+    assert!(requests[2].ends_with(
+        r#"{"compare":[{"result":"EQUAL","target":"CREATE","key":"am9icy82OTRk","create_revision":"7"}],"success":[{"request_put":{"key":"Zm9v","value":"YmFy"}}]}"#
+    ));
+}
+
+#[test]
+fn campaign_and_leader() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            (
+                "200 OK",
+                "",
+                r#"{"header":{},"leader":{"name":"bWFpbg==","key":"bWFpbi8x","rev":"4","lease":"1"}}"#,
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"header":{},"kv":{"key":"bWFpbi8x","create_revision":"4","mod_revision":"4","version":"1","value":"b25l","lease":"1"}}"#,
+            ),
+            ("200 OK", "", r#"{"header":{"revision":"5"}}"#),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let leader = runtime
+        .block_on(election::campaign(&client, "main", 1, b"one"))
+        .unwrap()
+        .data;
+
+    assert_eq!(
+        leader,
+        LeaderKey {
+            name: b"main".to_vec(),
+            key: b"main/1".to_vec(),
+            rev: 4,
+            lease: 1,
+        }
+    );
+
+    let kv = runtime
+        .block_on(election::leader(&client, "main"))
+        .unwrap()
+        .data;
+
+    assert_eq!(kv.key, b"main/1");
+    assert_eq!(kv.value, b"one");
+    assert_eq!(kv.create_revision, 4);
+
+    runtime
+        .block_on(election::resign(&client, &leader))
+        .unwrap();
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/election/campaign"));
+    assert!(requests[1].starts_with("POST /v3/election/leader"));
+    assert!(requests[2].starts_with("POST /v3/election/resign"));
+    assert!(requests[2]
+        .ends_with(r#"{"leader":{"name":"bWFpbg==","key":"bWFpbi8x","rev":"4","lease":"1"}}"#));
+}
+
+#[test]
+fn observe() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "200 OK",
+            "",
+            concat!(
+                r#"{"result":{"header":{},"kv":{"key":"bWFpbi8x","value":"b25l"}}}"#,
+                "\n",
+                r#"{"result":{"header":{},"kv":{"key":"bWFpbi8y","value":"dHdv"}}}"#,
+                "\n",
+                r#"{"error":{"grpc_code":7,"message":"etcdserver: permission denied"}}"#,
+                "\n",
+            ),
+        )],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = election::observe(&client, "main")
+        .then(Ok::<_, ()>)
+        .collect();
+    let results = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().data.value, b"one");
+    assert_eq!(results[1].as_ref().unwrap().data.key, b"main/2");
+
+    match results[2] {
+        Err(ref errors) => match errors[0] {
+            Error::Rpc(ref error) => assert_eq!(error.code, 7),
+            ref error => panic!("expected Error::Rpc, got {:?}", error),
+        },
+        ref result => panic!("expected an error, got {:?}", result),
+    }
+
+    assert!(server.join().unwrap()[0].starts_with("POST /v3/election/observe"));
+}
+
+#[test]
+fn observe_only() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![(
+            "200 OK",
+            "",
+            concat!(
+                r#"{"result":{"header":{},"kv":{"key":"bWFpbi8x","create_revision":"3","value":"b25l"}}}"#,
+                "\n",
+                r#"{"result":{"header":{},"kv":{"key":"bWFpbi8x","create_revision":"3","value":"dW5v"}}}"#,
+                "\n",
+                r#"{"result":{"header":{},"kv":{"key":"bWFpbi8y","create_revision":"5","value":"dHdv"}}}"#,
+                "\n",
+                r#"{"result":{"header":{},"kv":{"key":"bWFpbi8x","create_revision":"8","value":"b25l"}}}"#,
+                "\n",
+                r#"{"error":{"grpc_code":7,"message":"etcdserver: permission denied"}}"#,
+                "\n",
+            ),
+        )],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+
+    let work = election::observe_only(&client, "main")
+        .map(|response| (response.data.key, response.data.create_revision))
+        .then(Ok::<_, ()>)
+        .collect();
+    let results = Runtime::new().unwrap().block_on(work).unwrap();
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].as_ref().ok(), Some(&(b"main/1".to_vec(), 3)));
+    assert_eq!(results[1].as_ref().ok(), Some(&(b"main/2".to_vec(), 5)));
+    assert_eq!(results[2].as_ref().ok(), Some(&(b"main/1".to_vec(), 8)));
+    assert!(results[3].is_err());
+
+    assert!(server.join().unwrap()[0].starts_with("POST /v3/election/observe"));
+}
+
+#[test]
+fn compact_to_current_revision() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            ("200 OK", "", r#"{"header":{"revision":"42"}}"#),
+            ("200 OK", "", r#"{"header":{"revision":"42"}}"#),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None).unwrap();
+    let mut runtime = Runtime::new().unwrap();
+
+    let revision = runtime
+        .block_on(kv::current_revision(&client))
+        .unwrap()
+        .data;
+
+    assert_eq!(revision, 42);
+
+    runtime
+        .block_on(kv::compact(&client, revision, true))
+        .unwrap();
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].starts_with("POST /v3/kv/range"));
+    assert!(requests[1].starts_with("POST /v3/kv/compaction"));
+    assert!(requests[1].ends_with(r#"{"revision":"42","physical":true}"#));
+}
+
+#[test]
+fn revision_history() {
+    let start = SystemTime::now() - Duration::from_secs(3600);
+    let minutes = |n: u64| start + Duration::from_secs(n * 60);
+    let mut history = RevisionHistory::new(Duration::from_secs(600));
+
+    assert_eq!(history.revision_older_than(Duration::from_secs(0)), None);
+
+    for n in 0..=30 {
+        history.record_at(minutes(n), n as i64 * 10);
+    }
+
+    history.record_at(minutes(5), 1000);
+
+    assert_eq!(history.revision_at(minutes(30)), Some(300));
+    assert_eq!(
+        history.revision_at(minutes(25) + Duration::from_secs(30)),
+        Some(250)
+    );
+    assert_eq!(history.revision_at(minutes(20)), Some(200));
+    assert_eq!(history.revision_at(minutes(19)), None);
+    assert_eq!(
+        history.revision_older_than(Duration::from_secs(1800)),
+        Some(300)
+    );
+    assert_eq!(history.revision_older_than(Duration::from_secs(7200)), None);
+}
+
+#[test]
+fn watch_reconnects() {
+    let (port, server) = serve_statuses(
+        "127.0.0.1:0",
+        vec![
+            ("503 Service Unavailable", "", "upstream unavailable"),
+            (
+                "200 OK",
+                "",
+                concat!(
+                    r#"{"result":{"header":{"revision":"20"},"created":true}}"#,
+                    "\n",
+                    r#"{"result":{"header":{},"events":[{"kv":{"key":"Zm9v","mod_revision":"21"}}]}}"#,
+                    "\n",
+                    r#"{"error":{"grpc_code":14,"message":"transport is closing"}}"#,
+                    "\n",
+                ),
+            ),
+            (
+                "200 OK",
+                "",
+                r#"{"result":{"header":{},"events":[{"kv":{"key":"Zm9v","mod_revision":"22"}}]}}"#,
+            ),
+        ],
+    );
+    let endpoint = format!("http://127.0.0.1:{}", port);
+    let client = Client::new(&[&endpoint], None)
+        .unwrap()
+        .with_options(RequestOptions {
+            reconnect_backoff: Backoff {
+                initial: Duration::from_millis(1),
+                max: Duration::from_millis(1),
+            },
+            ..RequestOptions::default()
+        });
+
+    let work = kv::watch(&client, "foo", None).collect();
+    let events = Runtime::new().unwrap().block_on(work).unwrap();
+    let revisions: Vec<_> = events
+        .iter()
+        .map(|event| event.data.kv.mod_revision)
+        .collect();
+
+    assert_eq!(revisions, vec![21, 22]);
+
+    let requests = server.join().unwrap();
+
+    assert!(requests[0].ends_with(r#""start_revision":"0"}}"#));
+    assert!(requests[1].ends_with(r#""start_revision":"0"}}"#));
+    assert!(requests[2].ends_with(r#""start_revision":"22"}}"#));
+}
+
+#[test]
+fn observe_with_body_logging() {
+    // A server that streams one message and then leaves the response open, like etcd does.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let (done, finished) = channel::<()>();
+    let server = spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 4096];
+        let message = concat!(
+            r#"{"result":{"header":{},"kv":{"key":"bWFpbi8x","value":"b25l"}}}"#,
+            "\n"
+        );
+
+        let _ = stream.read(&mut buffer).unwrap();
+
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+            message.len(),
+            message
+        )
+        .unwrap();
+
+        let _ = finished.recv();
+    });
+    let client = Client::new(&[&endpoint], None)
+        .unwrap()
+        .with_debug_logging(DebugLogging {
+            max_body_bytes: 1024,
+            ..DebugLogging::default()
+        });
+
+    let work = Timeout::new(
+        election::observe(&client, "main")
+            .into_future()
+            .map_err(|(errors, _)| errors),
+        Duration::from_secs(5),
+    );
+    let mut runtime = Runtime::new().unwrap();
+    let (leader, _) = runtime.block_on(work).unwrap();
+
+    assert_eq!(leader.unwrap().data.value, b"one");
+
+    done.send(()).unwrap();
+    server.join().unwrap();
+    runtime.shutdown_now();
+}